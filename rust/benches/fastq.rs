@@ -0,0 +1,68 @@
+//! Throughput benchmark for FASTQ subsampling.
+//!
+//! Run with `cargo bench --bench fastq`. Writes a synthetic FASTQ file to
+//! the system temp directory and reports reads/second for a range of parser
+//! buffer sizes, and for the needletail default reader as a baseline.
+
+use std::collections::HashSet;
+use std::fs::{remove_file, File};
+use std::io::{self, BufWriter, Write};
+use std::time::Instant;
+
+use blobtk::fastq::{open_fastx, open_fastx_with_capacity, subsample_single, DEFAULT_BUFFER_SIZE};
+
+const READ_COUNT: usize = 500_000;
+const READ_LENGTH: usize = 150;
+
+fn write_fastq(path: &std::path::Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let bases = b"ACGT";
+    let seq: Vec<u8> = (0..READ_LENGTH).map(|i| bases[(i * 7) % 4]).collect();
+    let qual = vec![b'I'; READ_LENGTH];
+    for i in 0..READ_COUNT {
+        writeln!(writer, "@read_{}/1 bench", i)?;
+        writer.write_all(&seq)?;
+        writer.write_all(b"\n+\n")?;
+        writer.write_all(&qual)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let path = std::env::temp_dir().join("blobtk_bench_reads.fq");
+    write_fastq(&path)?;
+    let read_names: HashSet<Vec<u8>> = (0..READ_COUNT)
+        .step_by(2)
+        .map(|i| format!("read_{}", i).into_bytes())
+        .collect();
+    let fastq_path = Some(path.clone());
+    let buffer_sizes = [8 * 1024, DEFAULT_BUFFER_SIZE, 1024 * 1024, 8 * 1024 * 1024];
+    for buffer_size in std::iter::once(None).chain(buffer_sizes.map(Some)) {
+        let start = Instant::now();
+        let reader = match buffer_size {
+            Some(buffer_size) => open_fastx_with_capacity(&fastq_path, buffer_size).unwrap(),
+            None => open_fastx(&fastq_path).unwrap(),
+        };
+        let mut sink = io::sink();
+        subsample_single(
+            &read_names,
+            reader,
+            &mut sink,
+            &None as &Option<Box<dyn Fn()>>,
+        );
+        let elapsed = start.elapsed().as_secs_f64();
+        let label = match buffer_size {
+            Some(buffer_size) => format!("buffer {:>9} bytes", buffer_size),
+            None => "needletail default".to_string(),
+        };
+        println!(
+            "{}: {:>8.3}s ({:.0} reads/s)",
+            label,
+            elapsed,
+            READ_COUNT as f64 / elapsed
+        );
+    }
+    remove_file(&path)?;
+    Ok(())
+}
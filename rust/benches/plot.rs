@@ -0,0 +1,91 @@
+//! Benchmark for snail and cumulative plot statistics.
+//!
+//! Run with `cargo bench --bench plot`. Builds a synthetic assembly with
+//! millions of contigs and reports the time taken to compute snail plot
+//! statistics and cumulative plot lines with a single thread and with the
+//! default thread pool.
+
+use std::time::Instant;
+
+use blobtk::cli::PlotOptions;
+use blobtk::plot::category::Category;
+use blobtk::plot::chart::Dimensions;
+use blobtk::plot::cumulative::{cumulative_lines, CumulativeData};
+use blobtk::plot::snail::snail_stats;
+
+const CONTIG_COUNT: usize = 2_000_000;
+const CATEGORY_COUNT: usize = 10;
+
+/// Deterministic pseudo-random contig lengths, GC proportions and N counts.
+fn synthetic_assembly() -> (Vec<usize>, Vec<f64>, Vec<usize>) {
+    let mut state: u64 = 42;
+    let mut next = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        state >> 33
+    };
+    let mut lengths = vec![];
+    let mut gcs = vec![];
+    let mut ncounts = vec![];
+    for _ in 0..CONTIG_COUNT {
+        let length = 1000 + (next() % 1_000_000) as usize / (1 + (next() % 100) as usize);
+        lengths.push(length);
+        gcs.push(0.3 + (next() % 400) as f64 / 1000.0);
+        ncounts.push(length / 100);
+    }
+    (lengths, gcs, ncounts)
+}
+
+fn main() {
+    let (lengths, gcs, ncounts) = synthetic_assembly();
+    let options = PlotOptions {
+        segments: 1000,
+        ..Default::default()
+    };
+    let cumulative_data = CumulativeData {
+        values: lengths.iter().map(|length| *length as f64).collect(),
+        cat: (0..CONTIG_COUNT).map(|i| i % CATEGORY_COUNT).collect(),
+        cat_order: (0..CATEGORY_COUNT)
+            .map(|cat| Category {
+                title: format!("cat_{}", cat),
+                indices: (cat..CONTIG_COUNT).step_by(CATEGORY_COUNT).collect(),
+                color: "#999999".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+    };
+    let dimensions = Dimensions::default();
+    let mut thread_counts = vec![1, rayon::current_num_threads()];
+    thread_counts.dedup();
+    for threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        pool.install(|| {
+            snail_stats(
+                &lengths,
+                None,
+                &gcs,
+                &None,
+                &ncounts,
+                &vec![],
+                None,
+                None,
+                "bench".to_string(),
+                "contig".to_string(),
+                &options,
+            )
+        });
+        let snail = start.elapsed().as_secs_f64();
+        let start = Instant::now();
+        pool.install(|| cumulative_lines(&cumulative_data, &dimensions, &options));
+        let cumulative = start.elapsed().as_secs_f64();
+        println!(
+            "{:>3} threads: snail {:>7.3}s, cumulative {:>7.3}s",
+            threads, snail, cumulative
+        );
+    }
+}
@@ -1,16 +1,29 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
 use std::io::{ErrorKind, Result, Write};
 // use std::ops::Index;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use indexmap::IndexMap;
+use parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{
+    SerializedColumnWriter, SerializedFileWriter, SerializedRowGroupWriter,
+};
 use pyo3::{self, pyclass};
-use rust_htslib::bam::{index, Header, IndexedReader, Read};
+use rust_htslib::bam::{
+    index, CompressionLevel, FetchDefinition, Format, Header, IndexedReader, Read, Reader, Record,
+    Writer,
+};
 use rust_htslib::htslib;
 
-use crate::cli::DepthOptions;
-use crate::io::get_writer;
-use crate::utils::styled_progress_bar;
+use crate::cli::{AlignmentFormat, DepthOptions, FilterOptions};
+use crate::fastq::suffix_file_name;
+use crate::io::{get_writer, read_lines};
+use crate::utils::{self, styled_progress_bar};
 
 fn add_extension(path: &mut PathBuf, extension: impl AsRef<Path>) {
     match path.extension() {
@@ -61,6 +74,118 @@ pub fn open_bam(
     reader
 }
 
+/// A region of a sequence to extract reads from.
+/// Coordinates are 0-based and half-open, as in BED files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub seq_name: Vec<u8>,
+    pub start: i64,
+    pub end: Option<i64>,
+}
+
+/// Parse a samtools-style region string (`<seq>`, `<seq>:<start>` or
+/// `<seq>:<start>-<end>`) with 1-based inclusive coordinates.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::bam::{parse_region, Region};
+/// assert_eq!(
+///     parse_region("ctg1:101-200"),
+///     Some(Region { seq_name: b"ctg1".to_vec(), start: 100, end: Some(200) })
+/// );
+/// assert_eq!(
+///     parse_region("ctg1"),
+///     Some(Region { seq_name: b"ctg1".to_vec(), start: 0, end: None })
+/// );
+/// assert_eq!(parse_region("ctg1:200-100"), None);
+/// ```
+pub fn parse_region(region: &str) -> Option<Region> {
+    let (seq_name, range) = match region.rsplit_once(':') {
+        Some((name, range)) if !name.is_empty() => (name, Some(range)),
+        _ => (region, None),
+    };
+    if seq_name.is_empty() {
+        return None;
+    }
+    let (start, end) = match range {
+        None => (0, None),
+        Some(range) => {
+            let range = range.replace(',', "");
+            match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse::<i64>().ok()? - 1,
+                    Some(end.parse::<i64>().ok()?),
+                ),
+                None => (range.parse::<i64>().ok()? - 1, None),
+            }
+        }
+    };
+    if start < 0 || end.is_some_and(|end| end <= start) {
+        return None;
+    }
+    Some(Region {
+        seq_name: seq_name.as_bytes().to_vec(),
+        start,
+        end,
+    })
+}
+
+/// Read regions from the first three columns of a BED file.
+pub fn regions_from_bed(bed_path: &PathBuf) -> Vec<Region> {
+    let mut regions = vec![];
+    if let Ok(lines) = read_lines(bed_path) {
+        for line in lines.map_while(|l| l.ok()) {
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            let cols: Vec<&str> = line.split('\t').collect();
+            if cols.len() < 3 {
+                eprintln!("Skipping invalid BED line {:?}", line);
+                continue;
+            }
+            match (cols[1].parse::<i64>(), cols[2].parse::<i64>()) {
+                (Ok(start), Ok(end)) if end > start => regions.push(Region {
+                    seq_name: cols[0].as_bytes().to_vec(),
+                    start,
+                    end: Some(end),
+                }),
+                _ => eprintln!("Skipping invalid BED line {:?}", line),
+            }
+        }
+    }
+    regions
+}
+
+/// Combine regions from the command line and a BED file.
+pub fn get_regions(regions: &Option<Vec<String>>, bed_path: &Option<PathBuf>) -> Vec<Region> {
+    let mut list = vec![];
+    if let Some(regions) = regions {
+        for region in regions {
+            match parse_region(region) {
+                Some(r) => list.push(r),
+                None => eprintln!("Skipping invalid region {:?}", region),
+            }
+        }
+    }
+    if let Some(bed_path) = bed_path {
+        list.extend(regions_from_bed(bed_path));
+    }
+    list
+}
+
+fn wanted_read(read: &Record) -> bool {
+    // TODO: include filter options in config
+    read.flags()
+        & (htslib::BAM_FUNMAP | htslib::BAM_FSECONDARY | htslib::BAM_FQCFAIL | htslib::BAM_FDUP)
+            as u16
+        == 0
+}
+
 pub fn reads_from_bam<F: Fn()>(
     seq_names: &HashSet<Vec<u8>>,
     mut bam: IndexedReader,
@@ -78,15 +203,7 @@ pub fn reads_from_bam<F: Fn()>(
         for read in bam
             .rc_records()
             .map(|x| x.expect("Failure parsing Bam file"))
-            // TODO: include filter options in config
-            .filter(|read| {
-                read.flags()
-                    & (htslib::BAM_FUNMAP
-                        | htslib::BAM_FSECONDARY
-                        | htslib::BAM_FQCFAIL
-                        | htslib::BAM_FDUP) as u16
-                    == 0
-            })
+            .filter(|read| wanted_read(read))
         {
             wanted_reads.insert(read.qname().to_vec());
         }
@@ -95,12 +212,250 @@ pub fn reads_from_bam<F: Fn()>(
             Some(cb) => cb(),
             None => (),
         }
+        if utils::is_cancelled() {
+            break;
+        }
         progress_bar.inc(1);
     }
     progress_bar.finish();
     wanted_reads
 }
 
+/// Find reads overlapping a set of regions, ignoring any region on a
+/// sequence that is not in `seq_names`.
+pub fn reads_from_bam_regions<F: Fn()>(
+    seq_names: &HashSet<Vec<u8>>,
+    regions: &[Region],
+    mut bam: IndexedReader,
+    callback: &Option<F>,
+) -> HashSet<Vec<u8>> {
+    let mut wanted_reads = HashSet::new();
+    let total = regions.len();
+    let progress_bar = styled_progress_bar(total, "Locating alignments");
+
+    for region in regions {
+        progress_bar.inc(1);
+        if !seq_names.contains(&region.seq_name) {
+            continue;
+        }
+        let fetched = match region.end {
+            Some(end) => bam.fetch((&region.seq_name, region.start, end)),
+            None if region.start > 0 => {
+                let header = bam.header();
+                let end = header
+                    .tid(&region.seq_name)
+                    .and_then(|tid| header.target_len(tid))
+                    .unwrap_or(0) as i64;
+                bam.fetch((&region.seq_name, region.start, end))
+            }
+            None => bam.fetch(&region.seq_name),
+        };
+        if fetched.is_err() {
+            eprintln!("Sequence {:?} not found in BAM file", region.seq_name);
+            continue;
+        }
+
+        for read in bam
+            .rc_records()
+            .map(|x| x.expect("Failure parsing Bam file"))
+            .filter(|read| wanted_read(read))
+        {
+            wanted_reads.insert(read.qname().to_vec());
+        }
+
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    wanted_reads
+}
+
+/// Assign each mapped read to the category of the sequence it aligns to in a
+/// single pass over the BAM file. Reads that align to sequences in more than
+/// one category keep the category of their first alignment.
+pub fn read_categories_from_bam<F: Fn()>(
+    seq_categories: &HashMap<Vec<u8>, usize>,
+    mut bam: IndexedReader,
+    callback: &Option<F>,
+) -> HashMap<Vec<u8>, usize> {
+    let mut read_categories = HashMap::new();
+    let tid_categories: Vec<Option<usize>> = bam
+        .header()
+        .target_names()
+        .iter()
+        .map(|name| seq_categories.get(*name).copied())
+        .collect();
+    if bam.fetch(FetchDefinition::All).is_err() {
+        eprintln!("Unable to read alignments from BAM file");
+        return read_categories;
+    }
+    let progress_bar = styled_progress_bar(0, "Assigning reads");
+    for read in bam
+        .rc_records()
+        .map(|x| x.expect("Failure parsing Bam file"))
+        .filter(|read| wanted_read(read))
+    {
+        if let Some(Some(category)) = tid_categories.get(read.tid() as usize) {
+            read_categories
+                .entry(read.qname().to_vec())
+                .or_insert(*category);
+        }
+        progress_bar.inc(1);
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    read_categories
+}
+
+/// Find unmapped reads and the mates of reads aligned to filtered-out
+/// sequences in a single pass over the BAM file.
+///
+/// Reads are kept if they are unmapped, their mate is unmapped, or one mate
+/// aligns to a sequence in `seq_names` while the other aligns elsewhere.
+/// With `mapped`, reads aligned to sequences in `seq_names` are also kept,
+/// so no separate pass over those sequences is needed.
+pub fn unmapped_reads_from_bam<F: Fn()>(
+    seq_names: &HashSet<Vec<u8>>,
+    mut bam: IndexedReader,
+    mapped: bool,
+    callback: &Option<F>,
+) -> HashSet<Vec<u8>> {
+    let mut wanted_reads = HashSet::new();
+    let kept_tids: Vec<bool> = bam
+        .header()
+        .target_names()
+        .iter()
+        .map(|name| seq_names.contains(*name))
+        .collect();
+    let is_kept = |tid: i32| tid >= 0 && kept_tids.get(tid as usize) == Some(&true);
+    if bam.fetch(FetchDefinition::All).is_err() {
+        eprintln!("Unable to read alignments from BAM file");
+        return wanted_reads;
+    }
+    let progress_bar = styled_progress_bar(0, "Locating unmapped reads");
+    for read in bam
+        .rc_records()
+        .map(|x| x.expect("Failure parsing Bam file"))
+        .filter(|read| read.flags() & (htslib::BAM_FSECONDARY | htslib::BAM_FQCFAIL) as u16 == 0)
+    {
+        let flags = read.flags();
+        let paired = flags & htslib::BAM_FPAIRED as u16 != 0;
+        let unmapped = flags & htslib::BAM_FUNMAP as u16 != 0;
+        let mate_unmapped = paired && flags & htslib::BAM_FMUNMAP as u16 != 0;
+        let split = paired && is_kept(read.tid()) != is_kept(read.mtid());
+        if unmapped
+            || mate_unmapped
+            || split
+            || (mapped && is_kept(read.tid()) && wanted_read(&read))
+        {
+            wanted_reads.insert(read.qname().to_vec());
+        }
+        progress_bar.inc(1);
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    wanted_reads
+}
+
+impl From<AlignmentFormat> for Format {
+    fn from(format: AlignmentFormat) -> Self {
+        match format {
+            AlignmentFormat::Sam => Format::Sam,
+            AlignmentFormat::Bam => Format::Bam,
+            AlignmentFormat::Cram => Format::Cram,
+        }
+    }
+}
+
+/// Write all alignments for a set of reads to a new SAM/BAM/CRAM file,
+/// named by adding the suffix to the input file name.
+/// The output keeps the input order and BAM/CRAM output is indexed.
+pub fn subsample<F: Fn()>(
+    read_names: &HashSet<Vec<u8>>,
+    options: &FilterOptions,
+    callback: &Option<F>,
+) -> Result<Option<PathBuf>> {
+    if !options.alignment_out {
+        return Ok(None);
+    }
+    let (bam_cram_path, input_format) = match (&options.bam, &options.cram) {
+        (Some(path), _) => (path, AlignmentFormat::Bam),
+        (None, Some(path)) => (path, AlignmentFormat::Cram),
+        (None, None) => return Ok(None),
+    };
+    let format = options.output_format.unwrap_or(input_format);
+    let reference = options.output_reference.as_ref().or(options.fasta.as_ref());
+    if format == AlignmentFormat::Cram && reference.is_none() {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "CRAM output requires a reference FASTA",
+        ));
+    }
+    let mut out_path = suffix_file_name(bam_cram_path, &options.suffix);
+    out_path.set_extension(format!("{:?}", format).to_lowercase());
+    let to_io_error = |err: rust_htslib::errors::Error| std::io::Error::other(err.to_string());
+
+    let mut reader = Reader::from_path(bam_cram_path).map_err(to_io_error)?;
+    if options.cram.is_some() {
+        if let Some(fasta) = options.fasta.as_ref() {
+            reader.set_reference(fasta).map_err(to_io_error)?;
+        }
+    }
+    let header = Header::from_template(reader.header());
+    let mut writer = Writer::from_path(&out_path, &header, format.into()).map_err(to_io_error)?;
+    utils::track_output(&out_path);
+    if format == AlignmentFormat::Cram {
+        writer
+            .set_reference(reference.unwrap())
+            .map_err(to_io_error)?;
+    }
+    if let Some(level) = options.compression_level {
+        writer
+            .set_compression_level(CompressionLevel::Level(level))
+            .map_err(to_io_error)?;
+    }
+    let progress_bar = styled_progress_bar(0, "Writing alignments");
+    for read in reader
+        .records()
+        .map(|x| x.expect("Failure parsing Bam file"))
+    {
+        if read_names.contains(read.qname()) {
+            writer.write(&read).map_err(to_io_error)?;
+            progress_bar.inc(1);
+        }
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    // close the file before indexing
+    drop(writer);
+    if utils::is_cancelled() {
+        return Ok(None);
+    }
+    if format != AlignmentFormat::Sam {
+        index::build(&out_path, None, index::Type::Csi(14), 1).map_err(to_io_error)?;
+    }
+    Ok(Some(out_path))
+}
+
 fn seq_lengths_from_header(
     bam: &IndexedReader,
     seq_names: &HashSet<Vec<u8>>,
@@ -221,6 +576,9 @@ pub fn bed_from_bam<F: Fn()>(
             Some(cb) => cb(),
             None => (),
         }
+        if utils::is_cancelled() {
+            break;
+        }
         match depth_to_bed(raw_cov, &length, step, &seq_name, &mut writer) {
             Err(err) if err.kind() == ErrorKind::BrokenPipe => return,
             Err(err) => panic!("unable to write {} to bed file: {}", &seq_name, err),
@@ -281,6 +639,9 @@ pub fn depth_from_bam<F: Fn()>(
             Some(cb) => cb(),
             None => (),
         }
+        if utils::is_cancelled() {
+            break;
+        }
         binned_covs.push(depth_to_cov(raw_cov, &length, step, &seq_name));
         // match depth_to_bed(raw_cov, &length, step, &seq_name, &mut writer) {
         //     Err(err) if err.kind() == ErrorKind::BrokenPipe => return,
@@ -293,6 +654,216 @@ pub fn depth_from_bam<F: Fn()>(
     binned_covs
 }
 
+/// Open a BAM or CRAM file based on the file extension.
+pub fn open_bam_or_cram(
+    path: &Path,
+    fasta_path: &Option<PathBuf>,
+    make_index: bool,
+) -> IndexedReader {
+    if path.extension() == Some(OsStr::new("cram")) {
+        open_bam(&None, &Some(path.to_path_buf()), fasta_path, make_index)
+    } else {
+        open_bam(&Some(path.to_path_buf()), &None, fasta_path, make_index)
+    }
+}
+
+/// A row of a depth matrix, with the depth in each sample or `None` if the
+/// bin is missing from that sample.
+struct DepthMatrixRow<'a> {
+    seq_name: &'a str,
+    start: usize,
+    end: usize,
+    values: Vec<Option<f64>>,
+}
+
+fn depth_matrix_rows(sample_covs: &[Vec<BinnedCov>]) -> Vec<DepthMatrixRow<'_>> {
+    let mut rows = vec![];
+    if sample_covs.is_empty() {
+        return rows;
+    }
+    for (i, cov) in sample_covs[0].iter().enumerate() {
+        let mut start: usize = 0;
+        for bin in 0..cov.bin_count {
+            let end = start.saturating_add(cov.step).min(cov.seq_length);
+            let values = sample_covs
+                .iter()
+                .map(|covs| covs.get(i).and_then(|c| c.bins.get(bin)).copied())
+                .collect();
+            rows.push(DepthMatrixRow {
+                seq_name: &cov.seq_name,
+                start,
+                end,
+                values,
+            });
+            start = end;
+        }
+    }
+    rows
+}
+
+fn depth_matrix_to_tsv(
+    samples: &[String],
+    sample_covs: &[Vec<BinnedCov>],
+    writer: &mut Box<dyn Write>,
+) -> Result<()> {
+    writeln!(writer, "seq_name\tstart\tend\t{}", samples.join("\t"))?;
+    for row in depth_matrix_rows(sample_covs) {
+        let values: Vec<String> = row
+            .values
+            .iter()
+            .map(|value| match value {
+                Some(value) => format!("{:.2}", value),
+                None => "NA".to_string(),
+            })
+            .collect();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            row.seq_name,
+            row.start,
+            row.end,
+            values.join("\t")
+        )?;
+    }
+    Ok(())
+}
+
+fn next_parquet_column<'a, W: Write + Send>(
+    row_group: &'a mut SerializedRowGroupWriter<'_, W>,
+) -> Result<SerializedColumnWriter<'a>> {
+    match row_group.next_column() {
+        Ok(Some(column)) => Ok(column),
+        Ok(None) => Err(std::io::Error::other("missing depth matrix column")),
+        Err(err) => Err(std::io::Error::other(err.to_string())),
+    }
+}
+
+/// Write a depth matrix as a Parquet file with `seq_name`, `start` and `end`
+/// columns and a nullable `Float64` column of depths for each sample.
+fn depth_matrix_to_parquet(
+    samples: &[String],
+    sample_covs: &[Vec<BinnedCov>],
+    path: &Path,
+) -> Result<()> {
+    let to_io_error = |err: ParquetError| std::io::Error::other(err.to_string());
+    let column = |name: &str, physical_type, repetition| {
+        parquet::schema::types::Type::primitive_type_builder(name, physical_type)
+            .with_repetition(repetition)
+            .build()
+            .map(Arc::new)
+    };
+    let mut fields = vec![Arc::new(
+        parquet::schema::types::Type::primitive_type_builder("seq_name", PhysicalType::BYTE_ARRAY)
+            .with_repetition(Repetition::REQUIRED)
+            .with_logical_type(Some(LogicalType::String))
+            .build()
+            .map_err(to_io_error)?,
+    )];
+    for name in ["start", "end"] {
+        fields.push(column(name, PhysicalType::INT64, Repetition::REQUIRED).map_err(to_io_error)?);
+    }
+    for sample in samples {
+        fields
+            .push(column(sample, PhysicalType::DOUBLE, Repetition::OPTIONAL).map_err(to_io_error)?);
+    }
+    let schema = parquet::schema::types::Type::group_type_builder("depth")
+        .with_fields(fields)
+        .build()
+        .map_err(to_io_error)?;
+    let rows = depth_matrix_rows(sample_covs);
+    let file = std::fs::File::create(path)?;
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer =
+        SerializedFileWriter::new(file, Arc::new(schema), properties).map_err(to_io_error)?;
+    let mut row_group = writer.next_row_group().map_err(to_io_error)?;
+    let seq_names: Vec<ByteArray> = rows.iter().map(|row| row.seq_name.into()).collect();
+    let starts: Vec<i64> = rows.iter().map(|row| row.start as i64).collect();
+    let ends: Vec<i64> = rows.iter().map(|row| row.end as i64).collect();
+    let mut column = next_parquet_column(&mut row_group)?;
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&seq_names, None, None)
+        .map_err(to_io_error)?;
+    column.close().map_err(to_io_error)?;
+    for values in [starts, ends] {
+        let mut column = next_parquet_column(&mut row_group)?;
+        column
+            .typed::<Int64Type>()
+            .write_batch(&values, None, None)
+            .map_err(to_io_error)?;
+        column.close().map_err(to_io_error)?;
+    }
+    for i in 0..samples.len() {
+        let values: Vec<f64> = rows.iter().filter_map(|row| row.values[i]).collect();
+        let def_levels: Vec<i16> = rows
+            .iter()
+            .map(|row| row.values[i].is_some() as i16)
+            .collect();
+        let mut column = next_parquet_column(&mut row_group)?;
+        column
+            .typed::<DoubleType>()
+            .write_batch(&values, Some(&def_levels), None)
+            .map_err(to_io_error)?;
+        column.close().map_err(to_io_error)?;
+    }
+    row_group.close().map_err(to_io_error)?;
+    writer.close().map_err(to_io_error)?;
+    Ok(())
+}
+
+/// Calculate binned depths for each of a set of BAM/CRAM files and write
+/// a single TSV matrix with one row per bin and one column per sample.
+///
+/// All files must have the same sequences and lengths in their headers.
+/// The matrix is written as Parquet if the output file name ends in
+/// `.parquet`, otherwise as TSV.
+pub fn get_depth_matrix<F: Fn()>(
+    bam_paths: &[PathBuf],
+    seq_names: &HashSet<Vec<u8>>,
+    options: &DepthOptions,
+    callback: &Option<F>,
+) -> Result<()> {
+    let mut samples = vec![];
+    let mut sample_covs = vec![];
+    let mut seq_lengths: Option<IndexMap<String, usize>> = None;
+    for bam_path in bam_paths {
+        let bam = open_bam_or_cram(bam_path, &options.fasta, true);
+        let lengths = seq_lengths_from_header(&bam, seq_names);
+        match &seq_lengths {
+            Some(first) if *first != lengths => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "sequences in {} do not match {}",
+                        bam_path.display(),
+                        bam_paths[0].display()
+                    ),
+                ));
+            }
+            Some(_) => (),
+            None => seq_lengths = Some(lengths),
+        }
+        samples.push(utils::sample_name(bam_path));
+        sample_covs.push(depth_from_bam(
+            seq_lengths.as_ref().unwrap(),
+            bam,
+            options,
+            callback,
+        ));
+    }
+    if let Some(matrix) = &options.matrix {
+        if matrix.extension() == Some(OsStr::new("parquet")) {
+            return depth_matrix_to_parquet(&samples, &sample_covs, matrix);
+        }
+    }
+    let mut writer = get_writer(&options.matrix);
+    match depth_matrix_to_tsv(&samples, &sample_covs, &mut writer) {
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+        Err(err) => Err(err),
+        Ok(_) => Ok(()),
+    }
+}
+
 pub fn get_bed_file<F: Fn()>(
     bam: IndexedReader,
     seq_names: &HashSet<Vec<u8>>,
@@ -312,3 +883,163 @@ pub fn get_depth<F: Fn()>(
     let seq_lengths = seq_lengths_from_header(&bam, seq_names);
     depth_from_bam(&seq_lengths, bam, options, callback)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_htslib::bam::header::HeaderRecord;
+    use rust_htslib::bam::record::{Cigar, CigarString};
+
+    /// Write a coordinate sorted BAM file of read pairs on a kept and a
+    /// filtered-out sequence.
+    fn write_pairs(path: &Path) {
+        let mut header = Header::new();
+        for name in ["kept", "removed"] {
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", &name)
+                    .push_tag(b"LN", &1000),
+            );
+        }
+        let paired = htslib::BAM_FPAIRED as u16;
+        let (first, second) = (htslib::BAM_FREAD1 as u16, htslib::BAM_FREAD2 as u16);
+        let (unmapped, mate_unmapped) = (htslib::BAM_FUNMAP as u16, htslib::BAM_FMUNMAP as u16);
+        // name, flags, tid, pos, mate tid, mate pos
+        let reads = [
+            ("both_kept", paired | first, 0, 100, 0, 200),
+            ("split", paired | first, 0, 150, 1, 100),
+            ("both_kept", paired | second, 0, 200, 0, 100),
+            ("both_removed", paired | first, 1, 50, 1, 300),
+            ("split", paired | second, 1, 100, 0, 150),
+            (
+                "mate_unmapped",
+                paired | mate_unmapped | first,
+                1,
+                200,
+                1,
+                200,
+            ),
+            ("mate_unmapped", paired | unmapped | second, 1, 200, 1, 200),
+            ("both_removed", paired | second, 1, 300, 1, 50),
+            (
+                "unmapped",
+                paired | unmapped | mate_unmapped | first,
+                -1,
+                -1,
+                -1,
+                -1,
+            ),
+            (
+                "unmapped",
+                paired | unmapped | mate_unmapped | second,
+                -1,
+                -1,
+                -1,
+                -1,
+            ),
+        ];
+        {
+            let mut writer = Writer::from_path(path, &header, Format::Bam).unwrap();
+            let cigar = CigarString(vec![Cigar::Match(10)]);
+            for (name, flags, tid, pos, mtid, mpos) in reads {
+                let mut read = Record::new();
+                let cigar = (flags & unmapped == 0).then_some(&cigar);
+                read.set(name.as_bytes(), cigar, b"ACGTACGTAC", &[40; 10]);
+                read.set_flags(flags);
+                read.set_tid(tid);
+                read.set_pos(pos);
+                read.set_mtid(mtid);
+                read.set_mpos(mpos);
+                writer.write(&read).unwrap();
+            }
+        }
+        index::build(path, None, index::Type::Csi(14), 1).unwrap();
+    }
+
+    #[test]
+    fn test_depth_matrix_to_parquet() {
+        use clap::Parser;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::Field;
+
+        let dir = std::env::temp_dir().join("blobtk_test_depth_matrix");
+        std::fs::create_dir_all(&dir).unwrap();
+        let matrix = |name: &str| {
+            let path = dir.join(name);
+            let options = DepthOptions::parse_from([
+                "depth",
+                "--bams",
+                "test/test.bam",
+                "-s",
+                "1000",
+                "-M",
+                path.to_str().unwrap(),
+            ]);
+            get_depth_matrix(
+                options.bams.as_ref().unwrap(),
+                &HashSet::new(),
+                &options,
+                &None as &Option<Box<dyn Fn()>>,
+            )
+            .unwrap();
+            path
+        };
+        let tsv = std::fs::read_to_string(matrix("depth.tsv")).unwrap();
+        let reader =
+            SerializedFileReader::new(std::fs::File::open(matrix("depth.parquet")).unwrap())
+                .unwrap();
+        let columns: Vec<String> = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect();
+        assert_eq!(columns, vec!["seq_name", "start", "end", "test"]);
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                let fields: Vec<String> = row
+                    .unwrap()
+                    .get_column_iter()
+                    .map(|(_, field)| match field {
+                        Field::Str(value) => value.clone(),
+                        Field::Long(value) => value.to_string(),
+                        Field::Double(value) => format!("{:.2}", value),
+                        _ => "NA".to_string(),
+                    })
+                    .collect();
+                fields.join("\t")
+            })
+            .collect();
+        assert_eq!(rows, tsv.lines().skip(1).collect::<Vec<&str>>());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unmapped_reads_from_bam() {
+        let dir = std::env::temp_dir().join("blobtk_test_unmapped");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pairs.bam");
+        write_pairs(&path);
+        let seq_names = HashSet::from([b"kept".to_vec()]);
+        let reads = |mapped: bool| {
+            let bam = IndexedReader::from_path(&path).unwrap();
+            let mut reads: Vec<String> =
+                unmapped_reads_from_bam(&seq_names, bam, mapped, &None as &Option<Box<dyn Fn()>>)
+                    .into_iter()
+                    .map(|read| String::from_utf8(read).unwrap())
+                    .collect();
+            reads.sort();
+            reads
+        };
+        assert_eq!(reads(false), vec!["mate_unmapped", "split", "unmapped"]);
+        assert_eq!(
+            reads(true),
+            vec!["both_kept", "mate_unmapped", "split", "unmapped"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use flate2::read::GzDecoder;
-use glob::glob;
+use glob::{glob, Pattern};
 use serde;
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
@@ -15,9 +16,13 @@ use url::Url;
 
 use crate::cli;
 use crate::error;
+use crate::io;
 
 pub use cli::PlotOptions;
 
+/// Functions to cache parsed field values in a BlobDir.
+pub mod cache;
+
 fn default_accession() -> String {
     "draft".to_string()
 }
@@ -128,6 +133,150 @@ pub struct Meta {
     pub busco_list: Option<Vec<(String, usize, String)>>,
 }
 
+/// Current BlobDir meta.json schema version.
+pub const META_VERSION: u8 = 1;
+
+/// Top level meta.json keys understood by blobtk and the viewer.
+const META_KEYS: [&str; 17] = [
+    "assembly",
+    "busco_list",
+    "field_list",
+    "fields",
+    "id",
+    "links",
+    "name",
+    "plot",
+    "reads",
+    "record_type",
+    "records",
+    "revision",
+    "settings",
+    "similarity",
+    "static_plots",
+    "taxon",
+    "version",
+];
+
+type MetaAdapter = fn(&mut serde_json::Value);
+
+/// Adapters to upgrade meta.json from each older schema version to the next.
+const META_ADAPTERS: [(u8, MetaAdapter); 1] = [(0, upgrade_meta_v0)];
+
+/// Version 0 meta.json files predate `records` and `record_type`.
+fn upgrade_meta_v0(meta: &mut serde_json::Value) {
+    let scaffold_count = meta["assembly"]["scaffold-count"].clone();
+    let level = meta["assembly"]["level"].clone();
+    if let Some(obj) = meta.as_object_mut() {
+        if !obj.contains_key("records") && scaffold_count.is_u64() {
+            obj.insert("records".to_string(), scaffold_count);
+        }
+        if !obj.contains_key("record_type") && level.is_string() {
+            obj.insert("record_type".to_string(), level);
+        }
+    }
+}
+
+/// Get the schema version of a meta.json value, treating a missing version as 0.
+pub fn meta_version(meta: &serde_json::Value) -> u8 {
+    match meta["version"].as_u64() {
+        Some(version) => version as u8,
+        None => 0,
+    }
+}
+
+static UNKNOWN_KEYS_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// List top level meta.json keys not in the current schema.
+pub fn unknown_meta_keys(meta: &serde_json::Value) -> Vec<String> {
+    match meta.as_object() {
+        Some(obj) => obj
+            .keys()
+            .filter(|key| !META_KEYS.contains(&key.as_str()))
+            .cloned()
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Upgrade a meta.json value in place to the current schema version.
+///
+/// Returns the original version. Versions newer than `META_VERSION` are left
+/// unchanged with a warning.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::blobdir::{upgrade_meta, META_VERSION};
+/// let mut meta = serde_json::json!({"assembly": {"scaffold-count": 3}});
+/// assert_eq!(upgrade_meta(&mut meta), 0);
+/// assert_eq!(meta["records"], 3);
+/// assert_eq!(meta["version"], META_VERSION);
+/// ```
+pub fn upgrade_meta(meta: &mut serde_json::Value) -> u8 {
+    let version = meta_version(meta);
+    if version > META_VERSION {
+        eprintln!(
+            "WARNING: meta.json version {} is newer than supported version {}",
+            version, META_VERSION
+        );
+        return version;
+    }
+    for (from_version, adapter) in META_ADAPTERS {
+        if version <= from_version {
+            adapter(meta);
+        }
+    }
+    if let Some(obj) = meta.as_object_mut() {
+        obj.insert("version".to_string(), serde_json::json!(META_VERSION));
+    }
+    version
+}
+
+/// Path to the meta.json file in a BlobDir, which may be gzipped.
+pub fn meta_path(blobdir: &PathBuf) -> PathBuf {
+    match get_path(blobdir, "meta.json") {
+        Some(path) => PathBuf::from(path),
+        None => blobdir.join("meta.json"),
+    }
+}
+
+/// Write a meta.json value to a BlobDir, replacing the existing file.
+pub fn write_meta_value(blobdir: &PathBuf, meta: &serde_json::Value) -> Result<(), error::Error> {
+    let mut writer = io::get_writer(&Some(meta_path(blobdir)));
+    writeln!(writer, "{}", serde_json::to_string_pretty(meta)?)?;
+    Ok(())
+}
+
+/// Add a field to the top level of a meta.json value, replacing any
+/// existing field with the same ID.
+pub fn add_field_meta(meta: &mut serde_json::Value, field: serde_json::Value) {
+    if let Some(fields) = meta["fields"].as_array_mut() {
+        fields.retain(|f| f["id"] != field["id"]);
+        fields.push(field);
+    }
+}
+
+/// Read a raw meta.json value from a BlobDir.
+pub fn read_meta_value(blobdir: &PathBuf) -> Result<serde_json::Value, error::Error> {
+    let reader = match file_reader(blobdir, "meta.json") {
+        Some(r) => r,
+        None => {
+            return Err(error::Error::FileNotFound(format!(
+                "{}",
+                blobdir.join("meta.json").display()
+            )))
+        }
+    };
+    match serde_json::from_reader(reader) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(error::Error::SerdeError(format!(
+            "{} {}",
+            blobdir.join("meta.json").display(),
+            err
+        ))),
+    }
+}
+
 fn default_revision() -> u8 {
     0
 }
@@ -147,7 +296,9 @@ pub struct Field<T> {
     // pub meta: FieldMeta,
     pub values: Vec<T>,
     pub keys: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub category_slot: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<Vec<String>>,
 }
 
@@ -157,6 +308,47 @@ impl<T> Field<T> {
     }
 }
 
+/// Check for missing values in numeric BlobDir fields.
+///
+/// Missing floats are read as `NaN` and missing integers as `None`.
+pub trait Missing {
+    fn is_missing(&self) -> bool;
+}
+
+impl Missing for f64 {
+    fn is_missing(&self) -> bool {
+        self.is_nan()
+    }
+}
+
+impl<T> Missing for Option<T> {
+    fn is_missing(&self) -> bool {
+        self.is_none()
+    }
+}
+
+fn float_value(value: &serde_json::Value) -> f64 {
+    match value {
+        serde_json::Value::Number(number) => number.as_f64().unwrap_or(f64::NAN),
+        serde_json::Value::String(string) => string.trim().parse().unwrap_or(f64::NAN),
+        _ => f64::NAN,
+    }
+}
+
+fn int_value(value: &serde_json::Value) -> Option<usize> {
+    match value {
+        serde_json::Value::Number(number) => match number.as_u64() {
+            Some(int) => Some(int as usize),
+            None => number
+                .as_f64()
+                .filter(|float| *float >= 0.0 && float.fract() == 0.0)
+                .map(|float| float as usize),
+        },
+        serde_json::Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 pub struct Filter {
     pub min: Option<f64>,
@@ -185,7 +377,8 @@ pub struct BuscoGene {
 pub fn get_path(dir: &PathBuf, prefix: &str) -> Option<String> {
     let mut path = dir.clone();
     path.push(prefix);
-    for e in glob(&format!("{}*", path.to_string_lossy())).expect("Failed to read glob pattern") {
+    let pattern = format!("{}*", Pattern::escape(&path.to_string_lossy()));
+    for e in glob(&pattern).expect("Failed to read glob pattern") {
         return Some(format!("{}", e.unwrap().to_string_lossy()));
     }
     None
@@ -222,21 +415,22 @@ pub struct Keys {
 /// ```
 
 pub fn parse_blobdir(blobdir: &PathBuf) -> Result<Meta, error::Error> {
-    let reader = match file_reader(blobdir, "meta.json") {
-        Some(r) => r,
-        None => {
-            return Err(error::Error::FileNotFound(format!(
-                "{}/meta.json",
-                &blobdir.to_str().unwrap()
-            )))
-        }
-    };
-    let mut meta: Meta = match serde_json::from_reader(reader) {
+    let mut value = read_meta_value(blobdir)?;
+    upgrade_meta(&mut value);
+    let unknown_keys = unknown_meta_keys(&value);
+    // BlobDirs are parsed repeatedly in one run, so only warn the first time
+    if !unknown_keys.is_empty() && !UNKNOWN_KEYS_WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "WARNING: ignoring unknown meta.json keys: {}",
+            unknown_keys.join(", ")
+        );
+    }
+    let mut meta: Meta = match serde_json::from_value(value) {
         Ok(meta) => meta,
         Err(err) => {
             return Err(error::Error::SerdeError(format!(
-                "{}/meta.json {}",
-                &blobdir.to_str().unwrap(),
+                "{} {}",
+                blobdir.join("meta.json").display(),
                 err.to_string()
             )))
         }
@@ -362,59 +556,253 @@ pub fn parse_field_busco(id: String, blobdir: &PathBuf) -> Option<Vec<Vec<BuscoG
     Some(values)
 }
 
-pub fn parse_field_cat(
-    id: String,
-    blobdir: &PathBuf,
-) -> Result<Vec<(String, usize)>, error::Error> {
+/// Values of a category field, stored as a code per record indexing a
+/// shared list of category names.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::blobdir::CatValues;
+/// let values = CatValues {
+///     codes: vec![1, 0, 1],
+///     keys: vec!["no-hit".to_string(), "Chordata".to_string()],
+/// };
+/// assert_eq!(values.name(2), "Chordata");
+/// assert_eq!(values.iter().nth(1), Some(("no-hit", 0)));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CatValues {
+    pub codes: Vec<u32>,
+    pub keys: Vec<String>,
+}
+
+impl CatValues {
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Category name of a record.
+    pub fn name(&self, i: usize) -> &str {
+        &self.keys[self.codes[i] as usize]
+    }
+
+    /// Category name and code of each record.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.codes
+            .iter()
+            .map(|code| (self.keys[*code as usize].as_str(), *code as usize))
+    }
+}
+
+pub fn parse_field_cat(id: String, blobdir: &PathBuf) -> Result<CatValues, error::Error> {
     let reader = match file_reader(blobdir, &format!("{}.json", &id)) {
         Some(reader) => reader,
         None => {
             return Err(error::Error::FileNotFound(format!(
-                "{}/{}.json",
-                &blobdir.to_str().unwrap(),
-                &id
+                "{}",
+                blobdir.join(format!("{}.json", id)).display()
             )))
         }
     };
-    let field: Field<usize> = serde_json::from_reader(reader).expect("unable to parse json");
-    let mut values: Vec<(String, usize)> = vec![];
-    let keys = field.keys.clone();
-    for value in field.values() {
-        values.push((keys[*value].clone(), *value))
-    }
-    Ok(values)
+    let field: Field<u32> = serde_json::from_reader(reader).expect("unable to parse json");
+    Ok(CatValues {
+        codes: field.values,
+        keys: field.keys,
+    })
 }
 
+/// Parse a float field, reading null or non-numeric values as `NaN`.
 pub fn parse_field_float(id: String, blobdir: &PathBuf) -> Result<Vec<f64>, error::Error> {
+    cache::cached(blobdir, &id, "float", || {
+        let reader = match file_reader(blobdir, &format!("{}.json", &id)) {
+            Some(reader) => reader,
+            None => {
+                return Err(error::Error::FileNotFound(format!(
+                    "{}",
+                    blobdir.join(format!("{}.json", id)).display()
+                )))
+            }
+        };
+        let field: Field<serde_json::Value> =
+            serde_json::from_reader(reader).expect("unable to parse json");
+        Ok(field.values().iter().map(float_value).collect())
+    })
+}
+
+/// Parse an integer field, reading null or non-integer values as `None`.
+pub fn parse_field_int(id: String, blobdir: &PathBuf) -> Result<Vec<Option<usize>>, error::Error> {
+    cache::cached(blobdir, &id, "int", || {
+        let reader = match file_reader(blobdir, &format!("{}.json", &id)) {
+            Some(reader) => reader,
+            None => {
+                return Err(error::Error::FileNotFound(format!(
+                    "{}",
+                    blobdir.join(format!("{}.json", id)).display()
+                )))
+            }
+        };
+        let field: Field<serde_json::Value> =
+            serde_json::from_reader(reader).expect("unable to parse json");
+        Ok(field.values().iter().map(int_value).collect())
+    })
+}
+
+/// Category indices for each window of each record.
+pub type WindowValues = Vec<Vec<Option<usize>>>;
+
+/// Parse a windowed category field as a list of window category indices
+/// per record, along with the category keys.
+///
+/// Windows may be stored as plain indices or as lists with the category
+/// index first. Windows without a valid index are read as `None`.
+pub fn parse_field_windows(
+    id: String,
+    blobdir: &PathBuf,
+) -> Result<(WindowValues, Vec<String>), error::Error> {
     let reader = match file_reader(blobdir, &format!("{}.json", &id)) {
         Some(reader) => reader,
         None => {
             return Err(error::Error::FileNotFound(format!(
-                "{}/{}.json",
-                &blobdir.to_str().unwrap(),
-                &id
+                "{}",
+                blobdir.join(format!("{}.json", id)).display()
             )))
         }
     };
-    let field: Field<f64> = serde_json::from_reader(reader).expect("unable to parse json");
-    let values = field.values().clone();
-    Ok(values)
+    let field: Field<Vec<serde_json::Value>> =
+        serde_json::from_reader(reader).expect("unable to parse json");
+    let values = field
+        .values()
+        .iter()
+        .map(|windows| {
+            windows
+                .iter()
+                .map(|window| match window {
+                    serde_json::Value::Array(list) if !list.is_empty() => int_value(&list[0]),
+                    _ => int_value(window),
+                })
+                .collect()
+        })
+        .collect();
+    Ok((values, field.keys))
 }
 
-pub fn parse_field_int(id: String, blobdir: &PathBuf) -> Result<Vec<usize>, error::Error> {
-    let reader = match file_reader(blobdir, &format!("{}.json", &id)) {
-        Some(reader) => reader,
-        None => {
-            return Err(error::Error::FileNotFound(format!(
-                "{}/{}.json",
-                &blobdir.to_str().unwrap(),
-                &id
-            )))
-        }
+/// Encode category labels as indices into a list of keys, with keys listed
+/// in order of first occurrence after any `initial` keys.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::blobdir::category_keys;
+/// let labels = vec!["b".to_string(), "none".to_string(), "b".to_string()];
+/// let (values, keys) = category_keys(&labels, &["none"]);
+/// assert_eq!(values, vec![1, 0, 1]);
+/// assert_eq!(keys, vec!["none".to_string(), "b".to_string()]);
+/// ```
+pub fn category_keys(labels: &[String], initial: &[&str]) -> (Vec<usize>, Vec<String>) {
+    let mut keys: Vec<String> = initial.iter().map(|key| key.to_string()).collect();
+    let mut indices: HashMap<String, usize> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (key.clone(), i))
+        .collect();
+    let values = labels
+        .iter()
+        .map(|label| {
+            *indices.entry(label.clone()).or_insert_with(|| {
+                keys.push(label.clone());
+                keys.len() - 1
+            })
+        })
+        .collect();
+    (values, keys)
+}
+
+/// ID of a taxonomic category field, either as given or built from a
+/// taxrule and rank.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::blobdir::category_field_id;
+/// assert_eq!(category_field_id(None, "bestsumorder", "phylum"), "bestsumorder_phylum");
+/// let category = "custom".to_string();
+/// assert_eq!(category_field_id(Some(&category), "bestsumorder", "phylum"), "custom");
+/// ```
+pub fn category_field_id(category: Option<&String>, taxrule: &str, rank: &str) -> String {
+    match category {
+        Some(field_id) => field_id.clone(),
+        None => format!("{}_{}", taxrule, rank),
+    }
+}
+
+/// Write a category field to a BlobDir as indices into a list of keys.
+pub fn write_field_cat(
+    id: &str,
+    values: &[usize],
+    keys: &[String],
+    blobdir: &Path,
+) -> Result<(), error::Error> {
+    let field = Field {
+        values: values.to_vec(),
+        keys: keys.to_vec(),
+        category_slot: None,
+        headers: None,
     };
-    let field: Field<usize> = serde_json::from_reader(reader).expect("unable to parse json");
-    let values = field.values().clone();
-    Ok(values)
+    let mut writer = io::get_writer(&Some(blobdir.join(format!("{}.json", id))));
+    serde_json::to_writer(&mut writer, &field)?;
+    Ok(())
+}
+
+/// Write an integer field to a BlobDir, writing missing values as null.
+pub fn write_field_int(
+    id: &str,
+    values: &[Option<usize>],
+    blobdir: &Path,
+) -> Result<(), error::Error> {
+    let field = Field {
+        values: values.to_vec(),
+        keys: vec![],
+        category_slot: None,
+        headers: None,
+    };
+    let mut writer = io::get_writer(&Some(blobdir.join(format!("{}.json", id))));
+    serde_json::to_writer(&mut writer, &field)?;
+    Ok(())
+}
+
+/// Write an array field to a BlobDir, with a header naming each array column.
+pub fn write_field_array<T: Clone + Serialize>(
+    id: &str,
+    values: &[T],
+    headers: &[&str],
+    blobdir: &Path,
+) -> Result<(), error::Error> {
+    let field = Field {
+        values: values.to_vec(),
+        keys: vec![],
+        category_slot: None,
+        headers: Some(headers.iter().map(|header| header.to_string()).collect()),
+    };
+    let mut writer = io::get_writer(&Some(blobdir.join(format!("{}.json", id))));
+    serde_json::to_writer(&mut writer, &field)?;
+    Ok(())
+}
+
+/// Write a float field to a BlobDir, writing missing (`NaN`) values as null.
+pub fn write_field_float(id: &str, values: &[f64], blobdir: &Path) -> Result<(), error::Error> {
+    let field = Field {
+        values: values.to_vec(),
+        keys: vec![],
+        category_slot: None,
+        headers: None,
+    };
+    let mut writer = io::get_writer(&Some(blobdir.join(format!("{}.json", id))));
+    serde_json::to_writer(&mut writer, &field)?;
+    Ok(())
 }
 
 pub fn parse_field_string(id: String, blobdir: &PathBuf) -> Result<Vec<String>, error::Error> {
@@ -422,9 +810,8 @@ pub fn parse_field_string(id: String, blobdir: &PathBuf) -> Result<Vec<String>,
         Some(reader) => reader,
         None => {
             return Err(error::Error::FileNotFound(format!(
-                "{}/{}.json",
-                &blobdir.to_str().unwrap(),
-                &id
+                "{}",
+                blobdir.join(format!("{}.json", id)).display()
             )))
         }
     };
@@ -506,6 +893,10 @@ pub fn filter_float_values(values: Vec<f64>, filter: Filter, indices: Vec<usize>
     };
     let mut output = vec![];
     for i in initial {
+        // Missing values never pass a filter
+        if values[i].is_missing() {
+            continue;
+        }
         let mut keep = true;
         if filter.max.is_some() {
             if values[i] > filter.max.unwrap() {
@@ -527,7 +918,11 @@ pub fn filter_float_values(values: Vec<f64>, filter: Filter, indices: Vec<usize>
     output
 }
 
-pub fn filter_int_values(values: Vec<usize>, filter: Filter, indices: Vec<usize>) -> Vec<usize> {
+pub fn filter_int_values(
+    values: Vec<Option<usize>>,
+    filter: Filter,
+    indices: Vec<usize>,
+) -> Vec<usize> {
     let initial: Vec<usize> = if indices.is_empty() {
         (0..(values.len() - 1)).collect()
     } else {
@@ -535,14 +930,19 @@ pub fn filter_int_values(values: Vec<usize>, filter: Filter, indices: Vec<usize>
     };
     let mut output = vec![];
     for i in initial {
+        // Missing values never pass a filter
+        let value = match values[i] {
+            Some(value) => value as f64,
+            None => continue,
+        };
         let mut keep = true;
         if filter.max.is_some() {
-            if values[i] as f64 > filter.max.unwrap() {
+            if value > filter.max.unwrap() {
                 keep = false;
             }
         }
         if filter.min.is_some() {
-            if (values[i] as f64) < filter.min.unwrap() {
+            if value < filter.min.unwrap() {
                 keep = false;
             }
         }
@@ -594,14 +994,49 @@ pub fn apply_filter_float(values: &Vec<f64>, indices: &Vec<usize>) -> Vec<f64> {
     output
 }
 
-pub fn apply_filter_int(values: &Vec<usize>, indices: &Vec<usize>) -> Vec<usize> {
+pub fn apply_filter_int<T: Clone>(values: &[T], indices: &[usize]) -> Vec<T> {
     let mut output = vec![];
     for i in indices {
-        output.push(values[i.clone()])
+        output.push(values[*i].clone())
     }
     output
 }
 
+/// Sort order of filtered records, from the sort order of all records.
+///
+/// Returns `None` unless `indices` are in ascending order, as ties would
+/// otherwise be ordered differently from sorting the filtered values.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::blobdir::apply_filter_order;
+/// assert_eq!(apply_filter_order(&[3, 1, 0, 2], &[0, 2, 3]), Some(vec![2, 0, 1]));
+/// assert_eq!(apply_filter_order(&[3, 1, 0, 2], &[2, 0]), None);
+/// ```
+pub fn apply_filter_order(order: &[usize], indices: &[usize]) -> Option<Vec<usize>> {
+    if indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return None;
+    }
+    let mut positions = vec![None; order.len()];
+    for (position, i) in indices.iter().enumerate() {
+        *positions.get_mut(*i)? = Some(position);
+    }
+    Some(order.iter().filter_map(|i| positions[*i]).collect())
+}
+
+/// Drop indices of records with a missing value, returning the remaining
+/// indices and the number of records skipped.
+pub fn skip_missing<T: Missing>(values: &[T], indices: &[usize]) -> (Vec<usize>, usize) {
+    let kept: Vec<usize> = indices
+        .iter()
+        .filter(|i| !values[**i].is_missing())
+        .cloned()
+        .collect();
+    let skipped = indices.len() - kept.len();
+    (kept, skipped)
+}
+
 pub fn apply_filter_busco(
     values: &Vec<Vec<BuscoGene>>,
     indices: &Vec<usize>,
@@ -613,32 +1048,29 @@ pub fn apply_filter_busco(
     output
 }
 
-pub fn apply_filter_cat(values: &Vec<(String, usize)>, indices: &Vec<usize>) -> Vec<String> {
+pub fn apply_filter_cat(values: &CatValues, indices: &Vec<usize>) -> Vec<String> {
     let mut output = vec![];
     for i in indices {
-        output.push(values[i.clone()].clone().0)
+        output.push(values.name(*i).to_string())
     }
     output
 }
 
-pub fn apply_filter_cat_tuple(
-    values: &Vec<(String, usize)>,
-    indices: &Vec<usize>,
-) -> Vec<(String, usize)> {
-    let mut output = vec![];
-    for i in indices {
-        output.push(values[i.clone()].clone())
+/// Filter category codes, keeping the same list of category names.
+pub fn apply_filter_cat_tuple(values: &CatValues, indices: &[usize]) -> CatValues {
+    CatValues {
+        codes: indices.iter().map(|i| values.codes[*i]).collect(),
+        keys: values.keys.clone(),
     }
-    output
 }
 
 pub fn get_plot_values(
     meta: &Meta,
     blobdir: &PathBuf,
     plot_map: &HashMap<String, String>,
-) -> Result<(HashMap<String, Vec<f64>>, Vec<(String, usize)>), error::Error> {
+) -> Result<(HashMap<String, Vec<f64>>, CatValues), error::Error> {
     let mut plot_values = HashMap::new();
-    let mut cat_values = vec![];
+    let mut cat_values = CatValues::default();
     let field_list = meta.field_list.clone().unwrap();
     for (axis, id) in plot_map {
         let field_meta_option = field_list.get(id);
@@ -653,7 +1085,10 @@ pub fn get_plot_values(
                     Some(Datatype::Integer) => {
                         let values: Vec<f64> = parse_field_int(field_meta.id.clone(), blobdir)?
                             .iter()
-                            .map(|x| x.clone() as f64)
+                            .map(|x| match x {
+                                Some(value) => *value as f64,
+                                None => f64::NAN,
+                            })
                             .collect();
                         plot_values.insert(axis.clone(), values);
                     }
@@ -668,7 +1103,10 @@ pub fn get_plot_values(
             }
             None => {
                 if axis == "cat" && id == "_" {
-                    cat_values = vec![("blank".to_string(), 0); meta.records]
+                    cat_values = CatValues {
+                        codes: vec![0; meta.records],
+                        keys: vec!["blank".to_string()],
+                    }
                 } else {
                     ()
                 }
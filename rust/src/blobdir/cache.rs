@@ -0,0 +1,127 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use rmp_serde;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{get_path, meta_path};
+use crate::error;
+use crate::utils;
+
+/// Sidecar directory for parsed field values. Fields are only cached in
+/// BlobDirs that have one.
+pub const CACHE_DIR: &str = ".blobtk_cache";
+
+#[derive(Deserialize, Serialize)]
+struct CachedValues<T> {
+    meta_mtime: u64,
+    field_mtime: u64,
+    values: Vec<T>,
+}
+
+fn mtime(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// Modification times of meta.json and a field file, which invalidate a
+/// cached entry when either changes.
+fn mtimes(blobdir: &PathBuf, id: &str) -> (u64, u64) {
+    let field_mtime = match get_path(blobdir, &format!("{}.json", id)) {
+        Some(path) => mtime(Path::new(&path)),
+        None => 0,
+    };
+    (mtime(&meta_path(blobdir)), field_mtime)
+}
+
+fn cache_path(blobdir: &Path, id: &str, kind: &str) -> PathBuf {
+    blobdir
+        .join(CACHE_DIR)
+        .join(format!("{}.{}.msgpack", id, kind))
+}
+
+/// Create the sidecar directory so fields read from a BlobDir are cached.
+pub fn enable(blobdir: &Path) -> Result<(), error::Error> {
+    fs::create_dir_all(blobdir.join(CACHE_DIR))?;
+    Ok(())
+}
+
+/// Load values derived from a field from the sidecar cache if it is
+/// enabled and up to date, otherwise compute them and store the result for
+/// the next run.
+///
+/// `kind` distinguishes values derived from the same field, e.g. parsed
+/// values and their sort order.
+pub fn cached<T, F>(
+    blobdir: &PathBuf,
+    id: &str,
+    kind: &str,
+    compute: F,
+) -> Result<Vec<T>, error::Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Result<Vec<T>, error::Error>,
+{
+    if !blobdir.join(CACHE_DIR).is_dir() {
+        return compute();
+    }
+    let path = cache_path(blobdir, id, kind);
+    let (meta_mtime, field_mtime) = mtimes(blobdir, id);
+    if let Ok(file) = File::open(&path) {
+        if let Ok(cached) = rmp_serde::from_read::<_, CachedValues<T>>(BufReader::new(file)) {
+            if cached.meta_mtime == meta_mtime && cached.field_mtime == field_mtime {
+                return Ok(cached.values);
+            }
+        }
+    }
+    let values = compute()?;
+    let cached = CachedValues {
+        meta_mtime,
+        field_mtime,
+        values,
+    };
+    if let Err(err) = utils::write_msgpack(&path, &cached) {
+        eprintln!("Unable to cache field {}: {}", id, err);
+    }
+    Ok(cached.values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blobdir::parse_field_float;
+
+    #[test]
+    fn test_cached() {
+        let blobdir = std::env::temp_dir().join("blobtk_test_field_cache");
+        let _ = fs::remove_dir_all(&blobdir);
+        fs::create_dir_all(&blobdir).unwrap();
+        for file in ["meta.json", "gc.json"] {
+            fs::copy(PathBuf::from("test/minimal").join(file), blobdir.join(file)).unwrap();
+        }
+        let parsed = parse_field_float("gc".to_string(), &blobdir).unwrap();
+        assert!(!cache_path(&blobdir, "gc", "float").exists());
+
+        enable(&blobdir).unwrap();
+        assert_eq!(
+            parse_field_float("gc".to_string(), &blobdir).unwrap(),
+            parsed
+        );
+        assert!(cache_path(&blobdir, "gc", "float").exists());
+        let values: Vec<f64> = cached(&blobdir, "gc", "float", || Ok(vec![])).unwrap();
+        assert_eq!(values, parsed);
+
+        // a changed field invalidates the entry
+        let field = fs::read(blobdir.join("gc.json")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(blobdir.join("gc.json"), field).unwrap();
+        let values: Vec<f64> = cached(&blobdir, "gc", "float", || Ok(vec![])).unwrap();
+        assert!(values.is_empty());
+    }
+}
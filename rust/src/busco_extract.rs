@@ -0,0 +1,86 @@
+//!
+//! Invoked by calling:
+//! `blobtk busco-extract <args>`
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+
+use crate::cli;
+use crate::fasta::{self, FastaRegion};
+use crate::io;
+
+pub use cli::BuscoExtractOptions;
+
+/// Get the lineage name from a BUSCO full_table header comment, falling back
+/// to the name of the BUSCO run directory.
+fn lineage_name(full_table: &Path, header: &[String]) -> String {
+    for line in header {
+        if let Some(rest) = line.strip_prefix("# The lineage dataset is: ") {
+            if let Some(lineage) = rest.split_whitespace().next() {
+                return lineage.to_string();
+            }
+        }
+    }
+    let dir_name = full_table
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match dir_name.strip_prefix("run_") {
+        Some(lineage) => lineage.to_string(),
+        None => "busco".to_string(),
+    }
+}
+
+/// Parse BUSCO gene regions with a wanted status from a full_table.tsv file.
+fn parse_full_table(
+    full_table: &Path,
+    statuses: &[String],
+) -> Result<(String, Vec<FastaRegion>), anyhow::Error> {
+    let mut header = vec![];
+    let mut regions = vec![];
+    for line in io::read_lines(full_table)? {
+        let line = line?;
+        if line.starts_with('#') {
+            header.push(line);
+            continue;
+        }
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 5 || !statuses.iter().any(|status| status == cols[1]) {
+            continue;
+        }
+        let (start, end) = match (cols[3].parse::<usize>(), cols[4].parse::<usize>()) {
+            (Ok(start), Ok(end)) => (start.min(end), start.max(end)),
+            _ => continue,
+        };
+        regions.push(FastaRegion {
+            id: format!("{} {}:{}-{}", cols[0], cols[2], start, end),
+            seq_name: cols[2].as_bytes().to_vec(),
+            start,
+            end,
+            reverse: cols.len() > 5 && cols[5] == "-",
+        });
+    }
+    Ok((lineage_name(full_table, &header), regions))
+}
+
+/// Execute the `busco-extract` subcommand from `blobtk`.
+/// Write sequences of BUSCO genes listed in full_table.tsv files to one
+/// FASTA file per lineage.
+pub fn busco_extract(options: &cli::BuscoExtractOptions) -> Result<(), anyhow::Error> {
+    for full_table in options.full_table.iter() {
+        let (lineage, regions) = parse_full_table(full_table, &options.status)?;
+        let out_path = options.output.join(format!("{}.fasta", lineage));
+        let mut writer = io::get_writer(&Some(out_path));
+        fasta::extract_regions(
+            &regions,
+            &options.fasta,
+            &mut *writer,
+            &None as &Option<Box<dyn Fn()>>,
+        );
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//!
+//! Invoked by calling:
+//! `blobtk classify <args>`
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow;
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli::{self, ClassifierFormat};
+use crate::io;
+use crate::taxonomy::parse::Nodes;
+use crate::taxonomy::parse_taxdump;
+
+pub use cli::ClassifyOptions;
+
+/// Get a taxid from a Kraken2 taxid column, which includes the taxon name
+/// when Kraken2 is run with `--use-names`.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::classify::kraken_taxid;
+/// assert_eq!(kraken_taxid("9606"), "9606");
+/// assert_eq!(kraken_taxid("Homo sapiens (taxid 9606)"), "9606");
+/// ```
+pub fn kraken_taxid(value: &str) -> String {
+    match value.rsplit_once("(taxid ") {
+        Some((_, taxid)) => taxid.trim_end_matches(')').trim().to_string(),
+        None => value.trim().to_string(),
+    }
+}
+
+/// Parse Kraken2 per-sequence output into a taxid for each sequence.
+fn parse_kraken(path: &Path) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut taxids = HashMap::new();
+    for line in io::read_lines(path)? {
+        let line = line?;
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 3 {
+            continue;
+        }
+        let taxid = match cols[0] {
+            "C" => kraken_taxid(cols[2]),
+            _ => "0".to_string(),
+        };
+        taxids.insert(cols[1].to_string(), taxid);
+    }
+    Ok(taxids)
+}
+
+/// Parse Centrifuge per-read output into the best scoring taxid for each
+/// sequence.
+fn parse_centrifuge(path: &Path) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut best: HashMap<String, (f64, String)> = HashMap::new();
+    for line in io::read_lines(path)? {
+        let line = line?;
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 4 || cols[0] == "readID" {
+            continue;
+        }
+        let score = cols[3].parse::<f64>().unwrap_or(0.0);
+        let entry = best
+            .entry(cols[0].to_string())
+            .or_insert((f64::NEG_INFINITY, "0".to_string()));
+        if score > entry.0 {
+            *entry = (score, cols[2].to_string());
+        }
+    }
+    Ok(best
+        .into_iter()
+        .map(|(id, (_, taxid))| (id, taxid))
+        .collect())
+}
+
+/// Get the names of the ancestors of a taxon (including itself) at each rank.
+fn ranked_names(nodes: &Nodes, taxid: &String, ranks: &[String]) -> Vec<String> {
+    let node = match nodes.nodes.get(taxid) {
+        Some(node) if taxid != "0" => node,
+        _ => return vec!["no-hit".to_string(); ranks.len()],
+    };
+    let mut lineage = nodes.lineage(&"1".to_string(), taxid);
+    lineage.push(node);
+    ranks
+        .iter()
+        .map(|rank| {
+            lineage
+                .iter()
+                .find(|node| node.rank == *rank)
+                .map_or("undef".to_string(), |node| node.scientific_name())
+        })
+        .collect()
+}
+
+/// Execute the `classify` subcommand from `blobtk`.
+pub fn classify(options: &cli::ClassifyOptions) -> Result<(), anyhow::Error> {
+    let taxids = match options.format {
+        ClassifierFormat::Kraken => parse_kraken(&options.input)?,
+        ClassifierFormat::Centrifuge => parse_centrifuge(&options.input)?,
+    };
+    let nodes = parse_taxdump(options.taxdump.clone())?;
+    let prefix = match &options.prefix {
+        Some(prefix) => prefix.clone(),
+        None => format!("{:?}", options.format).to_lowercase(),
+    };
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+
+    let no_hit = "0".to_string();
+    let mut cache: HashMap<&String, Vec<String>> = HashMap::new();
+    let mut labels = vec![vec![]; options.ranks.len()];
+    let mut classified = 0;
+    for identifier in identifiers.iter() {
+        let taxid = taxids.get(identifier).unwrap_or(&no_hit);
+        if taxid != "0" {
+            classified += 1;
+        }
+        let names = cache
+            .entry(taxid)
+            .or_insert_with(|| ranked_names(&nodes, taxid, &options.ranks));
+        for (i, name) in names.iter().enumerate() {
+            labels[i].push(name.clone());
+        }
+    }
+
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+    for (rank, rank_labels) in options.ranks.iter().zip(labels.iter()) {
+        let field_id = blobdir::category_field_id(None, &prefix, rank);
+        let (values, keys) = blobdir::category_keys(rank_labels, &["no-hit"]);
+        blobdir::write_field_cat(&field_id, &values, &keys, &options.blobdir)?;
+        blobdir::add_field_meta(
+            &mut meta_value,
+            json!({
+                "id": field_id,
+                "name": format!("{} {}", prefix, rank),
+                "type": "category",
+                "datatype": "string",
+                "preload": false,
+            }),
+        );
+    }
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+    eprintln!("{} of {} records classified", classified, identifiers.len());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//!
+//! Invoked by calling:
+//! `blobtk clean <args>`
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow;
+
+use crate::cli;
+use crate::utils;
+
+pub use cli::CleanOptions;
+
+/// Total size in bytes of the files in a directory.
+fn dir_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum())
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Whether a scratch directory may belong to a running process. Processes
+/// are checked through `/proc`, so any directory is treated as active where
+/// that is unavailable.
+fn is_active(name: &str) -> bool {
+    let proc = Path::new("/proc");
+    name == process::id().to_string() || !proc.exists() || proc.join(name).exists()
+}
+
+/// Execute the `clean` subcommand from `blobtk`.
+pub fn clean(options: &cli::CleanOptions) -> Result<(), anyhow::Error> {
+    let workspace = utils::workspace();
+    let mut paths: Vec<PathBuf> = vec![];
+    if let Ok(entries) = fs::read_dir(workspace.join("tmp")) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if options.all || !is_active(&name) {
+                paths.push(entry.path());
+            }
+        }
+    }
+    if options.cache && workspace.join("cache").exists() {
+        paths.push(workspace.join("cache"));
+    }
+    paths.sort();
+
+    let mut total = 0;
+    for path in paths.iter() {
+        let size = dir_size(path);
+        total += size;
+        println!("{}\t{}", path.display(), size);
+        if !options.dry_run {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    eprintln!(
+        "{} {} paths ({}B) from {}",
+        match options.dry_run {
+            true => "Would remove",
+            false => "Removed",
+        },
+        paths.len(),
+        utils::format_si(&(total as f64), 3),
+        workspace.display()
+    );
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 // use std::str::FromStr;
 // use std::string::ParseError;
@@ -9,9 +9,12 @@ use pyo3::pyclass;
 use serde;
 use serde::{Deserialize, Serialize};
 
+use crate::fastq::DEFAULT_BUFFER_SIZE;
 use crate::plot::axis::Scale;
 use crate::plot::data::Reducer;
 use crate::plot::ShowLegend;
+use crate::taxonomy::parse::FieldNormalization;
+use crate::utils::DEFAULT_SEED;
 
 // fn float_range(s: &str, min: f64, max: f64) -> Result<f64, String> {
 //     debug_assert!(min <= max, "minimum of {} exceeds maximum of {}", min, max);
@@ -47,10 +50,37 @@ fn bin_size_parser(s: &str) -> Result<usize, String> {
     Ok(val)
 }
 
+fn memory_size_parser(s: &str) -> Result<usize, String> {
+    let s = s.trim().to_uppercase();
+    let s = s.strip_suffix('B').unwrap_or(&s);
+    let (number, power) = match s.char_indices().last() {
+        Some((i, 'K')) => (&s[..i], 1),
+        Some((i, 'M')) => (&s[..i], 2),
+        Some((i, 'G')) => (&s[..i], 3),
+        Some((i, 'T')) => (&s[..i], 4),
+        _ => (s, 0),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(value) if value >= 0.0 => Ok((value * 1024_f64.powi(power)) as usize),
+        _ => Err(format!("invalid memory size: {}", s)),
+    }
+}
+
 /// Top level arguments to `blobtk`
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Arguments {
+    /// Seed for the simulated data from `example`, recorded in output summaries
+    #[arg(long = "seed", global = true, default_value_t = DEFAULT_SEED)]
+    pub seed: u64,
+    /// Record wall time and peak memory for each pipeline stage in this JSON
+    /// file and in any JSON summary written by the command
+    #[arg(long = "timings", global = true, value_name = "JSON")]
+    pub timings: Option<PathBuf>,
+    /// Directory for intermediate files and caches [default: blobtk in the
+    /// system temp directory]
+    #[arg(long = "workspace", global = true)]
+    pub workspace: Option<PathBuf>,
     #[clap(subcommand)]
     pub cmd: SubCommand,
 }
@@ -58,18 +88,105 @@ pub struct Arguments {
 /// `blobtk` subcommands
 #[derive(Subcommand, Debug)]
 pub enum SubCommand {
+    /// Extract BUSCO gene sequences into per-lineage FASTA files.
+    /// Called as `blobtk busco-extract`
+    #[command(name = "busco-extract")]
+    BuscoExtract(BuscoExtractOptions),
+    /// Import Kraken2 or Centrifuge classifications into a BlobDir.
+    /// Called as `blobtk classify`
+    Classify(ClassifyOptions),
+    /// Remove intermediate files from the workspace.
+    /// Called as `blobtk clean`
+    Clean(CleanOptions),
+    /// Score windowed taxonomic consistency of records in a BlobDir.
+    /// Called as `blobtk consistency`
+    Consistency(ConsistencyOptions),
+    /// Apply curation decisions to an assembly FASTA file.
+    /// Called as `blobtk curate`
+    Curate(CurateOptions),
     /// Calculate sequencing coverage depth.
     /// Called as `blobtk depth`
     Depth(DepthOptions),
+    /// Write a synthetic example dataset with known contamination.
+    /// Called as `blobtk example`
+    Example(ExampleOptions),
+    /// Import NCBI FCS-GX and FCS-adaptor reports into a BlobDir.
+    /// Called as `blobtk fcs`
+    Fcs(FcsOptions),
+    /// Add telomere and gap feature fields to a BlobDir.
+    /// Called as `blobtk features`
+    Features(FeaturesOptions),
     /// Filter files based on list of sequence names.
     /// Called as `blobtk filter`
     Filter(FilterOptions),
+    /// Calculate FASTQ read statistics.
+    /// Called as `blobtk fqstats`
+    #[command(name = "fqstats")]
+    FqStats(FqStatsOptions),
+    /// Add k-mer composition embedding fields to a BlobDir.
+    /// Called as `blobtk kmer`
+    Kmer(KmerOptions),
+    /// Flag GC and coverage outliers within taxonomic categories.
+    /// Called as `blobtk outliers`
+    Outliers(OutliersOptions),
+    /// Partition reads into FASTQ files by taxonomic category.
+    /// Called as `blobtk partition`
+    Partition(PartitionOptions),
     /// Process a BlobDir and produce static plots.
     /// Called as `blobtk plot`
     Plot(PlotOptions),
+    /// Flag likely haplotigs and duplicated records in a BlobDir.
+    /// Called as `blobtk purge`
+    Purge(PurgeOptions),
+    /// Propose records to keep, remove or review using screening rules.
+    /// Called as `blobtk screen`
+    Screen(ScreenOptions),
     /// [experimental] Process a taxonomy and lookup lineages.
     /// Called as `blobtk taxonomy`
-    Taxonomy(TaxonomyOptions),
+    Taxonomy(Box<TaxonomyOptions>),
+    /// Upgrade a BlobDir meta.json to the current schema version.
+    /// Called as `blobtk upgrade`
+    Upgrade(UpgradeOptions),
+}
+
+/// Options to pass to `blobtk busco-extract`
+#[derive(Parser, Debug)]
+pub struct BuscoExtractOptions {
+    /// Path to BUSCO full_table.tsv file(s), one per lineage
+    #[arg(long = "full-table", short = 't', num_args(1..), required = true)]
+    pub full_table: Vec<PathBuf>,
+    /// Path to assembly FASTA input file
+    #[arg(long, short = 'a')]
+    pub fasta: PathBuf,
+    /// Directory for per-lineage FASTA output files
+    #[arg(long, short = 'o', default_value = ".")]
+    pub output: PathBuf,
+    /// BUSCO statuses to extract
+    #[arg(long, num_args(1..), default_values_t = [String::from("Complete")])]
+    pub status: Vec<String>,
+}
+
+/// Options to pass to `blobtk consistency`
+#[derive(Parser, Debug)]
+pub struct ConsistencyOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Taxonomic rank to score
+    #[arg(long, default_value_t = String::from("phylum"))]
+    pub rank: String,
+    /// Taxrule used to assign taxonomy in the BlobDir
+    #[arg(long, default_value_t = String::from("bestsumorder"))]
+    pub taxrule: String,
+    /// Category field to score (overrides --rank and --taxrule)
+    #[arg(long = "category")]
+    pub category: Option<String>,
+    /// Windowed category field [default: <category>_windows]
+    #[arg(long = "windows")]
+    pub windows: Option<String>,
+    /// ID of the consistency field to add [default: <category>_consistency]
+    #[arg(long = "field-id")]
+    pub field_id: Option<String>,
 }
 
 /// Options to pass to `blobtk depth`
@@ -77,7 +194,7 @@ pub enum SubCommand {
 #[command(group(
     ArgGroup::new("alignment")
         .required(false)
-        .args(["bam", "cram"]),
+        .args(["bam", "cram", "bams"]),
 ))]
 #[pyclass]
 pub struct DepthOptions {
@@ -105,6 +222,14 @@ pub struct DepthOptions {
     /// Output bed file name
     #[arg(long = "bed", short = 'O', value_name = "BED")]
     pub bed: Option<PathBuf>,
+    /// Paths to BAM/CRAM files to combine in a multi-sample depth matrix
+    #[arg(long = "bams", num_args(1..), value_name = "BAM", conflicts_with = "bed")]
+    pub bams: Option<Vec<PathBuf>>,
+    /// Output depth matrix file name (one row per bin, one column per
+    /// sample), written as Parquet for a `.parquet` extension or TSV
+    /// otherwise [default: STDOUT]
+    #[arg(long = "matrix", short = 'M', value_name = "MATRIX", requires = "bams")]
+    pub matrix: Option<PathBuf>,
 }
 
 /// Options to pass to `blobtk filter`
@@ -165,6 +290,366 @@ pub struct FilterOptions {
     /// Path to output list of read IDs
     #[arg(long = "read-list", short = 'O', value_name = "TXT")]
     pub read_list: Option<PathBuf>,
+    /// Only extract reads overlapping region(s) (<seq>:<start>-<end>)
+    #[arg(long = "region", short = 'g', value_name = "REGION")]
+    pub region: Option<Vec<String>>,
+    /// Path to BED file of regions to extract reads from
+    #[arg(long = "bed", value_name = "BED")]
+    pub bed: Option<PathBuf>,
+    /// Flag to also extract unmapped reads, and pairs split between listed
+    /// and filtered-out sequences
+    #[arg(long = "unmapped", short = 'U', default_value_t = false)]
+    pub unmapped: bool,
+    /// Parser buffer size in bytes for FASTQ input
+    #[arg(long = "buffer-size", default_value_t = DEFAULT_BUFFER_SIZE)]
+    pub buffer_size: usize,
+    /// Flag to output filtered alignments
+    #[arg(
+        long = "alignment-out",
+        requires = "alignment",
+        default_value_t = false
+    )]
+    pub alignment_out: bool,
+    /// Format for filtered alignments (defaults to the input format)
+    #[arg(long = "output-format", value_enum, requires = "alignment_out")]
+    pub output_format: Option<AlignmentFormat>,
+    /// Compression level for filtered alignments (0 for none to 9 for smallest)
+    #[arg(
+        long = "compression-level",
+        requires = "alignment_out",
+        value_parser = clap::value_parser!(u32).range(0..=9)
+    )]
+    pub compression_level: Option<u32>,
+    /// Path to reference FASTA for CRAM output (defaults to the assembly FASTA)
+    #[arg(
+        long = "output-reference",
+        value_name = "FASTA",
+        requires = "alignment_out"
+    )]
+    pub output_reference: Option<PathBuf>,
+}
+
+/// Formats for alignment output
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum AlignmentFormat {
+    Sam,
+    Bam,
+    Cram,
+}
+
+/// Options to pass to `blobtk fqstats`
+#[derive(Parser, Debug)]
+pub struct FqStatsOptions {
+    /// Path to FASTQ file (forward or single reads)
+    #[arg(long = "fastq", short = 'f', value_name = "FASTQ")]
+    pub fastq1: PathBuf,
+    /// Path to paired FASTQ file (reverse reads)
+    #[arg(long = "fastq2", short = 'r', value_name = "FASTQ")]
+    pub fastq2: Option<PathBuf>,
+    /// Output JSON file name (MultiQC custom content format)
+    #[arg(long, short = 'o', value_name = "JSON")]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk kmer`
+#[derive(Parser, Debug)]
+pub struct KmerOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to assembly FASTA input file
+    #[arg(long, short = 'f')]
+    pub fasta: PathBuf,
+    /// K-mer length
+    #[arg(long, short = 'k', default_value_t = 4, value_parser = clap::value_parser!(u8).range(2..=6))]
+    pub k: u8,
+    /// Minimum sequence length to include in the embedding
+    #[arg(long = "min-length", default_value_t = 1000)]
+    pub min_length: usize,
+    /// Prefix for embedding field IDs
+    #[arg(long, default_value_t = String::from("kmer"))]
+    pub prefix: String,
+}
+
+/// Methods to flag outliers
+#[derive(ValueEnum, Clone, Debug, Default)]
+pub enum OutlierMethod {
+    #[default]
+    Zscore,
+    Iqr,
+}
+
+/// Options to pass to `blobtk outliers`
+#[derive(Parser, Debug)]
+pub struct OutliersOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// GC field
+    #[arg(long = "gc-field", default_value_t = String::from("gc"))]
+    pub gc_field: String,
+    /// Coverage field [default: BlobDir plot y field]
+    #[arg(long = "cov-field")]
+    pub cov_field: Option<String>,
+    /// Taxonomic rank to group records by
+    #[arg(long, default_value_t = String::from("phylum"))]
+    pub rank: String,
+    /// Taxrule used to assign taxonomy in the BlobDir
+    #[arg(long, default_value_t = String::from("bestsumorder"))]
+    pub taxrule: String,
+    /// Category field to group records by (overrides --rank and --taxrule)
+    #[arg(long = "category")]
+    pub category: Option<String>,
+    /// Method used to flag outliers
+    #[arg(long, value_enum, default_value_t = OutlierMethod::Zscore)]
+    pub method: OutlierMethod,
+    /// Outlier threshold [default: 3 for zscore, 1.5 for iqr]
+    #[arg(long)]
+    pub threshold: Option<f64>,
+    /// Output file for list of outlier identifiers
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk partition`
+#[derive(Parser, Debug)]
+#[command(group(
+    ArgGroup::new("alignment")
+        .required(true)
+        .args(["bam", "cram"]),
+))]
+pub struct PartitionOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to BAM file
+    #[arg(long, short = 'b')]
+    pub bam: Option<PathBuf>,
+    /// Path to CRAM file
+    #[arg(long, short = 'c', requires = "fasta")]
+    pub cram: Option<PathBuf>,
+    /// Path to assembly FASTA input file (required for CRAM)
+    #[arg(long, short = 'a')]
+    pub fasta: Option<PathBuf>,
+    /// Path to FASTQ file to partition (forward or single reads)
+    #[arg(long = "fastq", short = 'f', value_name = "FASTQ")]
+    pub fastq1: PathBuf,
+    /// Path to paired FASTQ file to partition (reverse reads)
+    #[arg(long = "fastq2", short = 'r', value_name = "FASTQ")]
+    pub fastq2: Option<PathBuf>,
+    /// Taxonomic rank to partition reads by
+    #[arg(long, default_value_t = String::from("phylum"))]
+    pub rank: String,
+    /// Taxrule used to assign taxonomy in the BlobDir
+    #[arg(long, default_value_t = String::from("bestsumorder"))]
+    pub taxrule: String,
+    /// Category field to partition reads by (overrides --rank and --taxrule)
+    #[arg(long = "category")]
+    pub category: Option<String>,
+}
+
+/// Actions for records with no taxonomic hit
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NoHit {
+    Keep,
+    Remove,
+    #[default]
+    Review,
+}
+
+/// Options to pass to `blobtk screen`
+#[derive(Parser, Debug)]
+pub struct ScreenOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to YAML format rules file (values override command line options)
+    #[arg(long = "config", short = 'c')]
+    pub config_file: Option<PathBuf>,
+    /// Target taxa to keep [default: BlobDir taxon at --rank]
+    #[arg(long, short = 't', num_args(1..))]
+    pub target: Option<Vec<String>>,
+    /// Taxonomic rank to screen
+    #[arg(long, default_value_t = String::from("phylum"))]
+    pub rank: String,
+    /// Taxrule used to assign taxonomy in the BlobDir
+    #[arg(long, default_value_t = String::from("bestsumorder"))]
+    pub taxrule: String,
+    /// Category field to screen (overrides --rank and --taxrule)
+    #[arg(long = "category")]
+    pub category: Option<String>,
+    /// Coverage field [default: BlobDir plot y field]
+    #[arg(long = "cov-field")]
+    pub cov_field: Option<String>,
+    /// Minimum coverage to keep target records without review
+    #[arg(long = "min-coverage", default_value_t = 0.0)]
+    pub min_coverage: f64,
+    /// Minimum length to remove non-target records without review
+    #[arg(long = "min-length", default_value_t = 0)]
+    pub min_length: usize,
+    /// Action for records with no taxonomic hit
+    #[arg(long = "no-hit", value_enum, default_value_t = NoHit::Review)]
+    pub no_hit: NoHit,
+    /// Directory for keep/remove/review lists and summary report
+    #[arg(long, short = 'o', default_value = ".")]
+    pub output: PathBuf,
+}
+
+/// Per-sequence classification output formats
+#[derive(ValueEnum, Clone, Debug, Default)]
+pub enum ClassifierFormat {
+    #[default]
+    Kraken,
+    Centrifuge,
+}
+
+/// Options to pass to `blobtk classify`
+#[derive(Parser, Debug)]
+pub struct ClassifyOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to per-sequence classification file
+    #[arg(long, short = 'i')]
+    pub input: PathBuf,
+    /// Classification file format
+    #[arg(long, short = 'f', value_enum, default_value_t = ClassifierFormat::Kraken)]
+    pub format: ClassifierFormat,
+    /// Path to NCBI format taxdump directory used by the classifier
+    #[arg(long, short = 't')]
+    pub taxdump: PathBuf,
+    /// Ranks to store as category fields
+    #[arg(
+        long,
+        num_args(1..),
+        default_values_t = ["superkingdom", "phylum", "order", "family", "genus", "species"].map(String::from)
+    )]
+    pub ranks: Vec<String>,
+    /// Prefix for category field IDs [default: classifier format]
+    #[arg(long)]
+    pub prefix: Option<String>,
+}
+
+/// Options to pass to `blobtk curate`
+#[derive(Parser, Debug)]
+pub struct CurateOptions {
+    /// Path to assembly FASTA input file
+    #[arg(long, short = 'f')]
+    pub fasta: PathBuf,
+    /// Path to TSV curation file of `remove`, `trim` and `break` actions
+    #[arg(long, short = 'c')]
+    pub curation: PathBuf,
+    /// Path to curated FASTA output file
+    #[arg(long, short = 'o')]
+    pub output: PathBuf,
+    /// Path to AGP output file describing curated sequences
+    #[arg(long)]
+    pub agp: Option<PathBuf>,
+    /// Path to changelog output file [default: STDOUT]
+    #[arg(long)]
+    pub changelog: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk features`
+#[derive(Parser, Debug)]
+pub struct FeaturesOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to assembly FASTA input file
+    #[arg(long, short = 'f')]
+    pub fasta: PathBuf,
+    /// Telomeric repeat motif
+    #[arg(long, default_value_t = String::from("TTAGGG"))]
+    pub motif: String,
+    /// Minimum number of tandem motif copies in a telomeric repeat run
+    #[arg(long = "min-repeats", default_value_t = 10)]
+    pub min_repeats: usize,
+    /// Maximum distance from a sequence end for a telomere
+    #[arg(long = "end-distance", default_value_t = 1000)]
+    pub end_distance: usize,
+    /// Minimum length of a run of Ns to count as a gap
+    #[arg(long = "min-gap", default_value_t = 10)]
+    pub min_gap: usize,
+    /// Window size for windowed fields
+    #[arg(long = "window-size", short = 'w', default_value_t = 100000)]
+    pub window_size: usize,
+}
+
+/// Options to pass to `blobtk clean`
+#[derive(Parser, Debug)]
+pub struct CleanOptions {
+    /// Also remove cached data
+    #[arg(long)]
+    pub cache: bool,
+    /// Remove all temporary files, including those of runs that may still
+    /// be active
+    #[arg(long)]
+    pub all: bool,
+    /// List files that would be removed without removing them
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+/// Options to pass to `blobtk purge`
+#[derive(Parser, Debug)]
+pub struct PurgeOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to minimap2 PAF self-alignment of the assembly
+    #[arg(long, short = 'p')]
+    pub paf: Option<PathBuf>,
+    /// Coverage field to compare to the median coverage
+    #[arg(long = "cov-field")]
+    pub cov_field: Option<String>,
+    /// Minimum fraction of a record aligned to longer records
+    #[arg(long = "min-aligned", default_value_t = 0.8)]
+    pub min_aligned: f64,
+    /// Maximum ratio of record coverage to median coverage
+    #[arg(long = "max-cov-ratio", default_value_t = 0.75)]
+    pub max_cov_ratio: f64,
+    /// Path to purge candidate list output file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk example`
+#[derive(Parser, Debug)]
+pub struct ExampleOptions {
+    /// Directory for the example dataset
+    #[arg(long, short = 'o', default_value = "example")]
+    pub output: PathBuf,
+    /// Number of assembly records
+    #[arg(long, short = 'n', default_value_t = 60)]
+    pub records: usize,
+    /// Fraction of records from contaminants
+    #[arg(long, default_value_t = 0.2)]
+    pub contamination: f64,
+    /// Length of simulated reads
+    #[arg(long = "read-length", default_value_t = 100)]
+    pub read_length: usize,
+}
+
+/// Options to pass to `blobtk fcs`
+#[derive(Parser, Debug)]
+pub struct FcsOptions {
+    /// Path to BlobDir directory
+    #[arg(long, short = 'd')]
+    pub blobdir: PathBuf,
+    /// Path to FCS-GX action report
+    #[arg(long = "gx-report", short = 'g')]
+    pub gx_report: Option<PathBuf>,
+    /// Path to FCS-adaptor report
+    #[arg(long = "adaptor-report", short = 'a')]
+    pub adaptor_report: Option<PathBuf>,
+    /// Path to `blobtk screen` TSV report to combine with FCS actions
+    #[arg(long = "screen", short = 's')]
+    pub screen: Option<PathBuf>,
+    /// Path to unified decontamination report
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
 }
 
 #[derive(ValueEnum, Clone, Debug, Default)]
@@ -270,6 +755,13 @@ pub struct PlotOptions {
     /// Individual colours to modify palette (<index>=<hexcode>)
     #[arg(long)]
     pub color: Option<Vec<String>>,
+    /// Directory to cache processed plot data between runs
+    /// [default with no value: <workspace>/cache/plot]
+    #[arg(long = "cache-dir", num_args(0..=1), default_missing_value = "")]
+    pub cache_dir: Option<PathBuf>,
+    /// Cache parsed and sorted fields in the BlobDir for later runs
+    #[arg(long = "field-cache")]
+    pub field_cache: bool,
 }
 
 /// Valid taxonomy formats
@@ -278,12 +770,337 @@ pub struct PlotOptions {
 pub enum TaxonomyFormat {
     NCBI,
     GBIF,
+    ITIS,
+    #[clap(name = "coldp")]
+    ColDP,
+    GTDB,
+    SILVA,
+    #[clap(name = "worms")]
+    WoRMS,
+    IRMNG,
+    #[clap(name = "dwca")]
+    DwCA,
+    Datasets,
+    ENA,
+    BOLD,
+    Lineage,
+}
+
+#[derive(ValueEnum, Parser, Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxonomyOutputFormat {
+    /// NCBI taxdump nodes.dmp and names.dmp files
+    NCBI,
+    /// JSON Lines nodes.jsonl file with names and lineages
+    #[clap(name = "jsonl")]
+    JSONL,
+    /// SQLite taxonomy.sqlite database with nodes, names, xrefs and a
+    /// lineage closure table
+    #[clap(name = "sqlite")]
+    SQLite,
+    /// DOT nodes.dot graph of the subtree with rank and name attributes
+    #[clap(name = "dot")]
+    DOT,
+    /// GraphML nodes.graphml graph of the subtree with rank and name
+    /// attributes
+    #[clap(name = "graphml")]
+    GraphML,
+}
+
+/// Compression for taxdump output files
+#[derive(ValueEnum, Parser, Serialize, Deserialize, Clone, Debug)]
+pub enum TaxdumpCompression {
+    /// Gzipped nodes.dmp.gz and names.dmp.gz files
+    #[clap(name = "gz")]
+    #[serde(rename = "gz")]
+    Gzip,
+    /// taxdump.tar.gz archive containing nodes.dmp and names.dmp
+    #[clap(name = "tar.gz")]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+/// Scheme for taxon IDs of taxa added to the backbone
+#[derive(ValueEnum, Parser, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MintScheme {
+    /// Source taxon ID prefixed with the xref label (<label>:<id>)
+    #[default]
+    Xref,
+    /// Consecutive integers from --mint-start, or after the largest
+    /// integer taxon ID in use
+    Numeric,
+    /// Consecutive integers from --mint-start (default 1) after
+    /// --mint-prefix
+    Prefix,
+    /// Integer from a checksum of the xref label and source taxon ID
+    Hash,
+}
+
+/// Edit distance used to find fuzzy name matches
+#[derive(ValueEnum, Parser, Serialize, Deserialize, Clone, Debug, Default)]
+pub enum FuzzyAlgorithm {
+    /// Insertions, deletions and substitutions
+    #[default]
+    #[clap(name = "levenshtein")]
+    #[serde(rename = "levenshtein")]
+    Levenshtein,
+    /// Also count transpositions of adjacent characters as one edit
+    #[clap(name = "damerau-levenshtein")]
+    #[serde(rename = "damerau-levenshtein")]
+    DamerauLevenshtein,
+}
+
+/// Options to pass to `blobtk taxonomy lca`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyLcaOptions {
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Path to a file with a list of taxon IDs per line, separated by
+    /// whitespace, commas or semicolons [default: STDIN]
+    #[arg(long, short = 'i')]
+    pub input: Option<PathBuf>,
+    /// Path to TSV output file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk taxonomy lookup`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyLookupOptions {
+    /// Taxon names or IDs to look up
+    pub queries: Vec<String>,
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Name classes to match names against if no scientific name matches
+    #[arg(long = "name-classes", num_args(1..), default_value = "synonym")]
+    pub name_classes: Vec<String>,
+    /// Match names respecting case rather than ignoring it
+    #[arg(long = "case-sensitive")]
+    pub case_sensitive: bool,
+    /// Path to a file with a taxon name or ID per line
+    /// [default: STDIN if no queries are given]
+    #[arg(long, short = 'i')]
+    pub input: Option<PathBuf>,
+    /// Path to TSV output file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk taxonomy serve`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyServeOptions {
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Name classes to match names against if no scientific name matches
+    #[arg(long = "name-classes", num_args(1..), default_value = "synonym")]
+    pub name_classes: Vec<String>,
+    /// Match names respecting case rather than ignoring it
+    #[arg(long = "case-sensitive")]
+    pub case_sensitive: bool,
+    /// Report names from fuzzy matches in their original case
+    #[arg(long = "preserve-case")]
+    pub preserve_case: bool,
+    /// Default edit distance for fuzzy matches, if a request does not set
+    /// max_distance
+    #[arg(long = "max-distance", default_value_t = 2)]
+    pub max_distance: usize,
+    /// Allow one edit per this many characters of the query, up to the
+    /// maximum distance
+    #[arg(long = "chars-per-edit")]
+    pub chars_per_edit: Option<usize>,
+    /// Edit distance algorithm for fuzzy matches
+    #[arg(long = "fuzzy-algorithm", default_value = "levenshtein")]
+    pub fuzzy_algorithm: FuzzyAlgorithm,
+    /// Fall back to names that sound alike if no fuzzy matches are found
+    #[arg(long)]
+    pub phonetic: bool,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+    /// Port to listen on
+    #[arg(long, short = 'p', default_value_t = 8080)]
+    pub port: u16,
+}
+
+/// Options to pass to `blobtk taxonomy annotate`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyAnnotateOptions {
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Header of the column with taxon IDs or names
+    #[arg(long, short = 'c', default_value = "tax_id")]
+    pub column: String,
+    /// Ranks to add lineage columns for
+    #[arg(
+        long,
+        num_args(1..),
+        default_values = ["superkingdom", "kingdom", "phylum", "class", "order", "family", "genus", "species"]
+    )]
+    pub ranks: Vec<String>,
+    /// Also add a taxon ID column for each rank
+    #[arg(long)]
+    pub ids: bool,
+    /// Check input columns named after ranks against the matched lineage,
+    /// adding status and conflicts columns
+    #[arg(long = "check-ranks")]
+    pub check_ranks: bool,
+    /// Ranks of input columns to use in turn, nearest first, to choose
+    /// between taxa that share a name
+    #[arg(
+        long = "higher-ranks",
+        num_args(1..),
+        default_values = ["genus", "family", "order", "class", "phylum", "kingdom"]
+    )]
+    pub higher_ranks: Vec<String>,
+    /// Name classes to match names against if no scientific name matches
+    #[arg(long = "name-classes", num_args(1..), default_value = "synonym")]
+    pub name_classes: Vec<String>,
+    /// Match names respecting case rather than ignoring it
+    #[arg(long = "case-sensitive")]
+    pub case_sensitive: bool,
+    /// Path to input TSV file with a header row [default: STDIN]
+    #[arg(long, short = 'i')]
+    pub input: Option<PathBuf>,
+    /// Path to TSV output file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+    /// Path to write rows that could not be annotated, with an error column
+    #[arg(long)]
+    pub rejects: Option<PathBuf>,
+    /// Path to write normalised input rows with assigned_taxon_id and
+    /// match_status columns
+    #[arg(long)]
+    pub processed: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk taxonomy fetch`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyFetchOptions {
+    /// Taxonomy to download (ncbi or gbif)
+    pub taxonomy_format: TaxonomyFormat,
+    /// Release date as YYYY-MM-DD [default: current]
+    #[arg(long)]
+    pub release: Option<String>,
+    /// Expected MD5 checksum of the downloaded file
+    /// [default: published checksum, if available]
+    #[arg(long)]
+    pub md5: Option<String>,
+    /// Directory to save releases to [default: <workspace>/cache/taxonomy]
+    #[arg(long = "cache-dir")]
+    pub cache_dir: Option<PathBuf>,
+    /// Download again even if the release is already cached
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Options to pass to `blobtk taxonomy lineage`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyLineageOptions {
+    /// Taxon ID or name
+    pub query: String,
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Name classes to match names against if no scientific name matches
+    #[arg(long = "name-classes", num_args(1..), default_value = "synonym")]
+    pub name_classes: Vec<String>,
+    /// Only include species, genus, family, order, class, phylum, kingdom
+    /// and superkingdom
+    #[arg(long)]
+    pub canonical: bool,
+    /// Print names as a single semicolon separated line
+    #[arg(long)]
+    pub string: bool,
+}
+
+/// Options to pass to `blobtk taxonomy validate`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyValidateOptions {
+    /// Path to taxonomy file/directory
+    #[arg(long = "taxdump", short = 't')]
+    pub path: PathBuf,
+    /// Format of the taxonomy
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Path to TSV report file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+/// Options to pass to `blobtk taxonomy diff`
+#[derive(Parser, Clone, Debug)]
+pub struct TaxonomyDiffOptions {
+    /// Path to old taxonomy file/directory
+    pub old: PathBuf,
+    /// Path to new taxonomy file/directory
+    pub new: PathBuf,
+    /// Format of both taxonomies
+    #[arg(long = "taxonomy-format", short = 'f', default_value = "ncbi")]
+    pub taxonomy_format: TaxonomyFormat,
+    /// Path to TSV output file [default: STDOUT]
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum TaxonomyCommand {
+    /// Report taxa added, removed, merged, re-ranked or renamed between two
+    /// taxonomies.
+    /// Called as `blobtk taxonomy diff`
+    Diff(TaxonomyDiffOptions),
+    /// Report the lowest common ancestor of each list of taxon IDs.
+    /// Called as `blobtk taxonomy lca`
+    Lca(TaxonomyLcaOptions),
+    /// Look up taxon names or IDs and report their rank and lineage.
+    /// Called as `blobtk taxonomy lookup`
+    Lookup(TaxonomyLookupOptions),
+    /// Serve taxon lookups over HTTP.
+    /// Called as `blobtk taxonomy serve`
+    Serve(TaxonomyServeOptions),
+    /// Append lineage columns to a TSV file with a column of taxon IDs or
+    /// names.
+    /// Called as `blobtk taxonomy annotate`
+    Annotate(TaxonomyAnnotateOptions),
+    /// Print the lineage of a taxon.
+    /// Called as `blobtk taxonomy lineage`
+    Lineage(TaxonomyLineageOptions),
+    /// Download a reference taxonomy release.
+    /// Called as `blobtk taxonomy fetch`
+    Fetch(TaxonomyFetchOptions),
+    /// Check a taxonomy for cycles, orphaned parents, duplicate taxon IDs,
+    /// missing scientific names and rank inversions.
+    /// Called as `blobtk taxonomy validate`
+    Validate(TaxonomyValidateOptions),
 }
 
 /// Options to pass to `blobtk taxonomy`
 #[derive(Default, Parser, Serialize, Deserialize, Clone, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 #[pyclass]
 pub struct TaxonomyOptions {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub cmd: Option<TaxonomyCommand>,
     /// Path to backbone taxonomy file/directory
     #[arg(long = "taxdump", short = 't')]
     pub path: Option<PathBuf>,
@@ -292,6 +1109,9 @@ pub struct TaxonomyOptions {
     /// Root taxon/taxa for filtered taxonomy
     #[arg(long = "root-id", short = 'r')]
     pub root_taxon_id: Option<Vec<String>>,
+    /// Root taxon/taxa for filtered taxonomy by scientific name
+    #[arg(long = "root")]
+    pub root_name: Option<Vec<String>>,
     /// Base taxon for filtered taxonomy lineages
     #[arg(long = "base-id", short = 'b')]
     pub base_taxon_id: Option<String>,
@@ -301,9 +1121,125 @@ pub struct TaxonomyOptions {
     /// Path to output filtered backbone taxonomy
     #[arg(long = "taxdump-out")]
     pub out: Option<PathBuf>,
+    /// Label to record in the metadata of generated taxdumps
+    #[arg(long)]
+    pub label: Option<String>,
+    /// Output format for filtered backbone taxonomy [default: ncbi]
+    #[arg(long = "output-format")]
+    pub output_format: Option<TaxonomyOutputFormat>,
+    /// Compress taxdump output files
+    #[arg(long = "compress")]
+    pub compress: Option<TaxdumpCompression>,
+    /// Write taxdump nodes sorted by taxon ID rather than in lineage order
+    #[arg(long = "sorted")]
+    #[serde(default)]
+    pub sorted: bool,
+    /// Also write a rankedlineage.dmp file with the filtered backbone taxonomy
+    #[arg(long = "ranked-lineage")]
+    #[serde(default)]
+    pub ranked_lineage: bool,
+    /// Path to write a TSV mapping taxa from each taxonomy in `taxonomies`
+    /// to the backbone
+    #[arg(long = "mapping-out")]
+    pub mapping_out: Option<PathBuf>,
+    /// Path to write node counts per rank and source, and lookup counts per
+    /// taxonomy, as JSON (`.json`) or TSV
+    #[arg(long = "stats")]
+    pub stats: Option<PathBuf>,
+    /// Scheme for taxon IDs of taxa added to the backbone [default: xref]
+    #[arg(long = "mint", value_enum)]
+    pub mint: Option<MintScheme>,
+    /// First taxon ID for the numeric and prefix schemes, or offset for the
+    /// hash scheme
+    #[arg(long = "mint-start")]
+    pub mint_start: Option<u64>,
+    /// Prefix for taxon IDs from the prefix scheme [default: new]
+    #[arg(long = "mint-prefix")]
+    pub mint_prefix: Option<String>,
+    /// Path to a TSV file of taxon IDs assigned to added taxa, read and
+    /// updated so IDs stay the same across runs
+    #[arg(long = "mint-map")]
+    pub mint_map: Option<PathBuf>,
+    /// Taxon IDs or scientific names to fetch from the ENA taxonomy API
+    /// (records are written to --taxdump with --taxonomy-format ena)
+    #[arg(long = "ena-query", num_args(1..))]
+    pub ena_query: Option<Vec<String>>,
+    /// Directory to cache ENA taxonomy API responses
+    /// [default with no value: <workspace>/cache/ena]
+    #[arg(long = "ena-cache", num_args(0..=1), default_missing_value = "")]
+    pub ena_cache: Option<PathBuf>,
+    /// Delay between ENA taxonomy API requests in milliseconds [default: 200]
+    #[arg(long = "ena-delay")]
+    pub ena_delay: Option<u64>,
     // /// Path to GBIF backbone taxonomy file (simple text)
     // #[arg(long = "gbif-backbone", short = 'g')]
     // pub gbif_backbone: Option<PathBuf>,
+    /// Field delimiter for tabular taxonomy files, e.g. `,` or `tab`
+    /// [default: format specific]
+    #[arg(long = "delimiter")]
+    pub delimiter: Option<String>,
+    /// Quote character for tabular taxonomy files, or `none` to disable
+    /// quoting [default: format specific]
+    #[arg(long = "quote")]
+    pub quote: Option<String>,
+    /// Skip lines starting with this character in tabular taxonomy files
+    #[arg(long = "comment")]
+    pub comment: Option<char>,
+    /// Allow rows with differing numbers of fields in tabular taxonomy files
+    /// [default: format specific]
+    #[arg(long = "ragged-rows")]
+    pub ragged_rows: Option<bool>,
+    /// Path to write rows that fail to parse instead of stopping
+    #[arg(long = "error-report")]
+    pub error_report: Option<PathBuf>,
+    /// Match missing headers ignoring case, whitespace, underscores and
+    /// hyphens
+    #[arg(long = "fuzzy-headers")]
+    #[serde(default)]
+    pub fuzzy_headers: bool,
+    /// Alternative names to try for each expected header
+    #[clap(skip)]
+    pub header_synonyms: Option<HashMap<String, Vec<String>>>,
+    /// Values to treat as missing in every column of tabular taxonomy files
+    #[arg(long = "null-values", num_args(1..))]
+    pub null_values: Option<Vec<String>>,
+    /// Values to treat as missing in named columns, replacing `null_values`
+    #[clap(skip)]
+    pub null_values_by_field: Option<HashMap<String, Vec<String>>>,
+    /// Values to fill in for missing values in named columns, with
+    /// `{column}` replaced by the value of another column
+    #[clap(skip)]
+    pub field_defaults: Option<HashMap<String, String>>,
+    /// Normalisation to apply to values in named columns
+    #[clap(skip)]
+    pub field_normalization: Option<HashMap<String, FieldNormalization>>,
+    /// Ranks to rename while parsing, e.g. `superkingdom: domain`
+    #[clap(skip)]
+    pub rank_aliases: Option<HashMap<String, String>>,
+    /// Ranks to roll up into their parent species, keeping their names as
+    /// synonyms (e.g. subspecies varietas forma)
+    #[arg(long = "roll-up", num_args(1..))]
+    pub roll_up: Option<Vec<String>>,
+    /// Ranks of taxa to match to the backbone, from lowest to highest
+    /// [default: subspecies species genus family]
+    #[arg(long = "ranks", num_args(1..))]
+    pub ranks: Option<Vec<String>>,
+    /// Ranks of ancestors to combine with names when matching taxa
+    /// [default: family order class phylum kingdom]
+    #[arg(long = "higher-ranks", num_args(1..))]
+    pub higher_ranks: Option<Vec<String>>,
+    /// Match names to the backbone respecting case rather than ignoring it
+    #[arg(long = "case-sensitive")]
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Memory budget for the name lookup table (e.g. 512M, 8G) before
+    /// switching to an on-disk index
+    #[arg(long = "max-memory", value_parser = memory_size_parser)]
+    pub max_memory: Option<usize>,
+    /// Directory to cache parsed taxonomies and lookup tables
+    /// [default with no value: <workspace>/cache/taxonomy]
+    #[arg(long = "cache-dir", num_args(0..=1), default_missing_value = "")]
+    pub cache_dir: Option<PathBuf>,
     /// Path to YAML format config file
     #[arg(long = "config", short = 'c')]
     pub config_file: Option<PathBuf>,
@@ -323,6 +1259,17 @@ fn default_name_classes() -> Vec<String> {
     vec!["scientific name".to_string()]
 }
 
+/// Options to pass to `blobtk upgrade`
+#[derive(Parser, Debug)]
+pub struct UpgradeOptions {
+    /// Path to BlobDir directory
+    #[arg(long = "blobdir", short = 'd')]
+    pub blobdir: PathBuf,
+    /// Output file name for upgraded meta.json [default: overwrite in place]
+    #[arg(long, short = 'o', value_name = "JSON")]
+    pub output: Option<PathBuf>,
+}
+
 /// Command line argument parser
 pub fn parse() -> Arguments {
     Arguments::parse()
@@ -0,0 +1,104 @@
+//!
+//! Invoked by calling:
+//! `blobtk consistency <args>`
+
+use anyhow;
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::error;
+
+pub use cli::ConsistencyOptions;
+
+/// Score each record by the fraction of windows with a hit that agree
+/// with the whole-record category.
+///
+/// Windows assigned to `no-hit` or without a category are ignored and
+/// records with no windowed hits are scored as `NaN`.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::consistency::taxonomic_consistency;
+/// let records = vec!["Chordata".to_string(), "Arthropoda".to_string()];
+/// let windows = vec![vec![Some(0), Some(0), Some(1), Some(2)], vec![Some(2)]];
+/// let keys = vec!["Chordata".to_string(), "Arthropoda".to_string(), "no-hit".to_string()];
+/// let scores = taxonomic_consistency(&records, &windows, &keys);
+/// assert_eq!(scores[0], 2.0 / 3.0);
+/// assert!(scores[1].is_nan());
+/// ```
+pub fn taxonomic_consistency(
+    records: &[String],
+    windows: &[Vec<Option<usize>>],
+    keys: &[String],
+) -> Vec<f64> {
+    records
+        .iter()
+        .zip(windows.iter())
+        .map(|(category, record_windows)| {
+            let hits: Vec<&String> = record_windows
+                .iter()
+                .filter_map(|window| window.and_then(|index| keys.get(index)))
+                .filter(|key| *key != "no-hit")
+                .collect();
+            if hits.is_empty() {
+                return f64::NAN;
+            }
+            let agree = hits.iter().filter(|key| **key == category).count();
+            agree as f64 / hits.len() as f64
+        })
+        .collect()
+}
+
+/// Execute the `consistency` subcommand from `blobtk`.
+/// Add a field to a BlobDir scoring how consistently windowed taxonomic
+/// assignments agree with the whole-record assignment.
+pub fn consistency(options: &cli::ConsistencyOptions) -> Result<(), anyhow::Error> {
+    let meta = blobdir::parse_blobdir(&options.blobdir)?;
+    let category_id =
+        blobdir::category_field_id(options.category.as_ref(), &options.taxrule, &options.rank);
+    let windows_id = match &options.windows {
+        Some(field_id) => field_id.clone(),
+        None => format!("{}_windows", category_id),
+    };
+    let field_id = match &options.field_id {
+        Some(field_id) => field_id.clone(),
+        None => format!("{}_consistency", category_id),
+    };
+    let field_list = meta.field_list.as_ref().unwrap();
+    for id in [&category_id, &windows_id] {
+        if !field_list.contains_key(id) {
+            return Err(error::Error::NotDefined(format!("field {}", id)).into());
+        }
+    }
+
+    let records: Vec<String> = blobdir::parse_field_cat(category_id.clone(), &options.blobdir)?
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let (windows, keys) = blobdir::parse_field_windows(windows_id, &options.blobdir)?;
+    let scores = taxonomic_consistency(&records, &windows, &keys);
+    let missing = scores.iter().filter(|score| score.is_nan()).count();
+    if missing > 0 {
+        eprintln!("{} records have no windowed hits", missing);
+    }
+    blobdir::write_field_float(&field_id, &scores, &options.blobdir)?;
+
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+    blobdir::add_field_meta(
+        &mut meta_value,
+        json!({
+            "id": field_id,
+            "name": format!("{} consistency", category_id),
+            "type": "variable",
+            "datatype": "float",
+            "scale": "scaleLinear",
+            "range": [0.0, 1.0],
+            "preload": false,
+        }),
+    );
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//!
+//! Invoked by calling:
+//! `blobtk curate <args>`
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+use needletail::parser::{write_fasta, LineEnding};
+
+use crate::cli;
+use crate::fasta::trim_seq_id;
+use crate::fastq::open_fastx;
+use crate::io;
+
+pub use cli::CurateOptions;
+
+/// Curation decisions for a single sequence.
+#[derive(Clone, Debug, Default)]
+pub struct Curation {
+    /// Remove the whole sequence
+    pub remove: bool,
+    /// 1-based inclusive ranges to trim
+    pub trims: Vec<(usize, usize)>,
+    /// 1-based positions to break after
+    pub breaks: Vec<usize>,
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let range = range.replace("..", "-");
+    let (start, end) = range.split_once(['-', ':'])?;
+    let (start, end) = (
+        start.trim().parse::<usize>().ok()?,
+        end.trim().parse::<usize>().ok()?,
+    );
+    Some((start.min(end), start.max(end)))
+}
+
+/// Parse a TSV curation file.
+///
+/// Each line has an action, a sequence ID and, for `trim` and `break`, a
+/// 1-based range (`start-end`) or position:
+///
+/// ```text
+/// remove  scaffold_12
+/// trim    scaffold_3  1-250
+/// break   scaffold_1  1500000
+/// ```
+pub fn parse_curation(path: &Path) -> Result<HashMap<Vec<u8>, Curation>, anyhow::Error> {
+    let mut curations: HashMap<Vec<u8>, Curation> = HashMap::new();
+    for (i, line) in io::read_lines(path)?.enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || anyhow::anyhow!("invalid curation on line {}: {}", i + 1, line);
+        if cols.len() < 2 {
+            return Err(invalid());
+        }
+        let curation = curations.entry(cols[1].as_bytes().to_vec()).or_default();
+        match (cols[0].to_lowercase().as_str(), cols.get(2)) {
+            ("remove", _) => curation.remove = true,
+            ("trim", Some(range)) => curation.trims.push(parse_range(range).ok_or_else(invalid)?),
+            ("break", Some(position)) => curation
+                .breaks
+                .push(position.parse::<usize>().map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(curations)
+}
+
+/// Split a sequence of length `length` into the 1-based inclusive segments
+/// left after trimming ranges and breaking after positions.
+///
+/// Trimming an internal range splits the sequence.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::curate::curate_segments;
+/// assert_eq!(curate_segments(100, &[], &[]), vec![(1, 100)]);
+/// assert_eq!(curate_segments(100, &[(1, 10)], &[50]), vec![(11, 50), (51, 100)]);
+/// assert_eq!(curate_segments(100, &[(41, 60)], &[]), vec![(1, 40), (61, 100)]);
+/// assert_eq!(curate_segments(100, &[(1, 100)], &[]), vec![]);
+/// ```
+pub fn curate_segments(
+    length: usize,
+    trims: &[(usize, usize)],
+    breaks: &[usize],
+) -> Vec<(usize, usize)> {
+    let mut keep = vec![true; length];
+    for (start, end) in trims {
+        for base in keep
+            .iter_mut()
+            .take((*end).min(length))
+            .skip(start.saturating_sub(1))
+        {
+            *base = false;
+        }
+    }
+    let mut segments = vec![];
+    let mut current: Option<usize> = None;
+    for (i, kept) in keep.iter().enumerate() {
+        let position = i + 1;
+        match (current, kept) {
+            (None, true) => current = Some(position),
+            (Some(start), false) => {
+                segments.push((start, position - 1));
+                current = None;
+            }
+            _ => (),
+        }
+        if let Some(start) = current {
+            if breaks.contains(&position) && position < length {
+                segments.push((start, position));
+                current = None;
+            }
+        }
+    }
+    if let Some(start) = current {
+        segments.push((start, length));
+    }
+    segments
+}
+
+/// Execute the `curate` subcommand from `blobtk`.
+pub fn curate(options: &cli::CurateOptions) -> Result<(), anyhow::Error> {
+    let curations = parse_curation(&options.curation)?;
+    let mut reader = match open_fastx(&Some(options.fasta.clone())) {
+        Some(reader) => reader,
+        None => return Ok(()),
+    };
+    let mut writer = io::get_writer(&Some(options.output.clone()));
+    let mut agp = options
+        .agp
+        .as_ref()
+        .map(|path| io::get_writer(&Some(path.clone())));
+    if let Some(agp) = agp.as_mut() {
+        writeln!(agp, "##agp-version\t2.1")?;
+    }
+    let mut changelog = io::get_writer(&options.changelog);
+    writeln!(changelog, "action\tsequence\tdetails")?;
+
+    let mut seen = 0;
+    while let Some(record) = reader.next() {
+        let seqrec = record.as_ref().expect("invalid record");
+        let seq_id = trim_seq_id(seqrec.id());
+        let name = String::from_utf8_lossy(&seq_id).to_string();
+        let seq = seqrec.seq();
+        let segments = match curations.get(&seq_id) {
+            Some(curation) if curation.remove => {
+                writeln!(changelog, "remove\t{}\tlength {}", name, seq.len())?;
+                vec![]
+            }
+            Some(curation) => {
+                for (start, end) in curation.trims.iter() {
+                    writeln!(changelog, "trim\t{}\t{}-{}", name, start, end)?;
+                }
+                for position in curation.breaks.iter() {
+                    writeln!(changelog, "break\t{}\t{}", name, position)?;
+                }
+                curate_segments(seq.len(), &curation.trims, &curation.breaks)
+            }
+            None => vec![(1, seq.len())],
+        };
+        if curations.contains_key(&seq_id) {
+            seen += 1;
+            if segments.is_empty() && !curations[&seq_id].remove {
+                writeln!(changelog, "remove\t{}\tno sequence left", name)?;
+            }
+        }
+        let renamed = segments.len() > 1;
+        for (i, (start, end)) in segments.iter().enumerate() {
+            let object = if renamed {
+                format!("{}_{}", name, i + 1)
+            } else {
+                name.clone()
+            };
+            if renamed {
+                writeln!(changelog, "rename\t{}\t{}:{}-{}", object, name, start, end)?;
+            }
+            write_fasta(
+                object.as_bytes(),
+                &seq[start - 1..*end],
+                &mut *writer,
+                LineEnding::Unix,
+            )?;
+            if let Some(agp) = agp.as_mut() {
+                writeln!(
+                    agp,
+                    "{}\t1\t{}\t1\tW\t{}\t{}\t{}\t+",
+                    object,
+                    end - start + 1,
+                    name,
+                    start,
+                    end
+                )?;
+            }
+        }
+    }
+    if seen < curations.len() {
+        eprintln!(
+            "{} sequences in the curation file were not found in the FASTA file",
+            curations.len() - seen
+        );
+    }
+    Ok(())
+}
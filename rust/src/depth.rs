@@ -11,9 +11,14 @@ use crate::io;
 pub use bam::BinnedCov;
 pub use cli::DepthOptions;
 
-/// Execute the `depth` subcommand from `blobtk`. Generate a BED file.
+/// Execute the `depth` subcommand from `blobtk`. Generate a BED file,
+/// or a depth matrix when multiple BAM files are provided.
 pub fn depth(options: &cli::DepthOptions) -> Result<(), anyhow::Error> {
     let seq_names = io::get_list(&options.list_file);
+    if let Some(bams) = &options.bams {
+        bam::get_depth_matrix(bams, &seq_names, options, &None as &Option<Box<dyn Fn()>>)?;
+        return Ok(());
+    }
     let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, true);
     bam::get_bed_file(bam, &seq_names, options, &None as &Option<Box<dyn Fn()>>);
     Ok(())
@@ -13,6 +13,14 @@ pub enum Error {
     InvalidImageSuffix(String),
     #[error("Unable to process JSON: {0}")]
     SerdeError(String),
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("Taxon not found: {0}")]
+    TaxonNotFound(String),
+    #[error("Ambiguous taxon name: {0}")]
+    AmbiguousTaxon(String),
+    #[error("Invalid taxonomy: {0}")]
+    InvalidTaxonomy(String),
 }
 
 impl From<std::io::Error> for Error {
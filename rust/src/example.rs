@@ -0,0 +1,501 @@
+//!
+//! Invoked by calling:
+//! `blobtk example <args>`
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+use rust_htslib::bam::{self, header::HeaderRecord, index, record::Cigar, record::CigarString};
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::io;
+use crate::utils;
+
+pub use cli::ExampleOptions;
+
+/// An organism contributing records to the example assembly.
+struct Origin {
+    /// Key in the `example_origin` field, which records the true source of
+    /// each record
+    key: &'static str,
+    species: &'static str,
+    taxid: &'static str,
+    phylum: &'static str,
+    superkingdom: &'static str,
+    gc: f64,
+    coverage: f64,
+    /// Median record length
+    length: f64,
+}
+
+const TARGET: Origin = Origin {
+    key: "target",
+    species: "Canis lupus",
+    taxid: "9612",
+    phylum: "Chordata",
+    superkingdom: "Eukaryota",
+    gc: 0.41,
+    coverage: 30.0,
+    length: 8000.0,
+};
+
+/// Contaminants, with the share of contaminant records from each.
+const CONTAMINANTS: [(Origin, f64); 2] = [
+    (
+        Origin {
+            key: "bacterial",
+            species: "Cutibacterium acnes",
+            taxid: "1747",
+            phylum: "Actinomycetota",
+            superkingdom: "Bacteria",
+            gc: 0.6,
+            coverage: 120.0,
+            length: 3000.0,
+        },
+        0.6,
+    ),
+    (
+        Origin {
+            key: "fungal",
+            species: "Saccharomyces cerevisiae",
+            taxid: "4932",
+            phylum: "Ascomycota",
+            superkingdom: "Eukaryota",
+            gc: 0.38,
+            coverage: 6.0,
+            length: 2500.0,
+        },
+        0.4,
+    ),
+];
+
+/// Fraction of target records without a similarity search hit.
+const NO_HIT: f64 = 0.1;
+
+const BUSCO_LINEAGE: &str = "eukaryota_odb10";
+const BUSCO_COUNT: usize = 255;
+
+/// Deterministic pseudo-random numbers (SplitMix64), so the same `--seed`
+/// always gives the same dataset.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in [0, 1).
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal value.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * self.uniform()).cos()
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.uniform() * n as f64) as usize
+    }
+}
+
+/// A simulated assembly record.
+struct Record<'a> {
+    id: String,
+    origin: &'a Origin,
+    seq: Vec<u8>,
+    /// Start positions of simulated reads
+    reads: Vec<i64>,
+    hit: bool,
+    busco: Vec<(String, usize)>,
+}
+
+impl Record<'_> {
+    fn gc(&self) -> f64 {
+        let gc = self
+            .seq
+            .iter()
+            .filter(|base| matches!(base, b'G' | b'C'))
+            .count();
+        let atgc = self.seq.len() - self.ncount();
+        match atgc {
+            0 => 0.0,
+            _ => (gc as f64 / atgc as f64 * 10000.0).round() / 10000.0,
+        }
+    }
+
+    fn ncount(&self) -> usize {
+        self.seq.iter().filter(|base| **base == b'N').count()
+    }
+
+    fn coverage(&self, read_length: usize) -> f64 {
+        let coverage = (self.reads.len() * read_length) as f64 / self.seq.len() as f64;
+        (coverage * 100.0).round() / 100.0
+    }
+}
+
+/// Simulate a record with the composition and coverage of an origin.
+fn simulate<'a>(origin: &'a Origin, read_length: usize, rng: &mut Rng) -> Record<'a> {
+    let length = (origin.length * (0.8 * rng.normal()).exp()).max(1000.0) as usize;
+    let gc = origin.gc + 0.015 * rng.normal();
+    let mut seq: Vec<u8> = (0..length)
+        .map(|_| match (rng.uniform() < gc, rng.uniform() < 0.5) {
+            (true, true) => b'G',
+            (true, false) => b'C',
+            (false, true) => b'A',
+            (false, false) => b'T',
+        })
+        .collect();
+    // scaffold gaps in some longer records
+    if length > 10000 && rng.uniform() < 0.3 {
+        let start = length / 4 + rng.below(length / 2);
+        seq[start..start + 100].fill(b'N');
+    }
+    let coverage = origin.coverage * (0.15 * rng.normal()).exp();
+    let read_count = (coverage * length as f64 / read_length as f64).round() as usize;
+    let mut reads: Vec<i64> = (0..read_count)
+        .map(|_| rng.below(length - read_length + 1) as i64)
+        .collect();
+    reads.sort_unstable();
+    Record {
+        id: String::new(),
+        origin,
+        seq,
+        reads,
+        hit: true,
+        busco: vec![],
+    }
+}
+
+fn write_fasta(records: &[Record], path: &Path) -> Result<(), anyhow::Error> {
+    let mut writer = io::get_writer(&Some(path.to_path_buf()));
+    for record in records {
+        writeln!(writer, ">{}", record.id)?;
+        for line in record.seq.chunks(80) {
+            writeln!(writer, "{}", String::from_utf8_lossy(line))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write coordinate sorted, CSI indexed alignments of the simulated reads.
+fn write_bam(records: &[Record], read_length: usize, path: &Path) -> Result<(), anyhow::Error> {
+    let mut header = bam::Header::new();
+    header.push_record(
+        HeaderRecord::new(b"HD")
+            .push_tag(b"VN", &"1.6")
+            .push_tag(b"SO", &"coordinate"),
+    );
+    for record in records {
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", &record.id)
+                .push_tag(b"LN", &record.seq.len()),
+        );
+    }
+    {
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam)?;
+        let cigar = CigarString(vec![Cigar::Match(read_length as u32)]);
+        let qual = vec![40; read_length];
+        for (tid, record) in records.iter().enumerate() {
+            for (i, pos) in record.reads.iter().enumerate() {
+                let start = *pos as usize;
+                let mut read = bam::Record::new();
+                read.set(
+                    format!("{}_{}", record.id, i + 1).as_bytes(),
+                    Some(&cigar),
+                    &record.seq[start..start + read_length],
+                    &qual,
+                );
+                read.set_tid(tid as i32);
+                read.set_pos(*pos);
+                read.set_mtid(-1);
+                read.set_mpos(-1);
+                read.set_mapq(60);
+                writer.write(&read)?;
+            }
+        }
+    }
+    index::build(path, None, index::Type::Csi(14), 1)?;
+    Ok(())
+}
+
+/// Write BLAST tabular hits (`-outfmt "6 qseqid staxids bitscore std"`)
+/// for records with a hit.
+fn write_hits(records: &[Record], path: &Path) -> Result<(), anyhow::Error> {
+    let mut writer = io::get_writer(&Some(path.to_path_buf()));
+    for record in records.iter().filter(|record| record.hit) {
+        let length = record.seq.len().min(2000);
+        let bitscore = (length as f64 * 1.8).round();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\tEX_{}.1\t98.5\t{}\t30\t0\t1\t{}\t1\t{}\t0.0\t{}",
+            record.id,
+            record.origin.taxid,
+            bitscore,
+            record.id,
+            record.origin.taxid,
+            length,
+            length,
+            length,
+            bitscore
+        )?;
+    }
+    Ok(())
+}
+
+fn write_blobdir(records: &[Record], read_length: usize, dir: &Path) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(dir)?;
+    let ids: Vec<String> = records.iter().map(|record| record.id.clone()).collect();
+    blobdir::write_field_array("identifiers", &ids, &[], dir)?;
+    let gc: Vec<f64> = records.iter().map(Record::gc).collect();
+    blobdir::write_field_float("gc", &gc, dir)?;
+    let lengths: Vec<Option<usize>> = records.iter().map(|r| Some(r.seq.len())).collect();
+    blobdir::write_field_int("length", &lengths, dir)?;
+    let ncounts: Vec<Option<usize>> = records.iter().map(|r| Some(r.ncount())).collect();
+    blobdir::write_field_int("ncount", &ncounts, dir)?;
+    let coverage: Vec<f64> = records.iter().map(|r| r.coverage(read_length)).collect();
+    blobdir::write_field_float("reads_cov", &coverage, dir)?;
+
+    let mut taxonomy_fields = vec![];
+    for rank in ["superkingdom", "phylum", "species"] {
+        let labels: Vec<String> = records
+            .iter()
+            .map(|record| match (record.hit, rank) {
+                (false, _) => "no-hit".to_string(),
+                (true, "superkingdom") => record.origin.superkingdom.to_string(),
+                (true, "phylum") => record.origin.phylum.to_string(),
+                (true, _) => record.origin.species.to_string(),
+            })
+            .collect();
+        let (values, keys) = blobdir::category_keys(&labels, &["no-hit"]);
+        let id = format!("bestsumorder_{}", rank);
+        blobdir::write_field_cat(&id, &values, &keys, dir)?;
+        taxonomy_fields.push(json!({
+            "id": id,
+            "name": id,
+            "preload": rank == "phylum",
+            "data": [{"id": "bestsumorder_positions", "type": "array", "datatype": "mixed"}],
+        }));
+    }
+    let positions: Vec<serde_json::Value> = records
+        .iter()
+        .map(|record| match record.hit {
+            true => json!([[record.origin.taxid, 1, record.seq.len().min(2000), 0]]),
+            false => json!([]),
+        })
+        .collect();
+    blobdir::write_field_array(
+        "bestsumorder_positions",
+        &positions,
+        &["taxid", "start", "end", "index"],
+        dir,
+    )?;
+    let labels: Vec<String> = records.iter().map(|r| r.origin.key.to_string()).collect();
+    let (values, keys) = blobdir::category_keys(&labels, &[TARGET.key]);
+    blobdir::write_field_cat("example_origin", &values, &keys, dir)?;
+    let busco: Vec<Vec<(String, usize)>> = records.iter().map(|r| r.busco.clone()).collect();
+    let busco_keys = ["Complete", "Duplicated", "Fragmented"].map(String::from);
+    let field = blobdir::Field {
+        values: busco,
+        keys: busco_keys.to_vec(),
+        category_slot: Some(1),
+        headers: Some(vec!["Busco id".to_string(), "Status".to_string()]),
+    };
+    let busco_id = format!("{}_busco", BUSCO_LINEAGE);
+    let mut writer = io::get_writer(&Some(dir.join(format!("{}.json", busco_id))));
+    serde_json::to_writer(&mut writer, &field)?;
+    drop(writer);
+
+    let range = |values: &[f64]| {
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        json!([min, max])
+    };
+    let lengths: Vec<f64> = records.iter().map(|r| r.seq.len() as f64).collect();
+    let ncounts: Vec<f64> = records.iter().map(|r| r.ncount() as f64).collect();
+    let meta = json!({
+        "id": "example",
+        "name": "example",
+        "assembly": {
+            "file": "assembly.fasta",
+            "level": "scaffold",
+            "scaffold-count": records.len(),
+            "span": lengths.iter().sum::<f64>() as usize,
+        },
+        "fields": [
+            {"id": "identifiers", "type": "identifier"},
+            {"id": "gc", "name": "GC", "type": "variable", "datatype": "float",
+                "scale": "scaleLinear", "preload": true, "range": range(&gc)},
+            {"id": "length", "name": "Length", "type": "variable", "datatype": "integer",
+                "scale": "scaleLog", "preload": true, "clamp": false, "range": range(&lengths)},
+            {"id": "ncount", "name": "N count", "type": "variable", "datatype": "integer",
+                "scale": "scaleLinear", "range": range(&ncounts)},
+            {"id": "coverage", "type": "variable", "datatype": "float", "scale": "scaleLog",
+                "children": [{"id": "reads_cov", "name": "reads_cov", "preload": true,
+                    "clamp": 0.01, "range": range(&coverage)}]},
+            {"id": "taxonomy", "type": "category", "datatype": "string",
+                "children": [{"id": "bestsumorder", "children": taxonomy_fields}]},
+            {"id": "example_origin", "name": "Example origin", "type": "category",
+                "datatype": "string"},
+            {"id": "busco", "type": "multiarray", "datatype": "mixed", "category_slot": 1,
+                "headers": ["Busco id", "Status"],
+                "children": [{"id": busco_id, "count": BUSCO_COUNT, "set": BUSCO_LINEAGE}]},
+        ],
+        "links": {},
+        "plot": {"x": "gc", "y": "reads_cov", "z": "length", "cat": "bestsumorder_phylum"},
+        "record_type": "scaffold",
+        "records": records.len(),
+        "taxon": {
+            "name": TARGET.species,
+            "taxid": TARGET.taxid,
+            "phylum": TARGET.phylum,
+            "superkingdom": TARGET.superkingdom,
+        },
+        "version": blobdir::META_VERSION,
+        "revision": 0,
+    });
+    blobdir::write_meta_value(&dir.to_path_buf(), &meta)?;
+    Ok(())
+}
+
+/// Execute the `example` subcommand from `blobtk`.
+pub fn example(options: &cli::ExampleOptions) -> Result<(), anyhow::Error> {
+    if !(0.0..1.0).contains(&options.contamination) {
+        anyhow::bail!("--contamination must be at least 0 and less than 1");
+    }
+    if options.read_length == 0 || options.read_length > 1000 {
+        anyhow::bail!("--read-length must be between 1 and 1000");
+    }
+    let mut rng = Rng(utils::seed());
+    let contaminant_count = (options.records as f64 * options.contamination).round() as usize;
+    let mut records = vec![];
+    for i in 0..options.records {
+        let origin = match i < contaminant_count {
+            true => {
+                let share = i as f64 / contaminant_count as f64;
+                match share < CONTAMINANTS[0].1 {
+                    true => &CONTAMINANTS[0].0,
+                    false => &CONTAMINANTS[1].0,
+                }
+            }
+            false => &TARGET,
+        };
+        let mut record = simulate(origin, options.read_length, &mut rng);
+        record.hit = origin.key != TARGET.key || rng.uniform() >= NO_HIT;
+        records.push(record);
+    }
+    // assemblies list records from longest to shortest
+    records.sort_by_key(|record| std::cmp::Reverse(record.seq.len()));
+    for (i, record) in records.iter_mut().enumerate() {
+        record.id = format!("scaffold_{}", i + 1);
+    }
+
+    // place most BUSCO genes on target records, roughly in proportion to
+    // length, with duplicates on fungal records
+    let target: Vec<usize> = (0..records.len())
+        .filter(|i| records[*i].origin.key == TARGET.key)
+        .collect();
+    let span: usize = target.iter().map(|i| records[*i].seq.len()).sum();
+    for gene in 0..BUSCO_COUNT {
+        let busco_id = format!("{}at2759", 10000 + gene * 37);
+        if target.is_empty() || rng.uniform() < 0.04 {
+            continue;
+        }
+        let mut offset = rng.below(span);
+        let index = target
+            .iter()
+            .find(|i| {
+                let length = records[**i].seq.len();
+                if offset < length {
+                    return true;
+                }
+                offset -= length;
+                false
+            })
+            .copied()
+            .unwrap_or(target[0]);
+        let status = match rng.uniform() < 0.05 {
+            true => 2,
+            false => 0,
+        };
+        records[index].busco.push((busco_id.clone(), status));
+        let fungal: Vec<usize> = (0..records.len())
+            .filter(|i| records[*i].origin.key == CONTAMINANTS[1].0.key)
+            .collect();
+        if !fungal.is_empty() && status == 0 && rng.uniform() < 0.1 {
+            records[index].busco.last_mut().unwrap().1 = 1;
+            let other = fungal[rng.below(fungal.len())];
+            records[other].busco.push((busco_id, 1));
+        }
+    }
+
+    fs::create_dir_all(&options.output)?;
+    write_fasta(&records, &options.output.join("assembly.fasta"))?;
+    write_bam(
+        &records,
+        options.read_length,
+        &options.output.join("reads.bam"),
+    )?;
+    write_hits(&records, &options.output.join("hits.tsv"))?;
+    write_blobdir(
+        &records,
+        options.read_length,
+        &options.output.join("blobdir"),
+    )?;
+    for origin in [&TARGET, &CONTAMINANTS[0].0, &CONTAMINANTS[1].0] {
+        let selected: Vec<&Record> = records
+            .iter()
+            .filter(|record| record.origin.key == origin.key)
+            .collect();
+        eprintln!(
+            "{}\t{} records\t{} bp",
+            origin.key,
+            selected.len(),
+            selected
+                .iter()
+                .map(|record| record.seq.len())
+                .sum::<usize>()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example() {
+        let dir = std::env::temp_dir().join("blobtk_test_example");
+        let _ = fs::remove_dir_all(&dir);
+        example(&cli::ExampleOptions {
+            output: dir.clone(),
+            records: 20,
+            contamination: 0.25,
+            read_length: 100,
+        })
+        .unwrap();
+        let blobdir = dir.join("blobdir");
+        let meta = blobdir::parse_blobdir(&blobdir).unwrap();
+        assert_eq!(meta.records, 20);
+        let origin = blobdir::parse_field_cat("example_origin".to_string(), &blobdir).unwrap();
+        let contaminants = origin.iter().filter(|(key, _)| *key != "target").count();
+        assert_eq!(contaminants, 5);
+        let lengths = blobdir::parse_field_int("length".to_string(), &blobdir).unwrap();
+        assert!(lengths.windows(2).all(|pair| pair[0] >= pair[1]));
+        for file in ["assembly.fasta", "reads.bam", "reads.bam.csi", "hits.tsv"] {
+            assert!(dir.join(file).exists());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
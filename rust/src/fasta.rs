@@ -1,16 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 extern crate needletail;
 use needletail::parser::{write_fasta, LineEnding};
-use needletail::FastxReader;
+use needletail::{FastxReader, Sequence};
 
 use crate::fastq::{open_fastx, suffix_file_name};
 use crate::io::get_writer;
-use crate::utils::styled_progress_bar;
+use crate::utils::{self, styled_progress_bar};
 
-fn trim_seq_id(input: &[u8]) -> Vec<u8> {
+pub(crate) fn trim_seq_id(input: &[u8]) -> Vec<u8> {
     input
         .iter()
         .copied()
@@ -44,6 +44,9 @@ fn subsample_fasta<F: Fn()>(
             Some(cb) => cb(),
             None => (),
         }
+        if utils::is_cancelled() {
+            break;
+        }
     }
     progress_bar.finish();
 }
@@ -70,3 +73,71 @@ pub fn subsample<F: Fn()>(
         subsample_fasta(seq_names, r, &mut *writer, callback);
     }
 }
+
+/// A region of a sequence to extract from a FASTA file.
+#[derive(Clone, Debug)]
+pub struct FastaRegion {
+    /// ID to use for the extracted sequence
+    pub id: String,
+    pub seq_name: Vec<u8>,
+    /// 1-based start position
+    pub start: usize,
+    /// 1-based inclusive end position
+    pub end: usize,
+    /// Extract the reverse complement
+    pub reverse: bool,
+}
+
+/// Write regions of sequences in a FASTA file, in the order they occur in
+/// the FASTA file.
+pub fn extract_regions<F: Fn()>(
+    regions: &[FastaRegion],
+    fasta_path: &Path,
+    writer: &mut dyn Write,
+    callback: &Option<F>,
+) {
+    let mut seq_regions: HashMap<&[u8], Vec<&FastaRegion>> = HashMap::new();
+    for region in regions {
+        seq_regions
+            .entry(&region.seq_name)
+            .or_default()
+            .push(region);
+    }
+    let total = regions.len();
+    let progress_bar = styled_progress_bar(total, "Extracting regions");
+
+    let mut reader = match open_fastx(&Some(fasta_path.to_path_buf())) {
+        Some(reader) => reader,
+        None => return,
+    };
+    while let Some(record) = reader.next() {
+        let seqrec = record.as_ref().expect("invalid record");
+        let seq_id: Vec<u8> = trim_seq_id(seqrec.id());
+        if let Some(wanted) = seq_regions.get(seq_id.as_slice()) {
+            let seq = seqrec.seq();
+            for region in wanted {
+                let start = region.start.max(1) - 1;
+                let end = region.end.min(seq.len());
+                if start >= end {
+                    continue;
+                }
+                let slice = &seq[start..end];
+                let region_seq = if region.reverse {
+                    slice.reverse_complement()
+                } else {
+                    slice.to_vec()
+                };
+                write_fasta(region.id.as_bytes(), &region_seq, writer, LineEnding::Unix)
+                    .expect("Unable to write FASTA");
+                progress_bar.inc(1);
+            }
+        }
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+}
@@ -1,13 +1,24 @@
-use std::collections::HashSet;
-use std::io::Write;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 extern crate needletail;
-use needletail::parser::{write_fastq, LineEnding};
-use needletail::{parse_fastx_file, FastxReader};
+use flate2::read::MultiGzDecoder;
+use needletail::errors::ParseError;
+use needletail::parser::{FastaReader, FastqReader, LineEnding};
+use needletail::{parse_fastx_file, parse_fastx_reader, FastxReader};
+use serde::Serialize;
 
 use crate::io::get_writer;
-use crate::utils::styled_progress_bar;
+use crate::utils::{self, compact_float, styled_progress_bar};
+
+/// Default size in bytes of the buffer used when reading FASTA/FASTQ files.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+const GZ_MAGIC: [u8; 2] = [0x1F, 0x8B];
 
 pub fn open_fastx(fastx_path: &Option<PathBuf>) -> Option<Box<dyn FastxReader>> {
     let reader = fastx_path
@@ -16,22 +27,70 @@ pub fn open_fastx(fastx_path: &Option<PathBuf>) -> Option<Box<dyn FastxReader>>
     reader
 }
 
-fn trim_read_id(input: &[u8]) -> Vec<u8> {
-    input
+/// Open a FASTA/FASTQ file with a parser buffer of `buffer_size` bytes.
+///
+/// Plain and gzipped files are parsed with a buffer of the requested size,
+/// other compression formats use the needletail default.
+pub fn open_fastx_with_capacity(
+    fastx_path: &Option<PathBuf>,
+    buffer_size: usize,
+) -> Option<Box<dyn FastxReader>> {
+    fastx_path
+        .as_ref()
+        .map(|path| fastx_reader_with_capacity(path, buffer_size).expect("valid path/file"))
+}
+
+fn fastx_reader_with_capacity(
+    path: &Path,
+    buffer_size: usize,
+) -> Result<Box<dyn FastxReader>, ParseError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 2];
+    let gzipped = file.read_exact(&mut magic).is_ok() && magic == GZ_MAGIC;
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader: Box<dyn Read + Send> = match gzipped {
+        true => Box::new(MultiGzDecoder::new(file)),
+        false => Box::new(file),
+    };
+    let mut first = [0; 1];
+    let reader = match reader.read(&mut first)? {
+        0 => None,
+        _ => Some(Cursor::new(first).chain(reader)),
+    };
+    // the parser buffer must hold at least 3 bytes
+    let capacity = buffer_size.max(3);
+    match (first[0], reader) {
+        (b'>', Some(reader)) => Ok(Box::new(FastaReader::with_capacity(reader, capacity))),
+        (b'@', Some(reader)) => Ok(Box::new(FastqReader::with_capacity(reader, capacity))),
+        // needletail reports empty or unknown files and handles other
+        // compression formats
+        _ => parse_fastx_reader(File::open(path)?),
+    }
+}
+
+/// Trim a read ID at the first space or `/` without copying.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::fastq::trim_read_id;
+/// assert_eq!(trim_read_id(b"read_1/1 extra"), b"read_1");
+/// assert_eq!(trim_read_id(b"read_2 extra"), b"read_2");
+/// ```
+pub fn trim_read_id(input: &[u8]) -> &[u8] {
+    let end = input
         .iter()
-        .copied()
-        .by_ref()
-        .take_while(|&x| x != b' ' && x != b'/')
-        .collect()
+        .position(|&x| x == b' ' || x == b'/')
+        .unwrap_or(input.len());
+    &input[..end]
 }
 
-fn subsample_paired<F: Fn()>(
+pub fn subsample_paired<F: Fn()>(
     read_names: &HashSet<Vec<u8>>,
     mut reader: Box<dyn FastxReader>,
     mut paired_reader: Box<dyn FastxReader>,
     writer: &mut dyn Write,
     paired_writer: &mut dyn Write,
-    read_suffix: &[Vec<u8>; 2],
     callback: &Option<F>,
 ) {
     let total = read_names.len();
@@ -41,52 +100,41 @@ fn subsample_paired<F: Fn()>(
         let seqrec = record.expect("invalid record");
         let paired_record = paired_reader.next().unwrap();
         let paired_seqrec = paired_record.expect("invalid paired record");
-        let mut seq_id: Vec<u8> = trim_read_id(seqrec.id());
-        let mut paired_id: Vec<u8> = trim_read_id(paired_seqrec.id());
+        let seq_id = trim_read_id(seqrec.id());
+        let paired_id = trim_read_id(paired_seqrec.id());
         if seq_id != paired_id {
             panic!(
                 "Fasta files not sorted consistently {:?} ne {:?}",
-                String::from_utf8(seq_id),
-                String::from_utf8(paired_id)
+                String::from_utf8_lossy(seq_id),
+                String::from_utf8_lossy(paired_id)
             );
         }
-        if read_names.contains(&seq_id) || read_names.contains(&paired_id) {
-            seq_id.extend(&read_suffix[0]);
-            write_fastq(
-                seqrec.id(),
-                &seqrec.seq(),
-                seqrec.qual(),
-                writer,
-                LineEnding::Unix,
-            )
-            .expect("Unable to write FASTQ");
-            paired_id.extend(&read_suffix[1]);
-            write_fastq(
-                paired_seqrec.id(),
-                &paired_seqrec.seq(),
-                paired_seqrec.qual(),
-                paired_writer,
-                LineEnding::Unix,
-            )
-            .expect("Unable to write FASTQ");
+        if read_names.contains(seq_id) {
+            seqrec
+                .write(writer, Some(LineEnding::Unix))
+                .expect("Unable to write FASTQ");
+            paired_seqrec
+                .write(paired_writer, Some(LineEnding::Unix))
+                .expect("Unable to write FASTQ");
             progress_bar.inc(1);
             if progress_bar.position() as usize == total {
                 break;
             }
         }
-        match callback {
-            Some(cb) => cb(),
-            None => (),
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
         }
     }
     progress_bar.finish();
 }
 
-fn subsample_single<F: Fn()>(
+pub fn subsample_single<F: Fn()>(
     read_names: &HashSet<Vec<u8>>,
     mut reader: Box<dyn FastxReader>,
     writer: &mut dyn Write,
-    read_suffix: &[Vec<u8>; 2],
     callback: &Option<F>,
 ) {
     let total = read_names.len();
@@ -94,17 +142,10 @@ fn subsample_single<F: Fn()>(
 
     while let Some(record) = reader.next() {
         let seqrec = record.as_ref().expect("invalid record");
-        let mut seq_id: Vec<u8> = trim_read_id(seqrec.id());
-        if read_names.contains(&seq_id) {
-            seq_id.extend(&read_suffix[0]);
-            write_fastq(
-                seqrec.id(),
-                &seqrec.seq(),
-                seqrec.qual(),
-                writer,
-                LineEnding::Unix,
-            )
-            .expect("Unable to write FASTQ");
+        if read_names.contains(trim_read_id(seqrec.id())) {
+            seqrec
+                .write(writer, Some(LineEnding::Unix))
+                .expect("Unable to write FASTQ");
             progress_bar.inc(1);
             if progress_bar.position() as usize == total {
                 break;
@@ -114,6 +155,9 @@ fn subsample_single<F: Fn()>(
             Some(cb) => cb(),
             None => (),
         }
+        if utils::is_cancelled() {
+            break;
+        }
     }
     progress_bar.finish();
 }
@@ -147,20 +191,14 @@ pub fn suffix_file_name(path: impl AsRef<Path>, suffix: &String) -> PathBuf {
     result
 }
 
-fn set_read_suffix(read_names: &HashSet<Vec<u8>>) -> [Vec<u8>; 2] {
-    let first_name = read_names.iter().next().unwrap();
-    if first_name.contains(&b'/') {
-        return [vec![] as Vec<u8>, vec![] as Vec<u8>];
-    }
-    [vec![b'/', b'1'], vec![b'/', b'2']]
-}
-
+#[allow(clippy::too_many_arguments)]
 pub fn subsample<F: Fn()>(
     read_names: &HashSet<Vec<u8>>,
     fastq_path_1: &Option<PathBuf>,
     fastq_path_2: &Option<PathBuf>,
     fastq_out: &bool,
     suffix: &String,
+    buffer_size: usize,
     callback: &Option<F>,
 ) {
     if fastq_path_1.is_none() {
@@ -169,9 +207,8 @@ pub fn subsample<F: Fn()>(
     if !fastq_out {
         return;
     }
-    let reader = open_fastx(fastq_path_1);
-    let paired_reader = open_fastx(fastq_path_2);
-    let read_suffix = set_read_suffix(read_names);
+    let reader = open_fastx_with_capacity(fastq_path_1, buffer_size);
+    let paired_reader = open_fastx_with_capacity(fastq_path_2, buffer_size);
     let out_path = suffix_file_name(fastq_path_1.as_ref().unwrap(), suffix);
     let mut writer = get_writer(&Some(out_path));
     if let Some(pr) = paired_reader {
@@ -183,10 +220,308 @@ pub fn subsample<F: Fn()>(
             pr,
             &mut *writer,
             &mut *paired_writer,
-            &read_suffix,
             callback,
         );
     } else if let Some(r) = reader {
-        subsample_single(read_names, r, &mut *writer, &read_suffix, callback);
+        subsample_single(read_names, r, &mut *writer, callback);
+    }
+}
+
+/// Write each read (or read pair) to the output file for its category.
+pub fn partition<F: Fn()>(
+    read_categories: &HashMap<Vec<u8>, usize>,
+    categories: &[String],
+    fastq_path_1: &PathBuf,
+    fastq_path_2: &Option<PathBuf>,
+    buffer_size: usize,
+    callback: &Option<F>,
+) {
+    let mut reader = open_fastx_with_capacity(&Some(fastq_path_1.clone()), buffer_size).unwrap();
+    let mut paired_reader = open_fastx_with_capacity(fastq_path_2, buffer_size);
+    let mut writers: Vec<Option<Box<dyn Write>>> = categories.iter().map(|_| None).collect();
+    let mut paired_writers: Vec<Option<Box<dyn Write>>> = categories.iter().map(|_| None).collect();
+    let progress_bar = styled_progress_bar(read_categories.len(), "Partitioning FASTQ");
+
+    while let Some(record) = reader.next() {
+        let seqrec = record.expect("invalid record");
+        let paired_record = paired_reader
+            .as_mut()
+            .map(|r| r.next().unwrap().expect("invalid paired record"));
+        let category = match read_categories.get(trim_read_id(seqrec.id())) {
+            Some(category) => *category,
+            None => continue,
+        };
+        let writer = writers[category].get_or_insert_with(|| {
+            get_writer(&Some(suffix_file_name(fastq_path_1, &categories[category])))
+        });
+        seqrec
+            .write(writer, Some(LineEnding::Unix))
+            .expect("Unable to write FASTQ");
+        if let Some(paired_seqrec) = paired_record {
+            let paired_writer = paired_writers[category].get_or_insert_with(|| {
+                get_writer(&Some(suffix_file_name(
+                    fastq_path_2.as_ref().unwrap(),
+                    &categories[category],
+                )))
+            });
+            paired_seqrec
+                .write(paired_writer, Some(LineEnding::Unix))
+                .expect("Unable to write FASTQ");
+        }
+        progress_bar.inc(1);
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+}
+
+/// Number of reads to track when estimating duplication.
+const DUPLICATION_SAMPLE_SIZE: usize = 100_000;
+
+/// Summary statistics for a FASTQ file.
+#[derive(Serialize, Debug, Default)]
+pub struct FastqStats {
+    pub read_count: usize,
+    pub base_count: usize,
+    pub min_length: usize,
+    pub max_length: usize,
+    #[serde(with = "compact_float")]
+    pub mean_length: f64,
+    #[serde(with = "compact_float")]
+    pub gc_percent: f64,
+    #[serde(with = "compact_float")]
+    pub mean_quality: f64,
+    #[serde(with = "compact_float")]
+    pub duplication_percent: f64,
+    #[serde(skip_serializing)]
+    quality_sums: Vec<u64>,
+    #[serde(skip_serializing)]
+    quality_counts: Vec<u64>,
+    #[serde(skip_serializing)]
+    gc_count: usize,
+    #[serde(skip_serializing)]
+    duplicates: usize,
+    #[serde(skip_serializing)]
+    seen: HashSet<u64>,
+    pub length_distribution: BTreeMap<usize, usize>,
+    pub gc_distribution: BTreeMap<usize, usize>,
+    pub quality_by_position: Vec<f64>,
+}
+
+impl FastqStats {
+    fn add_record(&mut self, seq: &[u8], qual: Option<&[u8]>) {
+        let length = seq.len();
+        self.read_count += 1;
+        self.base_count += length;
+        if self.read_count == 1 || length < self.min_length {
+            self.min_length = length;
+        }
+        self.max_length = self.max_length.max(length);
+        *self.length_distribution.entry(length).or_insert(0) += 1;
+
+        let gc = seq
+            .iter()
+            .filter(|b| matches!(b, b'G' | b'C' | b'g' | b'c'))
+            .count();
+        self.gc_count += gc;
+        if let Some(gc_bin) = (gc * 100 + length / 2).checked_div(length) {
+            *self.gc_distribution.entry(gc_bin).or_insert(0) += 1;
+        }
+
+        if let Some(qual) = qual {
+            if self.quality_sums.len() < qual.len() {
+                self.quality_sums.resize(qual.len(), 0);
+                self.quality_counts.resize(qual.len(), 0);
+            }
+            for (i, q) in qual.iter().enumerate() {
+                self.quality_sums[i] += q.saturating_sub(33) as u64;
+                self.quality_counts[i] += 1;
+            }
+        }
+
+        if self.read_count <= DUPLICATION_SAMPLE_SIZE {
+            let mut hasher = DefaultHasher::new();
+            seq.hash(&mut hasher);
+            if !self.seen.insert(hasher.finish()) {
+                self.duplicates += 1;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.read_count == 0 {
+            return;
+        }
+        self.mean_length = self.base_count as f64 / self.read_count as f64;
+        if self.base_count > 0 {
+            self.gc_percent = self.gc_count as f64 * 100.0 / self.base_count as f64;
+        }
+        self.quality_by_position = self
+            .quality_sums
+            .iter()
+            .zip(self.quality_counts.iter())
+            .map(|(sum, count)| (*sum as f64 / *count as f64 * 100.0).round() / 100.0)
+            .collect();
+        let quality_total: u64 = self.quality_sums.iter().sum();
+        let quality_count: u64 = self.quality_counts.iter().sum();
+        if quality_count > 0 {
+            self.mean_quality = quality_total as f64 / quality_count as f64;
+        }
+        let sampled = self.read_count.min(DUPLICATION_SAMPLE_SIZE);
+        self.duplication_percent = self.duplicates as f64 * 100.0 / sampled as f64;
+        self.seen.clear();
+    }
+}
+
+/// Summary statistics for a pair of FASTQ files, read in step.
+#[derive(Serialize, Debug, Default)]
+pub struct PairStats {
+    /// Records read from both files
+    pub pair_count: usize,
+    /// Records left over in the longer file
+    pub unpaired_count: usize,
+    /// Pairs whose read IDs differ
+    pub id_mismatch_count: usize,
+    pub pair_base_count: usize,
+    #[serde(with = "compact_float")]
+    pub mean_pair_length: f64,
+    /// Duplicate pairs, with identical forward and reverse sequences
+    #[serde(with = "compact_float")]
+    pub duplication_percent: f64,
+    #[serde(skip_serializing)]
+    duplicates: usize,
+    #[serde(skip_serializing)]
+    seen: HashSet<u64>,
+}
+
+impl PairStats {
+    fn add_pair(&mut self, id: &[u8], seq: &[u8], paired_id: &[u8], paired_seq: &[u8]) {
+        self.pair_count += 1;
+        self.pair_base_count += seq.len() + paired_seq.len();
+        if trim_read_id(id) != trim_read_id(paired_id) {
+            self.id_mismatch_count += 1;
+        }
+        if self.pair_count <= DUPLICATION_SAMPLE_SIZE {
+            let mut hasher = DefaultHasher::new();
+            seq.hash(&mut hasher);
+            paired_seq.hash(&mut hasher);
+            if !self.seen.insert(hasher.finish()) {
+                self.duplicates += 1;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.pair_count == 0 {
+            return;
+        }
+        self.mean_pair_length = self.pair_base_count as f64 / self.pair_count as f64;
+        let sampled = self.pair_count.min(DUPLICATION_SAMPLE_SIZE);
+        self.duplication_percent = self.duplicates as f64 * 100.0 / sampled as f64;
+        self.seen.clear();
+    }
+}
+
+/// Calculate summary statistics for each of a pair of (optionally gzipped)
+/// FASTQ files and for the read pairs, reading both files in step.
+pub fn fastq_pair_stats<F: Fn()>(
+    fastq_path: &PathBuf,
+    paired_path: &PathBuf,
+    callback: &Option<F>,
+) -> (FastqStats, FastqStats, PairStats) {
+    let mut stats = FastqStats::default();
+    let mut paired_stats = FastqStats::default();
+    let mut pair_stats = PairStats::default();
+    let mut reader = parse_fastx_file(fastq_path).expect("valid path/file");
+    let mut paired_reader = parse_fastx_file(paired_path).expect("valid path/file");
+    let progress_bar = styled_progress_bar(0, "Reading FASTQ pairs");
+    loop {
+        match (reader.next(), paired_reader.next()) {
+            (Some(record), Some(paired_record)) => {
+                let seqrec = record.expect("invalid record");
+                let paired_seqrec = paired_record.expect("invalid paired record");
+                stats.add_record(&seqrec.seq(), seqrec.qual());
+                paired_stats.add_record(&paired_seqrec.seq(), paired_seqrec.qual());
+                pair_stats.add_pair(
+                    seqrec.id(),
+                    &seqrec.seq(),
+                    paired_seqrec.id(),
+                    &paired_seqrec.seq(),
+                );
+            }
+            (Some(record), None) => {
+                let seqrec = record.expect("invalid record");
+                stats.add_record(&seqrec.seq(), seqrec.qual());
+                pair_stats.unpaired_count += 1;
+            }
+            (None, Some(paired_record)) => {
+                let paired_seqrec = paired_record.expect("invalid paired record");
+                paired_stats.add_record(&paired_seqrec.seq(), paired_seqrec.qual());
+                pair_stats.unpaired_count += 1;
+            }
+            (None, None) => break,
+        }
+        progress_bar.inc(1);
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    stats.finish();
+    paired_stats.finish();
+    pair_stats.finish();
+    (stats, paired_stats, pair_stats)
+}
+
+/// Calculate summary statistics for a (optionally gzipped) FASTQ file.
+pub fn fastq_stats<F: Fn()>(fastq_path: &PathBuf, callback: &Option<F>) -> FastqStats {
+    let mut stats = FastqStats::default();
+    let mut reader = parse_fastx_file(fastq_path).expect("valid path/file");
+    let progress_bar = styled_progress_bar(0, "Reading FASTQ");
+    while let Some(record) = reader.next() {
+        let seqrec = record.expect("invalid record");
+        stats.add_record(&seqrec.seq(), seqrec.qual());
+        progress_bar.inc(1);
+        if let Some(cb) = callback {
+            cb()
+        }
+        if utils::is_cancelled() {
+            break;
+        }
+    }
+    progress_bar.finish();
+    stats.finish();
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_ids(path: &str, buffer_size: usize) -> Vec<Vec<u8>> {
+        let mut reader = open_fastx_with_capacity(&Some(PathBuf::from(path)), buffer_size).unwrap();
+        let mut ids = vec![];
+        while let Some(record) = reader.next() {
+            ids.push(record.unwrap().id().to_vec());
+        }
+        ids
+    }
+
+    #[test]
+    fn test_open_fastx_with_capacity() {
+        // buffers smaller than a record grow as needed
+        for path in ["test/reads_1.fq.gz", "test/minimal.fa"] {
+            let ids = read_ids(path, DEFAULT_BUFFER_SIZE);
+            assert!(!ids.is_empty());
+            assert_eq!(read_ids(path, 0), ids);
+            assert_eq!(read_ids(path, 16), ids);
+        }
     }
 }
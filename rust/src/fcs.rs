@@ -0,0 +1,206 @@
+//!
+//! Invoked by calling:
+//! `blobtk fcs <args>`
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::error;
+use crate::io;
+
+pub use cli::FcsOptions;
+
+/// Severity of an FCS action, higher values take precedence.
+///
+/// Both FCS-GX actions (`EXCLUDE`, `TRIM`, `FIX`, `REVIEW`, `INFO`) and
+/// FCS-adaptor actions (`ACTION_EXCLUDE`, `ACTION_TRIM`) are recognised.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::fcs::action_severity;
+/// assert!(action_severity("EXCLUDE") > action_severity("ACTION_TRIM"));
+/// assert!(action_severity("REVIEW_RARE") > action_severity("INFO"));
+/// assert_eq!(action_severity("none"), 0);
+/// ```
+pub fn action_severity(action: &str) -> u8 {
+    match action.trim_start_matches("ACTION_") {
+        "EXCLUDE" => 4,
+        "TRIM" | "FIX" => 3,
+        "REVIEW" | "REVIEW_RARE" => 2,
+        "INFO" => 1,
+        _ => 0,
+    }
+}
+
+/// Parse an FCS report into the most severe action for each sequence.
+///
+/// Lines starting with `#` are treated as headers. Column indices are zero-based.
+fn parse_fcs_report(
+    path: &Path,
+    id_col: usize,
+    action_col: usize,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut actions: HashMap<String, String> = HashMap::new();
+    for line in io::read_lines(path)? {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let row: Vec<&str> = line.split('\t').collect();
+        if row.len() <= id_col.max(action_col) {
+            return Err(anyhow::anyhow!("unexpected FCS report line: {}", line));
+        }
+        let action = row[action_col].trim_start_matches("ACTION_").to_string();
+        let entry = actions.entry(row[id_col].to_string()).or_default();
+        if action_severity(&action) > action_severity(entry) {
+            *entry = action;
+        }
+    }
+    Ok(actions)
+}
+
+/// Parse a `blobtk screen` TSV report into an action for each sequence.
+fn parse_screen_report(path: &Path) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut lines = io::read_lines(path)?;
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Ok(HashMap::new()),
+    };
+    let columns: Vec<&str> = header.split('\t').collect();
+    let position = |name: &str| {
+        columns
+            .iter()
+            .position(|col| *col == name)
+            .ok_or_else(|| error::Error::NotDefined(format!("screen report column {}", name)))
+    };
+    let (id_col, action_col) = (position("identifier")?, position("action")?);
+    let mut actions = HashMap::new();
+    for line in lines {
+        let line = line?;
+        let row: Vec<&str> = line.split('\t').collect();
+        if row.len() > id_col.max(action_col) {
+            actions.insert(row[id_col].to_string(), row[action_col].to_string());
+        }
+    }
+    Ok(actions)
+}
+
+/// Combine FCS-GX, FCS-adaptor and `blobtk screen` actions into a single proposal.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::fcs::propose_action;
+/// assert_eq!(propose_action("EXCLUDE", "none", "keep"), "remove");
+/// assert_eq!(propose_action("none", "TRIM", "keep"), "trim");
+/// assert_eq!(propose_action("REVIEW", "none", "keep"), "review");
+/// assert_eq!(propose_action("none", "none", "remove"), "remove");
+/// assert_eq!(propose_action("INFO", "none", ""), "keep");
+/// ```
+pub fn propose_action(gx: &str, adaptor: &str, screen: &str) -> &'static str {
+    let severity = action_severity(gx).max(action_severity(adaptor));
+    if severity == 4 || screen == "remove" {
+        "remove"
+    } else if severity == 3 {
+        "trim"
+    } else if severity == 2 || screen == "review" {
+        "review"
+    } else {
+        "keep"
+    }
+}
+
+/// Write per-record actions as a category field and return the value for each record.
+fn write_action_field(
+    id: &str,
+    name: &str,
+    actions: &HashMap<String, String>,
+    identifiers: &[String],
+    meta_value: &mut serde_json::Value,
+    blobdir: &Path,
+) -> Result<Vec<String>, anyhow::Error> {
+    let record_actions: Vec<String> = identifiers
+        .iter()
+        .map(|identifier| match actions.get(identifier) {
+            Some(action) if !action.is_empty() => action.clone(),
+            _ => "none".to_string(),
+        })
+        .collect();
+    let (values, keys) = blobdir::category_keys(&record_actions, &["none"]);
+    blobdir::write_field_cat(id, &values, &keys, blobdir)?;
+    blobdir::add_field_meta(
+        meta_value,
+        json!({
+            "id": id,
+            "name": name,
+            "type": "category",
+            "datatype": "string",
+            "preload": false,
+        }),
+    );
+    Ok(record_actions)
+}
+
+/// Import FCS report actions into a BlobDir and write a unified decontamination report.
+pub fn fcs(options: &cli::FcsOptions) -> Result<(), anyhow::Error> {
+    if options.gx_report.is_none() && options.adaptor_report.is_none() {
+        return Err(error::Error::NotDefined("--gx-report or --adaptor-report".to_string()).into());
+    }
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+
+    let none = vec!["none".to_string(); identifiers.len()];
+    let gx = match &options.gx_report {
+        Some(path) => write_action_field(
+            "fcs_gx_action",
+            "FCS-GX action",
+            &parse_fcs_report(path, 0, 4)?,
+            &identifiers,
+            &mut meta_value,
+            &options.blobdir,
+        )?,
+        None => none.clone(),
+    };
+    let adaptor = match &options.adaptor_report {
+        Some(path) => write_action_field(
+            "fcs_adaptor_action",
+            "FCS-adaptor action",
+            &parse_fcs_report(path, 0, 2)?,
+            &identifiers,
+            &mut meta_value,
+            &options.blobdir,
+        )?,
+        None => none,
+    };
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+
+    let screen = match &options.screen {
+        Some(path) => parse_screen_report(path)?,
+        None => HashMap::new(),
+    };
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "identifier\tfcs_gx\tfcs_adaptor\tscreen\tproposal")?;
+    for (i, identifier) in identifiers.iter().enumerate() {
+        let screen_action = screen.get(identifier).map_or("", |action| action.as_str());
+        let proposal = propose_action(&gx[i], &adaptor[i], screen_action);
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            identifier, gx[i], adaptor[i], screen_action, proposal
+        )?;
+        *counts.entry(proposal).or_default() += 1;
+    }
+    for (proposal, count) in counts {
+        eprintln!("{}: {} records", proposal, count);
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+//!
+//! Invoked by calling:
+//! `blobtk features <args>`
+
+use std::collections::HashMap;
+
+use anyhow;
+use needletail::Sequence;
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::fasta::trim_seq_id;
+use crate::fastq::open_fastx;
+use crate::utils::styled_progress_bar;
+
+pub use cli::FeaturesOptions;
+
+/// Find runs of at least `min_repeats` tandem copies of a motif, returning
+/// 0-based half-open intervals. Matching is case-insensitive.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::features::find_repeat_runs;
+/// let seq = b"ACGTttagggTTAGGGTTAGGGACGT";
+/// assert_eq!(find_repeat_runs(seq, b"TTAGGG", 3), vec![(4, 22)]);
+/// assert_eq!(find_repeat_runs(seq, b"TTAGGG", 4), vec![]);
+/// ```
+pub fn find_repeat_runs(seq: &[u8], motif: &[u8], min_repeats: usize) -> Vec<(usize, usize)> {
+    let matches_at = |i: usize| {
+        i + motif.len() <= seq.len() && seq[i..i + motif.len()].eq_ignore_ascii_case(motif)
+    };
+    let mut runs = vec![];
+    let mut i = 0;
+    while i < seq.len() {
+        if !matches_at(i) {
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        let mut copies = 0;
+        while matches_at(end) {
+            end += motif.len();
+            copies += 1;
+        }
+        if copies >= min_repeats.max(1) {
+            runs.push((i, end));
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// Find runs of at least `min_gap` Ns, returning 0-based half-open intervals.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::features::find_gaps;
+/// assert_eq!(find_gaps(b"ACNNNNGTNA", 2), vec![(2, 6)]);
+/// ```
+pub fn find_gaps(seq: &[u8], min_gap: usize) -> Vec<(usize, usize)> {
+    let mut gaps = vec![];
+    let mut start = None;
+    for (i, base) in seq.iter().chain([b'A'].iter()).enumerate() {
+        match (start, *base == b'N' || *base == b'n') {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                if i - s >= min_gap.max(1) {
+                    gaps.push((s, i));
+                }
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    gaps
+}
+
+/// Count the bases covered by intervals in each window of a sequence,
+/// returning `[start, end, bases]` for each window with 0-based half-open
+/// coordinates.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::features::window_coverage;
+/// let windows = window_coverage(&[(5, 15)], 25, 10);
+/// assert_eq!(windows, vec![[0, 10, 5], [10, 20, 5], [20, 25, 0]]);
+/// ```
+pub fn window_coverage(
+    intervals: &[(usize, usize)],
+    length: usize,
+    window_size: usize,
+) -> Vec<[usize; 3]> {
+    let window_size = window_size.max(1);
+    (0..length)
+        .step_by(window_size)
+        .map(|start| {
+            let end = (start + window_size).min(length);
+            let bases = intervals
+                .iter()
+                .map(|(s, e)| e.min(&end).saturating_sub(*s.max(&start)))
+                .sum();
+            [start, end, bases]
+        })
+        .collect()
+}
+
+/// Execute the `features` subcommand from `blobtk`.
+pub fn features(options: &cli::FeaturesOptions) -> Result<(), anyhow::Error> {
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let positions: HashMap<&[u8], usize> = identifiers
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_bytes(), i))
+        .collect();
+    let motif = options.motif.as_bytes();
+    let motif_rc = motif.reverse_complement();
+
+    let mut telomere_ends = vec![None; identifiers.len()];
+    let mut gap_counts = vec![None; identifiers.len()];
+    let mut telomere_windows = vec![vec![]; identifiers.len()];
+    let mut gap_windows = vec![vec![]; identifiers.len()];
+    let progress_bar = styled_progress_bar(identifiers.len(), "Finding features");
+    if let Some(mut reader) = open_fastx(&Some(options.fasta.clone())) {
+        while let Some(record) = reader.next() {
+            let seqrec = record.as_ref().expect("invalid record");
+            let seq_id = trim_seq_id(seqrec.id());
+            let position = match positions.get(seq_id.as_slice()) {
+                Some(position) => *position,
+                None => continue,
+            };
+            let seq = seqrec.seq();
+            let mut telomeres = find_repeat_runs(&seq, motif, options.min_repeats);
+            if motif_rc != motif {
+                telomeres.extend(find_repeat_runs(&seq, &motif_rc, options.min_repeats));
+                telomeres.sort();
+            }
+            let gaps = find_gaps(&seq, options.min_gap);
+            let at_start = telomeres.iter().any(|(s, _)| *s <= options.end_distance);
+            let at_end = telomeres
+                .iter()
+                .any(|(_, e)| e + options.end_distance >= seq.len());
+            telomere_ends[position] = Some(at_start as usize + at_end as usize);
+            gap_counts[position] = Some(gaps.len());
+            telomere_windows[position] =
+                window_coverage(&telomeres, seq.len(), options.window_size);
+            gap_windows[position] = window_coverage(&gaps, seq.len(), options.window_size);
+            progress_bar.inc(1);
+        }
+    }
+    progress_bar.finish();
+
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+    let headers = ["start", "end", "bases"];
+    for (field_id, name, values, windows, max) in [
+        (
+            "telomere_ends",
+            "Telomeric ends",
+            &telomere_ends,
+            &telomere_windows,
+            2,
+        ),
+        (
+            "gap_count",
+            "Gap count",
+            &gap_counts,
+            &gap_windows,
+            gap_counts.iter().flatten().copied().max().unwrap_or(0),
+        ),
+    ] {
+        let windows_id = format!("{}_windows", field_id);
+        blobdir::write_field_int(field_id, values, &options.blobdir)?;
+        blobdir::write_field_array(&windows_id, windows, &headers, &options.blobdir)?;
+        blobdir::add_field_meta(
+            &mut meta_value,
+            json!({
+                "id": field_id,
+                "name": name,
+                "type": "variable",
+                "datatype": "integer",
+                "scale": "scaleLinear",
+                "range": [0, max],
+                "preload": false,
+                "data": [{
+                    "id": windows_id,
+                    "type": "array",
+                    "datatype": "mixed",
+                    "headers": headers,
+                    "window_size": options.window_size,
+                }],
+            }),
+        );
+    }
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+    let with_telomeres = telomere_ends.iter().flatten().filter(|ends| **ends > 0);
+    eprintln!(
+        "{} records with telomeric ends, {} gaps",
+        with_telomeres.count(),
+        gap_counts.iter().flatten().sum::<usize>()
+    );
+    Ok(())
+}
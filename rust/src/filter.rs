@@ -14,10 +14,12 @@ pub use cli::FilterOptions;
 
 /// Execute the `filter` subcommand from `blobtk`.
 /// Pass a list of sequence names and a BAM file to generate
-/// a list of read names and filtered FASTA/FASTQ files.
+/// a list of read names and filtered FASTA/FASTQ/alignment files.
+/// Reads can optionally be restricted to those overlapping a set of regions
+/// and/or extended to include all unmapped reads.
 pub fn filter(options: &cli::FilterOptions) -> Result<(), anyhow::Error> {
     let seq_names = io::get_list(&options.list_file);
-    if seq_names.is_empty() {
+    if seq_names.is_empty() && !options.unmapped {
         return Ok(());
     }
     fasta::subsample(
@@ -31,15 +33,43 @@ pub fn filter(options: &cli::FilterOptions) -> Result<(), anyhow::Error> {
         return Ok(());
     }
     let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, true);
-    let read_names = bam::reads_from_bam(&seq_names, bam, &None as &Option<Box<dyn Fn()>>);
+    let regions = bam::get_regions(&options.region, &options.bed);
+    // without regions, unmapped reads and reads on the listed sequences are
+    // found in the same pass
+    let read_names = match (regions.is_empty(), options.unmapped) {
+        (true, true) => {
+            bam::unmapped_reads_from_bam(&seq_names, bam, true, &None as &Option<Box<dyn Fn()>>)
+        }
+        (true, false) => bam::reads_from_bam(&seq_names, bam, &None as &Option<Box<dyn Fn()>>),
+        (false, _) => {
+            let mut read_names = bam::reads_from_bam_regions(
+                &seq_names,
+                &regions,
+                bam,
+                &None as &Option<Box<dyn Fn()>>,
+            );
+            if options.unmapped {
+                let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, false);
+                read_names.extend(bam::unmapped_reads_from_bam(
+                    &seq_names,
+                    bam,
+                    false,
+                    &None as &Option<Box<dyn Fn()>>,
+                ));
+            }
+            read_names
+        }
+    };
     fastq::subsample(
         &read_names,
         &options.fastq1,
         &options.fastq2,
         &options.fastq_out,
         &options.suffix,
+        options.buffer_size,
         &None as &Option<Box<dyn Fn()>>,
     );
+    bam::subsample(&read_names, options, &None as &Option<Box<dyn Fn()>>)?;
     match io::write_list(&read_names, &options.read_list) {
         Err(err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
         Err(err) => panic!("unable to write read list file: {}", err),
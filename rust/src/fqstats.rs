@@ -0,0 +1,155 @@
+//!
+//! Invoked by calling:
+//! `blobtk fqstats <args>`
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cli;
+use crate::fastq::{self, FastqStats, PairStats};
+use crate::io;
+use crate::utils::{self, compact_float};
+
+pub use cli::FqStatsOptions;
+
+/// Summary values for the MultiQC table.
+#[derive(Serialize, Debug)]
+struct SummaryRow {
+    read_count: usize,
+    base_count: usize,
+    #[serde(with = "compact_float")]
+    mean_length: f64,
+    #[serde(with = "compact_float")]
+    gc_percent: f64,
+    #[serde(with = "compact_float")]
+    mean_quality: f64,
+    #[serde(with = "compact_float")]
+    duplication_percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_pairs: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unpaired_reads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pair_id_mismatches: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pair_duplication_percent: Option<f64>,
+}
+
+impl SummaryRow {
+    fn from_stats(stats: &FastqStats, pairs: Option<&PairStats>) -> SummaryRow {
+        SummaryRow {
+            read_count: stats.read_count,
+            base_count: stats.base_count,
+            mean_length: stats.mean_length,
+            gc_percent: stats.gc_percent,
+            mean_quality: stats.mean_quality,
+            duplication_percent: stats.duplication_percent,
+            read_pairs: pairs.map(|p| p.pair_count),
+            unpaired_reads: pairs.map(|p| p.unpaired_count),
+            pair_id_mismatches: pairs.map(|p| p.id_mismatch_count),
+            pair_duplication_percent: pairs
+                .map(|p| (p.duplication_percent * 1000.0).round() / 1000.0),
+        }
+    }
+}
+
+/// Execute the `fqstats` subcommand from `blobtk`.
+/// Write a JSON report of FASTQ statistics in MultiQC custom content format.
+pub fn fqstats(options: &cli::FqStatsOptions) -> Result<(), anyhow::Error> {
+    let timer = utils::stage_timer("parse");
+    let mut stats = BTreeMap::new();
+    // paired files are keyed by read so files with the same name stay apart
+    let pairs = match &options.fastq2 {
+        Some(fastq2) => {
+            let (forward, reverse, pairs) =
+                fastq::fastq_pair_stats(&options.fastq1, fastq2, &None as &Option<Box<dyn Fn()>>);
+            if pairs.unpaired_count > 0 {
+                eprintln!(
+                    "Paired FASTQ files have different read counts ({} and {})",
+                    forward.read_count, reverse.read_count
+                );
+            }
+            if pairs.id_mismatch_count > 0 {
+                eprintln!(
+                    "Read IDs differ in {} of {} pairs",
+                    pairs.id_mismatch_count, pairs.pair_count
+                );
+            }
+            stats.insert(
+                format!("{}_R1", utils::sample_name(&options.fastq1)),
+                forward,
+            );
+            stats.insert(format!("{}_R2", utils::sample_name(fastq2)), reverse);
+            Some(pairs)
+        }
+        None => {
+            stats.insert(
+                utils::sample_name(&options.fastq1),
+                fastq::fastq_stats(&options.fastq1, &None as &Option<Box<dyn Fn()>>),
+            );
+            None
+        }
+    };
+    drop(timer);
+    let table: BTreeMap<&String, SummaryRow> = stats
+        .iter()
+        .map(|(name, s)| (name, SummaryRow::from_stats(s, pairs.as_ref())))
+        .collect();
+    let mut report = json!({
+        "id": "blobtk_fqstats",
+        "section_name": "FASTQ statistics",
+        "description": "Read statistics calculated by blobtk fqstats",
+        "plot_type": "table",
+        "pconfig": {
+            "id": "blobtk_fqstats_table",
+            "namespace": "blobtk",
+        },
+        "data": table,
+        "details": stats,
+        "seed": utils::seed(),
+    });
+    if let Some(pairs) = pairs {
+        report["pairs"] = serde_json::to_value(pairs)?;
+    }
+    if let Some(timings) = utils::timings() {
+        report["timings"] = timings;
+    }
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "{}", serde_json::to_string_pretty(&report)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_paired_same_name() {
+        let dir = std::env::temp_dir().join("blobtk_test_fqstats");
+        let records = [
+            ("a", "@r1/1\nACGT\n+\nIIII\n"),
+            ("b", "@r1/2\nGGCC\n+\nIIII\n"),
+        ];
+        for (sub, record) in records {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("reads.fq"), record).unwrap();
+        }
+        let output = dir.join("stats.json");
+        fqstats(&cli::FqStatsOptions {
+            fastq1: dir.join("a/reads.fq"),
+            fastq2: Some(dir.join("b/reads.fq")),
+            output: Some(output.clone()),
+        })
+        .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(output).unwrap()).unwrap();
+        assert_eq!(report["data"]["reads_R1"]["read_pairs"], 1);
+        assert_eq!(report["data"]["reads_R2"]["gc_percent"], 100.0);
+        assert_eq!(report["pairs"]["id_mismatch_count"], 0);
+    }
+}
@@ -9,6 +9,8 @@ use flate2::write;
 use flate2::Compression;
 use std::ffi::OsStr;
 
+use crate::utils;
+
 fn read_stdin() -> Vec<Vec<u8>> {
     let stdin = io::stdin();
     let mut list: Vec<Vec<u8>> = vec![];
@@ -62,6 +64,7 @@ pub fn get_file_writer(file_path: &PathBuf) -> Box<dyn Write> {
         Err(why) => panic!("couldn't open {}: {}", file_path.display(), why),
         Ok(file) => file,
     };
+    utils::track_output(file_path);
 
     let writer: Box<dyn Write> = if file_path.extension() == Some(OsStr::new("gz")) {
         Box::new(BufWriter::with_capacity(
@@ -86,9 +89,12 @@ pub fn get_writer(file_path: &Option<PathBuf>) -> Box<dyn Write> {
     writer
 }
 
+/// Write entries one per line, sorted so the output does not depend on set order.
 pub fn write_list(entries: &HashSet<Vec<u8>>, file_path: &Option<PathBuf>) -> Result<()> {
     let mut writer = get_writer(file_path);
-    for line in entries.iter() {
+    let mut entries: Vec<_> = entries.iter().collect();
+    entries.sort();
+    for line in entries {
         writeln!(&mut writer, "{}", String::from_utf8(line.to_vec()).unwrap())?;
     }
     Ok(())
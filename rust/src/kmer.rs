@@ -0,0 +1,207 @@
+//!
+//! Invoked by calling:
+//! `blobtk kmer <args>`
+
+use std::collections::HashMap;
+
+use anyhow;
+use nalgebra::{DMatrix, SymmetricEigen};
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::fasta::trim_seq_id;
+use crate::fastq::open_fastx;
+use crate::utils::styled_progress_bar;
+
+pub use cli::KmerOptions;
+
+fn base_code(base: u8) -> Option<usize> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+fn reverse_complement_code(code: usize, k: usize) -> usize {
+    let mut code = code;
+    let mut rc = 0;
+    for _ in 0..k {
+        rc = (rc << 2) | (3 - (code & 3));
+        code >>= 2;
+    }
+    rc
+}
+
+/// Map each k-mer code to the index of its canonical k-mer, returning the
+/// map and the number of canonical k-mers.
+fn canonical_index(k: usize) -> (Vec<usize>, usize) {
+    let mut index = vec![0; 1 << (2 * k)];
+    let mut canonical: HashMap<usize, usize> = HashMap::new();
+    for (code, slot) in index.iter_mut().enumerate() {
+        let key = code.min(reverse_complement_code(code, k));
+        let next = canonical.len();
+        *slot = *canonical.entry(key).or_insert(next);
+    }
+    let count = canonical.len();
+    (index, count)
+}
+
+/// Count canonical k-mers in a sequence as frequencies, skipping k-mers
+/// containing bases other than A, C, G or T.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::kmer::kmer_frequencies;
+/// // AC and GT are reverse complements so share a canonical 2-mer
+/// let freqs = kmer_frequencies(b"ACNGT", 2);
+/// assert_eq!(freqs.len(), 10);
+/// assert_eq!(freqs.iter().sum::<f64>(), 1.0);
+/// assert_eq!(freqs.iter().filter(|f| **f > 0.0).count(), 1);
+/// ```
+pub fn kmer_frequencies(seq: &[u8], k: usize) -> Vec<f64> {
+    let (index, count) = canonical_index(k);
+    canonical_frequencies(seq, k, &index, count)
+}
+
+fn canonical_frequencies(seq: &[u8], k: usize, index: &[usize], count: usize) -> Vec<f64> {
+    let mask = (1 << (2 * k)) - 1;
+    let mut counts = vec![0.0; count];
+    let mut code = 0;
+    let mut valid = 0;
+    for base in seq {
+        match base_code(*base) {
+            Some(b) => {
+                code = ((code << 2) | b) & mask;
+                valid += 1;
+            }
+            None => valid = 0,
+        }
+        if valid >= k {
+            counts[index[code]] += 1.0;
+        }
+    }
+    let total: f64 = counts.iter().sum();
+    if total > 0.0 {
+        for value in counts.iter_mut() {
+            *value /= total;
+        }
+    }
+    counts
+}
+
+/// Project rows onto their first `n` principal components.
+///
+/// Frequencies are centred log-ratio transformed with a pseudocount before
+/// the projection. Component signs are set so the largest loading is
+/// positive, making output reproducible.
+pub fn principal_components(rows: &[Vec<f64>], n: usize) -> Vec<Vec<f64>> {
+    if rows.is_empty() {
+        return vec![];
+    }
+    let dim = rows[0].len();
+    let pseudocount = 1.0 / (dim as f64 * 100.0);
+    let mut data = DMatrix::from_fn(rows.len(), dim, |i, j| (rows[i][j] + pseudocount).ln());
+    for mut row in data.row_iter_mut() {
+        let mean = row.mean();
+        row.add_scalar_mut(-mean);
+    }
+    for mut column in data.column_iter_mut() {
+        let mean = column.mean();
+        column.add_scalar_mut(-mean);
+    }
+    let covariance = data.transpose() * &data / (rows.len().max(2) - 1) as f64;
+    let eigen = SymmetricEigen::new(covariance);
+    let mut order: Vec<usize> = (0..dim).collect();
+    order.sort_by(|a, b| eigen.eigenvalues[*b].total_cmp(&eigen.eigenvalues[*a]));
+    order
+        .iter()
+        .take(n)
+        .map(|i| {
+            let mut vector = eigen.eigenvectors.column(*i).clone_owned();
+            let max = vector
+                .iter()
+                .fold(0.0_f64, |max, v| if v.abs() > max.abs() { *v } else { max });
+            if max < 0.0 {
+                vector.neg_mut();
+            }
+            (&data * vector).iter().copied().collect()
+        })
+        .collect()
+}
+
+/// Execute the `kmer` subcommand from `blobtk`.
+pub fn kmer(options: &cli::KmerOptions) -> Result<(), anyhow::Error> {
+    let k = options.k as usize;
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let positions: HashMap<&[u8], usize> = identifiers
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_bytes(), i))
+        .collect();
+
+    let (index, count) = canonical_index(k);
+    let mut included = vec![];
+    let mut rows = vec![];
+    let progress_bar = styled_progress_bar(identifiers.len(), "Counting k-mers");
+    if let Some(mut reader) = open_fastx(&Some(options.fasta.clone())) {
+        while let Some(record) = reader.next() {
+            let seqrec = record.as_ref().expect("invalid record");
+            let seq_id = trim_seq_id(seqrec.id());
+            if let Some(position) = positions.get(seq_id.as_slice()) {
+                let seq = seqrec.seq();
+                if seq.len() >= options.min_length {
+                    included.push(*position);
+                    rows.push(canonical_frequencies(&seq, k, &index, count));
+                }
+                progress_bar.inc(1);
+            }
+        }
+    }
+    progress_bar.finish();
+    if rows.len() < 2 {
+        return Err(anyhow::anyhow!(
+            "at least 2 sequences longer than {} are needed for a k-mer embedding",
+            options.min_length
+        ));
+    }
+
+    let components = principal_components(&rows, 2);
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+    for (i, component) in components.iter().enumerate() {
+        let field_id = format!("{}_pc{}", options.prefix, i + 1);
+        let mut values = vec![f64::NAN; identifiers.len()];
+        for (position, value) in included.iter().zip(component.iter()) {
+            values[*position] = *value;
+        }
+        let min = component.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = component.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        blobdir::write_field_float(&field_id, &values, &options.blobdir)?;
+        blobdir::add_field_meta(
+            &mut meta_value,
+            json!({
+                "id": field_id,
+                "name": format!("{}-mer PC{}", k, i + 1),
+                "type": "variable",
+                "datatype": "float",
+                "scale": "scaleLinear",
+                "range": [min, max],
+                "preload": false,
+            }),
+        );
+    }
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+    eprintln!(
+        "Embedded {} of {} records using {} canonical {}-mers",
+        rows.len(),
+        identifiers.len(),
+        count,
+        k
+    );
+    Ok(())
+}
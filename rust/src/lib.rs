@@ -7,12 +7,30 @@ pub mod bam;
 /// Functions for processing a BlobDir.
 pub mod blobdir;
 
+/// Extract BUSCO gene sequences.
+pub mod busco_extract;
+
+/// Import k-mer classifier results into a BlobDir.
+pub mod classify;
+
+/// Remove intermediate files from the workspace.
+pub mod clean;
+
 /// The BlobTk Command Line Interface.
 pub mod cli;
 
+/// Score windowed taxonomic consistency.
+pub mod consistency;
+
+/// Apply curation decisions to an assembly.
+pub mod curate;
+
 /// Summarise windowed coverage depth.
 pub mod depth;
 
+/// Generate a synthetic example dataset.
+pub mod example;
+
 /// Error handline.
 pub mod error;
 
@@ -22,20 +40,47 @@ pub mod fasta;
 /// Functions for processing FASTQ files.
 pub mod fastq;
 
+/// Import NCBI FCS report actions into a BlobDir.
+pub mod fcs;
+
+/// Detect telomere and gap features.
+pub mod features;
+
 /// Filter files based on a list of sequence IDs.
 pub mod filter;
 
+/// Calculate FASTQ read statistics.
+pub mod fqstats;
+
 /// Functions for file/terminal IO.
 pub mod io;
 
+/// Calculate k-mer composition embeddings.
+pub mod kmer;
+
+/// Flag GC and coverage outliers in a BlobDir.
+pub mod outliers;
+
+/// Partition reads by taxonomic category.
+pub mod partition;
+
 /// Generate a plot.
 pub mod plot;
 
+/// Flag purge candidates using self-alignment and coverage.
+pub mod purge;
+
 /// Python bindings.
 pub mod python;
 
+/// Propose decontamination actions using screening rules.
+pub mod screen;
+
 /// Parse and subset a taxonomy.
 pub mod taxonomy;
 
+/// Upgrade BlobDir metadata to the current schema version.
+pub mod upgrade;
+
 /// Utility functions.
 pub mod utils;
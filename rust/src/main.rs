@@ -2,25 +2,87 @@ use std::process;
 
 use anyhow;
 
+use blobtk::busco_extract;
+use blobtk::classify;
+use blobtk::clean;
 use blobtk::cli;
+use blobtk::consistency;
+use blobtk::curate;
 use blobtk::depth;
+use blobtk::example;
+use blobtk::fcs;
+use blobtk::features;
 use blobtk::filter;
+use blobtk::fqstats;
+use blobtk::kmer;
+use blobtk::outliers;
+use blobtk::partition;
 use blobtk::plot;
+use blobtk::purge;
+use blobtk::screen;
 use blobtk::taxonomy;
+use blobtk::upgrade;
+use blobtk::utils;
 
 fn cmd(args: cli::Arguments) -> Result<(), anyhow::Error> {
     match args.cmd {
+        cli::SubCommand::BuscoExtract(options) => busco_extract::busco_extract(&options)?,
+        cli::SubCommand::Classify(options) => classify::classify(&options)?,
+        cli::SubCommand::Clean(options) => clean::clean(&options)?,
+        cli::SubCommand::Consistency(options) => consistency::consistency(&options)?,
+        cli::SubCommand::Fcs(options) => fcs::fcs(&options)?,
+        cli::SubCommand::Features(options) => features::features(&options)?,
         cli::SubCommand::Filter(options) => filter::filter(&options)?,
+        cli::SubCommand::FqStats(options) => fqstats::fqstats(&options)?,
+        cli::SubCommand::Curate(options) => curate::curate(&options)?,
         cli::SubCommand::Depth(options) => depth::depth(&options)?,
+        cli::SubCommand::Example(options) => example::example(&options)?,
+        cli::SubCommand::Kmer(options) => kmer::kmer(&options)?,
+        cli::SubCommand::Outliers(options) => outliers::outliers(&options)?,
+        cli::SubCommand::Partition(options) => partition::partition(&options)?,
         cli::SubCommand::Plot(options) => plot::plot(&options)?,
+        cli::SubCommand::Purge(options) => purge::purge(&options)?,
+        cli::SubCommand::Screen(options) => screen::screen(&options)?,
         cli::SubCommand::Taxonomy(options) => taxonomy::taxonomy(&options)?,
+        cli::SubCommand::Upgrade(options) => upgrade::upgrade(&options)?,
     }
     Ok(())
 }
 fn main() {
     let args = cli::parse();
-    if let Err(e) = cmd(args) {
+    utils::set_seed(args.seed);
+    let timings = args.timings.clone();
+    if timings.is_some() {
+        utils::enable_timings();
+    }
+    if let Some(workspace) = args.workspace.clone() {
+        utils::set_workspace(workspace);
+    }
+    // The first interrupt asks long running loops to stop so partial outputs
+    // can be cleaned up, a second exits immediately
+    let _ = ctrlc::set_handler(|| {
+        if utils::is_cancelled() {
+            process::exit(130);
+        }
+        eprintln!("Cancelling, press Ctrl-C again to exit immediately");
+        utils::cancel();
+    });
+    let result = cmd(args).and_then(|_| Ok(utils::check_cancelled()?));
+    utils::clean_scratch();
+    if let Err(e) = result {
         eprintln!("ERROR: {e}");
+        if utils::is_cancelled() {
+            for path in utils::remove_partial_outputs() {
+                eprintln!("Removed partial output {}", path.display());
+            }
+            process::exit(130);
+        }
         process::exit(1);
     }
+    if let Some(path) = timings {
+        if let Err(e) = utils::write_timings(&path) {
+            eprintln!("ERROR: unable to write timings: {e}");
+            process::exit(1);
+        }
+    }
 }
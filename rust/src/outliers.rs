@@ -0,0 +1,135 @@
+//!
+//! Invoked by calling:
+//! `blobtk outliers <args>`
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow;
+
+use crate::blobdir;
+use crate::cli::{self, OutlierMethod};
+use crate::error;
+use crate::io;
+
+pub use cli::OutliersOptions;
+
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let lower = pos.floor() as usize;
+    let upper = pos.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+/// Flag values that are outliers within their group.
+///
+/// Missing (`NaN`) values are never flagged and groups with fewer than
+/// three values are not tested.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::cli::OutlierMethod;
+/// # use crate::blobtk::outliers::outlier_flags;
+/// let values = vec![0.40, 0.41, 0.39, 0.40, 0.42, 0.38, 0.70, f64::NAN];
+/// let groups = vec![0; 8];
+/// let flags = outlier_flags(&values, &groups, &OutlierMethod::Iqr, 1.5);
+/// assert_eq!(flags, vec![false, false, false, false, false, false, true, false]);
+/// ```
+pub fn outlier_flags(
+    values: &[f64],
+    groups: &[usize],
+    method: &OutlierMethod,
+    threshold: f64,
+) -> Vec<bool> {
+    let mut group_values: HashMap<usize, Vec<f64>> = HashMap::new();
+    for (value, group) in values.iter().zip(groups.iter()) {
+        if !value.is_nan() {
+            group_values.entry(*group).or_default().push(*value);
+        }
+    }
+    let mut bounds = HashMap::new();
+    for (group, mut list) in group_values {
+        if list.len() < 3 {
+            continue;
+        }
+        let bound = match method {
+            OutlierMethod::Zscore => {
+                let mean = list.iter().sum::<f64>() / list.len() as f64;
+                let var = list.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / list.len() as f64;
+                let sd = var.sqrt();
+                [mean - threshold * sd, mean + threshold * sd]
+            }
+            OutlierMethod::Iqr => {
+                list.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let q1 = quantile(&list, 0.25);
+                let q3 = quantile(&list, 0.75);
+                let iqr = q3 - q1;
+                [q1 - threshold * iqr, q3 + threshold * iqr]
+            }
+        };
+        bounds.insert(group, bound);
+    }
+    values
+        .iter()
+        .zip(groups.iter())
+        .map(|(value, group)| match bounds.get(group) {
+            Some([min, max]) => !value.is_nan() && (*value < *min || *value > *max),
+            None => false,
+        })
+        .collect()
+}
+
+/// Execute the `outliers` subcommand from `blobtk`.
+/// Write a list of records with GC or coverage values that are outliers
+/// within their taxonomic category, for use with `blobtk filter --list`.
+pub fn outliers(options: &cli::OutliersOptions) -> Result<(), anyhow::Error> {
+    let meta = blobdir::parse_blobdir(&options.blobdir)?;
+    let field_list = meta.field_list.as_ref().unwrap();
+    let category_id =
+        blobdir::category_field_id(options.category.as_ref(), &options.taxrule, &options.rank);
+    let cov_id = match options.cov_field.clone().or(meta.plot.y.clone()) {
+        Some(field_id) => field_id,
+        None => return Err(error::Error::NotDefined("coverage field".to_string()).into()),
+    };
+    for id in [&category_id, &options.gc_field, &cov_id] {
+        if !field_list.contains_key(id) {
+            return Err(error::Error::NotDefined(format!("field {}", id)).into());
+        }
+    }
+    let threshold = match (options.threshold, &options.method) {
+        (Some(threshold), _) => threshold,
+        (None, OutlierMethod::Zscore) => 3.0,
+        (None, OutlierMethod::Iqr) => 1.5,
+    };
+
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let groups: Vec<usize> = blobdir::parse_field_cat(category_id, &options.blobdir)?
+        .codes
+        .into_iter()
+        .map(|code| code as usize)
+        .collect();
+    let gc_values = blobdir::parse_field_float(options.gc_field.clone(), &options.blobdir)?;
+    let mut cov_values = blobdir::parse_field_float(cov_id.clone(), &options.blobdir)?;
+    // Compare log-scaled coverage on the scale it is plotted
+    if field_list[&cov_id].scale.as_deref() == Some("scaleLog") {
+        cov_values = cov_values.iter().map(|v| (v + 1.0).log10()).collect();
+    }
+
+    let gc_flags = outlier_flags(&gc_values, &groups, &options.method, threshold);
+    let cov_flags = outlier_flags(&cov_values, &groups, &options.method, threshold);
+    let mut writer = io::get_writer(&options.output);
+    let mut count = 0;
+    for (i, identifier) in identifiers.iter().enumerate() {
+        if gc_flags[i] || cov_flags[i] {
+            writeln!(writer, "{}", identifier)?;
+            count += 1;
+        }
+    }
+    eprintln!(
+        "Flagged {} of {} records as outliers",
+        count,
+        identifiers.len()
+    );
+    Ok(())
+}
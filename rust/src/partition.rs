@@ -0,0 +1,104 @@
+//!
+//! Invoked by calling:
+//! `blobtk partition <args>`
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow;
+
+use crate::bam;
+use crate::blobdir;
+use crate::cli;
+use crate::error;
+use crate::fastq;
+
+pub use cli::PartitionOptions;
+
+fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// File names for a list of categories, adding a numeric suffix where
+/// distinct categories would otherwise share a name.
+fn unique_file_names(categories: &[String]) -> Vec<String> {
+    let mut used = HashSet::new();
+    categories
+        .iter()
+        .map(|category| {
+            let name = safe_file_name(category);
+            if category.is_empty() {
+                return name;
+            }
+            let mut unique = name.clone();
+            let mut suffix = 1;
+            while !used.insert(unique.clone()) {
+                suffix += 1;
+                unique = format!("{}_{}", name, suffix);
+            }
+            unique
+        })
+        .collect()
+}
+
+/// Execute the `partition` subcommand from `blobtk`.
+/// Use a taxonomic category field from a BlobDir to split reads in a
+/// BAM file into one FASTQ file (or pair of files) per category.
+pub fn partition(options: &cli::PartitionOptions) -> Result<(), anyhow::Error> {
+    let meta = blobdir::parse_blobdir(&options.blobdir)?;
+    let field_id =
+        blobdir::category_field_id(options.category.as_ref(), &options.taxrule, &options.rank);
+    if !meta.field_list.as_ref().unwrap().contains_key(&field_id) {
+        return Err(error::Error::NotDefined(format!("field {}", field_id)).into());
+    }
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let cat_values = blobdir::parse_field_cat(field_id, &options.blobdir)?;
+
+    let mut categories: Vec<String> = vec![];
+    let mut seq_categories = HashMap::new();
+    for (identifier, (name, index)) in identifiers.iter().zip(cat_values.iter()) {
+        if index >= categories.len() {
+            categories.resize(index + 1, String::new());
+        }
+        categories[index] = name.to_string();
+        seq_categories.insert(identifier.as_bytes().to_vec(), index);
+    }
+
+    let categories = unique_file_names(&categories);
+    let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, false);
+    let read_categories =
+        bam::read_categories_from_bam(&seq_categories, bam, &None as &Option<Box<dyn Fn()>>);
+    fastq::partition(
+        &read_categories,
+        &categories,
+        &options.fastq1,
+        &options.fastq2,
+        fastq::DEFAULT_BUFFER_SIZE,
+        &None as &Option<Box<dyn Fn()>>,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_file_names() {
+        let categories: Vec<String> = ["a/b", "a_b", "", "a b", "c"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(
+            unique_file_names(&categories),
+            vec!["a_b", "a_b_2", "", "a_b_3", "c"]
+        );
+    }
+}
@@ -3,7 +3,7 @@
 //! `blobtk plot <args>`
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow;
@@ -13,6 +13,7 @@ use crate::cli;
 use crate::error;
 use crate::plot::blob::BlobData;
 use crate::plot::cumulative::CumulativeData;
+use crate::utils;
 // use crate::io;
 
 use clap::ValueEnum;
@@ -30,6 +31,9 @@ pub mod axis;
 /// Blob plot functions.
 pub mod blob;
 
+/// Plot data cache functions.
+pub mod cache;
+
 /// Category functions.
 pub mod category;
 
@@ -56,15 +60,21 @@ pub fn save_svg(document: &Document, options: &PlotOptions) {
 }
 
 pub fn save_png(document: &Document, options: &PlotOptions) {
-    let mut fontdb = fontdb::Database::new();
-    fontdb.load_system_fonts();
     let mut buf = Vec::new();
     svg::write(&mut buf, document).unwrap();
+    let mut fontdb = fontdb::Database::new();
+    fontdb.load_system_fonts();
+    let pixmap = render_png(&buf, 2000, &fontdb);
+    pixmap.save_png(options.output.as_str()).unwrap();
+}
+
+/// Render SVG data to a pixmap of a given width. Text is drawn with fonts
+/// from `fontdb` and left out if none match.
+pub fn render_png(data: &[u8], width: u32, fontdb: &fontdb::Database) -> tiny_skia::Pixmap {
     let opt = usvg::Options::default();
-    let mut tree = usvg::Tree::from_data(&buf.as_slice(), &opt).unwrap();
-    tree.convert_text(&fontdb);
+    let mut tree = usvg::Tree::from_data(data, &opt).unwrap();
+    tree.convert_text(fontdb);
 
-    let width = 2000;
     let height = (width as f64 * tree.size.height() / tree.size.width()) as u32;
     let mut pixmap = tiny_skia::Pixmap::new(width, height).unwrap();
     resvg::render(
@@ -74,7 +84,7 @@ pub fn save_png(document: &Document, options: &PlotOptions) {
         pixmap.as_mut(),
     )
     .unwrap();
-    pixmap.save_png(options.output.as_str()).unwrap();
+    pixmap
 }
 
 pub enum Suffix {
@@ -102,8 +112,16 @@ pub enum ShowLegend {
     None,
 }
 
-/// Make a snail plot
-pub fn plot_snail(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<(), anyhow::Error> {
+fn report_missing(skipped: usize) {
+    if skipped > 0 {
+        eprintln!("Skipped {} records with missing values", skipped);
+    }
+}
+
+fn snail_data(
+    meta: &blobdir::Meta,
+    options: &cli::PlotOptions,
+) -> Result<snail::SnailStats, anyhow::Error> {
     let gc_values = blobdir::parse_field_float("gc".to_string(), &options.blobdir)?;
     let length_values = blobdir::parse_field_int("length".to_string(), &options.blobdir)?;
     let n_values = blobdir::parse_field_float("n".to_string(), &options.blobdir);
@@ -113,14 +131,39 @@ pub fn plot_snail(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<()
 
     let filters = blobdir::parse_filters(&options, None);
     let wanted_indices = blobdir::set_filters(filters, &meta, &options.blobdir);
+    let total = wanted_indices.len();
+    let (wanted_indices, _) = blobdir::skip_missing(&gc_values, &wanted_indices);
+    let (wanted_indices, _) = blobdir::skip_missing(&length_values, &wanted_indices);
+    let (wanted_indices, _) = blobdir::skip_missing(&ncount_values, &wanted_indices);
+    let wanted_indices = match n_values {
+        Ok(ref values) => blobdir::skip_missing(values, &wanted_indices).0,
+        Err(_) => wanted_indices,
+    };
+    report_missing(total - wanted_indices.len());
+    // the order of all records is only worth keeping if it is cached
+    let length_order = match options.blobdir.join(blobdir::cache::CACHE_DIR).is_dir() {
+        true => {
+            let order = blobdir::cache::cached(&options.blobdir, "length", "order", || {
+                Ok(utils::indexed_sort(&length_values))
+            })?;
+            blobdir::apply_filter_order(&order, &wanted_indices)
+        }
+        false => None,
+    };
 
     let gc_filtered = blobdir::apply_filter_float(&gc_values, &wanted_indices);
     let n_filtered = match n_values {
         Ok(values) => Some(blobdir::apply_filter_float(&values, &wanted_indices)),
         Err(_) => None,
     };
-    let length_filtered = blobdir::apply_filter_int(&length_values, &wanted_indices);
-    let ncount_filtered = blobdir::apply_filter_int(&ncount_values, &wanted_indices);
+    let length_filtered = blobdir::apply_filter_int(&length_values, &wanted_indices)
+        .into_iter()
+        .flatten()
+        .collect();
+    let ncount_filtered = blobdir::apply_filter_int(&ncount_values, &wanted_indices)
+        .into_iter()
+        .flatten()
+        .collect();
     let busco_list = meta.busco_list.clone();
     let (busco_total, busco_lineage, busco_filtered) = match busco_list {
         Some(list) if !list.is_empty() => {
@@ -136,6 +179,7 @@ pub fn plot_snail(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<()
 
     let snail_stats = snail::snail_stats(
         &length_filtered,
+        length_order,
         &gc_filtered,
         &n_filtered,
         &ncount_filtered,
@@ -146,12 +190,20 @@ pub fn plot_snail(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<()
         record_type,
         &options,
     );
+    Ok(snail_stats)
+}
+
+/// Make a snail plot
+pub fn plot_snail(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<(), anyhow::Error> {
+    let inputs = vec![options.filter.join(";"), options.segments.to_string()];
+    let snail_stats = cache::cached(options, "snail", &inputs, || snail_data(meta, options))?;
     let document: Document = snail::svg(&snail_stats, &options);
     save_by_suffix(options, document)?;
     Ok(())
 }
 
 fn save_by_suffix(options: &PlotOptions, document: Document) -> Result<(), error::Error> {
+    utils::check_cancelled()?;
     let output_str = options.output.as_str();
     let suffix_str = PathBuf::from(output_str)
         .extension()
@@ -165,6 +217,7 @@ fn save_by_suffix(options: &PlotOptions, document: Document) -> Result<(), error
         Ok(Suffix::SVG) => save_svg(&document, &options),
         Err(_) => return Err(error::Error::InvalidImageSuffix(suffix_str)),
     };
+    utils::track_output(Path::new(output_str));
     Ok(())
 }
 
@@ -251,9 +304,39 @@ fn insert_hashmap_option(
     Ok(())
 }
 
+/// Options that affect the data (rather than the appearance) of blob and
+/// cumulative plots.
+fn category_inputs(options: &PlotOptions) -> Vec<String> {
+    vec![
+        options.filter.join(";"),
+        format!("{:?}", options.cat_field),
+        format!("{:?}", options.cat_order),
+        options.cat_count.to_string(),
+        format!("{:?}", options.palette),
+        format!("{:?}", options.color),
+    ]
+}
+
 fn set_blob_data(
     options: &PlotOptions,
     meta: &blobdir::Meta,
+) -> Result<(HashMap<String, String>, BlobData), anyhow::Error> {
+    let mut inputs = category_inputs(options);
+    inputs.extend([
+        format!("{:?}", options.x_field),
+        format!("{:?}", options.y_field),
+        format!("{:?}", options.z_field),
+        format!("{:?}", options.x_limit),
+        format!("{:?}", options.y_limit),
+    ]);
+    cache::cached(options, "blob", &inputs, || {
+        process_blob_data(options, meta)
+    })
+}
+
+fn process_blob_data(
+    options: &PlotOptions,
+    meta: &blobdir::Meta,
 ) -> Result<(HashMap<String, String>, BlobData), anyhow::Error> {
     let mut plot_meta: HashMap<String, String> = HashMap::new();
     insert_hashmap_option(
@@ -295,6 +378,11 @@ fn set_blob_data(
     );
     let filters = blobdir::parse_filters(&options, Some(&plot_meta));
     let wanted_indices = blobdir::set_filters(filters, &meta, &options.blobdir);
+    let total = wanted_indices.len();
+    let (wanted_indices, _) = blobdir::skip_missing(&plot_values["x"], &wanted_indices);
+    let (wanted_indices, _) = blobdir::skip_missing(&plot_values["y"], &wanted_indices);
+    let (wanted_indices, _) = blobdir::skip_missing(&plot_values["z"], &wanted_indices);
+    report_missing(total - wanted_indices.len());
     let z = blobdir::apply_filter_float(&plot_values["z"], &wanted_indices);
     let filtered_cat_values = blobdir::apply_filter_cat_tuple(&cat_values, &wanted_indices);
     let (cat_order, cat_indices) = if wanted_indices.len() < plot_values["x"].len() {
@@ -373,10 +461,10 @@ pub fn plot_legend(meta: &blobdir::Meta, options: &cli::PlotOptions) -> Result<(
     Ok(())
 }
 
-pub fn plot_cumulative(
+fn cumulative_data(
     meta: &blobdir::Meta,
     options: &cli::PlotOptions,
-) -> Result<(), anyhow::Error> {
+) -> Result<CumulativeData, anyhow::Error> {
     let mut plot_meta: HashMap<String, String> = HashMap::new();
     plot_meta.insert("z".to_string(), "length".to_string());
 
@@ -403,12 +491,37 @@ pub fn plot_cumulative(
 
     let filters = blobdir::parse_filters(&options, None);
     let wanted_indices = blobdir::set_filters(filters, &meta, &options.blobdir);
+    let (wanted_indices, skipped) = blobdir::skip_missing(&plot_values["z"], &wanted_indices);
+    report_missing(skipped);
+    let values = blobdir::apply_filter_float(&plot_values["z"], &wanted_indices);
+    let (cat_order, cat_indices) = if wanted_indices.len() < plot_values["z"].len() {
+        let filtered_cat_values = blobdir::apply_filter_cat_tuple(&cat_values, &wanted_indices);
+        category::set_cat_order(
+            &filtered_cat_values,
+            &values,
+            &Some(cat_order[0].members.join(",")),
+            &options.cat_count,
+            &palette,
+        )
+    } else {
+        (cat_order, cat_indices)
+    };
 
     let cumulative_data = CumulativeData {
-        values: blobdir::apply_filter_float(&plot_values["z"], &wanted_indices),
-        cat: blobdir::apply_filter_int(&cat_indices, &wanted_indices),
+        values,
+        cat: cat_indices,
         cat_order,
     };
+    Ok(cumulative_data)
+}
+
+pub fn plot_cumulative(
+    meta: &blobdir::Meta,
+    options: &cli::PlotOptions,
+) -> Result<(), anyhow::Error> {
+    let cumulative_data = cache::cached(options, "cumulative", &category_inputs(options), || {
+        cumulative_data(meta, options)
+    })?;
 
     let dimensions = Dimensions {
         ..Default::default()
@@ -423,7 +536,13 @@ pub fn plot_cumulative(
 
 /// Execute the `plot` subcommand from `blobtk`.
 pub fn plot(options: &cli::PlotOptions) -> Result<(), anyhow::Error> {
+    if options.field_cache {
+        blobdir::cache::enable(&options.blobdir)?;
+    }
+    let timer = utils::stage_timer("parse");
     let meta = blobdir::parse_blobdir(&options.blobdir)?;
+    drop(timer);
+    let _timer = utils::stage_timer("rendering");
     let view = &options.view;
     match view {
         cli::View::Blob => plot_blob(&meta, &options)?,
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 use std::str::FromStr;
 
+use serde::{Deserialize, Serialize};
 use svg::node::element::{Group, Rectangle};
 use svg::Document;
 
@@ -17,7 +18,7 @@ use super::component::{legend_group, LegendEntry, LegendShape};
 use super::data::{Bin, HistogramData, Reducer, ScatterData, ScatterPoint};
 use super::ShowLegend;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BlobData {
     pub x: Vec<f64>,
     pub y: Vec<f64>,
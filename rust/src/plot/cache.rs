@@ -0,0 +1,72 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cli::PlotOptions;
+use crate::utils;
+
+/// Most recent modification time of any file in a BlobDir, in nanoseconds.
+fn blobdir_mtime(blobdir: &Path) -> u128 {
+    let mut latest = 0;
+    if let Ok(entries) = fs::read_dir(blobdir) {
+        for entry in entries.flatten() {
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                if let Ok(duration) = modified.duration_since(UNIX_EPOCH) {
+                    latest = latest.max(duration.as_nanos());
+                }
+            }
+        }
+    }
+    latest
+}
+
+/// Build a cache file path from the BlobDir mtime and the options that
+/// affect the data for a view.
+fn cache_path(cache_dir: &Path, blobdir: &Path, view: &str, inputs: &[String]) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(blobdir)
+        .unwrap_or(blobdir.to_path_buf())
+        .hash(&mut hasher);
+    blobdir_mtime(blobdir).hash(&mut hasher);
+    inputs.hash(&mut hasher);
+    cache_dir.join(format!("{}.{:016x}.json", view, hasher.finish()))
+}
+
+/// Load intermediate plot data from the cache directory if available,
+/// otherwise compute it and store the result for the next run.
+///
+/// `inputs` should list every option value that affects the computed data
+/// so cosmetic changes can reuse the cached values.
+pub fn cached<T, F>(
+    options: &PlotOptions,
+    view: &str,
+    inputs: &[String],
+    compute: F,
+) -> Result<T, anyhow::Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Result<T, anyhow::Error>,
+{
+    let cache_dir = match &options.cache_dir {
+        Some(dir) => utils::cache_dir(dir, "plot"),
+        None => return compute(),
+    };
+    let path = cache_path(&cache_dir, &options.blobdir, view, inputs);
+    if let Ok(file) = File::open(&path) {
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(data) => return Ok(data),
+            Err(err) => eprintln!("Ignoring invalid cache file {}: {}", path.display(), err),
+        }
+    }
+    let data = compute()?;
+    fs::create_dir_all(&cache_dir)?;
+    serde_json::to_writer(BufWriter::new(File::create(&path)?), &data)?;
+    Ok(data)
+}
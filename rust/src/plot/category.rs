@@ -1,9 +1,12 @@
 use std::borrow::BorrowMut;
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
+
+use crate::blobdir::CatValues;
 use crate::utils::format_si;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Category {
     pub title: String,
     pub total: bool,
@@ -50,48 +53,36 @@ impl Category {
 }
 
 pub fn set_cat_order(
-    values: &Vec<(String, usize)>,
+    values: &CatValues,
     z_values: &Vec<f64>,
     order: &Option<String>,
     count: &usize,
     palette: &Vec<String>,
 ) -> (Vec<Category>, Vec<usize>) {
-    let mut indices = HashMap::new();
-    let mut title_list = vec![];
-    for (i, entry) in values.iter().enumerate() {
-        title_list.push(entry.clone().0);
-        if !indices.contains_key(&entry.0) {
-            indices.insert(entry.clone().0, vec![i]);
-        } else {
-            let list = indices.get_mut(&entry.0).unwrap();
-            list.push(i);
+    let mut indices: Vec<Vec<usize>> = vec![vec![]; values.keys.len()];
+    let mut spans = vec![0.0; values.keys.len()];
+    for (i, code) in values.codes.iter().enumerate() {
+        let code = *code as usize;
+        indices[code].push(i);
+        // Missing z values count towards frequency but not span
+        if !z_values[i].is_nan() {
+            spans[code] += z_values[i];
         }
     }
-    let frequencies = values
-        .iter()
-        .enumerate()
-        .map(|(i, x)| (x.clone().0, i))
-        .fold(HashMap::new(), |mut map, (val, i)| {
-            map.entry(val)
-                .and_modify(|(frq, span)| {
-                    *frq += 1;
-                    *span += z_values[i]
-                })
-                .or_insert((1, z_values[i]));
-            map
-        });
-    let mut sorted_cats: Vec<_> = frequencies.clone().into_iter().collect();
+    let mut sorted_cats: Vec<usize> = (0..values.keys.len())
+        .filter(|code| !indices[*code].is_empty())
+        .collect();
     sorted_cats.sort_by(|x, y| {
-        if (x.1).0 == (y.1).0 {
-            if (x.1).1 == (y.1).1 {
-                x.0.partial_cmp(&y.0).unwrap()
-            } else {
-                (y.1).1.partial_cmp(&(x.1).1).unwrap()
-            }
-        } else {
-            (y.1).0.cmp(&(x.1).0)
-        }
+        indices[*y]
+            .len()
+            .cmp(&indices[*x].len())
+            .then_with(|| spans[*y].partial_cmp(&spans[*x]).unwrap())
+            .then_with(|| values.keys[*x].cmp(&values.keys[*y]))
     });
+    let codes: HashMap<&str, usize> = sorted_cats
+        .iter()
+        .map(|code| (values.keys[*code].as_str(), *code))
+        .collect();
 
     let mut cat_order = vec![];
     let mut all_indices: Vec<usize> = vec![];
@@ -100,12 +91,11 @@ pub fn set_cat_order(
     if order.is_some() {
         // TODO: prevent duplication when adding remaining cats
         for entry in order.clone().unwrap().split(",") {
-            if frequencies.contains_key(entry) {
-                let title = entry.to_string();
+            if let Some(code) = codes.get(entry) {
                 cat_order.push(Category {
                     title: entry.to_string(),
-                    members: vec![title.clone()],
-                    indices: indices[&title].clone(),
+                    members: vec![entry.to_string()],
+                    indices: indices[*code].clone(),
                     color: palette[index].clone(),
                     ..Default::default()
                 });
@@ -113,17 +103,18 @@ pub fn set_cat_order(
             index += 1;
         }
     }
-    for (title, _) in &sorted_cats {
-        all_indices.append(indices.clone().get_mut(title).unwrap());
+    for code in sorted_cats.iter() {
+        let title = &values.keys[*code];
+        all_indices.extend(&indices[*code]);
         all_members.push(title.clone());
-        if cat_order.iter().any(|cat| cat.title == title.clone()) {
+        if cat_order.iter().any(|cat| cat.title == *title) {
             continue;
         }
         if index < count - 1 || index == count - 1 && *count == sorted_cats.len() {
             cat_order.push(Category {
                 title: title.clone(),
                 members: vec![title.clone()],
-                indices: indices[title].clone(),
+                indices: indices[*code].clone(),
                 color: palette[index].clone(),
                 ..Default::default()
             });
@@ -132,14 +123,14 @@ pub fn set_cat_order(
             cat_order.push(Category {
                 title: "other".to_string(),
                 members: vec![title.clone()],
-                indices: indices[title].clone(),
+                indices: indices[*code].clone(),
                 color: palette[count - 1].clone(),
                 ..Default::default()
             });
         } else {
             let other_cat = cat_order[count - 1].borrow_mut();
             other_cat.members.push(title.clone());
-            other_cat.indices.append(indices.get_mut(title).unwrap());
+            other_cat.indices.extend(&indices[*code]);
         }
     }
     cat_order.insert(
@@ -158,7 +149,9 @@ pub fn set_cat_order(
         let mut lengths = vec![];
         for i in cat.indices.iter() {
             cat_indices[*i] = index;
-            lengths.push(z_values[*i]);
+            if !z_values[*i].is_nan() {
+                lengths.push(z_values[*i]);
+            }
         }
         lengths.sort_by(|a, b| b.partial_cmp(a).unwrap());
         cat.count = Some(cat.indices.len());
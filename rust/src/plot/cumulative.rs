@@ -1,8 +1,10 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use svg::node::element::Rectangle;
 use svg::Document;
 
 use crate::cli::Origin;
-use crate::utils::linear_scale_float;
+use crate::utils::{linear_scale_float, prefix_sums};
 use crate::{cli, plot};
 
 use plot::category::Category;
@@ -13,7 +15,7 @@ use super::chart::{Chart, Dimensions};
 use super::data::{Line, LineData};
 use super::ShowLegend;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CumulativeData {
     pub values: Vec<f64>,
     pub cat: Vec<usize>,
@@ -58,25 +60,38 @@ pub fn cumulative_lines(
         Some(Origin::Y) => cat_order.sort_by(|x, y| y.span.cmp(&x.span)),
         _ => (),
     };
+    // categories are sorted and summed in parallel, lengths are whole
+    // numbers so the sums are exact
+    let cumulative_spans: Vec<Vec<f64>> = cat_order
+        .par_iter()
+        .map(|cat| {
+            let mut lengths: Vec<f64> = cat
+                .indices
+                .iter()
+                .map(|i| cumulative_data.values[*i])
+                .collect();
+            lengths.par_sort_unstable_by(|a, b| b.partial_cmp(a).unwrap());
+            prefix_sums(&lengths)
+        })
+        .collect();
     // let mut ordered_points = vec![vec![]; cat_order.len()];
     let mut end_coords = [0.0, y_range[0]];
-    for (index, cat) in cat_order.iter().enumerate() {
-        let mut lengths = vec![];
-        for i in cat.indices.iter() {
-            lengths.push(cumulative_data.values[*i]);
-        }
-        lengths.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    for ((index, cat), cumulative_spans) in cat_order.iter().enumerate().zip(cumulative_spans) {
         let mut coords = vec![end_coords.clone()];
-        let mut cumulative_span = 0.0;
-        for (i, length) in lengths.iter().enumerate() {
-            // add coords to line
-            cumulative_span += length;
-            coords.push([
-                coords[0][0] + linear_scale_float((i + 1) as f64, &x_domain, &x_range),
-                coords[0][1] - dimensions.height
-                    + linear_scale_float(cumulative_span as f64, &y_domain, &y_range),
-            ]);
-        }
+        let origin = coords[0];
+        coords.par_extend(
+            cumulative_spans
+                .par_iter()
+                .enumerate()
+                .map(|(i, cumulative_span)| {
+                    // add coords to line
+                    [
+                        origin[0] + linear_scale_float((i + 1) as f64, &x_domain, &x_range),
+                        origin[1] - dimensions.height
+                            + linear_scale_float(*cumulative_span, &y_domain, &y_range),
+                    ]
+                }),
+        );
         if index > 0 {
             end_coords = match options.origin {
                 Some(Origin::X) | Some(Origin::Y) => {
@@ -2,6 +2,7 @@ use std::cmp::{self, Ordering};
 use std::collections::HashSet;
 use std::f64::consts::PI;
 
+use rayon::prelude::*;
 use serde;
 use serde::{Deserialize, Serialize};
 use svg::node::element::{Group, Line, Path, Rectangle, Text};
@@ -141,8 +142,13 @@ fn count_buscos(
     }
 }
 
+/// Summarise records for a snail plot.
+///
+/// `length_order` is the order of records by descending length, as given by
+/// `utils::indexed_sort`, and is computed if not given.
 pub fn snail_stats(
     length_values: &Vec<usize>,
+    length_order: Option<Vec<usize>>,
     gc_values: &Vec<f64>,
     n_vals: &Option<Vec<f64>>,
     ncount_values: &Vec<usize>,
@@ -153,9 +159,8 @@ pub fn snail_stats(
     record_type: String,
     options: &cli::PlotOptions,
 ) -> SnailStats {
-    let span = length_values.iter().sum();
-    let n = ncount_values.iter().sum();
-    let mut new_vals = vec![];
+    let span = length_values.par_iter().sum();
+    let n = ncount_values.par_iter().sum();
     let busco_total = match busco_total {
         Some(total) => total,
         None => 0,
@@ -164,83 +169,72 @@ pub fn snail_stats(
         Some(lineage) => lineage,
         None => "".to_string(),
     };
+    let new_vals: Vec<f64>;
     let n_values = match n_vals {
         Some(vals) => vals,
         None => {
-            for (i, length) in length_values.iter().enumerate() {
-                new_vals.push(ncount_values[i] as f64 / length.clone() as f64);
-            }
+            new_vals = length_values
+                .par_iter()
+                .zip(ncount_values.par_iter())
+                .map(|(length, ncount)| *ncount as f64 / *length as f64)
+                .collect();
             &new_vals
         }
     };
     let atgc = span - n;
     let segment = span / options.segments;
-    let order = utils::indexed_sort(&length_values);
+    let order = length_order.unwrap_or_else(|| utils::indexed_sort(length_values));
     // TODO: check span > segments
-    let mut position: usize = 0;
-    let mut binned_gcs: Vec<SummaryStats> = vec![];
-    let mut binned_ns: Vec<SummaryStats> = vec![];
+    let sorted_lengths: Vec<usize> = order.par_iter().map(|i| length_values[*i]).collect();
+    let cumulative_lengths = utils::prefix_sums(&sorted_lengths);
+    // each segment ends with the first scaffold to reach its share of the
+    // span, and starts with the last scaffold of the previous segment
+    let ends: Vec<usize> = (1..=options.segments)
+        .map(|i| cumulative_lengths.partition_point(|sum| *sum < segment * i))
+        .collect();
+    let summary = |mut values: Vec<f64>| {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        SummaryStats {
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        }
+    };
+    let (binned_gcs, binned_ns): (Vec<SummaryStats>, Vec<SummaryStats>) = (0..ends.len())
+        .into_par_iter()
+        .map(|bin| {
+            let start = if bin == 0 { 0 } else { ends[bin - 1] };
+            let indices = &order[start..=ends[bin]];
+            (
+                summary(indices.iter().map(|i| gc_values[*i] * 100.0).collect()),
+                summary(indices.iter().map(|i| n_values[*i] * 100.0).collect()),
+            )
+        })
+        .unzip();
+    let binned_scaffold_counts: Vec<usize> = ends.iter().map(|end| end + 1).collect();
+    let binned_scaffold_lengths: Vec<usize> = ends.iter().map(|end| sorted_lengths[*end]).collect();
+
+    let counted = &order[..=ends.last().copied().unwrap_or(0)];
+    let n_span: usize = counted.par_iter().map(|i| ncount_values[*i]).sum();
+    // floating point spans are summed in order so results do not depend on
+    // the number of threads
+    let mut gc_span = 0.0;
+    let mut at_span = 0.0;
     let mut busco_list = HashSet::new();
     let mut busco_frag = HashSet::new();
     let mut busco_dup = HashSet::new();
-    let mut scaffold_index: usize = 0;
-    let mut scaffold_sum: usize = length_values[order[scaffold_index]];
-    let mut gc_span = gc_values[order[scaffold_index]]
-        * ((length_values[order[scaffold_index]] - ncount_values[order[scaffold_index]]) as f64);
-    let mut at_span = (1.0 - gc_values[order[scaffold_index]])
-        * ((length_values[order[scaffold_index]] - ncount_values[order[scaffold_index]]) as f64);
-    let mut n_span = ncount_values[order[scaffold_index]];
-    if !busco_values.is_empty() {
-        count_buscos(
-            &busco_values[order[scaffold_index]],
-            &mut busco_frag,
-            &mut busco_list,
-            &mut busco_dup,
-        );
-    }
-
-    let mut binned_scaffold_lengths: Vec<usize> = vec![];
-    let mut binned_scaffold_counts: Vec<usize> = vec![];
-    for _ in 0..options.segments {
-        position += segment;
-        let mut gcs: Vec<f64> = vec![gc_values[order[scaffold_index]] * 100.0];
-        let mut ns: Vec<f64> = vec![n_values[order[scaffold_index]] * 100.0];
-        while scaffold_sum < position {
-            scaffold_index += 1;
-            scaffold_sum += length_values[order[scaffold_index]];
-            gcs.push(gc_values[order[scaffold_index]] * 100.0);
-            ns.push(n_values[order[scaffold_index]] * 100.0);
-
-            gc_span += gc_values[order[scaffold_index]]
-                * ((length_values[order[scaffold_index]] - ncount_values[order[scaffold_index]])
-                    as f64);
-            at_span += (1.0 - gc_values[order[scaffold_index]])
-                * ((length_values[order[scaffold_index]] - ncount_values[order[scaffold_index]])
-                    as f64);
-            n_span += ncount_values[order[scaffold_index]];
-            if !busco_values.is_empty() {
-                count_buscos(
-                    &busco_values[order[scaffold_index]],
-                    &mut busco_frag,
-                    &mut busco_list,
-                    &mut busco_dup,
-                );
-            }
+    for i in counted {
+        let atgc_length = (length_values[*i] - ncount_values[*i]) as f64;
+        gc_span += gc_values[*i] * atgc_length;
+        at_span += (1.0 - gc_values[*i]) * atgc_length;
+        if !busco_values.is_empty() {
+            count_buscos(
+                &busco_values[*i],
+                &mut busco_frag,
+                &mut busco_list,
+                &mut busco_dup,
+            );
         }
-        binned_scaffold_counts.push(scaffold_index + 1);
-        binned_scaffold_lengths.push(length_values[order[scaffold_index]]);
-        gcs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-        binned_gcs.push(SummaryStats {
-            min: gcs[0],
-            max: gcs[gcs.len() - 1],
-            mean: gcs.iter().sum::<f64>() / gcs.len() as f64,
-        });
-        ns.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-        binned_ns.push(SummaryStats {
-            min: ns[0],
-            max: ns[ns.len() - 1],
-            mean: ns.iter().sum::<f64>() / ns.len() as f64,
-        });
     }
     SnailStats {
         span,
@@ -0,0 +1,235 @@
+//!
+//! Invoked by calling:
+//! `blobtk purge <args>`
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli;
+use crate::error;
+use crate::io;
+
+pub use cli::PurgeOptions;
+
+/// Alignments of a sequence to longer sequences in a self-alignment.
+#[derive(Clone, Debug, Default)]
+pub struct SelfAlignment {
+    /// Sequence length
+    pub length: usize,
+    /// 0-based half-open aligned intervals
+    pub intervals: Vec<(usize, usize)>,
+    /// Matching bases per target sequence
+    pub targets: HashMap<String, usize>,
+}
+
+impl SelfAlignment {
+    /// Fraction of the sequence covered by alignments.
+    pub fn aligned_fraction(&self) -> f64 {
+        match self.length {
+            0 => 0.0,
+            length => covered_length(&self.intervals) as f64 / length as f64,
+        }
+    }
+
+    /// Target sequence with the most matching bases.
+    pub fn best_target(&self) -> Option<&String> {
+        self.targets
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(target, _)| target)
+    }
+}
+
+/// Count the bases covered by a set of possibly overlapping intervals.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::purge::covered_length;
+/// assert_eq!(covered_length(&[(0, 10), (5, 15), (20, 25)]), 20);
+/// ```
+pub fn covered_length(intervals: &[(usize, usize)]) -> usize {
+    let mut sorted = intervals.to_vec();
+    sorted.sort();
+    let mut covered = 0;
+    let mut current: Option<(usize, usize)> = None;
+    for (start, end) in sorted {
+        current = match current {
+            Some((s, e)) if start <= e => Some((s, e.max(end))),
+            Some((s, e)) => {
+                covered += e - s;
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((s, e)) = current {
+        covered += e - s;
+    }
+    covered
+}
+
+/// Parse a minimap2 PAF self-alignment.
+///
+/// Only alignments of each sequence to a longer sequence are kept (ties are
+/// broken by name), so only the shorter of a duplicated pair is flagged.
+pub fn parse_paf(path: &Path) -> Result<HashMap<String, SelfAlignment>, anyhow::Error> {
+    let mut alignments: HashMap<String, SelfAlignment> = HashMap::new();
+    for line in io::read_lines(path)? {
+        let line = line?;
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 12 || cols[0] == cols[5] {
+            continue;
+        }
+        let parse = |i: usize| {
+            cols[i]
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("invalid PAF line: {}", line))
+        };
+        let (length, start, end) = (parse(1)?, parse(2)?, parse(3)?);
+        let (target_length, matches) = (parse(6)?, parse(9)?);
+        if (target_length, cols[5]) < (length, cols[0]) {
+            continue;
+        }
+        let alignment = alignments.entry(cols[0].to_string()).or_default();
+        alignment.length = length;
+        alignment.intervals.push((start, end));
+        *alignment.targets.entry(cols[5].to_string()).or_default() += matches;
+    }
+    Ok(alignments)
+}
+
+/// Label a sequence as a `haplotig` purge candidate or `keep`.
+///
+/// Each available measure must pass its threshold: the aligned fraction must
+/// be at least `min_aligned` and the ratio of coverage to median coverage at
+/// most `max_cov_ratio`.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::purge::purge_label;
+/// assert_eq!(purge_label(Some(0.9), None, 0.8, 0.75), "haplotig");
+/// assert_eq!(purge_label(Some(0.9), Some(1.0), 0.8, 0.75), "keep");
+/// assert_eq!(purge_label(None, Some(0.5), 0.8, 0.75), "haplotig");
+/// assert_eq!(purge_label(None, None, 0.8, 0.75), "keep");
+/// ```
+pub fn purge_label(
+    aligned: Option<f64>,
+    cov_ratio: Option<f64>,
+    min_aligned: f64,
+    max_cov_ratio: f64,
+) -> &'static str {
+    let candidate = match (aligned, cov_ratio) {
+        (None, None) => false,
+        (aligned, cov_ratio) => {
+            aligned.is_none_or(|aligned| aligned >= min_aligned)
+                && cov_ratio.is_none_or(|ratio| ratio <= max_cov_ratio)
+        }
+    };
+    match candidate {
+        true => "haplotig",
+        false => "keep",
+    }
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted: Vec<f64> = values
+        .iter()
+        .copied()
+        .filter(|v| !v.is_nan() && *v > 0.0)
+        .collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    Some(match sorted.len() % 2 {
+        0 => (sorted[mid - 1] + sorted[mid]) / 2.0,
+        _ => sorted[mid],
+    })
+}
+
+/// Execute the `purge` subcommand from `blobtk`.
+pub fn purge(options: &cli::PurgeOptions) -> Result<(), anyhow::Error> {
+    if options.paf.is_none() && options.cov_field.is_none() {
+        return Err(error::Error::NotDefined("PAF file or coverage field".to_string()).into());
+    }
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let alignments = match &options.paf {
+        Some(paf) => Some(parse_paf(paf)?),
+        None => None,
+    };
+    let cov_ratios = match &options.cov_field {
+        Some(field_id) => {
+            let values = blobdir::parse_field_float(field_id.clone(), &options.blobdir)?;
+            let median = median(&values)
+                .ok_or_else(|| anyhow::anyhow!("no coverage values in field {}", field_id))?;
+            Some(values.iter().map(|v| v / median).collect::<Vec<f64>>())
+        }
+        None => None,
+    };
+
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "identifier\tstatus\taligned\ttarget\tcov_ratio")?;
+    let mut labels = vec![];
+    for (i, identifier) in identifiers.iter().enumerate() {
+        let alignment = alignments
+            .as_ref()
+            .map(|alignments| alignments.get(identifier).cloned().unwrap_or_default());
+        let aligned = alignment.as_ref().map(|a| a.aligned_fraction());
+        let cov_ratio = cov_ratios
+            .as_ref()
+            .map(|ratios| ratios[i])
+            .filter(|ratio| !ratio.is_nan());
+        let label = purge_label(
+            aligned,
+            cov_ratio,
+            options.min_aligned,
+            options.max_cov_ratio,
+        );
+        if label != "keep" {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                identifier,
+                label,
+                aligned.map_or("".to_string(), |a| format!("{:.3}", a)),
+                alignment
+                    .as_ref()
+                    .and_then(|a| a.best_target().cloned())
+                    .unwrap_or_default(),
+                cov_ratio.map_or("".to_string(), |r| format!("{:.3}", r)),
+            )?;
+        }
+        labels.push(label.to_string());
+    }
+
+    let field_id = "purge_candidate";
+    let (values, keys) = blobdir::category_keys(&labels, &["keep"]);
+    blobdir::write_field_cat(field_id, &values, &keys, &options.blobdir)?;
+    let mut meta_value = blobdir::read_meta_value(&options.blobdir)?;
+    blobdir::upgrade_meta(&mut meta_value);
+    blobdir::add_field_meta(
+        &mut meta_value,
+        json!({
+            "id": field_id,
+            "name": "Purge candidate",
+            "type": "category",
+            "datatype": "string",
+            "preload": false,
+        }),
+    );
+    blobdir::write_meta_value(&options.blobdir, &meta_value)?;
+    eprintln!(
+        "Flagged {} of {} records as purge candidates",
+        labels.iter().filter(|label| *label != "keep").count(),
+        identifiers.len()
+    );
+    Ok(())
+}
@@ -13,6 +13,7 @@ fn blobtk(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     let depth = PyModule::new(py, "depth")?;
     depth.add_function(wrap_pyfunction!(depth::bam_to_bed, m)?)?;
     depth.add_function(wrap_pyfunction!(depth::bam_to_depth, m)?)?;
+    depth.add_function(wrap_pyfunction!(depth::bams_to_matrix, m)?)?;
     m.add_submodule(depth)?;
 
     Ok(())
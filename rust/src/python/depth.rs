@@ -5,12 +5,17 @@ use std::path::PathBuf;
 use crate::bam::{self, BinnedCov};
 use crate::cli::DepthOptions;
 use crate::io;
-use crate::python::utils::{extract_to_option_list, extract_to_option_pathbuf, extract_to_usize};
+use crate::python::utils::{
+    cancelled_result, check_signals, extract_to_option_list, extract_to_option_pathbuf,
+    extract_to_option_vec_pathbuf, extract_to_usize,
+};
+use crate::utils;
 use pyo3::prelude::*;
 
 #[pymethods]
 impl DepthOptions {
     #[new]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         bin_size: usize,
         list: Option<HashSet<Vec<u8>>>,
@@ -19,6 +24,8 @@ impl DepthOptions {
         cram: Option<PathBuf>,
         fasta: Option<PathBuf>,
         bed: Option<PathBuf>,
+        bams: Option<Vec<PathBuf>>,
+        matrix: Option<PathBuf>,
     ) -> Self {
         DepthOptions {
             list,
@@ -28,12 +35,15 @@ impl DepthOptions {
             fasta,
             bin_size,
             bed,
+            bams,
+            matrix,
         }
     }
 }
 
 #[pyfunction]
 pub fn bam_to_bed_with_options(options: &DepthOptions, py: Python) -> PyResult<usize> {
+    utils::reset_cancel();
     let seq_names = match options.list.to_owned() {
         Some(value) => value,
         _ => {
@@ -42,15 +52,16 @@ pub fn bam_to_bed_with_options(options: &DepthOptions, py: Python) -> PyResult<u
         }
     };
     let ctrlc_wrapper = || {
-        py.check_signals().unwrap();
+        check_signals(py);
     };
     let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, true);
     bam::get_bed_file(bam, &seq_names, options, &Some(Box::new(ctrlc_wrapper)));
-    Ok(1)
+    cancelled_result(1)
 }
 
 #[pyfunction]
-pub fn bam_to_depth_with_options(options: &DepthOptions, py: Python) -> Vec<BinnedCov> {
+pub fn bam_to_depth_with_options(options: &DepthOptions, py: Python) -> PyResult<Vec<BinnedCov>> {
+    utils::reset_cancel();
     let seq_names = match options.list.to_owned() {
         Some(value) => value,
         _ => {
@@ -59,10 +70,33 @@ pub fn bam_to_depth_with_options(options: &DepthOptions, py: Python) -> Vec<Binn
         }
     };
     let ctrlc_wrapper = || {
-        py.check_signals().unwrap();
+        check_signals(py);
     };
     let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, true);
-    bam::get_depth(bam, &seq_names, options, &Some(Box::new(ctrlc_wrapper)))
+    cancelled_result(bam::get_depth(
+        bam,
+        &seq_names,
+        options,
+        &Some(Box::new(ctrlc_wrapper)),
+    ))
+}
+
+#[pyfunction]
+pub fn bams_to_matrix_with_options(options: &DepthOptions, py: Python) -> PyResult<usize> {
+    utils::reset_cancel();
+    let seq_names = match options.list.to_owned() {
+        Some(value) => value,
+        _ => {
+            let value = options.list_file.to_owned();
+            io::get_list(&value)
+        }
+    };
+    let ctrlc_wrapper = || {
+        check_signals(py);
+    };
+    let bams = options.bams.to_owned().unwrap_or_default();
+    bam::get_depth_matrix(&bams, &seq_names, options, &Some(Box::new(ctrlc_wrapper)))?;
+    cancelled_result(bams.len())
 }
 
 fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) -> DepthOptions {
@@ -72,6 +106,8 @@ fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) ->
     let cram = extract_to_option_pathbuf(py, &map, "cram");
     let fasta = extract_to_option_pathbuf(py, &map, "fasta");
     let bed = extract_to_option_pathbuf(py, &map, "bed");
+    let bams = extract_to_option_vec_pathbuf(py, &map, "bams");
+    let matrix = extract_to_option_pathbuf(py, &map, "matrix");
     let bin_size = extract_to_usize(py, &map, "bin_size");
     DepthOptions {
         bin_size,
@@ -81,6 +117,8 @@ fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) ->
         cram,
         fasta,
         bed,
+        bams,
+        matrix,
     }
 }
 
@@ -97,10 +135,24 @@ pub fn bam_to_bed(py: Python<'_>, kwds: Option<HashMap<String, PyObject>>) -> Py
 
 #[pyfunction]
 #[pyo3(signature = (**kwds))]
-pub fn bam_to_depth(py: Python<'_>, kwds: Option<HashMap<String, PyObject>>) -> Vec<BinnedCov> {
+pub fn bam_to_depth(
+    py: Python<'_>,
+    kwds: Option<HashMap<String, PyObject>>,
+) -> PyResult<Vec<BinnedCov>> {
     let options = match kwds {
         Some(map) => convert_hashmap_to_options(py, map),
         None => panic!["No arguments provided"],
     };
     bam_to_depth_with_options(&options, py)
 }
+
+#[pyfunction]
+#[pyo3(signature = (**kwds))]
+pub fn bams_to_matrix(py: Python<'_>, kwds: Option<HashMap<String, PyObject>>) -> PyResult<()> {
+    let options = match kwds {
+        Some(map) => convert_hashmap_to_options(py, map),
+        None => panic!["No arguments provided"],
+    };
+    bams_to_matrix_with_options(&options, py)?;
+    Ok(())
+}
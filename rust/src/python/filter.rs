@@ -3,18 +3,39 @@ use std::collections::HashSet;
 use std::path::PathBuf;
 
 use crate::bam;
-use crate::cli::FilterOptions;
+use crate::cli::{AlignmentFormat, FilterOptions};
 use crate::fasta;
-use crate::fastq;
+use crate::fastq::{self, DEFAULT_BUFFER_SIZE};
 use crate::io;
 use crate::python::utils::{
-    extract_to_bool, extract_to_default_string, extract_to_option_list, extract_to_option_pathbuf,
+    cancelled_result, check_signals, extract_to_bool, extract_to_default_string,
+    extract_to_default_usize, extract_to_option_list, extract_to_option_pathbuf,
+    extract_to_option_string, extract_to_option_u32, extract_to_option_vec_string,
 };
+use crate::utils;
+use clap::ValueEnum;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
+/// Parse an alignment output format name, e.g. `"cram"`.
+fn alignment_format(format: Option<String>) -> PyResult<Option<AlignmentFormat>> {
+    format
+        .map(|format| {
+            AlignmentFormat::from_str(&format, true)
+                .map_err(|_| PyValueError::new_err(format!("invalid output_format: {}", format)))
+        })
+        .transpose()
+}
+
 #[pymethods]
 impl FilterOptions {
     #[new]
+    #[pyo3(signature = (
+        suffix, fasta_out, fastq_out, list, list_file, bam, cram, fasta, fastq1, fastq2,
+        read_list, region = None, bed = None, *, unmapped = false,
+        buffer_size = DEFAULT_BUFFER_SIZE, alignment_out = false, output_format = None,
+        compression_level = None, output_reference = None
+    ))]
     #[allow(clippy::too_many_arguments)]
     fn new(
         suffix: String,
@@ -28,11 +49,21 @@ impl FilterOptions {
         fastq1: Option<PathBuf>,
         fastq2: Option<PathBuf>,
         read_list: Option<PathBuf>,
-    ) -> Self {
-        FilterOptions {
+        region: Option<Vec<String>>,
+        bed: Option<PathBuf>,
+        unmapped: bool,
+        buffer_size: usize,
+        alignment_out: bool,
+        output_format: Option<String>,
+        compression_level: Option<u32>,
+        output_reference: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        Ok(FilterOptions {
             suffix,
             fasta_out,
             fastq_out,
+            unmapped,
+            buffer_size,
             list,
             list_file,
             bam,
@@ -41,14 +72,21 @@ impl FilterOptions {
             fastq1,
             fastq2,
             read_list,
-        }
+            region,
+            bed,
+            alignment_out,
+            output_format: alignment_format(output_format)?,
+            compression_level,
+            output_reference,
+        })
     }
 }
 
 #[pyfunction]
 pub fn fastx_with_options(options: &FilterOptions, py: Python) -> PyResult<usize> {
+    utils::reset_cancel();
     let ctrlc_wrapper = || {
-        py.check_signals().unwrap();
+        check_signals(py);
     };
 
     let seq_names = match options.list.to_owned() {
@@ -58,7 +96,7 @@ pub fn fastx_with_options(options: &FilterOptions, py: Python) -> PyResult<usize
             io::get_list(&value)
         }
     };
-    if seq_names.is_empty() {
+    if seq_names.is_empty() && !options.unmapped {
         return Ok(0);
     }
     fasta::subsample(
@@ -68,11 +106,36 @@ pub fn fastx_with_options(options: &FilterOptions, py: Python) -> PyResult<usize
         &options.suffix,
         &Some(Box::new(ctrlc_wrapper)),
     );
-    if options.bam.is_none() && options.cram.is_none() {
-        return Ok(0);
+    if (options.bam.is_none() && options.cram.is_none()) || utils::is_cancelled() {
+        return cancelled_result(0);
     }
     let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, true);
-    let read_names = bam::reads_from_bam(&seq_names, bam, &Some(Box::new(ctrlc_wrapper)));
+    let regions = bam::get_regions(&options.region, &options.bed);
+    let read_names = match (regions.is_empty(), options.unmapped) {
+        (true, true) => {
+            bam::unmapped_reads_from_bam(&seq_names, bam, true, &Some(Box::new(ctrlc_wrapper)))
+        }
+        (true, false) => bam::reads_from_bam(&seq_names, bam, &Some(Box::new(ctrlc_wrapper))),
+        (false, _) => {
+            let mut read_names = bam::reads_from_bam_regions(
+                &seq_names,
+                &regions,
+                bam,
+                &Some(Box::new(ctrlc_wrapper)),
+            );
+            if options.unmapped {
+                let bam = bam::open_bam(&options.bam, &options.cram, &options.fasta, false);
+                read_names.extend(bam::unmapped_reads_from_bam(
+                    &seq_names,
+                    bam,
+                    false,
+                    &Some(Box::new(ctrlc_wrapper)),
+                ));
+            }
+            read_names
+        }
+    };
+    bam::subsample(&read_names, options, &Some(Box::new(ctrlc_wrapper)))?;
     io::write_list(&read_names, &options.read_list)?;
     fastq::subsample(
         &read_names,
@@ -80,12 +143,16 @@ pub fn fastx_with_options(options: &FilterOptions, py: Python) -> PyResult<usize
         &options.fastq2,
         &options.fastq_out,
         &options.suffix,
+        options.buffer_size,
         &Some(Box::new(ctrlc_wrapper)),
     );
-    Ok(read_names.len())
+    cancelled_result(read_names.len())
 }
 
-fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) -> FilterOptions {
+fn convert_hashmap_to_options(
+    py: Python<'_>,
+    map: HashMap<String, PyObject>,
+) -> PyResult<FilterOptions> {
     let list = extract_to_option_list(py, &map, "list");
     let list_file = extract_to_option_pathbuf(py, &map, "list_file");
     let bam = extract_to_option_pathbuf(py, &map, "bam");
@@ -94,10 +161,18 @@ fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) ->
     let fastq1 = extract_to_option_pathbuf(py, &map, "fastq1");
     let fastq2 = extract_to_option_pathbuf(py, &map, "fastq2");
     let read_list = extract_to_option_pathbuf(py, &map, "read_list");
+    let region = extract_to_option_vec_string(py, &map, "region");
+    let bed = extract_to_option_pathbuf(py, &map, "bed");
     let suffix = extract_to_default_string(py, &map, "suffix", "filtered");
     let fasta_out = extract_to_bool(py, &map, "fasta_out");
     let fastq_out = extract_to_bool(py, &map, "fastq_out");
-    FilterOptions {
+    let unmapped = extract_to_bool(py, &map, "unmapped");
+    let buffer_size = extract_to_default_usize(py, &map, "buffer_size", DEFAULT_BUFFER_SIZE);
+    let alignment_out = extract_to_bool(py, &map, "alignment_out");
+    let output_format = alignment_format(extract_to_option_string(py, &map, "output_format"))?;
+    let compression_level = extract_to_option_u32(py, &map, "compression_level");
+    let output_reference = extract_to_option_pathbuf(py, &map, "output_reference");
+    Ok(FilterOptions {
         suffix,
         fasta_out,
         fastq_out,
@@ -109,14 +184,22 @@ fn convert_hashmap_to_options(py: Python<'_>, map: HashMap<String, PyObject>) ->
         fastq1,
         fastq2,
         read_list,
-    }
+        region,
+        bed,
+        unmapped,
+        buffer_size,
+        alignment_out,
+        output_format,
+        compression_level,
+        output_reference,
+    })
 }
 
 #[pyfunction]
 #[pyo3(signature = (**kwds))]
 pub fn fastx(py: Python<'_>, kwds: Option<HashMap<String, PyObject>>) -> PyResult<usize> {
     let options = match kwds {
-        Some(map) => convert_hashmap_to_options(py, map),
+        Some(map) => convert_hashmap_to_options(py, map)?,
         None => panic!["No arguments provided"],
     };
     fastx_with_options(&options, py)
@@ -2,8 +2,29 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::PathBuf;
 
+use pyo3::exceptions::PyKeyboardInterrupt;
 use pyo3::prelude::*;
 
+use crate::utils;
+
+/// Request cancellation if Python has a pending signal such as
+/// `KeyboardInterrupt`, for use in long running loop callbacks.
+pub fn check_signals(py: Python<'_>) {
+    if py.check_signals().is_err() {
+        utils::cancel();
+    }
+}
+
+/// Return a value, or remove partial outputs and raise `KeyboardInterrupt`
+/// if the run was cancelled.
+pub fn cancelled_result<T>(value: T) -> PyResult<T> {
+    if utils::is_cancelled() {
+        utils::remove_partial_outputs();
+        return Err(PyKeyboardInterrupt::new_err("cancelled"));
+    }
+    Ok(value)
+}
+
 pub fn extract_to_option_list(
     py: Python<'_>,
     map: &HashMap<String, PyObject>,
@@ -36,6 +57,54 @@ pub fn extract_to_option_pathbuf(
     option
 }
 
+pub fn extract_to_option_vec_pathbuf(
+    py: Python<'_>,
+    map: &HashMap<String, PyObject>,
+    key: &str,
+) -> Option<Vec<PathBuf>> {
+    let hash_key = String::from(key);
+    let option: Option<Vec<PathBuf>> = map
+        .get(&hash_key)
+        .map(|value| value.extract::<Vec<PathBuf>>(py).unwrap());
+    option
+}
+
+pub fn extract_to_option_vec_string(
+    py: Python<'_>,
+    map: &HashMap<String, PyObject>,
+    key: &str,
+) -> Option<Vec<String>> {
+    let hash_key = String::from(key);
+    let option: Option<Vec<String>> = map
+        .get(&hash_key)
+        .map(|value| value.extract::<Vec<String>>(py).unwrap());
+    option
+}
+
+pub fn extract_to_option_string(
+    py: Python<'_>,
+    map: &HashMap<String, PyObject>,
+    key: &str,
+) -> Option<String> {
+    let hash_key = String::from(key);
+    let option: Option<String> = map
+        .get(&hash_key)
+        .map(|value| value.extract::<String>(py).unwrap());
+    option
+}
+
+pub fn extract_to_option_u32(
+    py: Python<'_>,
+    map: &HashMap<String, PyObject>,
+    key: &str,
+) -> Option<u32> {
+    let hash_key = String::from(key);
+    let option: Option<u32> = map
+        .get(&hash_key)
+        .map(|value| value.extract::<u32>(py).unwrap());
+    option
+}
+
 pub fn extract_to_default_string(
     py: Python<'_>,
     map: &HashMap<String, PyObject>,
@@ -67,3 +136,17 @@ pub fn extract_to_usize(py: Python<'_>, map: &HashMap<String, PyObject>, key: &s
     };
     value
 }
+
+pub fn extract_to_default_usize(
+    py: Python<'_>,
+    map: &HashMap<String, PyObject>,
+    key: &str,
+    default: usize,
+) -> usize {
+    let hash_key = String::from(key);
+    let value = match map.get(&hash_key) {
+        Some(value) => value.extract::<usize>(py).unwrap(),
+        _ => default,
+    };
+    value
+}
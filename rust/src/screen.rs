@@ -0,0 +1,251 @@
+//!
+//! Invoked by calling:
+//! `blobtk screen <args>`
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+
+use anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::blobdir;
+use crate::cli::{self, NoHit};
+use crate::error;
+use crate::io;
+use crate::utils;
+
+pub use cli::ScreenOptions;
+
+/// Screening rules, read from a YAML file and/or the command line.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ScreenRules {
+    pub target: Option<Vec<String>>,
+    pub rank: Option<String>,
+    pub taxrule: Option<String>,
+    pub category: Option<String>,
+    pub cov_field: Option<String>,
+    pub min_coverage: Option<f64>,
+    pub min_length: Option<usize>,
+    pub no_hit: Option<NoHit>,
+}
+
+/// Proposed action for a record.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Keep,
+    Remove,
+    Review,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Action::Keep => "keep",
+            Action::Remove => "remove",
+            Action::Review => "review",
+        }
+    }
+}
+
+fn load_rules(options: &cli::ScreenOptions) -> Result<ScreenRules, anyhow::Error> {
+    let config = match &options.config_file {
+        Some(config_file) => match File::open(config_file) {
+            Ok(reader) => serde_yaml::from_reader(reader)?,
+            Err(_) => {
+                return Err(error::Error::FileNotFound(format!("{}", config_file.display())).into())
+            }
+        },
+        None => ScreenRules::default(),
+    };
+    Ok(ScreenRules {
+        target: config.target.or(options.target.clone()),
+        rank: config.rank.or(Some(options.rank.clone())),
+        taxrule: config.taxrule.or(Some(options.taxrule.clone())),
+        category: config.category.or(options.category.clone()),
+        cov_field: config.cov_field.or(options.cov_field.clone()),
+        min_coverage: config.min_coverage.or(Some(options.min_coverage)),
+        min_length: config.min_length.or(Some(options.min_length)),
+        no_hit: config.no_hit.or(Some(options.no_hit.clone())),
+    })
+}
+
+fn taxon_at_rank(taxon: &blobdir::TaxonMeta, rank: &str) -> Option<String> {
+    match rank {
+        "superkingdom" => taxon.superkingdom.clone(),
+        "kingdom" => taxon.kingdom.clone(),
+        "phylum" => taxon.phylum.clone(),
+        "class" => taxon.class.clone(),
+        "order" => taxon.order.clone(),
+        "family" => taxon.family.clone(),
+        "genus" => taxon.genus.clone(),
+        _ => None,
+    }
+}
+
+/// Apply screening rules to a single record, returning an action and reason.
+///
+/// - records with no hit use the `no_hit` action
+/// - target records are kept unless coverage is below `min_coverage`
+/// - non-target records are removed unless shorter than `min_length`
+///
+/// Records needing a judgement call are marked for review.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::cli::NoHit;
+/// # use crate::blobtk::screen::{screen_record, Action};
+/// let target = vec!["Chordata".to_string()];
+/// let action = screen_record("Proteobacteria", 5000, 30.0, &target, 5.0, 1000, &NoHit::Review);
+/// assert_eq!(action, (Action::Remove, "non-target"));
+/// let action = screen_record("Chordata", 5000, 1.0, &target, 5.0, 1000, &NoHit::Review);
+/// assert_eq!(action, (Action::Review, "low coverage"));
+/// ```
+pub fn screen_record(
+    category: &str,
+    length: usize,
+    coverage: f64,
+    target: &[String],
+    min_coverage: f64,
+    min_length: usize,
+    no_hit: &NoHit,
+) -> (Action, &'static str) {
+    if category == "no-hit" {
+        let action = match no_hit {
+            NoHit::Keep => Action::Keep,
+            NoHit::Remove => Action::Remove,
+            NoHit::Review => Action::Review,
+        };
+        return (action, "no-hit");
+    }
+    if target.iter().any(|taxon| taxon == category) {
+        if min_coverage > 0.0 && (coverage.is_nan() || coverage < min_coverage) {
+            return (Action::Review, "low coverage");
+        }
+        return (Action::Keep, "target");
+    }
+    if length < min_length {
+        return (Action::Review, "short non-target");
+    }
+    (Action::Remove, "non-target")
+}
+
+/// Execute the `screen` subcommand from `blobtk`.
+/// Apply screening rules to a BlobDir and write keep, remove and review
+/// lists for use with `blobtk filter --list`, a per-record table and a
+/// JSON summary report.
+pub fn screen(options: &cli::ScreenOptions) -> Result<(), anyhow::Error> {
+    let timer = utils::stage_timer("parse");
+    let mut rules = load_rules(options)?;
+    let meta = blobdir::parse_blobdir(&options.blobdir)?;
+    let field_list = meta.field_list.as_ref().unwrap();
+    let rank = rules.rank.clone().unwrap_or_default();
+    let category_id = blobdir::category_field_id(
+        rules.category.as_ref(),
+        rules.taxrule.as_deref().unwrap_or_default(),
+        &rank,
+    );
+    if !field_list.contains_key(&category_id) {
+        return Err(error::Error::NotDefined(format!("field {}", category_id)).into());
+    }
+    if rules.target.is_none() {
+        rules.target = taxon_at_rank(&meta.taxon, &rank).map(|taxon| vec![taxon]);
+    }
+    let target = match &rules.target {
+        Some(target) => target.clone(),
+        None => return Err(error::Error::NotDefined("target".to_string()).into()),
+    };
+    let cov_id = rules.cov_field.clone().or(meta.plot.y.clone());
+    let min_coverage = rules.min_coverage.unwrap_or_default();
+    let min_length = rules.min_length.unwrap_or_default();
+    let no_hit = rules.no_hit.clone().unwrap_or_default();
+
+    let identifiers = blobdir::parse_field_string("identifiers".to_string(), &options.blobdir)?;
+    let categories = blobdir::parse_field_cat(category_id, &options.blobdir)?;
+    let lengths = blobdir::parse_field_int("length".to_string(), &options.blobdir)?;
+    let coverages = match &cov_id {
+        Some(cov_id) if field_list.contains_key(cov_id) => {
+            blobdir::parse_field_float(cov_id.clone(), &options.blobdir)?
+        }
+        _ => vec![f64::NAN; identifiers.len()],
+    };
+    drop(timer);
+
+    let timer = utils::stage_timer("matching");
+    let mut lists: BTreeMap<Action, Vec<&String>> = BTreeMap::new();
+    let mut spans: BTreeMap<Action, usize> = BTreeMap::new();
+    let mut reasons: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut table = io::get_writer(&Some(options.output.join("screen.tsv")));
+    writeln!(
+        table,
+        "identifier\tcategory\tlength\tcoverage\taction\treason"
+    )?;
+    for (i, identifier) in identifiers.iter().enumerate() {
+        let length = lengths[i].unwrap_or(0);
+        let (action, reason) = screen_record(
+            categories.name(i),
+            length,
+            coverages[i],
+            &target,
+            min_coverage,
+            min_length,
+            &no_hit,
+        );
+        writeln!(
+            table,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            identifier,
+            categories.name(i),
+            length,
+            if coverages[i].is_nan() {
+                "".to_string()
+            } else {
+                coverages[i].to_string()
+            },
+            action.as_str(),
+            reason
+        )?;
+        lists.entry(action).or_default().push(identifier);
+        *spans.entry(action).or_default() += length;
+        *reasons.entry(reason).or_default() += 1;
+    }
+
+    let mut actions = BTreeMap::new();
+    for action in [Action::Keep, Action::Remove, Action::Review] {
+        let list = lists.remove(&action).unwrap_or_default();
+        let mut writer = io::get_writer(&Some(
+            options.output.join(format!("{}.txt", action.as_str())),
+        ));
+        for identifier in list.iter() {
+            writeln!(writer, "{}", identifier)?;
+        }
+        actions.insert(
+            action.as_str().to_string(),
+            json!({
+                "count": list.len(),
+                "span": spans.get(&action).unwrap_or(&0),
+            }),
+        );
+    }
+    drop(timer);
+    let mut summary = json!({
+        "blobdir": meta.id,
+        "records": identifiers.len(),
+        "rules": rules,
+        "actions": actions,
+        "reasons": reasons,
+    });
+    if let Some(timings) = utils::timings() {
+        summary["timings"] = timings;
+    }
+    let mut writer = io::get_writer(&Some(options.output.join("screen.json")));
+    writeln!(writer, "{}", serde_json::to_string_pretty(&summary)?)?;
+    for (action, value) in summary["actions"].as_object().unwrap() {
+        eprintln!("{}: {} records", action, value["count"]);
+    }
+    Ok(())
+}
@@ -4,13 +4,16 @@
 
 use anyhow;
 use flate2::read::GzDecoder;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 // use std::time::{Duration, Instant};
 
 use crate::cli;
 use crate::error;
+use crate::io;
+use crate::utils;
 
 /// Functions for ncbi taxonomy processing.
 pub mod parse;
@@ -18,13 +21,69 @@ pub mod parse;
 /// Functions for name lookup.
 pub mod lookup;
 
+/// Functions to fetch taxa from the ENA taxonomy API.
+pub mod ena;
+
+/// Functions to map sequence and assembly accessions to taxa.
+pub mod accession;
+
+/// Functions to export taxa to a SQLite database.
+pub mod sqlite;
+
+/// Functions to compare two taxonomies.
+pub mod diff;
+
+/// Functions to summarise taxonomies.
+pub mod stats;
+
+/// Functions to find lowest common ancestors.
+pub mod lca;
+
+/// Functions to cache parsed taxonomies and lookup tables.
+pub mod cache;
+
+/// Functions to serve taxon lookups over HTTP.
+pub mod serve;
+
+/// Functions to add lineage columns to tables.
+pub mod annotate;
+
+/// Functions to print lineages.
+pub mod lineage;
+
+/// Functions to unpack taxonomy archives.
+pub mod archive;
+
+/// Functions to download reference taxonomies.
+pub mod fetch;
+
+/// Functions to record the provenance of generated taxdumps.
+pub mod metadata;
+
+/// Functions to check the integrity of a taxonomy.
+pub mod validate;
+
+/// Functions to assign taxon IDs to added taxa.
+pub mod mint;
+
 pub use cli::TaxonomyOptions;
 
-pub use parse::{parse_taxdump, write_taxdump};
+pub use parse::{
+    parse_taxdump, write_dot, write_graphml, write_jsonl, write_ranked_lineage, write_taxdump,
+};
+
+pub use lookup::{build_lookup, lookup_nodes, LookupRanks, LookupTable};
+
+pub use sqlite::write_sqlite;
 
-pub use lookup::lookup_nodes;
+pub use stats::{write_stats, TaxonomyStats};
 
-use self::parse::{parse_gbif, Nodes};
+use self::lookup::NameIndex;
+use self::parse::{
+    parse_bold, parse_coldp, parse_datasets, parse_dwca, parse_ena_jsonl, parse_gbif, parse_gtdb,
+    parse_irmng, parse_itis, parse_lineage_tsv, parse_silva, parse_worms, read_vocabulary,
+    CsvContext, CsvSettings, Nodes,
+};
 
 // use std::error::Error;
 // use csv::Reader;
@@ -41,7 +100,7 @@ use self::parse::{parse_gbif, Nodes};
 pub fn file_reader(path: PathBuf) -> Option<Box<dyn BufRead>> {
     let file = File::open(&path).expect("no such file");
 
-    if path.ends_with(".gz") {
+    if path.extension().is_some_and(|ext| ext == "gz") {
         return Some(Box::new(BufReader::new(GzDecoder::new(file))));
     } else {
         return Some(Box::new(BufReader::new(file)));
@@ -82,14 +141,134 @@ fn load_options(options: &cli::TaxonomyOptions) -> Result<cli::TaxonomyOptions,
                 Some(root_taxon_id) => Some(root_taxon_id),
                 None => options.root_taxon_id.clone(),
             },
+            root_name: match taxonomy_options.root_name {
+                Some(root_name) => Some(root_name),
+                None => options.root_name.clone(),
+            },
             base_taxon_id: match taxonomy_options.base_taxon_id {
                 Some(base_taxon_id) => Some(base_taxon_id),
                 None => options.base_taxon_id.clone(),
             },
+            label: match taxonomy_options.label {
+                Some(label) => Some(label),
+                None => options.label.clone(),
+            },
             out: match taxonomy_options.out {
                 Some(out) => Some(out),
                 None => options.out.clone(),
             },
+            output_format: match taxonomy_options.output_format {
+                Some(output_format) => Some(output_format),
+                None => options.output_format.clone(),
+            },
+            compress: match taxonomy_options.compress {
+                Some(compress) => Some(compress),
+                None => options.compress.clone(),
+            },
+            sorted: taxonomy_options.sorted || options.sorted,
+            ranked_lineage: taxonomy_options.ranked_lineage || options.ranked_lineage,
+            mapping_out: match taxonomy_options.mapping_out {
+                Some(mapping_out) => Some(mapping_out),
+                None => options.mapping_out.clone(),
+            },
+            stats: match taxonomy_options.stats {
+                Some(stats) => Some(stats),
+                None => options.stats.clone(),
+            },
+            mint: match taxonomy_options.mint {
+                Some(mint) => Some(mint),
+                None => options.mint.clone(),
+            },
+            mint_start: match taxonomy_options.mint_start {
+                Some(mint_start) => Some(mint_start),
+                None => options.mint_start,
+            },
+            mint_prefix: match taxonomy_options.mint_prefix {
+                Some(mint_prefix) => Some(mint_prefix),
+                None => options.mint_prefix.clone(),
+            },
+            mint_map: match taxonomy_options.mint_map {
+                Some(mint_map) => Some(mint_map),
+                None => options.mint_map.clone(),
+            },
+            ena_query: match taxonomy_options.ena_query {
+                Some(ena_query) => Some(ena_query),
+                None => options.ena_query.clone(),
+            },
+            ena_cache: match taxonomy_options.ena_cache {
+                Some(ena_cache) => Some(ena_cache),
+                None => options.ena_cache.clone(),
+            },
+            ena_delay: match taxonomy_options.ena_delay {
+                Some(ena_delay) => Some(ena_delay),
+                None => options.ena_delay,
+            },
+            ranks: match taxonomy_options.ranks {
+                Some(ranks) => Some(ranks),
+                None => options.ranks.clone(),
+            },
+            higher_ranks: match taxonomy_options.higher_ranks {
+                Some(higher_ranks) => Some(higher_ranks),
+                None => options.higher_ranks.clone(),
+            },
+            max_memory: match taxonomy_options.max_memory {
+                Some(max_memory) => Some(max_memory),
+                None => options.max_memory,
+            },
+            cache_dir: match taxonomy_options.cache_dir {
+                Some(cache_dir) => Some(cache_dir),
+                None => options.cache_dir.clone(),
+            },
+            delimiter: match taxonomy_options.delimiter {
+                Some(delimiter) => Some(delimiter),
+                None => options.delimiter.clone(),
+            },
+            quote: match taxonomy_options.quote {
+                Some(quote) => Some(quote),
+                None => options.quote.clone(),
+            },
+            comment: match taxonomy_options.comment {
+                Some(comment) => Some(comment),
+                None => options.comment,
+            },
+            ragged_rows: match taxonomy_options.ragged_rows {
+                Some(ragged_rows) => Some(ragged_rows),
+                None => options.ragged_rows,
+            },
+            error_report: match taxonomy_options.error_report {
+                Some(error_report) => Some(error_report),
+                None => options.error_report.clone(),
+            },
+            fuzzy_headers: taxonomy_options.fuzzy_headers || options.fuzzy_headers,
+            case_sensitive: taxonomy_options.case_sensitive || options.case_sensitive,
+            header_synonyms: match taxonomy_options.header_synonyms {
+                Some(header_synonyms) => Some(header_synonyms),
+                None => options.header_synonyms.clone(),
+            },
+            null_values: match taxonomy_options.null_values {
+                Some(null_values) => Some(null_values),
+                None => options.null_values.clone(),
+            },
+            null_values_by_field: match taxonomy_options.null_values_by_field {
+                Some(null_values_by_field) => Some(null_values_by_field),
+                None => options.null_values_by_field.clone(),
+            },
+            field_defaults: match taxonomy_options.field_defaults {
+                Some(field_defaults) => Some(field_defaults),
+                None => options.field_defaults.clone(),
+            },
+            field_normalization: match taxonomy_options.field_normalization {
+                Some(field_normalization) => Some(field_normalization),
+                None => options.field_normalization.clone(),
+            },
+            rank_aliases: match taxonomy_options.rank_aliases {
+                Some(rank_aliases) => Some(rank_aliases),
+                None => options.rank_aliases.clone(),
+            },
+            roll_up: match taxonomy_options.roll_up {
+                Some(roll_up) => Some(roll_up),
+                None => options.roll_up.clone(),
+            },
             xref_label: match taxonomy_options.xref_label {
                 Some(xref_label) => Some(xref_label),
                 None => options.xref_label.clone(),
@@ -106,13 +285,183 @@ fn load_options(options: &cli::TaxonomyOptions) -> Result<cli::TaxonomyOptions,
     Ok(options.clone())
 }
 
+/// Combine root taxon IDs with the taxon IDs of any root taxa selected by
+/// name, failing if a name is not found or matches more than one taxon.
+/// List taxa matching an ambiguous name with their rank and parent.
+pub(crate) fn describe_candidates(nodes: &Nodes, tax_ids: &[String]) -> String {
+    let candidates: Vec<String> = tax_ids
+        .iter()
+        .map(|tax_id| {
+            let node = &nodes.nodes[tax_id];
+            let parent = nodes
+                .nodes
+                .get(&node.parent_tax_id)
+                .map_or(String::new(), |parent| parent.scientific_name());
+            format!("{} ({}, in {})", tax_id, node.rank, parent)
+        })
+        .collect();
+    candidates.join(", ")
+}
+
+fn root_taxon_ids(
+    nodes: &Nodes,
+    options: &cli::TaxonomyOptions,
+) -> Result<Option<Vec<String>>, error::Error> {
+    let names = match options.root_name.as_ref() {
+        Some(names) => names,
+        None => return Ok(options.root_taxon_id.clone()),
+    };
+    let mut root_ids = options.root_taxon_id.clone().unwrap_or_default();
+    let index = NameIndex::new(nodes, &options.name_classes, false, false);
+    for name in names {
+        let tax_ids = index.get(name);
+        match tax_ids.len() {
+            0 => return Err(error::Error::TaxonNotFound(name.clone())),
+            1 => root_ids.extend(tax_ids),
+            _ => {
+                return Err(error::Error::AmbiguousTaxon(format!(
+                    "{} matches {}, use --root-id to select one",
+                    name,
+                    describe_candidates(nodes, &tax_ids)
+                )));
+            }
+        }
+    }
+    Ok(Some(root_ids))
+}
+
+/// Convert a delimiter or quote option to a byte, accepting `tab` for a tab.
+fn csv_byte(value: &str) -> Result<u8, error::Error> {
+    match value {
+        "tab" | "\\t" => Ok(b'\t'),
+        value if value.len() == 1 => Ok(value.as_bytes()[0]),
+        value => Err(error::Error::NotDefined(format!(
+            "single byte delimiter or quote for {}",
+            value
+        ))),
+    }
+}
+
+fn csv_settings(options: &cli::TaxonomyOptions) -> Result<CsvSettings, error::Error> {
+    let field_normalization = options.field_normalization.clone().unwrap_or_default();
+    let mut vocabularies = HashMap::new();
+    for (field, normalization) in field_normalization.iter() {
+        if let Some(path) = normalization.vocabulary.as_ref() {
+            if !path.exists() {
+                return Err(error::Error::FileNotFound(format!("{}", path.display())));
+            }
+            vocabularies.insert(field.clone(), read_vocabulary(path, normalization)?);
+        }
+    }
+    Ok(CsvSettings {
+        delimiter: options.delimiter.as_deref().map(csv_byte).transpose()?,
+        quote: match options.quote.as_deref() {
+            Some("none") => Some(None),
+            Some(quote) => Some(Some(csv_byte(quote)?)),
+            None => None,
+        },
+        comment: options.comment.map(|comment| comment as u8),
+        flexible: options.ragged_rows,
+        error_report: options.error_report.clone(),
+        fuzzy_headers: options.fuzzy_headers,
+        header_synonyms: options.header_synonyms.clone().unwrap_or_default(),
+        null_values: options.null_values.clone(),
+        null_values_by_field: options.null_values_by_field.clone().unwrap_or_default(),
+        field_defaults: options.field_defaults.clone().unwrap_or_default(),
+        field_normalization,
+        vocabularies,
+    })
+}
+
 fn taxdump_to_nodes(options: &cli::TaxonomyOptions) -> Result<Nodes, error::Error> {
-    let options = load_options(&options)?;
-    let nodes;
-    if let Some(taxdump) = options.path.clone() {
-        nodes = match options.taxonomy_format {
-            Some(cli::TaxonomyFormat::NCBI) => parse_taxdump(taxdump).unwrap(),
-            Some(cli::TaxonomyFormat::GBIF) => parse_gbif(taxdump).unwrap(),
+    Ok(cached_nodes(options, &mut CsvContext::default())?.0)
+}
+
+/// Parse a taxonomy, loading it from `cache_dir` if it has been parsed
+/// with the same inputs and options before. Also returns the cache key, if
+/// caching is enabled.
+///
+/// Counts of missing values filled from defaults are added to `csv`, for
+/// taxonomies loaded from the cache as well as parsed ones.
+fn cached_nodes(
+    options: &cli::TaxonomyOptions,
+    csv: &mut CsvContext,
+) -> Result<(Nodes, Option<String>), error::Error> {
+    let options = load_options(options)?;
+    let cache_dir = options
+        .cache_dir
+        .as_ref()
+        .filter(|_| options.ena_query.is_none())
+        .map(|cache_dir| utils::cache_dir(cache_dir, "taxonomy"));
+    let key = match cache_dir {
+        Some(_) => cache::taxonomy_key(&options).ok(),
+        None => None,
+    };
+    if let (Some(cache_dir), Some(key)) = (cache_dir.as_ref(), key.as_ref()) {
+        if let (Some(nodes), Some(imputed)) = (
+            cache::load_nodes(cache_dir, key),
+            cache::load_imputed(cache_dir, key),
+        ) {
+            eprintln!("Loaded {} nodes from cache", nodes.nodes.len());
+            csv.add_imputed(&imputed);
+            return Ok((nodes, Some(key.clone())));
+        }
+    }
+    // keep counts for this taxonomy separate while it is cached
+    let earlier = csv.take_imputed();
+    let nodes = parse_nodes(&options, csv)?;
+    if let (Some(cache_dir), Some(key)) = (cache_dir.as_ref(), key.as_ref()) {
+        if let Err(err) = cache::save_nodes(cache_dir, key, &nodes)
+            .and_then(|_| cache::save_imputed(cache_dir, key, csv.imputed()))
+        {
+            eprintln!("Unable to cache taxonomy: {}", err);
+        }
+    }
+    csv.add_imputed(&earlier);
+    Ok((nodes, key))
+}
+
+fn parse_nodes(
+    options: &cli::TaxonomyOptions,
+    csv: &mut CsvContext,
+) -> Result<Nodes, error::Error> {
+    csv.set_settings(csv_settings(options)?);
+    let mut nodes;
+    if let Some(queries) = options.ena_query.as_ref() {
+        nodes = ena::fetch_ena(
+            queries,
+            options
+                .ena_cache
+                .as_ref()
+                .map(|cache| utils::cache_dir(cache, "ena"))
+                .as_deref(),
+            options.ena_delay.unwrap_or(ena::DEFAULT_ENA_DELAY),
+            options
+                .path
+                .as_deref()
+                .filter(|_| matches!(options.taxonomy_format, Some(cli::TaxonomyFormat::ENA))),
+        )
+        .unwrap();
+    } else if let Some(taxdump) = options.path.clone() {
+        // archives are unpacked to scratch for the duration of parsing
+        let unpacked = archive::unpack(&taxdump)?;
+        let taxdump = unpacked
+            .as_ref()
+            .map_or(taxdump, |unpacked| unpacked.path());
+        let parsed = match options.taxonomy_format {
+            Some(cli::TaxonomyFormat::NCBI) => parse_taxdump(taxdump),
+            Some(cli::TaxonomyFormat::GBIF) => parse_gbif(taxdump, csv),
+            Some(cli::TaxonomyFormat::ITIS) => parse_itis(taxdump, csv),
+            Some(cli::TaxonomyFormat::ColDP) => parse_coldp(taxdump, csv),
+            Some(cli::TaxonomyFormat::GTDB) => parse_gtdb(taxdump, csv),
+            Some(cli::TaxonomyFormat::SILVA) => parse_silva(taxdump, csv),
+            Some(cli::TaxonomyFormat::WoRMS) => parse_worms(taxdump, csv),
+            Some(cli::TaxonomyFormat::IRMNG) => parse_irmng(taxdump, csv),
+            Some(cli::TaxonomyFormat::DwCA) => parse_dwca(taxdump, csv),
+            Some(cli::TaxonomyFormat::Datasets) => parse_datasets(taxdump),
+            Some(cli::TaxonomyFormat::ENA) => parse_ena_jsonl(taxdump),
+            Some(cli::TaxonomyFormat::BOLD) => parse_bold(taxdump, csv),
+            Some(cli::TaxonomyFormat::Lineage) => parse_lineage_tsv(taxdump),
             None => {
                 return Err(error::Error::FileNotFound(format!(
                     "{}",
@@ -120,16 +469,86 @@ fn taxdump_to_nodes(options: &cli::TaxonomyOptions) -> Result<Nodes, error::Erro
                 )))
             }
         };
+        utils::check_cancelled()?;
+        nodes = parsed.map_err(|err| error::Error::InvalidTaxonomy(err.to_string()))?;
+        nodes.shrink_to_fit();
     } else {
         return Err(error::Error::NotDefined(format!("taxdump")));
     }
+    if let Some(rank_aliases) = options.rank_aliases.as_ref() {
+        let count = nodes.alias_ranks(rank_aliases);
+        if count > 0 {
+            eprintln!("Renamed ranks of {} nodes", count);
+        }
+    }
+    if let Some(roll_up) = options.roll_up.as_ref() {
+        let count = nodes.roll_up(roll_up);
+        if count > 0 {
+            eprintln!("Rolled up {} nodes into their parent species", count);
+        }
+    }
     Ok(nodes)
 }
 
+/// Build a lookup table for the backbone, loading it from `cache_dir` if
+/// there is an entry for `key`. Only tables held in memory are cached.
+fn lookup_table(
+    nodes: &Nodes,
+    options: &cli::TaxonomyOptions,
+    ranks: &LookupRanks,
+    key: Option<String>,
+) -> Result<LookupTable, anyhow::Error> {
+    let cache_dir = options
+        .cache_dir
+        .as_ref()
+        .map(|cache_dir| utils::cache_dir(cache_dir, "taxonomy"));
+    let cache = cache_dir.zip(key);
+    if let Some((cache_dir, key)) = cache.as_ref() {
+        if let Some(table) = cache::load_lookup(cache_dir, key) {
+            return Ok(LookupTable::Memory(table));
+        }
+    }
+    let table = build_lookup(
+        nodes,
+        &options.name_classes,
+        ranks,
+        options.case_sensitive,
+        options.max_memory,
+    )?;
+    if let (Some((cache_dir, key)), LookupTable::Memory(table)) = (cache.as_ref(), &table) {
+        if let Err(err) = cache::save_lookup(cache_dir, key, table) {
+            eprintln!("Unable to cache lookup table: {}", err);
+        }
+    }
+    Ok(table)
+}
+
 /// Execute the `taxonomy` subcommand from `blobtk`.
 pub fn taxonomy(options: &cli::TaxonomyOptions) -> Result<(), anyhow::Error> {
+    match &options.cmd {
+        Some(cli::TaxonomyCommand::Diff(diff_options)) => return diff::diff(diff_options),
+        Some(cli::TaxonomyCommand::Lca(lca_options)) => return lca::lca(lca_options),
+        Some(cli::TaxonomyCommand::Lookup(lookup_options)) => {
+            return lookup::lookup(lookup_options)
+        }
+        Some(cli::TaxonomyCommand::Serve(serve_options)) => return serve::serve(serve_options),
+        Some(cli::TaxonomyCommand::Annotate(annotate_options)) => {
+            return annotate::annotate(annotate_options)
+        }
+        Some(cli::TaxonomyCommand::Lineage(lineage_options)) => {
+            return lineage::lineage(lineage_options)
+        }
+        Some(cli::TaxonomyCommand::Fetch(fetch_options)) => return fetch::fetch(fetch_options),
+        Some(cli::TaxonomyCommand::Validate(validate_options)) => {
+            return validate::validate(validate_options)
+        }
+        None => (),
+    }
     let options = load_options(&options)?;
-    let mut nodes = taxdump_to_nodes(&options).unwrap();
+    let timer = utils::stage_timer("parse");
+    let mut csv = CsvContext::default();
+    let (mut nodes, mut nodes_key) = cached_nodes(&options, &mut csv)?;
+    drop(timer);
     // if let Some(taxdump) = options.path.clone() {
     //     nodes = match options.taxonomy_format {
     //         Some(cli::TaxonomyFormat::NCBI) => parse_taxdump(taxdump)?,
@@ -145,24 +564,129 @@ pub fn taxonomy(options: &cli::TaxonomyOptions) -> Result<(), anyhow::Error> {
     //     }
     // }
 
+    let mut lookups = vec![];
     if let Some(taxonomies) = options.taxonomies.clone() {
-        for taxonomy in taxonomies {
-            let new_nodes = taxdump_to_nodes(&taxonomy).unwrap();
+        let mut mapping = match &options.mapping_out {
+            Some(path) => {
+                let mut writer = io::get_writer(&Some(path.clone()));
+                writeln!(writer, "{}", lookup::MAPPING_HEADER)?;
+                Some(writer)
+            }
+            None => None,
+        };
+        let ranks = LookupRanks::new(options.ranks.clone(), options.higher_ranks.clone());
+        let mut minter = mint::TaxIdMinter::new(&options, &nodes)?;
+        // minted IDs may depend on the mapping file so later lookup tables
+        // are only cached for xref IDs
+        let deterministic_ids = matches!(options.mint, None | Some(cli::MintScheme::Xref));
+        for mut taxonomy in taxonomies {
+            // rank aliases and roll ups apply to all taxonomies unless set
+            // separately
+            if taxonomy.rank_aliases.is_none() {
+                taxonomy.rank_aliases = options.rank_aliases.clone();
+            }
+            if taxonomy.roll_up.is_none() {
+                taxonomy.roll_up = options.roll_up.clone();
+            }
+            if taxonomy.cache_dir.is_none() {
+                taxonomy.cache_dir = options.cache_dir.clone();
+            }
+            let timer = utils::stage_timer("parse");
+            let (new_nodes, new_nodes_key) = cached_nodes(&taxonomy, &mut csv)?;
+            drop(timer);
+            let timer = utils::stage_timer("lookup build");
+            let lookup_key = nodes_key.as_ref().map(|key| {
+                cache::combine_keys(&[
+                    key,
+                    &format!("{:?}", options.name_classes),
+                    &format!("{:?}", ranks),
+                    &format!("{:?}", options.case_sensitive),
+                    &format!("{:?}", options.max_memory),
+                ])
+            });
+            let table = lookup_table(&nodes, &options, &ranks, lookup_key)?;
+            drop(timer);
             // match new_nodes to nodes
-            lookup_nodes(
+            let stats = lookup_nodes(
                 &new_nodes,
                 &mut nodes,
                 &taxonomy.name_classes,
                 &options.name_classes,
+                &ranks,
+                options.case_sensitive,
                 taxonomy.xref_label.clone(),
+                table,
+                &mut minter,
+                mapping.as_mut(),
             );
+            // the backbone now includes the mapped taxa
+            nodes_key = match (nodes_key, new_nodes_key) {
+                (Some(key), Some(new_key)) if deterministic_ids => {
+                    Some(cache::combine_keys(&[&key, &new_key]))
+                }
+                _ => None,
+            };
+            lookups.push(stats);
+            utils::check_cancelled()?;
         }
+        minter.save()?;
     }
 
+    let imputed = csv.take_imputed();
+    for (field, count) in imputed.iter() {
+        eprintln!("Filled {} missing {} values from defaults", count, field);
+    }
     if let Some(taxdump_out) = options.out.clone() {
-        let root_taxon_ids = options.root_taxon_id.clone();
+        let root_taxon_ids = root_taxon_ids(&nodes, &options)?;
         let base_taxon_id = options.base_taxon_id.clone();
-        write_taxdump(&nodes, root_taxon_ids, base_taxon_id, taxdump_out);
+        let _timer = utils::stage_timer("write");
+        if options.ranked_lineage {
+            write_ranked_lineage(
+                &nodes,
+                root_taxon_ids.clone(),
+                base_taxon_id.clone(),
+                taxdump_out.clone(),
+            );
+        }
+        match options.output_format {
+            Some(cli::TaxonomyOutputFormat::JSONL) => {
+                write_jsonl(&nodes, root_taxon_ids, base_taxon_id, taxdump_out)
+            }
+            Some(cli::TaxonomyOutputFormat::DOT) => {
+                write_dot(&nodes, root_taxon_ids, base_taxon_id, taxdump_out)
+            }
+            Some(cli::TaxonomyOutputFormat::GraphML) => {
+                write_graphml(&nodes, root_taxon_ids, base_taxon_id, taxdump_out)
+            }
+            Some(cli::TaxonomyOutputFormat::SQLite) => {
+                write_sqlite(&nodes, root_taxon_ids, base_taxon_id, taxdump_out)?
+            }
+            _ => {
+                let metadata = metadata::TaxdumpMetadata::new(
+                    &nodes,
+                    &options,
+                    &root_taxon_ids.clone().unwrap_or(vec!["1".to_string()]),
+                    base_taxon_id.clone(),
+                );
+                write_taxdump(
+                    &nodes,
+                    root_taxon_ids,
+                    base_taxon_id,
+                    taxdump_out,
+                    options.compress.clone(),
+                    options.sorted,
+                    Some(&metadata),
+                )?
+            }
+        }
+    }
+
+    // stats are written last so they include the timings of every stage
+    if let Some(stats) = options.stats.as_ref() {
+        let mut taxonomy_stats = TaxonomyStats::new(&nodes, lookups);
+        taxonomy_stats.imputed = imputed;
+        taxonomy_stats.timings = utils::timings();
+        write_stats(&taxonomy_stats, stats)?;
     }
 
     // if let Some(gbif_backbone) = options.gbif_backbone.clone() {
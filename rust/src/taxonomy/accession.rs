@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use anyhow;
+
+use super::file_reader;
+use super::parse::{Node, Nodes};
+
+/// Strip the version suffix from a sequence accession.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::accession::unversioned;
+/// assert_eq!(unversioned("NC_000001.11"), "NC_000001");
+/// assert_eq!(unversioned("NC_000001"), "NC_000001");
+/// ```
+pub fn unversioned(accession: &str) -> &str {
+    match accession.rsplit_once('.') {
+        Some((base, version)) if version.chars().all(|c| c.is_ascii_digit()) => base,
+        _ => accession,
+    }
+}
+
+/// A map of sequence or assembly accessions to taxon IDs.
+#[derive(Clone, Debug, Default)]
+pub struct AccessionMap {
+    taxids: HashMap<String, String>,
+}
+
+impl AccessionMap {
+    /// Load NCBI `*.accession2taxid` or `assembly_summary.txt` files, which
+    /// may be gzipped.
+    ///
+    /// Accession columns are found from the header, falling back to
+    /// `accession.version<tab>taxid` for files without one. If `accessions` is
+    /// given only matching entries are kept, which keeps memory use low for
+    /// the full NCBI mapping files.
+    pub fn from_files(
+        paths: &[PathBuf],
+        accessions: Option<&HashSet<String>>,
+    ) -> Result<AccessionMap, anyhow::Error> {
+        let wanted: Option<HashSet<&str>> =
+            accessions.map(|accessions| accessions.iter().map(|a| unversioned(a)).collect());
+        let mut taxids = HashMap::new();
+        for path in paths {
+            let reader = match file_reader(path.clone()) {
+                Some(reader) => reader,
+                None => continue,
+            };
+            let mut columns = (0, 1);
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                let cols: Vec<&str> = line.split('\t').collect();
+                if (i == 0 || line.starts_with('#')) && cols.iter().any(|col| col.trim() == "taxid")
+                {
+                    let index = |names: &[&str]| {
+                        cols.iter()
+                            .position(|col| names.contains(&col.trim_start_matches("# ")))
+                    };
+                    columns = match (
+                        index(&["accession.version", "assembly_accession"]),
+                        index(&["taxid"]),
+                    ) {
+                        (Some(accession), Some(taxid)) => (accession, taxid),
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "no accession column in {}",
+                                path.display()
+                            ))
+                        }
+                    };
+                    continue;
+                }
+                if line.starts_with('#') || cols.len() <= columns.0.max(columns.1) {
+                    continue;
+                }
+                let accession = cols[columns.0];
+                if let Some(wanted) = &wanted {
+                    if !wanted.contains(unversioned(accession)) {
+                        continue;
+                    }
+                }
+                let taxid = cols[columns.1].to_string();
+                taxids.insert(unversioned(accession).to_string(), taxid.clone());
+                taxids.insert(accession.to_string(), taxid);
+            }
+        }
+        Ok(AccessionMap { taxids })
+    }
+
+    /// Number of accessions in the map, counting versioned and unversioned
+    /// forms separately.
+    pub fn len(&self) -> usize {
+        self.taxids.len()
+    }
+
+    /// Whether the map has no accessions.
+    pub fn is_empty(&self) -> bool {
+        self.taxids.is_empty()
+    }
+
+    /// Get the taxon ID for an accession, falling back to the unversioned
+    /// accession if there is no exact match.
+    pub fn taxid(&self, accession: &str) -> Option<&String> {
+        self.taxids
+            .get(accession)
+            .or_else(|| self.taxids.get(unversioned(accession)))
+    }
+
+    /// Resolve an accession to a `Node`.
+    pub fn node<'a>(&self, accession: &str, nodes: &'a Nodes) -> Option<&'a Node> {
+        nodes.nodes.get(self.taxid(accession)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_accession_map() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let paths = [
+            PathBuf::from("test/taxonomy/accession2taxid/nucl_gb.accession2taxid.gz"),
+            PathBuf::from("test/taxonomy/accession2taxid/assembly_summary.txt"),
+        ];
+        let map = AccessionMap::from_files(&paths, None).unwrap();
+        assert_eq!(map.taxid("MN123456.1"), Some(&"9612".to_string()));
+        assert_eq!(map.taxid("MN123456.2"), Some(&"9612".to_string()));
+        assert_eq!(
+            map.node("GCA_000002285.4", &nodes).unwrap().rank,
+            "subspecies"
+        );
+        assert!(map.node("XX000000.1", &nodes).is_none());
+
+        let wanted = HashSet::from(["MN123457".to_string()]);
+        let map = AccessionMap::from_files(&paths, Some(&wanted)).unwrap();
+        assert_eq!(map.taxid("MN123456.1"), None);
+        assert_eq!(map.taxid("MN123457"), Some(&"9611".to_string()));
+    }
+}
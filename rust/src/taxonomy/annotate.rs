@@ -0,0 +1,505 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow;
+
+use super::archive;
+use super::lookup::NameIndex;
+use super::parse::{parse_delnodes, Node, Nodes};
+use super::{file_reader, taxdump_to_nodes};
+use crate::cli;
+use crate::io;
+
+/// A taxon and its ancestors by rank.
+fn lineage_by_rank<'a>(nodes: &'a Nodes, tax_id: &String) -> HashMap<&'a str, &'a Node> {
+    let mut by_rank = HashMap::new();
+    if let Some(node) = nodes.nodes.get(tax_id) {
+        for n in nodes
+            .lineage(&"1".to_string(), tax_id)
+            .into_iter()
+            .chain([node])
+        {
+            by_rank.insert(n.rank.as_str(), n);
+        }
+    }
+    by_rank
+}
+
+/// Lineage columns for a taxon: the scientific name of the taxon or
+/// ancestor at each rank, followed by its taxon ID if `ids` is set. Ranks
+/// missing from the lineage are left empty.
+pub fn lineage_columns(nodes: &Nodes, tax_id: &String, ranks: &[String], ids: bool) -> Vec<String> {
+    let by_rank = lineage_by_rank(nodes, tax_id);
+    let mut columns = vec![];
+    for rank in ranks {
+        let node = by_rank.get(rank.as_str());
+        columns.push(node.map(|n| n.scientific_name()).unwrap_or_default());
+        if ids {
+            columns.push(node.map(|n| n.tax_id.clone()).unwrap_or_default());
+        }
+    }
+    columns
+}
+
+/// Rank and name pairs from a row that contradict the lineage of a taxon,
+/// formatted as `rank=name`.
+///
+/// A name agrees with the lineage if it is the scientific name, or a name
+/// in `name_classes`, of the taxon or ancestor at that rank. Empty names
+/// and ranks missing from the lineage are not checked.
+pub fn rank_conflicts(
+    nodes: &Nodes,
+    tax_id: &String,
+    given: &[(&str, &str)],
+    name_classes: &Vec<String>,
+    case_sensitive: bool,
+) -> Vec<String> {
+    let by_rank = lineage_by_rank(nodes, tax_id);
+    let mut conflicts = vec![];
+    for (rank, name) in given {
+        if name.is_empty() {
+            continue;
+        }
+        if let Some(node) = by_rank.get(rank) {
+            if !has_name(node, name, name_classes, case_sensitive) {
+                conflicts.push(format!("{}={}", rank, name));
+            }
+        }
+    }
+    conflicts
+}
+
+/// Check whether a name is the scientific name, or a name in
+/// `name_classes`, of a taxon.
+fn has_name(node: &Node, name: &str, name_classes: &Vec<String>, case_sensitive: bool) -> bool {
+    let same = |a: &str, b: &str| match case_sensitive {
+        true => a == b,
+        false => a.to_lowercase() == b.to_lowercase(),
+    };
+    same(&node.scientific_name(), name)
+        || node
+            .names_by_class(Some(name_classes), false)
+            .iter()
+            .any(|n| same(n, name))
+}
+
+/// Choose between taxa sharing a name using the names given for higher
+/// ranks in a row.
+///
+/// Each of `higher_ranks` with a name in `given` is tried in turn, keeping
+/// the taxa with that name at that rank in their lineage, until a single
+/// taxon is left. A rank that no candidate agrees with is skipped.
+pub fn narrow_by_ranks(
+    nodes: &Nodes,
+    tax_ids: Vec<String>,
+    given: &[(&str, &str)],
+    higher_ranks: &[String],
+    name_classes: &Vec<String>,
+    case_sensitive: bool,
+) -> Vec<String> {
+    let mut candidates = tax_ids;
+    for rank in higher_ranks {
+        if candidates.len() < 2 {
+            break;
+        }
+        let name = match given.iter().find(|(given_rank, _)| given_rank == rank) {
+            Some((_, name)) if !name.trim().is_empty() => name.trim(),
+            _ => continue,
+        };
+        let agreeing: Vec<String> = candidates
+            .iter()
+            .filter(|tax_id| {
+                lineage_by_rank(nodes, tax_id)
+                    .get(rank.as_str())
+                    .is_some_and(|node| has_name(node, name, name_classes, case_sensitive))
+            })
+            .cloned()
+            .collect();
+        if !agreeing.is_empty() {
+            candidates = agreeing;
+        }
+    }
+    candidates
+}
+
+/// Normalise the fields of an input row.
+///
+/// Values are trimmed and values in rank columns that agree with the
+/// lineage are replaced by the canonical scientific name at that rank.
+/// Values listed in `conflicts` (as `rank=name`) are kept as given.
+pub fn normalise_fields(
+    fields: &[&str],
+    rank_columns: &[(usize, String)],
+    canonical: &[String],
+    conflicts: &[String],
+) -> Vec<String> {
+    let mut normalised: Vec<String> = fields.iter().map(|f| f.trim().to_string()).collect();
+    for ((i, rank), name) in rank_columns.iter().zip(canonical) {
+        let value = match fields.get(*i) {
+            Some(value) => value,
+            None => continue,
+        };
+        if name.is_empty()
+            || value.trim().is_empty()
+            || conflicts.contains(&format!("{}={}", rank, value))
+        {
+            continue;
+        }
+        normalised[*i] = name.clone();
+    }
+    normalised
+}
+
+/// Execute the `taxonomy annotate` subcommand from `blobtk`.
+pub fn annotate(options: &cli::TaxonomyAnnotateOptions) -> Result<(), anyhow::Error> {
+    let nodes = taxdump_to_nodes(&cli::TaxonomyOptions {
+        path: Some(options.path.clone()),
+        taxonomy_format: Some(options.taxonomy_format.clone()),
+        ..Default::default()
+    })?;
+    let index = NameIndex::new(&nodes, &options.name_classes, options.case_sensitive, false);
+    // deleted taxon IDs are flagged rather than matched as names
+    let deleted = match options.taxonomy_format {
+        cli::TaxonomyFormat::NCBI => {
+            let unpacked = archive::unpack(&options.path)?;
+            parse_delnodes(
+                &unpacked
+                    .as_ref()
+                    .map_or(options.path.clone(), |unpacked| unpacked.path()),
+            )
+        }
+        _ => HashSet::new(),
+    };
+    let mut lines = match &options.input {
+        Some(path) if path != Path::new("-") => file_reader(path.clone()).unwrap(),
+        _ => Box::new(BufReader::new(std::io::stdin().lock())),
+    }
+    .lines();
+
+    let mut writer = io::get_writer(&options.output);
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Ok(()),
+    };
+    let column = match header.split('\t').position(|h| h == options.column) {
+        Some(column) => column,
+        None => anyhow::bail!("no {} column in input header", options.column),
+    };
+    // input columns named after ranks are used to choose between taxa
+    // sharing a name and are checked against matched lineages
+    let ranks: HashSet<&str> = nodes
+        .nodes
+        .values()
+        .map(|node| node.rank.as_str())
+        .filter(|rank| !matches!(*rank, "no rank" | "clade"))
+        .collect();
+    let rank_columns: Vec<(usize, String)> = header
+        .split('\t')
+        .enumerate()
+        .filter(|(i, h)| *i != column && ranks.contains(h))
+        .map(|(i, h)| (i, h.to_string()))
+        .collect();
+    let mut new_headers = vec![];
+    for rank in options.ranks.iter() {
+        new_headers.push(rank.clone());
+        if options.ids {
+            new_headers.push(format!("{}_tax_id", rank));
+        }
+    }
+    if options.check_ranks {
+        new_headers.push("status".to_string());
+        new_headers.push("conflicts".to_string());
+    }
+    writeln!(writer, "{}\t{}", header, new_headers.join("\t"))?;
+    // rejected rows are written verbatim so they can be fixed and re-run
+    let mut rejects = match &options.rejects {
+        Some(path) => {
+            let mut rejects = io::get_writer(&Some(path.clone()));
+            writeln!(rejects, "{}\terror", header)?;
+            Some(rejects)
+        }
+        None => None,
+    };
+    // processed rows mirror the input so they can be indexed without
+    // matching again
+    let mut processed = match &options.processed {
+        Some(path) => {
+            let mut processed = io::get_writer(&Some(path.clone()));
+            writeln!(processed, "{}\tassigned_taxon_id\tmatch_status", header)?;
+            Some(processed)
+        }
+        None => None,
+    };
+    let rank_names: Vec<String> = rank_columns.iter().map(|(_, rank)| rank.clone()).collect();
+    let header_count = header.split('\t').count();
+
+    let (mut rows, mut unmatched, mut removed, mut inconsistent) = (0, 0, 0, 0);
+    let mut rejected = 0;
+    for line in lines {
+        let line = line?;
+        rows += 1;
+        let fields: Vec<&str> = line.split('\t').collect();
+        let value = fields.get(column).copied().unwrap_or_default();
+        let is_deleted = !nodes.nodes.contains_key(value) && deleted.contains(value);
+        let given: Vec<(&str, &str)> = rank_columns
+            .iter()
+            .map(|(i, rank)| (rank.as_str(), fields.get(*i).copied().unwrap_or_default()))
+            .collect();
+        let mut error = if fields.len() != header_count {
+            Some(format!(
+                "parse failure: expected {} columns, found {}",
+                header_count,
+                fields.len()
+            ))
+        } else if value.trim().is_empty() {
+            Some(format!("constraint violation: empty {}", options.column))
+        } else {
+            None
+        };
+        let mut status = match error {
+            Some(_) => "invalid",
+            None => "matched",
+        };
+        let tax_id = match nodes.nodes.contains_key(value) {
+            true => Some(value.to_string()),
+            false if is_deleted => {
+                error.get_or_insert(format!("taxonomy mismatch: taxon ID {} deleted", value));
+                None
+            }
+            false => {
+                let tax_ids = narrow_by_ranks(
+                    &nodes,
+                    index.get(value),
+                    &given,
+                    &options.higher_ranks,
+                    &options.name_classes,
+                    options.case_sensitive,
+                );
+                match tax_ids.len() {
+                    1 => tax_ids.into_iter().next(),
+                    count => {
+                        if error.is_none() {
+                            status = match count {
+                                0 => "unmatched",
+                                _ => "ambiguous",
+                            };
+                        }
+                        error.get_or_insert(match count {
+                            0 => format!("taxonomy mismatch: no taxon matches {}", value),
+                            _ => format!("taxonomy mismatch: {} taxa match {}", count, value),
+                        });
+                        None
+                    }
+                }
+            }
+        };
+        let conflicts = match &tax_id {
+            Some(tax_id) => rank_conflicts(
+                &nodes,
+                tax_id,
+                &given,
+                &options.name_classes,
+                options.case_sensitive,
+            ),
+            None => vec![],
+        };
+        if !conflicts.is_empty() {
+            if error.is_none() {
+                status = "inconsistent";
+            }
+            if options.check_ranks {
+                inconsistent += 1;
+                error.get_or_insert(format!("taxonomy mismatch: {}", conflicts.join(";")));
+            }
+        }
+        let columns = match &tax_id {
+            Some(tax_id) => {
+                let mut columns = lineage_columns(&nodes, tax_id, &options.ranks, options.ids);
+                if options.check_ranks {
+                    columns.push(
+                        match conflicts.is_empty() {
+                            true => "matched",
+                            false => "inconsistent",
+                        }
+                        .to_string(),
+                    );
+                    columns.push(conflicts.join(";"));
+                }
+                columns
+            }
+            None => {
+                let row_status = match is_deleted {
+                    true => {
+                        removed += 1;
+                        "deleted"
+                    }
+                    false => {
+                        unmatched += 1;
+                        "unmatched"
+                    }
+                };
+                if is_deleted && status != "invalid" {
+                    status = "deleted";
+                }
+                let mut columns = vec![String::new(); new_headers.len()];
+                if options.check_ranks {
+                    columns[new_headers.len() - 2] = row_status.to_string();
+                }
+                columns
+            }
+        };
+        writeln!(writer, "{}\t{}", line, columns.join("\t"))?;
+        if let Some(processed) = processed.as_mut() {
+            let canonical = match &tax_id {
+                Some(tax_id) => lineage_columns(&nodes, tax_id, &rank_names, false),
+                None => vec![String::new(); rank_names.len()],
+            };
+            let mut normalised = normalise_fields(&fields, &rank_columns, &canonical, &conflicts);
+            // keep the added columns aligned for rows with the wrong number
+            // of fields, which are written verbatim to the rejects file
+            normalised.resize(header_count, String::new());
+            let assigned = match (status, &tax_id) {
+                ("matched", Some(tax_id)) => tax_id.clone(),
+                _ => String::new(),
+            };
+            normalised.push(assigned);
+            normalised.push(status.to_string());
+            writeln!(processed, "{}", normalised.join("\t"))?;
+        }
+        if let (Some(rejects), Some(error)) = (rejects.as_mut(), error) {
+            rejected += 1;
+            writeln!(rejects, "{}\t{}", line, error)?;
+        }
+    }
+    if let Some(path) = &options.processed {
+        eprintln!("Wrote {} processed rows to {}", rows, path.display());
+    }
+    if let Some(path) = &options.rejects {
+        eprintln!("Wrote {} rejected rows to {}", rejected, path.display());
+    }
+    if unmatched > 0 {
+        eprintln!("No single taxon found for {} of {} rows", unmatched, rows);
+    }
+    if removed > 0 {
+        eprintln!(
+            "Taxon ID deleted from the taxonomy in {} of {} rows",
+            removed, rows
+        );
+    }
+    if inconsistent > 0 {
+        eprintln!(
+            "Rank columns contradict the matched lineage in {} of {} rows",
+            inconsistent, rows
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_lineage_columns() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let ranks: Vec<String> = ["family", "species", "subspecies"]
+            .iter()
+            .map(|rank| rank.to_string())
+            .collect();
+        assert_eq!(
+            lineage_columns(&nodes, &"9612".to_string(), &ranks, false),
+            vec!["Canidae", "Canis lupus", ""]
+        );
+        assert_eq!(
+            lineage_columns(&nodes, &"9615".to_string(), &ranks[1..], true),
+            vec!["Canis lupus", "9612", "Canis lupus familiaris", "9615"]
+        );
+        assert_eq!(
+            lineage_columns(&nodes, &"unknown".to_string(), &ranks, false),
+            vec!["", "", ""]
+        );
+    }
+
+    #[test]
+    fn test_normalise_fields() {
+        let fields = ["9612 ", "canidae", " Felidae", ""];
+        let rank_columns = vec![
+            (1, "family".to_string()),
+            (2, "family".to_string()),
+            (3, "genus".to_string()),
+        ];
+        let canonical: Vec<String> = ["Canidae", "Canidae", "Canis"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(
+            normalise_fields(
+                &fields,
+                &rank_columns,
+                &canonical,
+                &["family= Felidae".to_string()]
+            ),
+            vec!["9612", "Canidae", "Felidae", ""]
+        );
+    }
+
+    #[test]
+    fn test_narrow_by_ranks() {
+        let mut nodes = Nodes::default();
+        nodes.insert_root();
+        for (tax_id, parent_tax_id, rank, name) in [
+            ("o1", "1", "order", "Carnivora"),
+            ("f1", "o1", "family", "Canidae"),
+            ("f2", "o1", "family", "Felidae"),
+            ("g1", "f1", "genus", "Homonym"),
+            ("g2", "f2", "genus", "Homonym"),
+        ] {
+            nodes.insert(Node {
+                tax_id: tax_id.to_string(),
+                parent_tax_id: parent_tax_id.to_string(),
+                rank: rank.into(),
+                scientific_name: Some(name.to_string()),
+                ..Default::default()
+            });
+        }
+        let tax_ids = vec!["g1".to_string(), "g2".to_string()];
+        let ranks: Vec<String> = ["family", "order"].iter().map(|r| r.to_string()).collect();
+        let narrow = |given: &[(&str, &str)]| {
+            let mut narrowed =
+                narrow_by_ranks(&nodes, tax_ids.clone(), given, &ranks, &vec![], false);
+            narrowed.sort();
+            narrowed
+        };
+        assert_eq!(
+            narrow(&[("order", "Carnivora"), ("family", "felidae")]),
+            vec!["g2"]
+        );
+        // unknown and missing names are skipped
+        assert_eq!(narrow(&[("family", "Ursidae")]), vec!["g1", "g2"]);
+        assert_eq!(
+            narrow(&[("order", "Carnivora"), ("family", "")]),
+            vec!["g1", "g2"]
+        );
+    }
+
+    #[test]
+    fn test_rank_conflicts() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let classes = vec!["synonym".to_string()];
+        let given = [("family", "canidae"), ("genus", ""), ("subspecies", "x")];
+        assert!(rank_conflicts(&nodes, &"9612".to_string(), &given, &classes, false).is_empty());
+        assert_eq!(
+            rank_conflicts(&nodes, &"9612".to_string(), &given, &classes, true),
+            vec!["family=canidae"]
+        );
+        let given = [("family", "Felidae"), ("order", "Carnivora")];
+        assert_eq!(
+            rank_conflicts(&nodes, &"9612".to_string(), &given, &classes, false),
+            vec!["family=Felidae"]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::error;
+use crate::utils;
+
+/// A taxonomy archive unpacked to a scratch directory that is removed when
+/// dropped.
+pub struct Unpacked {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Unpacked {
+    /// Path to parse, the single file or directory in the archive if there
+    /// is only one, otherwise the directory it was unpacked to.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+}
+
+impl Drop for Unpacked {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Check whether a path is a `.tar.gz`, `.tgz`, `.tar` or `.zip` archive.
+///
+/// ```
+/// use std::path::Path;
+/// use blobtk::taxonomy::archive::is_archive;
+///
+/// assert!(is_archive(Path::new("taxdump.tar.gz")));
+/// assert!(is_archive(Path::new("backbone.zip")));
+/// assert!(!is_archive(Path::new("nodes.dmp.gz")));
+/// ```
+pub fn is_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+/// Unpack a taxonomy archive to a scratch directory, or return `None` if
+/// `path` is not an archive file.
+pub fn unpack(path: &Path) -> Result<Option<Unpacked>, error::Error> {
+    if !path.is_file() || !is_archive(path) {
+        return Ok(None);
+    }
+    let dir = utils::scratch_dir("archive")?;
+    let mut unpacked = Unpacked {
+        dir: dir.clone(),
+        path: dir.clone(),
+    };
+    let name = path.to_string_lossy().to_lowercase();
+    let reader = BufReader::new(File::open(path)?);
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(reader).map_err(std::io::Error::from)?;
+        archive.extract(&dir).map_err(std::io::Error::from)?;
+    } else if name.ends_with(".tar") {
+        tar::Archive::new(reader).unpack(&dir)?;
+    } else {
+        tar::Archive::new(GzDecoder::new(reader)).unpack(&dir)?;
+    }
+    let entries: Vec<PathBuf> = fs::read_dir(&dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    if let [entry] = entries.as_slice() {
+        unpacked.path = entry.clone();
+    }
+    Ok(Some(unpacked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_unpack() {
+        let taxdump = PathBuf::from("test/taxonomy/canidae/ncbi");
+        let dir = utils::scratch_dir("test_unpack").unwrap();
+        let path = dir.join("taxdump.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        for file in ["nodes.dmp", "names.dmp"] {
+            writer
+                .start_file(file, zip::write::FileOptions::default())
+                .unwrap();
+            writer
+                .write_all(&fs::read(taxdump.join(file)).unwrap())
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let unpacked = unpack(&path).unwrap().unwrap();
+        let unpacked_dir = unpacked.path();
+        assert_eq!(
+            parse_taxdump(unpacked_dir.clone()).unwrap(),
+            parse_taxdump(taxdump.clone()).unwrap()
+        );
+        drop(unpacked);
+        assert!(!unpacked_dir.exists());
+        assert!(unpack(&taxdump).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow;
+use crc32fast;
+use rmp_serde;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::parse::Nodes;
+use crate::cli;
+use crate::utils;
+
+/// Add the contents of a file, or of all files in a directory in path
+/// order, to a checksum, returning the number of bytes read.
+fn hash_path(hasher: &mut crc32fast::Hasher, path: &Path) -> std::io::Result<u64> {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        entries.sort();
+        let mut length = 0;
+        for entry in entries {
+            hasher.update(
+                entry
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_bytes(),
+            );
+            length += hash_path(hasher, &entry)?;
+        }
+        return Ok(length);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = vec![0; 1 << 20];
+    let mut length = 0;
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(length);
+        }
+        hasher.update(&buffer[..count]);
+        length += count as u64;
+    }
+}
+
+/// Cache key for a taxonomy, from the blobtk version, the options used to
+/// parse it and the contents of its input files, including vocabulary and
+/// mint mapping files named in the options.
+///
+/// Options that do not affect parsing are included too, so changing them
+/// gives a new key rather than risking a stale entry.
+pub fn taxonomy_key(options: &cli::TaxonomyOptions) -> Result<String, anyhow::Error> {
+    let path = match options.path.as_ref() {
+        Some(path) => path,
+        None => anyhow::bail!("no taxonomy path to cache"),
+    };
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    // serde_json::Value sorts map keys, so the options serialize consistently
+    let mut settings = serde_json::to_value(cli::TaxonomyOptions {
+        taxonomies: None,
+        ..options.clone()
+    })?;
+    if let Some(settings) = settings.as_object_mut() {
+        settings.remove("path");
+        settings.remove("cache_dir");
+    }
+    hasher.update(settings.to_string().as_bytes());
+    let mut length = hash_path(&mut hasher, path)?;
+    let mut option_files: Vec<(&String, &PathBuf)> = options
+        .field_normalization
+        .iter()
+        .flatten()
+        .filter_map(|(field, normalization)| {
+            normalization
+                .vocabulary
+                .as_ref()
+                .map(|vocabulary| (field, vocabulary))
+        })
+        .collect();
+    option_files.sort();
+    for (_, file) in option_files {
+        length += hash_path(&mut hasher, file)?;
+    }
+    // the mapping file is only read if it exists
+    if let Some(mint_map) = options.mint_map.as_ref().filter(|path| path.exists()) {
+        length += hash_path(&mut hasher, mint_map)?;
+    }
+    Ok(format!("{:08x}{:012x}", hasher.finalize(), length))
+}
+
+/// Combine cache keys, e.g. of a backbone and a taxonomy mapped onto it.
+///
+/// ```
+/// use blobtk::taxonomy::cache::combine_keys;
+///
+/// assert_eq!(combine_keys(&["a", "b"]), combine_keys(&["a", "b"]));
+/// assert_ne!(combine_keys(&["a", "b"]), combine_keys(&["b", "a"]));
+/// ```
+pub fn combine_keys(keys: &[&str]) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    for key in keys {
+        hasher.update(key.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:08x}{:012x}", hasher.finalize(), keys.len())
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    rmp_serde::from_read(reader).ok()
+}
+
+fn nodes_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("nodes_{}.msgpack", key))
+}
+
+fn imputed_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("imputed_{}.msgpack", key))
+}
+
+fn lookup_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("lookup_{}.msgpack", key))
+}
+
+/// Load parsed nodes from the cache.
+pub fn load_nodes(cache_dir: &Path, key: &str) -> Option<Nodes> {
+    read(&nodes_path(cache_dir, key))
+}
+
+/// Save parsed nodes to the cache.
+pub fn save_nodes(cache_dir: &Path, key: &str, nodes: &Nodes) -> Result<(), anyhow::Error> {
+    Ok(utils::write_msgpack(&nodes_path(cache_dir, key), nodes)?)
+}
+
+/// Load counts of values filled from defaults while parsing a taxonomy
+/// from the cache.
+pub fn load_imputed(cache_dir: &Path, key: &str) -> Option<BTreeMap<String, usize>> {
+    read(&imputed_path(cache_dir, key))
+}
+
+/// Save counts of values filled from defaults while parsing a taxonomy to
+/// the cache.
+pub fn save_imputed(
+    cache_dir: &Path,
+    key: &str,
+    imputed: &BTreeMap<String, usize>,
+) -> Result<(), anyhow::Error> {
+    Ok(utils::write_msgpack(
+        &imputed_path(cache_dir, key),
+        imputed,
+    )?)
+}
+
+/// Load an in-memory lookup table from the cache.
+pub fn load_lookup(cache_dir: &Path, key: &str) -> Option<HashMap<String, Vec<String>>> {
+    read(&lookup_path(cache_dir, key))
+}
+
+/// Save an in-memory lookup table to the cache.
+pub fn save_lookup(
+    cache_dir: &Path,
+    key: &str,
+    table: &HashMap<String, Vec<String>>,
+) -> Result<(), anyhow::Error> {
+    Ok(utils::write_msgpack(&lookup_path(cache_dir, key), table)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::parse::FieldNormalization;
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_nodes_cache() {
+        let path = PathBuf::from("test/taxonomy/canidae/ncbi");
+        let options = cli::TaxonomyOptions {
+            path: Some(path.clone()),
+            taxonomy_format: Some(cli::TaxonomyFormat::NCBI),
+            ..Default::default()
+        };
+        let key = taxonomy_key(&options).unwrap();
+        assert_eq!(key, taxonomy_key(&options).unwrap());
+        let other = cli::TaxonomyOptions {
+            taxonomy_format: Some(cli::TaxonomyFormat::GBIF),
+            ..options.clone()
+        };
+        assert_ne!(key, taxonomy_key(&other).unwrap());
+
+        // changing a vocabulary file changes the key
+        let vocabulary = std::env::temp_dir().join("blobtk_test_cache_vocabulary.tsv");
+        fs::write(&vocabulary, "sp.\tspecies\n").unwrap();
+        let with_vocabulary = cli::TaxonomyOptions {
+            field_normalization: Some(HashMap::from([(
+                "rank".to_string(),
+                FieldNormalization {
+                    vocabulary: Some(vocabulary.clone()),
+                    ..Default::default()
+                },
+            )])),
+            ..options.clone()
+        };
+        let vocabulary_key = taxonomy_key(&with_vocabulary).unwrap();
+        fs::write(&vocabulary, "ssp.\tsubspecies\n").unwrap();
+        assert_ne!(vocabulary_key, taxonomy_key(&with_vocabulary).unwrap());
+
+        let dir = std::env::temp_dir().join("blobtk_test_cache");
+        let _ = fs::remove_dir_all(&dir);
+        assert!(load_nodes(&dir, &key).is_none());
+        let nodes = parse_taxdump(path).unwrap();
+        save_nodes(&dir, &key, &nodes).unwrap();
+        assert_eq!(load_nodes(&dir, &key), Some(nodes));
+    }
+}
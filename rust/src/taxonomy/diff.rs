@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+
+use super::parse::Nodes;
+use super::taxdump_to_nodes;
+use crate::cli;
+use crate::io;
+
+/// A difference between two versions of a taxonomy.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct TaxonomyChange {
+    /// One of `added`, `removed`, `merged`, `rank` or `renamed`
+    pub change: &'static str,
+    pub tax_id: String,
+    /// Old rank or scientific name, empty for added taxa
+    pub old: String,
+    /// New rank or scientific name, the taxon ID a merged taxon was merged
+    /// into, or empty for removed taxa
+    pub new: String,
+}
+
+/// Parse an NCBI `merged.dmp` file into a map of old to new taxon IDs.
+pub fn parse_merged(path: &Path) -> HashMap<String, String> {
+    let mut merged = HashMap::new();
+    if let Ok(lines) = io::read_lines(path) {
+        for line in lines.map_while(Result::ok) {
+            let cols: Vec<&str> = line.trim_end_matches("\t|").split("\t|\t").collect();
+            if cols.len() >= 2 {
+                merged.insert(cols[0].to_string(), cols[1].to_string());
+            }
+        }
+    }
+    merged
+}
+
+/// Compare two taxonomies.
+///
+/// Taxa missing from `new` are reported as `merged` if they appear in
+/// `merged`, otherwise as `removed`. Changes are sorted by type, then by
+/// taxon ID.
+pub fn diff_nodes(
+    old: &Nodes,
+    new: &Nodes,
+    merged: &HashMap<String, String>,
+) -> Vec<TaxonomyChange> {
+    let mut changes = vec![];
+    let mut add = |change, tax_id: &String, old: String, new: String| {
+        changes.push(TaxonomyChange {
+            change,
+            tax_id: tax_id.clone(),
+            old,
+            new,
+        })
+    };
+    for (tax_id, node) in new.nodes.iter() {
+        if !old.nodes.contains_key(tax_id) {
+            add("added", tax_id, "".to_string(), node.scientific_name());
+        }
+    }
+    for (tax_id, old_node) in old.nodes.iter() {
+        match (new.nodes.get(tax_id), merged.get(tax_id)) {
+            (Some(new_node), _) => {
+                if old_node.rank != new_node.rank {
+                    add(
+                        "rank",
+                        tax_id,
+                        old_node.rank.to_string(),
+                        new_node.rank.to_string(),
+                    );
+                }
+                if old_node.scientific_name() != new_node.scientific_name() {
+                    add(
+                        "renamed",
+                        tax_id,
+                        old_node.scientific_name(),
+                        new_node.scientific_name(),
+                    );
+                }
+            }
+            (None, Some(new_tax_id)) => {
+                add(
+                    "merged",
+                    tax_id,
+                    old_node.scientific_name(),
+                    new_tax_id.clone(),
+                );
+            }
+            (None, None) => add(
+                "removed",
+                tax_id,
+                old_node.scientific_name(),
+                "".to_string(),
+            ),
+        }
+    }
+    let order = ["added", "removed", "merged", "rank", "renamed"];
+    changes.sort_by_key(|c| {
+        (
+            order.iter().position(|o| *o == c.change),
+            c.tax_id.len(),
+            c.tax_id.clone(),
+        )
+    });
+    changes
+}
+
+/// Execute the `taxonomy diff` subcommand from `blobtk`.
+pub fn diff(options: &cli::TaxonomyDiffOptions) -> Result<(), anyhow::Error> {
+    let load = |path: &Path| {
+        taxdump_to_nodes(&cli::TaxonomyOptions {
+            path: Some(path.to_path_buf()),
+            taxonomy_format: Some(options.taxonomy_format.clone()),
+            ..Default::default()
+        })
+    };
+    let old = load(&options.old)?;
+    let new = load(&options.new)?;
+    let merged = parse_merged(&options.new.join("merged.dmp"));
+    let changes = diff_nodes(&old, &new, &merged);
+
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "change\ttax_id\told\tnew")?;
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for change in changes.iter() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            change.change, change.tax_id, change.old, change.new
+        )?;
+        match counts.last_mut() {
+            Some((label, count)) if *label == change.change => *count += 1,
+            _ => counts.push((change.change, 1)),
+        }
+    }
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(label, count)| format!("{} {}", count, label))
+        .collect();
+    eprintln!(
+        "{} changes between {} and {} taxa: {}",
+        changes.len(),
+        old.nodes.len(),
+        new.nodes.len(),
+        summary.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_diff_nodes() {
+        let old = parse_taxdump(PathBuf::from("test/taxonomy/diff/old")).unwrap();
+        let new = parse_taxdump(PathBuf::from("test/taxonomy/diff/new")).unwrap();
+        let merged = parse_merged(Path::new("test/taxonomy/diff/new/merged.dmp"));
+        let changes: Vec<(&str, String, String, String)> = diff_nodes(&old, &new, &merged)
+            .into_iter()
+            .map(|c| (c.change, c.tax_id, c.old, c.new))
+            .collect();
+        let row = |change, tax_id: &str, old: &str, new: &str| {
+            (change, tax_id.to_string(), old.to_string(), new.to_string())
+        };
+        assert_eq!(
+            changes,
+            vec![
+                row("added", "6", "", "Canis familiaris"),
+                row("removed", "4", "Canis dirus", ""),
+                row("merged", "5", "Canis lupus lupus", "3"),
+                row("rank", "2", "subfamily", "genus"),
+                row("renamed", "3", "Canis lupis", "Canis lupus"),
+            ]
+        );
+    }
+}
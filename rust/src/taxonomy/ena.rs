@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow;
+use url::Url;
+
+use super::parse::{ena_lineage, ena_records_to_nodes, Nodes};
+use crate::io;
+
+const ENA_TAXONOMY_API: &str = "https://www.ebi.ac.uk/ena/taxonomy/rest/";
+
+/// Default delay between ENA taxonomy API requests in milliseconds.
+pub const DEFAULT_ENA_DELAY: u64 = 200;
+
+/// Rate limited and cached requests to the ENA taxonomy REST API.
+///
+/// Requests are made with the `curl` command, which must be available on
+/// the `PATH`.
+struct EnaClient {
+    cache: Option<PathBuf>,
+    delay: Duration,
+    last_request: Option<Instant>,
+}
+
+impl EnaClient {
+    fn cache_file(&self, endpoint: &str, query: &str) -> Option<PathBuf> {
+        let file_name: String = format!("{}_{}", endpoint, query)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.cache
+            .as_ref()
+            .map(|cache| cache.join(format!("{}.json", file_name)))
+    }
+
+    fn request(&mut self, url: &Url) -> Result<String, anyhow::Error> {
+        if let Some(last_request) = self.last_request {
+            let elapsed = last_request.elapsed();
+            if elapsed < self.delay {
+                sleep(self.delay - elapsed);
+            }
+        }
+        self.last_request = Some(Instant::now());
+        let output = Command::new("curl")
+            .args(["-sSf", "--retry", "3", url.as_str()])
+            .output()?;
+        match output.status.code() {
+            Some(0) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            // HTTP errors such as 404 are returned for unknown taxa
+            Some(22) => Ok("[]".to_string()),
+            _ => Err(anyhow::anyhow!(
+                "ENA request failed for {}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+        }
+    }
+
+    /// Get records from an endpoint, using a cached response if available.
+    fn get(
+        &mut self,
+        endpoint: &str,
+        query: &str,
+    ) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+        let cache_file = self.cache_file(endpoint, query);
+        let body = match &cache_file {
+            Some(path) if path.exists() => fs::read_to_string(path)?,
+            _ => {
+                let mut url = Url::parse(ENA_TAXONOMY_API)?;
+                url.path_segments_mut()
+                    .map_err(|_| anyhow::anyhow!("invalid ENA URL"))?
+                    .pop_if_empty()
+                    .push(endpoint)
+                    .push(query);
+                let body = self.request(&url)?;
+                if let Some(path) = &cache_file {
+                    let mut writer = io::get_writer(&Some(path.clone()));
+                    write!(writer, "{}", body)?;
+                }
+                body
+            }
+        };
+        let value: serde_json::Value = match body.trim() {
+            "" => return Ok(vec![]),
+            body => serde_json::from_str(body)?,
+        };
+        Ok(match value {
+            serde_json::Value::Array(records) => records,
+            record => vec![record],
+        })
+    }
+}
+
+fn record_path(record: &serde_json::Value) -> String {
+    let mut path = ena_lineage(record["lineage"].as_str().unwrap_or(""));
+    path.push(record["scientificName"].as_str().unwrap_or("").to_string());
+    path.join("; ")
+}
+
+/// Fetch records for a list of taxon IDs or scientific names and all of
+/// their ancestors from the ENA taxonomy REST API.
+///
+/// Responses are cached to disk when a cache directory is given, so
+/// repeated runs only request new taxa.
+pub fn fetch_ena_records(
+    queries: &[String],
+    cache: Option<&Path>,
+    delay: u64,
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let mut client = EnaClient {
+        cache: cache.map(Path::to_path_buf),
+        delay: Duration::from_millis(delay),
+        last_request: None,
+    };
+    let mut records = vec![];
+    let mut paths = HashSet::new();
+    for query in queries {
+        let found = if query.chars().all(|c| c.is_ascii_digit()) {
+            client.get("tax-id", query)?
+        } else {
+            client
+                .get("scientific-name", query)?
+                .into_iter()
+                .filter(|record| {
+                    record["scientificName"]
+                        .as_str()
+                        .is_some_and(|name| name.eq_ignore_ascii_case(query))
+                })
+                .collect()
+        };
+        if found.is_empty() {
+            eprintln!("No ENA taxonomy record found for {}", query);
+        }
+        for record in found {
+            let lineage = ena_lineage(record["lineage"].as_str().unwrap_or(""));
+            if !paths.insert(record_path(&record)) {
+                continue;
+            }
+            records.push(record);
+            // Fetch missing ancestors by name, from the parent upwards
+            for i in (0..lineage.len()).rev() {
+                if paths.contains(&lineage[..=i].join("; ")) {
+                    break;
+                }
+                let expected = lineage[..i].join("; ");
+                let ancestor = client
+                    .get("scientific-name", &lineage[i])?
+                    .into_iter()
+                    .find(|candidate| {
+                        ena_lineage(candidate["lineage"].as_str().unwrap_or("")).join("; ")
+                            == expected
+                    });
+                match ancestor {
+                    Some(ancestor) => {
+                        paths.insert(record_path(&ancestor));
+                        records.push(ancestor);
+                    }
+                    None => {
+                        eprintln!("No ENA taxonomy record found for ancestor {}", lineage[i]);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Fetch taxa from the ENA taxonomy REST API and convert them into `Nodes`,
+/// optionally writing the fetched records to a JSON Lines file for use with
+/// `parse_ena_jsonl`.
+pub fn fetch_ena(
+    queries: &[String],
+    cache: Option<&Path>,
+    delay: u64,
+    jsonl: Option<&Path>,
+) -> Result<Nodes, anyhow::Error> {
+    let records = fetch_ena_records(queries, cache, delay)?;
+    if let Some(jsonl) = jsonl {
+        let mut writer = io::get_writer(&Some(jsonl.to_path_buf()));
+        for record in records.iter() {
+            writeln!(writer, "{}", serde_json::to_string(record)?)?;
+        }
+    }
+    Ok(ena_records_to_nodes(&records))
+}
@@ -0,0 +1,202 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow;
+use flate2::read::GzDecoder;
+use md5::{Digest, Md5};
+use serde_json::json;
+
+use crate::cli;
+use crate::utils;
+
+const NCBI_TAXONOMY_URL: &str = "https://ftp.ncbi.nlm.nih.gov/pub/taxonomy";
+const GBIF_BACKBONE_URL: &str = "https://hosted-datasets.gbif.org/datasets/backbone";
+
+/// Download URL for a taxonomy release and the URL of its published MD5
+/// checksum, if there is one.
+///
+/// Releases are `current` or a `YYYY-MM-DD` date.
+///
+/// ```
+/// use blobtk::cli::TaxonomyFormat;
+/// use blobtk::taxonomy::fetch::release_url;
+///
+/// let (url, md5_url) = release_url(&TaxonomyFormat::NCBI, "2024-01-01").unwrap();
+/// assert!(url.ends_with("/taxdump_archive/taxdmp_2024-01-01.zip"));
+/// assert_eq!(md5_url, Some(format!("{}.md5", url)));
+/// assert!(release_url(&TaxonomyFormat::GBIF, "../current").is_err());
+/// ```
+pub fn release_url(
+    format: &cli::TaxonomyFormat,
+    release: &str,
+) -> Result<(String, Option<String>), anyhow::Error> {
+    let is_date = release.len() == 10
+        && release.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        });
+    if release != "current" && !is_date {
+        anyhow::bail!(
+            "release must be current or a YYYY-MM-DD date, not {}",
+            release
+        );
+    }
+    let url = match (format, release) {
+        (cli::TaxonomyFormat::NCBI, "current") => format!("{}/taxdump.tar.gz", NCBI_TAXONOMY_URL),
+        (cli::TaxonomyFormat::NCBI, date) => {
+            format!("{}/taxdump_archive/taxdmp_{}.zip", NCBI_TAXONOMY_URL, date)
+        }
+        (cli::TaxonomyFormat::GBIF, release) => {
+            return Ok((
+                format!("{}/{}/simple.txt.gz", GBIF_BACKBONE_URL, release),
+                None,
+            ))
+        }
+        (format, _) => anyhow::bail!("no download available for {:?} taxonomies", format),
+    };
+    let md5_url = format!("{}.md5", url);
+    Ok((url, Some(md5_url)))
+}
+
+/// MD5 checksum of a file as a hex string.
+fn file_md5(path: &Path) -> Result<String, anyhow::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Download a URL to a file with `curl`, returning false if the server
+/// reports it does not exist.
+fn download(url: &str, path: &Path) -> Result<bool, anyhow::Error> {
+    let output = Command::new("curl")
+        .args(["-sSfL", "--retry", "3", "-o"])
+        .arg(path)
+        .arg(url)
+        .output()?;
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(22) => Ok(false),
+        _ => Err(anyhow::anyhow!(
+            "download failed for {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Published checksum for a release, the first word of its `.md5` file.
+fn published_md5(md5_url: &str, dir: &Path) -> Result<Option<String>, anyhow::Error> {
+    let path = dir.join("published.md5.tmp");
+    let found = download(md5_url, &path)?;
+    let md5 = match found {
+        true => fs::read_to_string(&path)?
+            .split_whitespace()
+            .next()
+            .map(|md5| md5.to_lowercase()),
+        false => None,
+    };
+    let _ = fs::remove_file(&path);
+    Ok(md5)
+}
+
+/// Execute the `taxonomy fetch` subcommand from `blobtk`.
+///
+/// Releases are saved to `<cache_dir>/<format>/<release>/` with a
+/// `release.json` manifest recording the source URL and checksum, and the
+/// path to use with `--taxdump` is printed to STDOUT.
+pub fn fetch(options: &cli::TaxonomyFetchOptions) -> Result<(), anyhow::Error> {
+    let release = options.release.clone().unwrap_or("current".to_string());
+    let (url, md5_url) = release_url(&options.taxonomy_format, &release)?;
+    let cache_dir = utils::cache_dir(
+        options.cache_dir.as_deref().unwrap_or(Path::new("")),
+        "taxonomy",
+    );
+    let format = format!("{:?}", options.taxonomy_format).to_lowercase();
+    let dir = cache_dir.join(&format).join(&release);
+    let file_name = url.rsplit('/').next().unwrap().to_string();
+    let archive = dir.join(&file_name);
+    let manifest = dir.join("release.json");
+    // GBIF backbone files are parsed uncompressed
+    let taxdump: PathBuf = match options.taxonomy_format {
+        cli::TaxonomyFormat::GBIF => dir.join("backbone-simple.txt"),
+        _ => archive.clone(),
+    };
+
+    if !options.force && taxdump.exists() && manifest.exists() {
+        let recorded: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest)?)?;
+        let recorded_md5 = recorded["md5"].as_str().unwrap_or_default().to_string();
+        if let Some(md5) = options.md5.as_ref() {
+            if !md5.eq_ignore_ascii_case(&recorded_md5) {
+                anyhow::bail!(
+                    "cached {} {} release has checksum {}, not {}, use --force to download again",
+                    format,
+                    release,
+                    recorded_md5,
+                    md5
+                );
+            }
+        }
+        eprintln!("Using cached {} {} release", format, release);
+        println!("{}", taxdump.display());
+        return Ok(());
+    }
+
+    fs::create_dir_all(&dir)?;
+    let expected = match (options.md5.as_ref(), md5_url) {
+        (Some(md5), _) => Some(md5.to_lowercase()),
+        (None, Some(md5_url)) => published_md5(&md5_url, &dir)?,
+        (None, None) => None,
+    };
+    eprintln!("Downloading {}", url);
+    let tmp = dir.join(format!("{}.tmp{}", file_name, std::process::id()));
+    if !download(&url, &tmp)? {
+        anyhow::bail!("no {} release found at {}", format, url);
+    }
+    let md5 = file_md5(&tmp)?;
+    match expected {
+        Some(expected) if expected != md5 => {
+            let _ = fs::remove_file(&tmp);
+            anyhow::bail!(
+                "checksum mismatch for {}: expected {}, got {}",
+                url,
+                expected,
+                md5
+            );
+        }
+        Some(_) => eprintln!("Verified checksum {}", md5),
+        None => eprintln!("No published checksum, downloaded file has MD5 {}", md5),
+    }
+    fs::rename(&tmp, &archive)?;
+    if let cli::TaxonomyFormat::GBIF = options.taxonomy_format {
+        let mut reader = GzDecoder::new(BufReader::new(File::open(&archive)?));
+        io::copy(&mut reader, &mut File::create(&taxdump)?)?;
+        fs::remove_file(&archive)?;
+    }
+    let downloaded = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    fs::write(
+        &manifest,
+        serde_json::to_string_pretty(&json!({
+            "format": format,
+            "release": release,
+            "url": url,
+            "md5": md5,
+            "downloaded": downloaded,
+        }))?,
+    )?;
+    println!("{}", taxdump.display());
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow;
+
+use super::{file_reader, taxdump_to_nodes};
+use crate::cli;
+use crate::io;
+
+/// Split a line into taxon IDs separated by whitespace, commas or
+/// semicolons.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::lca::split_tax_ids;
+/// assert_eq!(split_tax_ids("9612, 9615;9627 "), vec!["9612", "9615", "9627"]);
+/// ```
+pub fn split_tax_ids(line: &str) -> Vec<String> {
+    line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|tax_id| !tax_id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Execute the `taxonomy lca` subcommand from `blobtk`.
+pub fn lca(options: &cli::TaxonomyLcaOptions) -> Result<(), anyhow::Error> {
+    let nodes = taxdump_to_nodes(&cli::TaxonomyOptions {
+        path: Some(options.path.clone()),
+        taxonomy_format: Some(options.taxonomy_format.clone()),
+        ..Default::default()
+    })?;
+    let reader: Box<dyn BufRead> = match &options.input {
+        Some(path) if path != Path::new("-") => file_reader(path.clone()).unwrap(),
+        _ => Box::new(BufReader::new(std::io::stdin().lock())),
+    };
+
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "tax_ids\tlca_tax_id\tscientific_name\trank")?;
+    let (mut lines, mut unresolved) = (0, 0);
+    for line in reader.lines() {
+        let tax_ids = split_tax_ids(&line?);
+        if tax_ids.is_empty() {
+            continue;
+        }
+        lines += 1;
+        let (tax_id, name, rank) = match nodes.lca(&tax_ids) {
+            Some(node) => (
+                node.tax_id.clone(),
+                node.scientific_name(),
+                node.rank.to_string(),
+            ),
+            None => {
+                unresolved += 1;
+                (String::new(), String::new(), String::new())
+            }
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            tax_ids.join(","),
+            tax_id,
+            name,
+            rank
+        )?;
+    }
+    if unresolved > 0 {
+        eprintln!(
+            "No taxon IDs found in the taxonomy for {} of {} lists",
+            unresolved, lines
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_lca() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let lca = |tax_ids: &[&str]| {
+            let tax_ids: Vec<String> = tax_ids.iter().map(|id| id.to_string()).collect();
+            nodes.lca(&tax_ids).map(|node| node.tax_id.clone())
+        };
+        assert_eq!(lca(&["9615"]), Some("9615".to_string()));
+        assert_eq!(lca(&["9615", "143281"]), Some("9612".to_string()));
+        assert_eq!(lca(&["9615", "9608"]), Some("9608".to_string()));
+        assert_eq!(lca(&["9615", "unknown"]), Some("9615".to_string()));
+        assert_eq!(lca(&["unknown"]), None);
+        assert_eq!(lca(&[]), None);
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow;
+
+use super::lookup::NameIndex;
+use super::parse::{Node, Nodes, RANKED_LINEAGE_RANKS};
+use super::{describe_candidates, taxdump_to_nodes};
+use crate::cli;
+use crate::error;
+
+/// Lineage of a taxon from the root, including the taxon itself, optionally
+/// restricted to the ranks in `rankedlineage.dmp`.
+pub fn lineage_nodes<'a>(nodes: &'a Nodes, tax_id: &String, canonical: bool) -> Vec<&'a Node> {
+    let mut lineage = nodes.lineage(&"1".to_string(), tax_id);
+    lineage.extend(nodes.nodes.get(tax_id));
+    lineage
+        .into_iter()
+        .filter(|node| !canonical || RANKED_LINEAGE_RANKS.contains(&node.rank.as_str()))
+        .collect()
+}
+
+/// Execute the `taxonomy lineage` subcommand from `blobtk`.
+pub fn lineage(options: &cli::TaxonomyLineageOptions) -> Result<(), anyhow::Error> {
+    let nodes = taxdump_to_nodes(&cli::TaxonomyOptions {
+        path: Some(options.path.clone()),
+        taxonomy_format: Some(options.taxonomy_format.clone()),
+        ..Default::default()
+    })?;
+    let tax_id = match nodes.nodes.contains_key(&options.query) {
+        true => options.query.clone(),
+        false => {
+            let index = NameIndex::new(&nodes, &options.name_classes, false, false);
+            let tax_ids = index.get(&options.query);
+            match tax_ids.len() {
+                0 => return Err(error::Error::TaxonNotFound(options.query.clone()).into()),
+                1 => tax_ids[0].clone(),
+                _ => {
+                    return Err(error::Error::AmbiguousTaxon(format!(
+                        "{} matches {}, use a taxon ID to select one",
+                        options.query,
+                        describe_candidates(&nodes, &tax_ids)
+                    ))
+                    .into())
+                }
+            }
+        }
+    };
+    let lineage = lineage_nodes(&nodes, &tax_id, options.canonical);
+    if options.string {
+        let names: Vec<String> = lineage.iter().map(|node| node.scientific_name()).collect();
+        println!("{}", names.join("; "));
+    } else {
+        for node in lineage {
+            println!("{}\t{}\t{}", node.rank, node.tax_id, node.scientific_name());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_lineage_nodes() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let tax_ids = |canonical| -> Vec<String> {
+            lineage_nodes(&nodes, &"9615".to_string(), canonical)
+                .iter()
+                .map(|node| node.tax_id.clone())
+                .collect()
+        };
+        let full = tax_ids(false);
+        assert_eq!(full.first().unwrap(), "1");
+        assert_eq!(full.last().unwrap(), "9615");
+        assert_eq!(
+            tax_ids(true),
+            vec!["2759", "33208", "7711", "40674", "33554", "9608", "9611", "9612"]
+        );
+    }
+}
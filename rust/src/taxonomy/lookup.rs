@@ -1,121 +1,582 @@
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use crate::taxonomy::parse::{Name, Node};
-use crate::{taxonomy::parse, utils::styled_progress_bar};
+use anyhow;
+use convert_case::{Case, Casing};
+use fst::{Map, MapBuilder};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::mint::TaxIdMinter;
+use super::{file_reader, taxdump_to_nodes};
+use crate::cli;
+use crate::io;
+use crate::taxonomy::parse::{Name, Node, Symbol};
+use crate::{
+    taxonomy::parse,
+    utils::{self, stage_timer, styled_progress_bar},
+};
 
 use parse::Nodes;
 
-pub fn build_lookup(nodes: &Nodes, name_classes: &Vec<String>) -> HashMap<String, Vec<String>> {
-    let ranks = [
-        "subspecies",
-        "species",
-        "genus",
-        "family",
-        "order",
-        "class",
-        "phylum",
-        "kingdom",
-    ];
-    let higher_ranks = ["family", "order", "class", "phylum", "kingdom"];
-    let mut table = HashMap::new();
-
-    let rank_set: HashSet<&str> = HashSet::from_iter(ranks.iter().cloned());
-    let higher_rank_set: HashSet<&str> = HashSet::from_iter(higher_ranks.iter().cloned());
-    let node_count = nodes.nodes.len();
-    let progress_bar = styled_progress_bar(node_count, "Building lookup hash");
-
-    for (tax_id, node) in nodes.nodes.iter() {
-        progress_bar.inc(1);
-        if rank_set.contains(node.rank.as_str()) {
-            let lineage = nodes.lineage(&"1".to_string(), tax_id);
-            let names = node.names_by_class(Some(&name_classes), true);
-            for n in lineage.iter().rev() {
-                let n_names = n.names_by_class(Some(&name_classes), true);
+/// Approximate memory used by each lookup table entry in addition to the
+/// key and taxon ID strings.
+const ENTRY_OVERHEAD: usize = 96;
+
+/// Number of nodes to build lookup keys for in parallel before adding them
+/// to the table.
+const LOOKUP_CHUNK_SIZE: usize = 100_000;
+
+/// A lookup table of name keys to taxon IDs.
+///
+/// Tables that would exceed a memory budget are spilled to an on-disk `fst`
+/// index, with keys added after the index is built held in memory.
+pub enum LookupTable {
+    Memory(HashMap<String, Vec<String>>),
+    Disk(DiskLookup),
+}
+
+impl LookupTable {
+    /// Get the taxon IDs for a key.
+    pub fn get(&self, key: &str) -> Option<Vec<String>> {
+        match self {
+            LookupTable::Memory(table) => table.get(key).cloned(),
+            LookupTable::Disk(table) => table.get(key),
+        }
+    }
+
+    /// Add a taxon ID to a key.
+    pub fn insert(&mut self, key: String, tax_id: String) {
+        let table = match self {
+            LookupTable::Memory(table) => table,
+            LookupTable::Disk(table) => &mut table.added,
+        };
+        table.entry(key).or_default().push(tax_id);
+    }
+}
+
+/// An on-disk lookup table, memory mapped from a temporary directory that is
+/// removed when the table is dropped.
+pub struct DiskLookup {
+    dir: PathBuf,
+    index: Map<Mmap>,
+    values: Mmap,
+    added: HashMap<String, Vec<String>>,
+}
+
+impl DiskLookup {
+    fn get(&self, key: &str) -> Option<Vec<String>> {
+        let mut tax_ids: Vec<String> = match self.index.get(key) {
+            Some(offset) => {
+                let values = &self.values[offset as usize..];
+                let end = values
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .unwrap_or(values.len());
+                String::from_utf8_lossy(&values[..end])
+                    .split('\t')
+                    .map(String::from)
+                    .collect()
+            }
+            None => vec![],
+        };
+        if let Some(added) = self.added.get(key) {
+            tax_ids.extend(added.iter().cloned());
+        }
+        match tax_ids.is_empty() {
+            true => None,
+            false => Some(tax_ids),
+        }
+    }
+}
+
+impl Drop for DiskLookup {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Build a lookup table, spilling sorted runs to disk whenever the estimated
+/// size exceeds `max_memory` bytes.
+struct LookupBuilder {
+    max_memory: Option<usize>,
+    table: HashMap<String, Vec<String>>,
+    size: usize,
+    dir: Option<PathBuf>,
+    runs: Vec<PathBuf>,
+}
+
+impl LookupBuilder {
+    fn new(max_memory: Option<usize>) -> LookupBuilder {
+        LookupBuilder {
+            max_memory,
+            table: HashMap::new(),
+            size: 0,
+            dir: None,
+            runs: vec![],
+        }
+    }
+
+    fn insert(&mut self, key: String, tax_id: String) -> Result<(), anyhow::Error> {
+        self.size += tax_id.len() + ENTRY_OVERHEAD;
+        match self.table.entry(key) {
+            Entry::Vacant(e) => {
+                self.size += e.key().len();
+                e.insert(vec![tax_id]);
+            }
+            Entry::Occupied(mut e) => {
+                e.get_mut().push(tax_id);
+            }
+        }
+        if self
+            .max_memory
+            .is_some_and(|max_memory| self.size > max_memory)
+        {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Write the in-memory table to a sorted run file.
+    fn spill(&mut self) -> Result<(), anyhow::Error> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let dir = utils::scratch_dir("lookup")?;
+                eprintln!(
+                    "Lookup table exceeds memory budget, using on-disk index in {}",
+                    dir.display()
+                );
+                self.dir = Some(dir.clone());
+                dir
+            }
+        };
+        let path = dir.join(format!("run_{}.tsv", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut entries: Vec<(String, Vec<String>)> = self.table.drain().collect();
+        entries.sort();
+        for (key, tax_ids) in entries {
+            writeln!(writer, "{}\t{}", key, tax_ids.join("\t"))?;
+        }
+        self.runs.push(path);
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Finish the table, merging any sorted runs into an on-disk index.
+    fn finish(mut self) -> Result<LookupTable, anyhow::Error> {
+        if self.runs.is_empty() {
+            return Ok(LookupTable::Memory(self.table));
+        }
+        self.spill()?;
+        let dir = self.dir.clone().unwrap();
+        let mut readers = vec![];
+        for run in self.runs.iter() {
+            readers.push(BufReader::new(File::open(run)?).lines());
+        }
+        let mut heap = BinaryHeap::new();
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = reader.next() {
+                heap.push(Reverse((line?, i)));
+            }
+        }
+        let mut index = MapBuilder::new(BufWriter::new(File::create(dir.join("lookup.fst"))?))?;
+        let mut values = BufWriter::new(File::create(dir.join("lookup.tsv"))?);
+        let mut offset = 0;
+        let mut current: Option<(String, Vec<String>)> = None;
+        let mut write_entry = |key: &str, tax_ids: &[String]| -> Result<(), anyhow::Error> {
+            index.insert(key, offset)?;
+            let line = format!("{}\n", tax_ids.join("\t"));
+            values.write_all(line.as_bytes())?;
+            offset += line.len() as u64;
+            Ok(())
+        };
+        while let Some(Reverse((line, i))) = heap.pop() {
+            if let Some(next) = readers[i].next() {
+                heap.push(Reverse((next?, i)));
+            }
+            let (key, tax_ids) = line.split_once('\t').unwrap_or((&line, ""));
+            let tax_ids = tax_ids.split('\t').map(String::from);
+            match current.as_mut() {
+                Some((current_key, current_ids)) if current_key == key => {
+                    current_ids.extend(tax_ids)
+                }
+                _ => {
+                    if let Some((current_key, current_ids)) = current.take() {
+                        write_entry(&current_key, &current_ids)?;
+                    }
+                    current = Some((key.to_string(), tax_ids.collect()));
+                }
+            }
+        }
+        if let Some((current_key, current_ids)) = current.take() {
+            write_entry(&current_key, &current_ids)?;
+        }
+        index.finish()?;
+        values.flush()?;
+        for run in self.runs.iter() {
+            fs::remove_file(run)?;
+        }
+        // Safety: the files are private to this process and not modified
+        // after they are mapped
+        let (index, values) = unsafe {
+            (
+                Mmap::map(&File::open(dir.join("lookup.fst"))?)?,
+                Mmap::map(&File::open(dir.join("lookup.tsv"))?)?,
+            )
+        };
+        Ok(LookupTable::Disk(DiskLookup {
+            dir,
+            index: Map::new(index)?,
+            values,
+            added: HashMap::new(),
+        }))
+    }
+}
+
+/// Ranks of taxa matched by `lookup_nodes`, ordered from lowest to highest,
+/// and of ancestors whose names are combined with theirs in lookup keys.
+#[derive(Clone, Debug)]
+pub struct LookupRanks {
+    pub ranks: Vec<String>,
+    pub higher_ranks: Vec<String>,
+}
+
+impl Default for LookupRanks {
+    fn default() -> LookupRanks {
+        let to_vec = |ranks: &[&str]| ranks.iter().map(|rank| rank.to_string()).collect();
+        LookupRanks {
+            ranks: to_vec(&["subspecies", "species", "genus", "family"]),
+            higher_ranks: to_vec(&["family", "order", "class", "phylum", "kingdom"]),
+        }
+    }
+}
+
+impl LookupRanks {
+    /// Use configured ranks, falling back to the defaults for either list.
+    pub fn new(ranks: Option<Vec<String>>, higher_ranks: Option<Vec<String>>) -> LookupRanks {
+        let defaults = LookupRanks::default();
+        LookupRanks {
+            ranks: ranks.unwrap_or(defaults.ranks),
+            higher_ranks: higher_ranks.unwrap_or(defaults.higher_ranks),
+        }
+    }
+}
+
+/// Name as used in lookup keys, lower case unless matching is case sensitive.
+fn name_key(name: &str, case_sensitive: bool) -> String {
+    match case_sensitive {
+        true => name.to_string(),
+        false => name.to_case(Case::Lower),
+    }
+}
+
+/// Build a lookup table of rank and name keys to taxon IDs.
+///
+/// Names are lower case in keys unless `case_sensitive` is set. If the table
+/// would use more than `max_memory` bytes, it is built as an on-disk index
+/// instead.
+pub fn build_lookup(
+    nodes: &Nodes,
+    name_classes: &Vec<String>,
+    ranks: &LookupRanks,
+    case_sensitive: bool,
+    max_memory: Option<usize>,
+) -> Result<LookupTable, anyhow::Error> {
+    let mut table = LookupBuilder::new(max_memory);
+
+    let rank_set: HashSet<&str> = ranks
+        .ranks
+        .iter()
+        .chain(ranks.higher_ranks.iter())
+        .map(String::as_str)
+        .collect();
+    let higher_rank_set: HashSet<&str> = ranks.higher_ranks.iter().map(String::as_str).collect();
+
+    // names of higher rank taxa are shared by all their descendants, so are
+    // found once rather than for every node
+    let higher_names: HashMap<&String, Vec<String>> = nodes
+        .nodes
+        .par_iter()
+        .filter(|(_, node)| higher_rank_set.contains(node.rank.as_str()))
+        .map(|(tax_id, node)| {
+            (
+                tax_id,
+                node.names_by_class(Some(name_classes), !case_sensitive),
+            )
+        })
+        .collect();
+    let root_id = "1".to_string();
+    let node_keys = |node: &Node| -> Vec<String> {
+        let mut keys = vec![];
+        let names = node.names_by_class(Some(name_classes), !case_sensitive);
+        for n in nodes.lineage(&root_id, &node.tax_id).iter().rev() {
+            if let Some(n_names) = higher_names.get(&n.tax_id) {
                 for name in names.iter() {
                     for n_name in n_names.iter() {
-                        if higher_rank_set.contains(n.rank.as_str()) {
-                            let key = format!(
-                                "{}:{}:{}:{}",
-                                node.rank_letter(),
-                                name,
-                                n.rank_letter(),
-                                n_name
-                            );
-                            match table.entry(key) {
-                                Entry::Vacant(e) => {
-                                    e.insert(vec![node.tax_id()]);
-                                }
-                                Entry::Occupied(mut e) => {
-                                    e.get_mut().push(node.tax_id());
-                                }
-                            }
-                        }
+                        keys.push(format!(
+                            "{}:{}:{}:{}",
+                            node.rank_key(),
+                            name,
+                            n.rank_key(),
+                            n_name
+                        ));
                     }
                 }
             }
         }
+        keys
+    };
+
+    let candidates: Vec<&Node> = nodes
+        .nodes
+        .values()
+        .filter(|node| rank_set.contains(node.rank.as_str()))
+        .collect();
+    let progress_bar = styled_progress_bar(candidates.len(), "Building lookup hash");
+    // keys are built in parallel a chunk at a time so the memory budget
+    // still applies while they are added to the table
+    for chunk in candidates.chunks(LOOKUP_CHUNK_SIZE) {
+        utils::check_cancelled()?;
+        let chunk_keys: Vec<Vec<String>> = chunk.par_iter().map(|node| node_keys(node)).collect();
+        for (node, keys) in chunk.iter().zip(chunk_keys) {
+            progress_bar.inc(1);
+            for key in keys {
+                table.insert(key, node.tax_id.clone())?;
+            }
+        }
     }
     progress_bar.finish();
-    table
+    table.finish()
+}
+
+/// The outcome of looking up a taxon from a new taxonomy in the backbone.
+///
+/// Matches have a confidence from 0 to 1, the fraction of the taxon's
+/// higher rank ancestors whose names support the match. Added taxa have
+/// the confidence of the ancestor they were placed under.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LookupRow {
+    /// Matched to an existing backbone taxon
+    Matched {
+        tax_id: String,
+        rank: String,
+        match_tax_id: String,
+        confidence: f64,
+    },
+    /// Added to the backbone under a matched ancestor
+    Added {
+        tax_id: String,
+        rank: String,
+        new_tax_id: String,
+        parent_tax_id: String,
+        confidence: f64,
+    },
+    /// Not matched to the backbone
+    Unmatched { tax_id: String, rank: String },
+}
+
+/// Header for the cross-taxonomy mapping table written by `lookup_nodes`.
+pub const MAPPING_HEADER: &str = "source_id\tmatched_ncbi_taxid\tmatch_type\trank\tconfidence";
+
+impl LookupRow {
+    /// Format as a mapping table row, with the source taxon ID prefixed by
+    /// the xref label if there is one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use crate::blobtk::taxonomy::lookup::LookupRow;
+    /// let row = LookupRow::Matched {
+    ///     tax_id: "5219173".to_string(),
+    ///     rank: "species".to_string(),
+    ///     match_tax_id: "9612".to_string(),
+    ///     confidence: 0.8,
+    /// };
+    /// assert_eq!(
+    ///     row.mapping_row(Some("gbif")),
+    ///     "gbif:5219173\t9612\tmatched\tspecies\t0.800"
+    /// );
+    /// ```
+    pub fn mapping_row(&self, xref_label: Option<&str>) -> String {
+        let (tax_id, matched, match_type, rank) = match self {
+            LookupRow::Matched {
+                tax_id,
+                rank,
+                match_tax_id,
+                ..
+            } => (tax_id, match_tax_id.as_str(), "matched", rank),
+            LookupRow::Added {
+                tax_id,
+                rank,
+                new_tax_id,
+                ..
+            } => (tax_id, new_tax_id.as_str(), "added", rank),
+            LookupRow::Unmatched { tax_id, rank } => (tax_id, "", "unmatched", rank),
+        };
+        let source_id = match xref_label {
+            Some(label) => format!("{}:{}", label, tax_id),
+            None => tax_id.clone(),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{:.3}",
+            source_id,
+            matched,
+            match_type,
+            rank,
+            self.confidence()
+        )
+    }
+
+    /// Confidence in the match or placement, 0 for unmatched taxa.
+    pub fn confidence(&self) -> f64 {
+        match self {
+            LookupRow::Matched { confidence, .. } | LookupRow::Added { confidence, .. } => {
+                *confidence
+            }
+            LookupRow::Unmatched { .. } => 0.0,
+        }
+    }
 }
 
+/// Counts of lookup results for one taxonomy.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LookupStats {
+    /// Xref label of the taxonomy, or an empty string if unlabelled
+    pub source: String,
+    /// Taxa matched to an existing backbone taxon
+    pub matched: usize,
+    /// Taxa added to the backbone as new taxa
+    pub added: usize,
+    /// Taxa not matched to the backbone
+    pub unmatched: usize,
+    /// Xref names added to backbone and new taxa
+    pub xrefs: usize,
+}
+
+impl LookupStats {
+    fn count(&mut self, row: &LookupRow) {
+        let labelled = !self.source.is_empty();
+        match row {
+            LookupRow::Matched { .. } => {
+                self.matched += 1;
+                self.xrefs += 1;
+            }
+            LookupRow::Added { .. } => {
+                self.added += 1;
+                self.xrefs += labelled as usize;
+            }
+            LookupRow::Unmatched { .. } => self.unmatched += 1,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn lookup_nodes(
     new_nodes: &Nodes,
     nodes: &mut Nodes,
     new_name_classes: &Vec<String>,
     name_classes: &Vec<String>,
+    ranks: &LookupRanks,
+    case_sensitive: bool,
+    xref_label: Option<String>,
+    table: LookupTable,
+    minter: &mut TaxIdMinter,
+    mut mapping: Option<&mut Box<dyn Write>>,
+) -> LookupStats {
+    let mut stats = LookupStats {
+        source: xref_label.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+    let label = xref_label.clone();
+    lookup_nodes_with(
+        new_nodes,
+        nodes,
+        new_name_classes,
+        name_classes,
+        ranks,
+        case_sensitive,
+        xref_label,
+        table,
+        minter,
+        |row| {
+            if let Some(writer) = mapping.as_mut() {
+                writeln!(writer, "{}", row.mapping_row(label.as_deref())).unwrap();
+            }
+            stats.count(&row);
+        },
+    );
+    stats
+}
+
+/// Look up taxa from a new taxonomy in the backbone, passing each row to
+/// `on_row` as it is matched rather than buffering results.
+///
+/// `table` is a lookup table for `nodes` from `build_lookup` and `minter`
+/// assigns taxon IDs to added taxa.
+#[allow(clippy::too_many_arguments)]
+pub fn lookup_nodes_with<F: FnMut(LookupRow)>(
+    new_nodes: &Nodes,
+    nodes: &mut Nodes,
+    new_name_classes: &Vec<String>,
+    name_classes: &Vec<String>,
+    ranks: &LookupRanks,
+    case_sensitive: bool,
     xref_label: Option<String>,
+    mut table: LookupTable,
+    minter: &mut TaxIdMinter,
+    mut on_row: F,
 ) {
-    let mut table = build_lookup(&nodes, &name_classes);
-    let ranks = [
-        "subspecies",
-        "species",
-        "genus",
-        "family",
-        // "order",
-        // "class",
-        // "phylum",
-    ];
+    let _timer = stage_timer("matching");
     let mut matched: HashMap<String, String> = HashMap::new();
-    let mut unmatched: HashMap<String, Vec<String>> = HashMap::new();
-    let higher_ranks = ["family", "order", "class", "phylum", "kingdom"];
-    let higher_rank_set: HashSet<&str> = HashSet::from_iter(higher_ranks.iter().cloned());
+    let mut confidences: HashMap<String, f64> = HashMap::new();
+    let higher_rank_set: HashSet<&str> = ranks.higher_ranks.iter().map(String::as_str).collect();
     let node_count = new_nodes.nodes.len();
     let progress_bar = styled_progress_bar(node_count, "Looking up names");
-    let mut hits = vec![];
 
     // for (tax_id, node) in new_nodes.nodes.iter() {
-    for rank in ranks.into_iter().rev() {
+    for rank in ranks.ranks.iter().rev() {
         for node in new_nodes.nodes_by_rank(rank) {
+            if utils::is_cancelled() {
+                break;
+            }
             let tax_id = &node.tax_id;
             progress_bar.inc(1);
             let lineage = new_nodes.lineage(&"1".to_string(), tax_id);
-            let names = node.names_by_class(Some(name_classes), true);
+            let names = node.names_by_class(Some(name_classes), !case_sensitive);
             let mut match_tax_id = None;
             let mut hanger_tax_id = None;
+            let mut hanger_confidence = 0.0;
+            // higher rank ancestors checked, and the taxa their names matched
+            let mut checked = 0;
+            let mut support = vec![];
             for n in lineage.into_iter().rev() {
                 if let Some(match_id) = matched.get(&n.tax_id) {
                     if hanger_tax_id.is_none() {
                         hanger_tax_id = Some(match_id.clone());
+                        hanger_confidence = confidences.get(&n.tax_id).copied().unwrap_or(0.0);
                     }
                 }
-                let n_names = n.names_by_class(Some(new_name_classes), true);
+                if higher_rank_set.contains(n.rank.as_str()) {
+                    checked += 1;
+                }
+                let n_names = n.names_by_class(Some(new_name_classes), !case_sensitive);
                 for name in names.iter() {
                     for n_name in n_names.iter() {
                         if higher_rank_set.contains(n.rank.as_str()) {
-                            let key = format!(
-                                "{}:{}:{}:{}",
-                                node.rank_letter(),
-                                name,
-                                n.rank_letter(),
-                                n_name
-                            );
+                            let key =
+                                format!("{}:{}:{}:{}", node.rank_key(), name, n.rank_key(), n_name);
                             match table.get(&key) {
                                 None => (),
                                 Some(value) => {
                                     if value.len() == 1 {
-                                        matched.insert(node.tax_id(), value[0].clone());
+                                        support.push(value[0].clone());
+                                        matched.insert(node.tax_id.clone(), value[0].clone());
                                         match_tax_id = Some(value[0].clone());
                                         break;
                                     }
@@ -129,7 +590,15 @@ pub fn lookup_nodes(
                 }
             }
             if let Some(ref_tax_id) = match_tax_id {
-                hits.push(ref_tax_id.clone());
+                let confidence =
+                    support.iter().filter(|id| **id == ref_tax_id).count() as f64 / checked as f64;
+                confidences.insert(node.tax_id.clone(), confidence);
+                on_row(LookupRow::Matched {
+                    tax_id: node.tax_id.clone(),
+                    rank: node.rank().to_string(),
+                    match_tax_id: ref_tax_id.clone(),
+                    confidence,
+                });
                 // add node.tax_id to names as an xref
                 let names = nodes
                     .nodes
@@ -144,74 +613,77 @@ pub fn lookup_nodes(
                 };
                 names.push(Name {
                     tax_id: ref_tax_id.clone(),
-                    name: node.tax_id(),
-                    unique_name: format!("{}:{}", &label, node.tax_id()),
-                    class: xref_label.clone(),
+                    name: node.tax_id.clone(),
+                    unique_name: format!("{}:{}", &label, node.tax_id),
+                    class: xref_label.as_deref().map(Symbol::from),
                 });
                 continue;
             } else {
                 if let Some(hanger_id) = hanger_tax_id {
                     // Create new node and hang on hanger_tax_id
-                    let new_tax_id = match xref_label {
-                        Some(ref l) => format!("{}:{}", l, node.tax_id()),
-                        None => format!(":{}", node.tax_id()),
+                    let xref = match xref_label {
+                        Some(ref l) => format!("{}:{}", l, node.tax_id),
+                        None => format!(":{}", node.tax_id),
                     };
-                    matched.insert(node.tax_id(), new_tax_id.clone());
+                    let new_tax_id = minter.mint(&xref, nodes);
+                    matched.insert(node.tax_id.clone(), new_tax_id.clone());
+                    confidences.insert(node.tax_id.clone(), hanger_confidence);
+
+                    let mut new_names: Vec<Name> = match node.names.clone() {
+                        Some(names) => names
+                            .iter()
+                            .map(|n| Name {
+                                tax_id: new_tax_id.clone(),
+                                ..n.clone()
+                            })
+                            .collect(),
+                        None => vec![],
+                    };
+                    // keep the original ID as an xref on the new node
+                    if let Some(ref label) = xref_label {
+                        new_names.push(Name {
+                            tax_id: new_tax_id.clone(),
+                            name: node.tax_id.clone(),
+                            unique_name: format!("{}:{}", label, node.tax_id),
+                            class: xref_label.as_deref().map(Symbol::from),
+                        });
+                    }
 
                     nodes.nodes.insert(
                         new_tax_id.clone(),
                         Node {
                             tax_id: new_tax_id.clone(),
                             parent_tax_id: hanger_id.clone(),
-                            names: match node.names.clone() {
-                                Some(names) => Some(
-                                    names
-                                        .iter()
-                                        .map(|n| Name {
-                                            tax_id: new_tax_id.clone(),
-                                            ..n.clone()
-                                        })
-                                        .collect(),
-                                ),
-                                None => None,
-                            },
-                            rank: node.rank(),
+                            names: Some(new_names),
+                            rank: node.rank.clone(),
                             scientific_name: node.scientific_name.clone(),
+                            source: Some(xref_label.clone().unwrap_or_default()),
+                            source_tax_id: Some(node.tax_id.clone()),
+                            ..Default::default()
                         },
                     );
-                    match nodes.children.entry(hanger_id.clone()) {
-                        Entry::Vacant(e) => {
-                            e.insert(vec![new_tax_id.clone()]);
-                        }
-                        Entry::Occupied(mut e) => {
-                            e.get_mut().push(new_tax_id.clone());
-                        }
-                    }
+                    nodes.add_child(&hanger_id, &new_tax_id);
                     let parent_node = nodes.nodes.get(&hanger_id).unwrap();
                     let key = format!(
                         "{}:{}:{}:{}",
-                        node.rank_letter(),
-                        node.lc_scientific_name(),
-                        parent_node.rank_letter(),
-                        parent_node.lc_scientific_name()
+                        node.rank_key(),
+                        name_key(&node.scientific_name(), case_sensitive),
+                        parent_node.rank_key(),
+                        name_key(&parent_node.scientific_name(), case_sensitive)
                     );
-                    match table.entry(key) {
-                        Entry::Vacant(e) => {
-                            e.insert(vec![new_tax_id]);
-                        }
-                        Entry::Occupied(mut e) => {
-                            e.get_mut().push(new_tax_id);
-                        }
-                    }
+                    table.insert(key, new_tax_id.clone());
+                    on_row(LookupRow::Added {
+                        tax_id: node.tax_id.clone(),
+                        rank: node.rank().to_string(),
+                        new_tax_id,
+                        parent_tax_id: hanger_id,
+                        confidence: hanger_confidence,
+                    });
                 } else {
-                    match unmatched.entry(node.rank()) {
-                        Entry::Vacant(e) => {
-                            e.insert(vec![node.lc_tax_id()]);
-                        }
-                        Entry::Occupied(mut e) => {
-                            e.get_mut().push(node.lc_tax_id());
-                        }
-                    }
+                    on_row(LookupRow::Unmatched {
+                        tax_id: node.tax_id.clone(),
+                        rank: node.rank().to_string(),
+                    });
                 }
             }
         }
@@ -235,5 +707,406 @@ pub fn lookup_nodes(
     //         },
     //     )
     // }
-    dbg!(unmatched);
+}
+
+/// Edit distance allowed for a fuzzy match to a name of `length`
+/// characters, one edit per `chars_per_edit` characters if set, up to
+/// `max_distance`.
+///
+/// ```
+/// use blobtk::taxonomy::lookup::fuzzy_distance;
+///
+/// assert_eq!(fuzzy_distance(11, 2, None), 2);
+/// assert_eq!(fuzzy_distance(11, 2, Some(8)), 1);
+/// assert_eq!(fuzzy_distance(40, 2, Some(8)), 2);
+/// assert_eq!(fuzzy_distance(5, 2, Some(8)), 0);
+/// ```
+pub fn fuzzy_distance(length: usize, max_distance: usize, chars_per_edit: Option<usize>) -> usize {
+    match chars_per_edit {
+        Some(chars) if chars > 0 => (length / chars).min(max_distance),
+        _ => max_distance,
+    }
+}
+
+fn edit_distance(a: &str, b: &str, algorithm: &cli::FuzzyAlgorithm) -> usize {
+    match algorithm {
+        cli::FuzzyAlgorithm::Levenshtein => strsim::levenshtein(a, b),
+        cli::FuzzyAlgorithm::DamerauLevenshtein => strsim::damerau_levenshtein(a, b),
+    }
+}
+
+/// Spelling variants treated as equivalent in latinised names, applied in
+/// order.
+const PHONETIC_REPLACEMENTS: [(&str, &str); 12] = [
+    ("ph", "f"),
+    ("rh", "r"),
+    ("th", "t"),
+    ("ch", "c"),
+    ("ck", "c"),
+    ("ae", "e"),
+    ("oe", "e"),
+    ("k", "c"),
+    ("y", "i"),
+    ("j", "i"),
+    ("z", "s"),
+    ("w", "v"),
+];
+
+/// Phonetic key for a latinised name, so spellings that sound alike, such
+/// as transliterations, share a key.
+///
+/// Each word is lower cased, common spelling variants are normalised and
+/// doubled letters are collapsed.
+///
+/// ```
+/// use blobtk::taxonomy::lookup::phonetic_key;
+///
+/// assert_eq!(phonetic_key("Phylloscopus"), phonetic_key("Filoskopus"));
+/// assert_eq!(phonetic_key("Caenorhabditis"), phonetic_key("Cenorabditis"));
+/// assert_ne!(phonetic_key("Canis lupus"), phonetic_key("Canis latrans"));
+/// ```
+pub fn phonetic_key(name: &str) -> String {
+    let words: Vec<String> = name
+        .split_whitespace()
+        .map(|word| {
+            let mut word: String = word
+                .chars()
+                .filter(|c| c.is_ascii_alphabetic())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            for (from, to) in PHONETIC_REPLACEMENTS {
+                word = word.replace(from, to);
+            }
+            let mut key = String::new();
+            for c in word.chars() {
+                if !key.ends_with(c) {
+                    key.push(c);
+                }
+            }
+            key
+        })
+        .filter(|key| !key.is_empty())
+        .collect();
+    words.join(" ")
+}
+
+/// An index of names to taxon IDs. Scientific names are preferred, falling
+/// back to names in other classes if no scientific names match.
+///
+/// Names are matched ignoring case unless `case_sensitive` is set. With
+/// `preserve_case`, names returned by `fuzzy` keep their original case
+/// rather than the lower case used for matching.
+pub struct NameIndex {
+    scientific: HashMap<String, Vec<String>>,
+    other: HashMap<String, Vec<String>>,
+    case_sensitive: bool,
+    original_names: HashMap<String, String>,
+    phonetic: Option<HashMap<String, Vec<String>>>,
+}
+
+impl NameIndex {
+    pub fn new(
+        nodes: &Nodes,
+        name_classes: &Vec<String>,
+        case_sensitive: bool,
+        preserve_case: bool,
+    ) -> NameIndex {
+        let mut scientific: HashMap<String, Vec<String>> = HashMap::new();
+        let mut other: HashMap<String, Vec<String>> = HashMap::new();
+        let mut original_names: HashMap<String, String> = HashMap::new();
+        let mut add_original = |name: &String| {
+            if preserve_case && !case_sensitive {
+                // keep the first spelling in sort order so output is stable
+                let original = original_names
+                    .entry(name_key(name, false))
+                    .or_insert_with(|| name.clone());
+                if name < original {
+                    *original = name.clone();
+                }
+            }
+        };
+        for node in nodes.nodes.values() {
+            let scientific_name = node.scientific_name();
+            add_original(&scientific_name);
+            scientific
+                .entry(name_key(&scientific_name, case_sensitive))
+                .or_default()
+                .push(node.tax_id.clone());
+            for name in node.names_by_class(Some(name_classes), false) {
+                add_original(&name);
+                other
+                    .entry(name_key(&name, case_sensitive))
+                    .or_default()
+                    .push(node.tax_id.clone());
+            }
+        }
+        for tax_ids in scientific.values_mut().chain(other.values_mut()) {
+            tax_ids.sort_by(|a, b| parse::compare_tax_ids(a, b));
+            tax_ids.dedup();
+        }
+        NameIndex {
+            scientific,
+            other,
+            case_sensitive,
+            original_names,
+            phonetic: None,
+        }
+    }
+
+    /// Index names by `phonetic_key` so `phonetic` can find candidates.
+    pub fn build_phonetic(&mut self) {
+        let mut phonetic: HashMap<String, Vec<String>> = HashMap::new();
+        for key in self.scientific.keys().chain(self.other.keys()) {
+            let names = phonetic.entry(phonetic_key(key)).or_default();
+            if !names.contains(key) {
+                names.push(key.clone());
+            }
+        }
+        self.phonetic = Some(phonetic);
+    }
+
+    /// Sort matched names by edit distance then name, reporting their
+    /// original case if preserved.
+    fn ranked_matches(
+        &self,
+        matches: HashMap<&String, (usize, Vec<String>)>,
+    ) -> Vec<(String, usize, Vec<String>)> {
+        let mut matches: Vec<(String, usize, Vec<String>)> = matches
+            .into_iter()
+            .map(|(key, (distance, mut tax_ids))| {
+                tax_ids.sort_by(|a, b| parse::compare_tax_ids(a, b));
+                tax_ids.dedup();
+                let name = self.original_names.get(key).unwrap_or(key);
+                (name.clone(), distance, tax_ids)
+            })
+            .collect();
+        matches.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        matches
+    }
+
+    /// Names within `max_distance` edits of `name` with their edit distance
+    /// and taxon IDs, closest first.
+    pub fn fuzzy(
+        &self,
+        name: &str,
+        max_distance: usize,
+        algorithm: &cli::FuzzyAlgorithm,
+    ) -> Vec<(String, usize, Vec<String>)> {
+        let name = name_key(name, self.case_sensitive);
+        let length = name.chars().count();
+        // distances are found in parallel, then merged and sorted so the
+        // order does not depend on the number of threads
+        let found: Vec<(&String, usize, &Vec<String>)> = self
+            .scientific
+            .par_iter()
+            .chain(self.other.par_iter())
+            .filter(|(key, _)| key.chars().count().abs_diff(length) <= max_distance)
+            .filter_map(|(key, tax_ids)| {
+                let distance = edit_distance(&name, key, algorithm);
+                (distance <= max_distance).then_some((key, distance, tax_ids))
+            })
+            .collect();
+        let mut matches: HashMap<&String, (usize, Vec<String>)> = HashMap::new();
+        for (key, distance, tax_ids) in found {
+            let entry = matches.entry(key).or_insert((distance, vec![]));
+            entry.1.extend(tax_ids.iter().cloned());
+        }
+        self.ranked_matches(matches)
+    }
+
+    /// Names with the same `phonetic_key` as `name`, with their edit
+    /// distance and taxon IDs, closest first. Empty unless `build_phonetic`
+    /// has been called.
+    pub fn phonetic(
+        &self,
+        name: &str,
+        algorithm: &cli::FuzzyAlgorithm,
+    ) -> Vec<(String, usize, Vec<String>)> {
+        let keys = match self
+            .phonetic
+            .as_ref()
+            .and_then(|phonetic| phonetic.get(&phonetic_key(name)))
+        {
+            Some(keys) => keys,
+            None => return vec![],
+        };
+        let name = name_key(name, self.case_sensitive);
+        let matches: HashMap<&String, (usize, Vec<String>)> = keys
+            .par_iter()
+            .map(|key| {
+                let tax_ids = self
+                    .scientific
+                    .get(key)
+                    .into_iter()
+                    .chain(self.other.get(key))
+                    .flatten()
+                    .cloned()
+                    .collect();
+                (key, (edit_distance(&name, key, algorithm), tax_ids))
+            })
+            .collect();
+        self.ranked_matches(matches)
+    }
+
+    /// Taxon IDs of nodes with a name matching `name`.
+    pub fn get(&self, name: &str) -> Vec<String> {
+        let name = name_key(name, self.case_sensitive);
+        self.scientific
+            .get(&name)
+            .or_else(|| self.other.get(&name))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Execute the `taxonomy lookup` subcommand from `blobtk`.
+pub fn lookup(options: &cli::TaxonomyLookupOptions) -> Result<(), anyhow::Error> {
+    let nodes = taxdump_to_nodes(&cli::TaxonomyOptions {
+        path: Some(options.path.clone()),
+        taxonomy_format: Some(options.taxonomy_format.clone()),
+        ..Default::default()
+    })?;
+    let mut queries = options.queries.clone();
+    if queries.is_empty() || options.input.is_some() {
+        let reader: Box<dyn BufRead> = match &options.input {
+            Some(path) if path != Path::new("-") => file_reader(path.clone()).unwrap(),
+            _ => Box::new(BufReader::new(std::io::stdin().lock())),
+        };
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                queries.push(line.trim().to_string());
+            }
+        }
+    }
+    let index = NameIndex::new(&nodes, &options.name_classes, options.case_sensitive, false);
+    let root_id = "1".to_string();
+
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "query\ttax_id\trank\tscientific_name\tlineage")?;
+    let (mut unmatched, mut ambiguous) = (0, 0);
+    for query in queries.iter() {
+        let tax_ids = match nodes.nodes.contains_key(query) {
+            true => vec![query.clone()],
+            false => index.get(query),
+        };
+        match tax_ids.len() {
+            0 => unmatched += 1,
+            1 => (),
+            _ => ambiguous += 1,
+        }
+        if tax_ids.is_empty() {
+            writeln!(writer, "{}\t\t\t\t", query)?;
+        }
+        for tax_id in tax_ids.iter() {
+            let node = &nodes.nodes[tax_id];
+            let lineage: Vec<String> = nodes
+                .lineage(&root_id, tax_id)
+                .iter()
+                .filter(|n| n.tax_id != root_id)
+                .map(|n| n.scientific_name())
+                .collect();
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                query,
+                tax_id,
+                node.rank,
+                node.scientific_name(),
+                lineage.join("; ")
+            )?;
+        }
+    }
+    if unmatched > 0 || ambiguous > 0 {
+        eprintln!(
+            "{} of {} queries not found, {} matched more than one taxon",
+            unmatched,
+            queries.len(),
+            ambiguous
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_name_index() {
+        let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let classes = vec!["scientific name".to_string(), "synonym".to_string()];
+        let index = NameIndex::new(&nodes, &classes, false, false);
+        assert_eq!(index.get("canis LUPUS"), vec!["9612"]);
+        assert_eq!(index.get("Eucarya"), vec!["2759"]);
+        assert!(index.get("not a taxon").is_empty());
+        assert!(NameIndex::new(&nodes, &vec![], false, false)
+            .get("Eucarya")
+            .is_empty());
+        let mut homonym = nodes.nodes["9611"].clone();
+        homonym.tax_id = "gbif:1".to_string();
+        nodes.nodes.insert(homonym.tax_id.clone(), homonym);
+        let homonyms = NameIndex::new(&nodes, &classes, false, false);
+        assert_eq!(homonyms.get("Canis"), vec!["9611", "gbif:1"]);
+        let fuzzy = index.fuzzy("Canis lupis", 1, &cli::FuzzyAlgorithm::Levenshtein);
+        assert_eq!(
+            fuzzy[0],
+            ("canis lupus".to_string(), 1, vec!["9612".to_string()])
+        );
+        let index = NameIndex::new(&nodes, &classes, true, false);
+        assert_eq!(index.get("Canis lupus"), vec!["9612".to_string()]);
+        assert!(index.get("canis LUPUS").is_empty());
+        let index = NameIndex::new(&nodes, &classes, false, true);
+        let fuzzy = index.fuzzy("canis lupis", 1, &cli::FuzzyAlgorithm::Levenshtein);
+        assert_eq!(fuzzy[0].0, "Canis lupus");
+        let algorithm = cli::FuzzyAlgorithm::DamerauLevenshtein;
+        assert!(index
+            .fuzzy("Canis lpuus", 1, &cli::FuzzyAlgorithm::Levenshtein)
+            .is_empty());
+        assert_eq!(index.fuzzy("Canis lpuus", 1, &algorithm)[0].2, vec!["9612"]);
+        // matches do not depend on the number of threads
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            single.install(|| index.fuzzy("Canis", 4, &algorithm)),
+            index.fuzzy("Canis", 4, &algorithm)
+        );
+    }
+
+    #[test]
+    fn test_disk_lookup() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let name_classes = vec!["scientific name".to_string()];
+        let memory = match build_lookup(&nodes, &name_classes, &LookupRanks::default(), false, None)
+            .unwrap()
+        {
+            LookupTable::Memory(table) => table,
+            LookupTable::Disk(_) => panic!("expected an in-memory lookup table"),
+        };
+        let mut disk = build_lookup(
+            &nodes,
+            &name_classes,
+            &LookupRanks::default(),
+            false,
+            Some(1000),
+        )
+        .unwrap();
+        assert!(matches!(disk, LookupTable::Disk(_)));
+        for (key, tax_ids) in memory.iter() {
+            let mut expected = tax_ids.clone();
+            let mut found = disk.get(key).unwrap();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+        assert_eq!(disk.get("s:missing:g:missing"), None);
+        disk.insert("s:missing:g:missing".to_string(), "new".to_string());
+        assert_eq!(
+            disk.get("s:missing:g:missing"),
+            Some(vec!["new".to_string()])
+        );
+    }
 }
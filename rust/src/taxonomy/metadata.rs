@@ -0,0 +1,330 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow;
+use serde::{Deserialize, Serialize};
+
+use super::parse::{Node, Nodes};
+use crate::cli;
+
+/// Provenance file written alongside generated taxdumps.
+pub const METADATA_FILE: &str = "taxdump_metadata.json";
+
+/// Sources of taxa added to the backbone, written alongside generated
+/// taxdumps that include any.
+pub const PROVENANCE_FILE: &str = "taxon_provenance.tsv";
+
+/// Header of the taxon provenance file.
+pub const PROVENANCE_HEADER: &str =
+    "tax_id\tparent_tax_id\tsource\tsource_tax_id\tformat\tpath\tcreated";
+
+/// A taxonomy used to generate a taxdump.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaxdumpSource {
+    pub format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xref_label: Option<String>,
+    /// Release and checksum recorded by `blobtk taxonomy fetch`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+impl TaxdumpSource {
+    fn new(options: &cli::TaxonomyOptions) -> TaxdumpSource {
+        let manifest: Option<serde_json::Value> = options
+            .path
+            .as_ref()
+            .map(|path| match path.is_dir() {
+                true => path.join("release.json"),
+                false => path.with_file_name("release.json"),
+            })
+            .and_then(|manifest| fs::read_to_string(manifest).ok())
+            .and_then(|manifest| serde_json::from_str(&manifest).ok());
+        let field = |key: &str| {
+            manifest
+                .as_ref()
+                .and_then(|manifest| manifest[key].as_str().map(String::from))
+        };
+        TaxdumpSource {
+            format: options
+                .taxonomy_format
+                .as_ref()
+                .map(|format| format!("{:?}", format).to_lowercase())
+                .unwrap_or_default(),
+            path: options.path.as_ref().map(|path| path.display().to_string()),
+            xref_label: options.xref_label.clone(),
+            release: field("release"),
+            md5: field("md5"),
+        }
+    }
+}
+
+/// Provenance of a generated taxdump, so custom taxonomies can be told
+/// apart from official releases.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaxdumpMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub blobtk_version: String,
+    pub command: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub sources: Vec<TaxdumpSource>,
+    pub root_taxon_ids: Vec<String>,
+    pub nodes: usize,
+    pub names: usize,
+}
+
+impl TaxdumpMetadata {
+    /// Describe a taxdump written for `root_ids` from taxonomies parsed with
+    /// `options`.
+    pub fn new(
+        nodes: &Nodes,
+        options: &cli::TaxonomyOptions,
+        root_ids: &[String],
+        base_id: Option<String>,
+    ) -> TaxdumpMetadata {
+        let mut sources = vec![TaxdumpSource::new(options)];
+        for taxonomy in options.taxonomies.iter().flatten() {
+            sources.push(TaxdumpSource::new(taxonomy));
+        }
+        let mut seen = HashSet::new();
+        let mut names = 0;
+        for node in nodes.subtree(root_ids.to_vec(), base_id) {
+            if seen.insert(&node.tax_id) {
+                names += node.names.as_ref().map_or(0, Vec::len);
+            }
+        }
+        TaxdumpMetadata {
+            label: options.label.clone(),
+            blobtk_version: env!("CARGO_PKG_VERSION").to_string(),
+            command: std::env::args().collect(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            sources,
+            root_taxon_ids: root_ids.to_vec(),
+            nodes: seen.len(),
+            names,
+        }
+    }
+
+    /// Write the metadata file to a taxdump directory, returning its path.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, anyhow::Error> {
+        let path = dir.join(METADATA_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Write the source of each taxon in `nodes` that was added from another
+    /// taxonomy to a taxdump directory, returning its path if any were.
+    ///
+    /// Taxa added when the backbone was generated keep the rows from its
+    /// provenance file, so every added taxon can be traced through chained
+    /// runs.
+    pub fn write_provenance(
+        &self,
+        nodes: &[&Node],
+        dir: &Path,
+    ) -> Result<Option<PathBuf>, anyhow::Error> {
+        let inherited: HashMap<String, String> = self
+            .sources
+            .first()
+            .and_then(|backbone| backbone.path.as_ref())
+            .and_then(|path| fs::read_to_string(Path::new(path).join(PROVENANCE_FILE)).ok())
+            .map(|rows| {
+                rows.lines()
+                    .skip(1)
+                    .filter_map(|row| {
+                        row.split_once('\t')
+                            .map(|(tax_id, _)| (tax_id.to_string(), row.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut rows = vec![];
+        let mut seen = HashSet::new();
+        for node in nodes {
+            if !seen.insert(&node.tax_id) {
+                continue;
+            }
+            let label = match node.source.as_ref() {
+                Some(label) => label,
+                None => {
+                    if let Some(row) = inherited.get(&node.tax_id) {
+                        rows.push(row.clone());
+                    }
+                    continue;
+                }
+            };
+            let source = self
+                .sources
+                .iter()
+                .skip(1)
+                .find(|source| source.xref_label.as_deref().unwrap_or_default() == label);
+            rows.push(
+                [
+                    node.tax_id.as_str(),
+                    node.parent_tax_id.as_str(),
+                    label,
+                    node.source_tax_id.as_deref().unwrap_or_default(),
+                    source.map_or("", |source| source.format.as_str()),
+                    source
+                        .and_then(|source| source.path.as_deref())
+                        .unwrap_or_default(),
+                    &self.created.to_string(),
+                ]
+                .join("\t"),
+            );
+        }
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let path = dir.join(PROVENANCE_FILE);
+        fs::write(
+            &path,
+            format!("{}\n{}\n", PROVENANCE_HEADER, rows.join("\n")),
+        )?;
+        Ok(Some(path))
+    }
+
+    /// Load the metadata file from a taxdump directory, if there is one.
+    pub fn load(dir: &Path) -> Option<TaxdumpMetadata> {
+        let metadata = fs::read_to_string(dir.join(METADATA_FILE)).ok()?;
+        serde_json::from_str(&metadata).ok()
+    }
+}
+
+/// Report the provenance of a generated taxdump loaded from `dir`, warning
+/// if it no longer has the number of nodes and names that were written.
+pub fn report(dir: &Path, nodes: &Nodes) {
+    let metadata = match TaxdumpMetadata::load(dir) {
+        Some(metadata) => metadata,
+        None => return,
+    };
+    let sources: Vec<String> = metadata
+        .sources
+        .iter()
+        .map(|source| {
+            let release = source
+                .release
+                .as_ref()
+                .map_or(String::new(), |release| format!(" {}", release));
+            format!("{}{}", source.format, release)
+        })
+        .collect();
+    eprintln!(
+        "Loading generated taxdump{} (blobtk {} from {})",
+        metadata
+            .label
+            .as_ref()
+            .map_or(String::new(), |label| format!(" {}", label)),
+        metadata.blobtk_version,
+        sources.join(", ")
+    );
+    let names: usize = nodes
+        .nodes
+        .values()
+        .map(|node| node.names.as_ref().map_or(0, Vec::len))
+        .sum();
+    if metadata.nodes != nodes.nodes.len() || metadata.names != names {
+        eprintln!(
+            "Warning: taxdump has {} nodes and {} names but {} and {} were written",
+            nodes.nodes.len(),
+            names,
+            metadata.nodes,
+            metadata.names
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::taxonomy::{parse_taxdump, write_taxdump};
+
+    #[test]
+    fn test_metadata() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let options = cli::TaxonomyOptions {
+            path: Some(PathBuf::from("test/taxonomy/canidae/ncbi")),
+            taxonomy_format: Some(cli::TaxonomyFormat::NCBI),
+            label: Some("canids".to_string()),
+            ..Default::default()
+        };
+        let root_ids = vec!["9608".to_string()];
+        let metadata = TaxdumpMetadata::new(&nodes, &options, &root_ids, None);
+        let out_dir = std::env::temp_dir().join("blobtk_test_metadata");
+        let _ = fs::remove_dir_all(&out_dir);
+        write_taxdump(
+            &nodes,
+            Some(root_ids),
+            None,
+            out_dir.clone(),
+            None,
+            false,
+            Some(&metadata),
+        )
+        .unwrap();
+        assert_eq!(TaxdumpMetadata::load(&out_dir), Some(metadata.clone()));
+        let written = parse_taxdump(out_dir.clone()).unwrap();
+        assert_eq!(written.nodes.len(), metadata.nodes);
+        assert_eq!(metadata.sources[0].format, "ncbi");
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        nodes.nodes.insert(
+            "gbif:1".to_string(),
+            Node {
+                tax_id: "gbif:1".to_string(),
+                parent_tax_id: "9612".to_string(),
+                source: Some("gbif".to_string()),
+                source_tax_id: Some("1".to_string()),
+                ..Default::default()
+            },
+        );
+        let options = cli::TaxonomyOptions {
+            path: Some(PathBuf::from("test/taxonomy/canidae/ncbi")),
+            taxonomies: Some(vec![cli::TaxonomyOptions {
+                path: Some(PathBuf::from("test/taxonomy/canidae/gbif")),
+                xref_label: Some("gbif".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let metadata = TaxdumpMetadata::new(&nodes, &options, &["9608".to_string()], None);
+        let out_dir = std::env::temp_dir().join("blobtk_test_provenance");
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(&out_dir).unwrap();
+        let backbone = vec![&nodes.nodes["9612"]];
+        assert_eq!(
+            metadata.write_provenance(&backbone, &out_dir).unwrap(),
+            None
+        );
+        let added = vec![&nodes.nodes["9612"], &nodes.nodes["gbif:1"]];
+        let path = metadata
+            .write_provenance(&added, &out_dir)
+            .unwrap()
+            .unwrap();
+        let rows = fs::read_to_string(path).unwrap();
+        assert_eq!(
+            rows.lines().nth(1).unwrap(),
+            format!(
+                "gbif:1\t9612\tgbif\t1\t\ttest/taxonomy/canidae/gbif\t{}",
+                metadata.created
+            )
+        );
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+}
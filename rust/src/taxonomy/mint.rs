@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use crc32fast;
+
+use super::file_reader;
+use super::parse::Nodes;
+use crate::cli::{self, MintScheme};
+use crate::error;
+use crate::io;
+
+/// Header of a `--mint-map` file.
+pub const MINT_MAP_HEADER: &str = "xref\ttax_id";
+
+/// Assigns taxon IDs to taxa added to the backbone by `lookup_nodes`.
+///
+/// Taxa are identified by their xref, the source taxon ID prefixed with
+/// the xref label (`<label>:<id>`), which is also the taxon ID given by the
+/// default `xref` scheme. IDs read from a mapping file are reused for the
+/// same xref, so added taxa keep their IDs across runs.
+#[derive(Clone, Debug, Default)]
+pub struct TaxIdMinter {
+    scheme: MintScheme,
+    prefix: String,
+    start: Option<u64>,
+    next: u64,
+    /// Taxon IDs by xref, from the mapping file and this run
+    minted: BTreeMap<String, String>,
+    used: HashSet<String>,
+    map_path: Option<PathBuf>,
+}
+
+/// Parse the number after a prefix in a taxon ID.
+fn numeric_suffix(tax_id: &str, prefix: &str) -> Option<u64> {
+    tax_id.strip_prefix(prefix)?.parse().ok()
+}
+
+impl TaxIdMinter {
+    /// Set up a minter from the `--mint` options, reading the mapping file
+    /// if it exists.
+    pub fn new(options: &cli::TaxonomyOptions, nodes: &Nodes) -> Result<TaxIdMinter, error::Error> {
+        let scheme = options.mint.clone().unwrap_or_default();
+        let prefix = match scheme {
+            MintScheme::Prefix => options
+                .mint_prefix
+                .clone()
+                .unwrap_or_else(|| "new".to_string()),
+            _ => "".to_string(),
+        };
+        let mut minted = BTreeMap::new();
+        if let Some(path) = options.mint_map.as_ref().filter(|path| path.exists()) {
+            let reader = match file_reader(path.clone()) {
+                Some(reader) => reader,
+                None => return Err(error::Error::FileNotFound(path.display().to_string())),
+            };
+            for line in reader.lines().skip(1) {
+                let line = line?;
+                if let Some((xref, tax_id)) = line.split_once('\t') {
+                    minted.insert(xref.to_string(), tax_id.to_string());
+                }
+            }
+        }
+        let used: HashSet<String> = minted.values().cloned().collect();
+        // continue after the largest ID in use, unless a start is given
+        let next = match (&scheme, options.mint_start) {
+            (MintScheme::Numeric | MintScheme::Prefix, Some(start)) => start,
+            (MintScheme::Numeric, None) => {
+                nodes
+                    .nodes
+                    .keys()
+                    .chain(used.iter())
+                    .filter_map(|tax_id| numeric_suffix(tax_id, ""))
+                    .max()
+                    .unwrap_or(0)
+                    + 1
+            }
+            (MintScheme::Prefix, None) => {
+                used.iter()
+                    .filter_map(|tax_id| numeric_suffix(tax_id, &prefix))
+                    .max()
+                    .unwrap_or(0)
+                    + 1
+            }
+            _ => 0,
+        };
+        Ok(TaxIdMinter {
+            scheme,
+            prefix,
+            start: options.mint_start,
+            next,
+            minted,
+            used,
+            map_path: options.mint_map.clone(),
+        })
+    }
+
+    /// Taxon ID for an added taxon, reusing the ID from the mapping file if
+    /// there is one and it is not taken by a backbone taxon.
+    pub fn mint(&mut self, xref: &str, nodes: &Nodes) -> String {
+        if let Some(tax_id) = self.minted.get(xref) {
+            if !nodes.nodes.contains_key(tax_id) {
+                return tax_id.clone();
+            }
+            eprintln!(
+                "Taxon ID {} for {} is in use, assigning a new ID",
+                tax_id, xref
+            );
+        }
+        let taken =
+            |tax_id: &String| nodes.nodes.contains_key(tax_id) || self.used.contains(tax_id);
+        let tax_id = match self.scheme {
+            MintScheme::Xref => xref.to_string(),
+            MintScheme::Numeric | MintScheme::Prefix => loop {
+                let tax_id = format!("{}{}", self.prefix, self.next);
+                self.next += 1;
+                if !taken(&tax_id) {
+                    break tax_id;
+                }
+            },
+            MintScheme::Hash => {
+                let mut value = self.start.unwrap_or(0) + crc32fast::hash(xref.as_bytes()) as u64;
+                // step past collisions, which are rare enough that IDs
+                // rarely depend on the order taxa are added in
+                while taken(&value.to_string()) {
+                    value += 1;
+                }
+                value.to_string()
+            }
+        };
+        self.used.insert(tax_id.clone());
+        self.minted.insert(xref.to_string(), tax_id.clone());
+        tax_id
+    }
+
+    /// Write all assigned taxon IDs to the mapping file, if there is one.
+    pub fn save(&self) -> Result<(), error::Error> {
+        let path = match self.map_path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut writer = io::get_writer(&Some(path.clone()));
+        writeln!(writer, "{}", MINT_MAP_HEADER)?;
+        for (xref, tax_id) in self.minted.iter() {
+            writeln!(writer, "{}\t{}", xref, tax_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_mint() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let mint = |scheme, mint_map: Option<PathBuf>| {
+            TaxIdMinter::new(
+                &cli::TaxonomyOptions {
+                    mint: Some(scheme),
+                    mint_map,
+                    ..Default::default()
+                },
+                &nodes,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            mint(MintScheme::Xref, None).mint("gbif:1", &nodes),
+            "gbif:1"
+        );
+        let largest = nodes
+            .nodes
+            .keys()
+            .filter_map(|tax_id| tax_id.parse::<u64>().ok())
+            .max()
+            .unwrap();
+        let mut minter = mint(MintScheme::Numeric, None);
+        assert_eq!(minter.mint("gbif:1", &nodes), (largest + 1).to_string());
+        assert_eq!(minter.mint("gbif:2", &nodes), (largest + 2).to_string());
+        assert_eq!(minter.mint("gbif:1", &nodes), (largest + 1).to_string());
+        assert_eq!(
+            mint(MintScheme::Prefix, None).mint("gbif:1", &nodes),
+            "new1"
+        );
+        let hashed = mint(MintScheme::Hash, None).mint("gbif:1", &nodes);
+        assert_eq!(mint(MintScheme::Hash, None).mint("gbif:1", &nodes), hashed);
+        assert!(hashed.parse::<u64>().is_ok());
+
+        let path = std::env::temp_dir().join("blobtk_test_mint.tsv");
+        let _ = fs::remove_file(&path);
+        let mut minter = mint(MintScheme::Numeric, Some(path.clone()));
+        minter.mint("gbif:1", &nodes);
+        minter.save().unwrap();
+        // mapped IDs are kept and new IDs continue after them
+        let mut minter = mint(MintScheme::Numeric, Some(path));
+        assert_eq!(minter.mint("gbif:2", &nodes), (largest + 2).to_string());
+        assert_eq!(minter.mint("gbif:1", &nodes), (largest + 1).to_string());
+    }
+}
@@ -5,47 +5,167 @@
 
 // println!("{}", parser(line));
 
-use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fmt;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use anyhow;
 use convert_case::{Case, Casing};
 use csv::ReaderBuilder;
 use nom::{
     bytes::complete::{tag, take_until},
-    combinator::map,
+    combinator::{map, verify},
     multi::separated_list0,
     IResult,
 };
-// use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use struct_iterable::Iterable;
 
+use super::metadata::{self, TaxdumpMetadata, METADATA_FILE, PROVENANCE_FILE};
+use super::validate::Issue;
+use crate::cli;
 use crate::io;
+use crate::utils;
+
+/// Interned string for values repeated across many nodes and names, such
+/// as ranks and name classes, so each distinct value is stored once.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Symbol(Arc<str>);
+
+static SYMBOLS: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+impl Symbol {
+    /// Get the shared copy of a value, adding it if not seen before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use crate::blobtk::taxonomy::parse::Symbol;
+    /// let a = Symbol::new("species");
+    /// let b = Symbol::from("species".to_string());
+    /// assert_eq!(a, "species");
+    /// assert!(std::ptr::eq(a.as_str(), b.as_str()));
+    /// ```
+    pub fn new(value: &str) -> Symbol {
+        let mut symbols = SYMBOLS
+            .get_or_init(|| Mutex::new(HashSet::new()))
+            .lock()
+            .unwrap();
+        match symbols.get(value) {
+            Some(symbol) => Symbol(symbol.clone()),
+            None => {
+                let symbol: Arc<str> = Arc::from(value);
+                symbols.insert(symbol.clone());
+                Symbol(symbol)
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Symbol {
+        Symbol::new("")
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Symbol {
+        Symbol::new(value)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Symbol {
+        Symbol::new(&value)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(value: &String) -> Symbol {
+        Symbol::new(value)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Ok(Symbol::new(&value))
+    }
+}
 
 /// A taxon name
-#[derive(Clone, Debug, Default, Eq, Iterable, Ord, PartialEq, PartialOrd)]
+#[derive(
+    Clone, Debug, Default, Deserialize, Eq, Iterable, Ord, PartialEq, PartialOrd, Serialize,
+)]
 pub struct Name {
     pub tax_id: String,
     pub name: String,
     pub unique_name: String,
-    pub class: Option<String>,
+    pub class: Option<Symbol>,
 }
 
 impl Name {
     /// Parse a node.
     pub fn parse(input: &str) -> IResult<&str, Self> {
         // This parser outputs a Vec(&str).
-        let parse_name = separated_list0(tag("\t|\t"), take_until("\t|"));
+        let parse_name = verify(
+            separated_list0(tag("\t|\t"), take_until("\t|")),
+            |v: &Vec<&str>| v.len() >= 4,
+        );
         // Map the Vec(&str) into a Node.
         map(parse_name, |v: Vec<&str>| Name {
             tax_id: v[0].to_string(),
             name: v[1].to_string(),
-            class: Some(v[3].to_string()),
+            class: Some(v[3].into()),
             ..Default::default()
         })(input)
     }
@@ -65,6 +185,8 @@ impl fmt::Display for Name {
                 values.push(format!("{:?}", string_opt));
             } else if let Some(string_opt) = field_value.downcast_ref::<String>() {
                 values.push(string_opt.clone());
+            } else if let Some(symbol_opt) = field_value.downcast_ref::<Option<Symbol>>() {
+                values.push(symbol_opt.as_deref().unwrap_or("").to_string());
             }
         }
         write!(f, "{}\t|", values.join("\t|\t"))
@@ -72,35 +194,66 @@ impl fmt::Display for Name {
 }
 
 /// A taxonomy node
-#[derive(Clone, Debug, Default, Eq, Iterable, Ord, PartialEq, PartialOrd)]
+#[derive(
+    Clone, Debug, Default, Deserialize, Eq, Iterable, Ord, PartialEq, PartialOrd, Serialize,
+)]
 pub struct Node {
     pub tax_id: String,
     pub parent_tax_id: String,
-    pub rank: String,
+    pub rank: Symbol,
+    /// Remaining `nodes.dmp` columns after rank, such as division and
+    /// genetic code IDs, kept so they can be written back out unchanged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<String>>,
     pub names: Option<Vec<Name>>,
     pub scientific_name: Option<String>,
+    /// Ancestor tax_ids from root to parent, from `taxidlineage.dmp`.
+    pub lineage: Option<Vec<String>>,
+    /// Ancestor names by rank, from `rankedlineage.dmp`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranked_lineage: Option<BTreeMap<String, String>>,
+    /// Potential hosts, from `host.dmp`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+    /// Type material as (type, identifier) pairs, from `typematerial.dmp`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_material: Option<Vec<(String, String)>>,
+    /// Xref label of the taxonomy a taxon was added from by `lookup_nodes`,
+    /// empty if unlabelled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Taxon ID in the taxonomy a taxon was added from by `lookup_nodes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_tax_id: Option<String>,
 }
 
 impl Node {
     /// Parse a node.
     pub fn parse(input: &str) -> IResult<&str, Self> {
         // This parser outputs a Vec(&str).
-        let parse_node = separated_list0(tag("\t|\t"), take_until("\t|"));
+        let parse_node = verify(
+            separated_list0(tag("\t|\t"), take_until("\t|")),
+            |v: &Vec<&str>| v.len() >= 3,
+        );
         // Map the Vec(&str) into a Node.
         map(parse_node, |v: Vec<&str>| Node {
             tax_id: v[0].to_string(),
             parent_tax_id: v[1].to_string(),
-            rank: v[2].to_string(),
+            rank: v[2].into(),
+            columns: match v.len() {
+                0..=3 => None,
+                _ => Some(v[3..].iter().map(|s| s.to_string()).collect()),
+            },
             ..Default::default()
         })(input)
     }
 
-    pub fn tax_id(&self) -> String {
-        self.tax_id.clone()
+    pub fn tax_id(&self) -> &str {
+        &self.tax_id
     }
 
-    pub fn rank(&self) -> String {
-        self.rank.clone()
+    pub fn rank(&self) -> &str {
+        &self.rank
     }
 
     pub fn rank_letter(&self) -> char {
@@ -110,6 +263,16 @@ impl Node {
         self.rank.chars().next().unwrap()
     }
 
+    /// Rank code used in lookup keys, a single letter for the default lookup
+    /// ranks and the full rank otherwise so other ranks do not collide.
+    pub fn rank_key(&self) -> String {
+        match self.rank.as_str() {
+            "subspecies" | "species" | "genus" | "family" | "order" | "class" | "phylum"
+            | "kingdom" => self.rank_letter().to_string(),
+            rank => rank.to_string(),
+        }
+    }
+
     pub fn scientific_name(&self) -> String {
         match self.scientific_name.as_ref() {
             Some(name) => name.clone(),
@@ -127,11 +290,11 @@ impl Node {
 
     pub fn names_by_class(&self, classes_vec: Option<&Vec<String>>, lc: bool) -> Vec<String> {
         let mut filtered_names = vec![];
-        if let Some(names) = self.names.clone() {
+        if let Some(names) = self.names.as_ref() {
             for name in names {
                 if let Some(classes) = classes_vec {
-                    if let Some(class) = name.class {
-                        if classes.contains(&class) {
+                    if let Some(class) = name.class.as_ref() {
+                        if classes.iter().any(|c| class == c) {
                             if lc {
                                 filtered_names.push(name.name.to_case(Case::Lower));
                             } else {
@@ -152,7 +315,16 @@ impl Node {
 
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let ignore = vec!["names", "scientific_name"];
+        let ignore = [
+            "names",
+            "scientific_name",
+            "lineage",
+            "ranked_lineage",
+            "hosts",
+            "type_material",
+            "source",
+            "source_tax_id",
+        ];
         let mut values = vec![];
         for (field_name, field_value) in self.iter() {
             if !ignore.contains(&field_name) {
@@ -168,6 +340,12 @@ impl fmt::Display for Node {
                     values.push(format!("{:?}", string_opt));
                 } else if let Some(string_opt) = field_value.downcast_ref::<String>() {
                     values.push(string_opt.clone());
+                } else if let Some(symbol) = field_value.downcast_ref::<Symbol>() {
+                    values.push(symbol.to_string());
+                } else if let Some(Some(columns)) =
+                    field_value.downcast_ref::<Option<Vec<String>>>()
+                {
+                    values.extend(columns.iter().cloned());
                 }
             }
         }
@@ -176,13 +354,73 @@ impl fmt::Display for Node {
 }
 
 /// A set of taxonomy nodes
-#[derive(Clone, Debug, Default, Eq, Iterable, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Eq, Iterable, PartialEq, Serialize)]
 pub struct Nodes {
     pub nodes: HashMap<String, Node>,
     pub children: HashMap<String, Vec<String>>,
 }
 
 impl Nodes {
+    /// Build a set of nodes keyed by taxon ID, with sorted child lists.
+    pub fn from_nodes(nodes: HashMap<String, Node>) -> Nodes {
+        let mut taxonomy = Nodes {
+            nodes,
+            children: HashMap::new(),
+        };
+        let edges: Vec<(String, String)> = taxonomy
+            .nodes
+            .values()
+            .map(|node| (node.parent_tax_id.clone(), node.tax_id.clone()))
+            .collect();
+        for (parent_tax_id, tax_id) in edges {
+            taxonomy.add_child(&parent_tax_id, &tax_id);
+        }
+        for child_ids in taxonomy.children.values_mut() {
+            child_ids.sort();
+        }
+        taxonomy
+    }
+
+    /// Record a taxon as a child of its parent. Roots are their own parent
+    /// and are not added as children.
+    pub fn add_child(&mut self, parent_tax_id: &str, tax_id: &str) {
+        if parent_tax_id != tax_id {
+            self.children
+                .entry(parent_tax_id.to_string())
+                .or_default()
+                .push(tax_id.to_string());
+        }
+    }
+
+    /// Add a node and record it as a child of its parent, replacing any
+    /// node with the same taxon ID.
+    pub fn insert(&mut self, node: Node) {
+        self.add_child(&node.parent_tax_id, &node.tax_id);
+        self.nodes.insert(node.tax_id.clone(), node);
+    }
+
+    /// Add a root node with taxon ID `1`, for taxonomies with no root of
+    /// their own.
+    pub fn insert_root(&mut self) {
+        let root_id = "1".to_string();
+        self.nodes.insert(
+            root_id.clone(),
+            Node {
+                tax_id: root_id.clone(),
+                parent_tax_id: root_id.clone(),
+                rank: "no rank".into(),
+                scientific_name: Some("root".to_string()),
+                names: Some(vec![Name {
+                    tax_id: root_id,
+                    name: "root".to_string(),
+                    class: Some("scientific name".into()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+        );
+    }
+
     /// Get parent Node.
     pub fn parent(&self, taxon_id: &String) -> Option<&Node> {
         let node = self.nodes.get(taxon_id).unwrap();
@@ -190,7 +428,21 @@ impl Nodes {
     }
 
     /// Get lineage from root to target.
+    ///
+    /// Uses a stored lineage from `taxidlineage.dmp` when it includes the root.
     pub fn lineage(&self, root_id: &String, taxon_id: &String) -> Vec<&Node> {
+        if let Some(stored) = self
+            .nodes
+            .get(taxon_id)
+            .and_then(|node| node.lineage.as_ref())
+        {
+            if let Some(index) = stored.iter().position(|tax_id| tax_id == root_id) {
+                return stored[index..]
+                    .iter()
+                    .filter_map(|tax_id| self.nodes.get(tax_id))
+                    .collect();
+            }
+        }
         let mut nodes = vec![];
         let mut tax_id = taxon_id;
         if tax_id == root_id {
@@ -212,6 +464,25 @@ impl Nodes {
         nodes.into_iter().rev().collect()
     }
 
+    /// Child taxon IDs of a node, in `compare_tax_ids` order.
+    pub fn sorted_children(&self, tax_id: &String) -> Vec<&String> {
+        let mut children: Vec<&String> = self.children.get(tax_id).into_iter().flatten().collect();
+        children.sort_by(|a, b| compare_tax_ids(a, b));
+        children
+    }
+
+    /// Iterate depth-first over a node and its descendants, visiting
+    /// children in `compare_tax_ids` order.
+    pub fn descendants(&self, root_id: &String) -> Descendants<'_> {
+        Descendants::new(self, root_id, Traversal::DepthFirst)
+    }
+
+    /// Iterate breadth-first over a node and its descendants, visiting
+    /// children in `compare_tax_ids` order.
+    pub fn descendants_breadth_first(&self, root_id: &String) -> Descendants<'_> {
+        Descendants::new(self, root_id, Traversal::BreadthFirst)
+    }
+
     /// Write nodes.dmp file for a root taxon.
     pub fn write_taxdump(
         &self,
@@ -236,59 +507,383 @@ impl Nodes {
                     }
                 }
             }
-            if let Some(root_node) = self.nodes.get(&root_id) {
-                writeln!(nodes_writer, "{}", &root_node).unwrap();
-                if let Some(names) = root_node.names.as_ref() {
+            for node in self.descendants(&root_id) {
+                writeln!(nodes_writer, "{}", &node).unwrap();
+                if let Some(names) = node.names.as_ref() {
                     for name in names {
                         writeln!(names_writer, "{}", &name).unwrap();
                     }
                 }
-                if let Some(children) = self.children.get(&root_id) {
-                    for child in children {
-                        self.write_taxdump(vec![child.clone()], None, nodes_writer, names_writer)
+            }
+        }
+    }
+
+    /// Write nodes.dmp and names.dmp files for a root taxon with nodes
+    /// sorted by taxon ID.
+    pub fn write_sorted_taxdump(
+        &self,
+        root_ids: Vec<String>,
+        base_id: Option<String>,
+        nodes_writer: &mut Box<dyn Write>,
+        names_writer: &mut Box<dyn Write>,
+    ) {
+        let mut subtree = self.subtree(root_ids, base_id);
+        subtree.sort_by(|a, b| compare_tax_ids(&a.tax_id, &b.tax_id));
+        subtree.dedup_by(|a, b| a.tax_id == b.tax_id);
+        for node in subtree {
+            writeln!(nodes_writer, "{}", node).unwrap();
+            for name in node.names.iter().flatten() {
+                writeln!(names_writer, "{}", name).unwrap();
+            }
+        }
+    }
+
+    /// Write nodes as JSON Lines for a root taxon, with each node's lineage
+    /// of ancestor tax_ids from `lineage_root_id`.
+    pub fn write_jsonl(
+        &self,
+        root_ids: Vec<String>,
+        base_id: Option<String>,
+        lineage_root_id: &String,
+        writer: &mut Box<dyn Write>,
+    ) {
+        for node in self.subtree(root_ids, base_id) {
+            let mut node = node.clone();
+            node.lineage = Some(
+                self.lineage(lineage_root_id, &node.tax_id)
+                    .iter()
+                    .map(|ancestor| ancestor.tax_id.clone())
+                    .collect(),
+            );
+            writeln!(writer, "{}", serde_json::to_string(&node).unwrap()).unwrap();
+        }
+    }
+
+    /// Write a subtree as a DOT graph with rank and name attributes.
+    ///
+    /// Taxa added from other taxonomies by `lookup_nodes` have IDs prefixed
+    /// with their xref label and are drawn with dashed outlines.
+    pub fn write_dot(
+        &self,
+        root_ids: Vec<String>,
+        base_id: Option<String>,
+        writer: &mut Box<dyn Write>,
+    ) {
+        let subtree = self.subtree(root_ids, base_id);
+        let included: HashSet<&String> = subtree.iter().map(|node| &node.tax_id).collect();
+        let quote =
+            |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+        writeln!(writer, "digraph taxonomy {{").unwrap();
+        writeln!(writer, "    node [shape=box];").unwrap();
+        for node in subtree.iter() {
+            let source = node_source(node);
+            writeln!(
+                writer,
+                "    {} [label={}, rank={}, name={}, source={}{}];",
+                quote(&node.tax_id),
+                quote(&format!("{}\n{}", node.scientific_name(), node.rank)),
+                quote(&node.rank),
+                quote(&node.scientific_name()),
+                quote(source),
+                if source == "backbone" {
+                    ""
+                } else {
+                    ", style=dashed"
+                }
+            )
+            .unwrap();
+        }
+        for node in subtree.iter() {
+            if node.parent_tax_id != node.tax_id && included.contains(&node.parent_tax_id) {
+                writeln!(
+                    writer,
+                    "    {} -> {};",
+                    quote(&node.parent_tax_id),
+                    quote(&node.tax_id)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(writer, "}}").unwrap();
+    }
+
+    /// Write a subtree as a GraphML graph with rank, name and source
+    /// attributes.
+    pub fn write_graphml(
+        &self,
+        root_ids: Vec<String>,
+        base_id: Option<String>,
+        writer: &mut Box<dyn Write>,
+    ) {
+        let subtree = self.subtree(root_ids, base_id);
+        let included: HashSet<&String> = subtree.iter().map(|node| &node.tax_id).collect();
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )
+        .unwrap();
+        for key in ["rank", "name", "source"] {
+            writeln!(
+                writer,
+                r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="string"/>"#,
+                key
+            )
+            .unwrap();
+        }
+        writeln!(writer, r#"  <graph id="taxonomy" edgedefault="directed">"#).unwrap();
+        for node in subtree.iter() {
+            writeln!(writer, r#"    <node id="{}">"#, xml_escape(&node.tax_id)).unwrap();
+            for (key, value) in [
+                ("rank", node.rank.to_string()),
+                ("name", node.scientific_name()),
+                ("source", node_source(node).to_string()),
+            ] {
+                writeln!(
+                    writer,
+                    r#"      <data key="{}">{}</data>"#,
+                    key,
+                    xml_escape(&value)
+                )
+                .unwrap();
+            }
+            writeln!(writer, "    </node>").unwrap();
+        }
+        for node in subtree.iter() {
+            if node.parent_tax_id != node.tax_id && included.contains(&node.parent_tax_id) {
+                writeln!(
+                    writer,
+                    r#"    <edge source="{}" target="{}"/>"#,
+                    xml_escape(&node.parent_tax_id),
+                    xml_escape(&node.tax_id)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(writer, "  </graph>").unwrap();
+        writeln!(writer, "</graphml>").unwrap();
+    }
+
+    /// Write a `rankedlineage.dmp` file for a root taxon.
+    pub fn write_ranked_lineage(
+        &self,
+        root_ids: Vec<String>,
+        base_id: Option<String>,
+        writer: &mut Box<dyn Write>,
+    ) {
+        for node in self.subtree(root_ids, base_id) {
+            let ancestors: HashMap<&str, String> = self
+                .lineage(&"1".to_string(), &node.tax_id)
+                .iter()
+                .map(|ancestor| (ancestor.rank.as_str(), ancestor.scientific_name()))
+                .collect();
+            let mut values = vec![node.tax_id.clone(), node.scientific_name()];
+            for rank in RANKED_LINEAGE_RANKS {
+                values.push(ancestors.get(rank).cloned().unwrap_or_default());
+            }
+            writeln!(writer, "{}\t|", values.join("\t|\t")).unwrap();
+        }
+    }
+
+    /// Nodes in the same order as `write_taxdump`: ancestors of each root
+    /// from the base taxon, then each root and its descendants depth first.
+    pub fn subtree(&self, root_ids: Vec<String>, base_id: Option<String>) -> Vec<&Node> {
+        let mut nodes = vec![];
+        let mut ancestors = HashSet::new();
+        for root_id in root_ids {
+            if let Some(base_id) = base_id.as_ref() {
+                for anc_node in self.lineage(base_id, &root_id) {
+                    if ancestors.insert(anc_node.tax_id.clone()) {
+                        nodes.push(anc_node);
                     }
                 }
             }
+            nodes.extend(self.descendants(&root_id));
+        }
+        nodes
+    }
+
+    /// Rename ranks listed in `aliases`, so ranks from different sources can
+    /// be compared. Returns the number of nodes changed.
+    pub fn alias_ranks(&mut self, aliases: &HashMap<String, String>) -> usize {
+        let mut count = 0;
+        for node in self.nodes.values_mut() {
+            if let Some(rank) = aliases.get(node.rank.as_str()) {
+                if node.rank != *rank {
+                    node.rank = rank.into();
+                    count += 1;
+                }
+            }
         }
+        count
     }
 
-    pub fn nodes_by_rank(&self, rank: &str) -> Vec<Node> {
+    /// Merge nodes at any of `ranks` into their nearest species ancestor.
+    ///
+    /// Names of merged nodes are added to the species, with scientific names
+    /// kept as synonyms, and their remaining children are moved to the
+    /// species. Nodes without a species ancestor are left in place. Returns
+    /// the number of nodes merged.
+    pub fn roll_up(&mut self, ranks: &[String]) -> usize {
+        let mut species_ids: Vec<(String, String)> = vec![];
+        for (tax_id, node) in self.nodes.iter() {
+            if !ranks.iter().any(|rank| node.rank == *rank) {
+                continue;
+            }
+            let root_id = "1".to_string();
+            if let Some(species) = self
+                .lineage(&root_id, tax_id)
+                .into_iter()
+                .rev()
+                .find(|n| n.rank == "species")
+            {
+                species_ids.push((tax_id.clone(), species.tax_id.clone()));
+            }
+        }
+        species_ids.sort_by(|a, b| compare_tax_ids(&a.0, &b.0));
+        let rolled: HashMap<&String, &String> = species_ids.iter().map(|(a, b)| (a, b)).collect();
+        for (tax_id, species_id) in species_ids.iter() {
+            let node = self.nodes.remove(tax_id).unwrap();
+            let names: Vec<Name> = node
+                .names
+                .into_iter()
+                .flatten()
+                .map(|name| Name {
+                    tax_id: species_id.clone(),
+                    class: match name.class.as_deref() {
+                        Some("scientific name") => Some("synonym".into()),
+                        _ => name.class,
+                    },
+                    ..name
+                })
+                .collect();
+            if let Some(species) = self.nodes.get_mut(species_id) {
+                species.names.get_or_insert_with(Vec::new).extend(names);
+            }
+            for child_id in self.children.remove(tax_id).into_iter().flatten() {
+                if rolled.contains_key(&child_id) {
+                    continue;
+                }
+                if let Some(child) = self.nodes.get_mut(&child_id) {
+                    child.parent_tax_id = species_id.clone();
+                }
+                self.add_child(species_id, &child_id);
+            }
+            if let Some(siblings) = self.children.get_mut(&node.parent_tax_id) {
+                siblings.retain(|id| id != tax_id);
+            }
+        }
+        species_ids.len()
+    }
+
+    /// Release spare capacity left after parsing, which for a full NCBI or
+    /// GBIF taxonomy is a large share of the memory used by names and child
+    /// lists.
+    pub fn shrink_to_fit(&mut self) {
+        for node in self.nodes.values_mut() {
+            if let Some(names) = node.names.as_mut() {
+                names.shrink_to_fit();
+            }
+        }
+        for children in self.children.values_mut() {
+            children.shrink_to_fit();
+        }
+        self.nodes.shrink_to_fit();
+        self.children.shrink_to_fit();
+    }
+
+    /// Lowest common ancestor of a set of taxa.
+    ///
+    /// Taxon IDs not in the taxonomy are ignored, returning `None` if none
+    /// are found.
+    pub fn lca(&self, tax_ids: &[String]) -> Option<&Node> {
+        let path = |tax_id: &String| -> Option<Vec<&Node>> {
+            let mut node = self.nodes.get(tax_id)?;
+            let mut path = vec![node];
+            while let Some(parent) = self.nodes.get(&node.parent_tax_id) {
+                if parent.tax_id == node.tax_id || path.len() > self.nodes.len() {
+                    break;
+                }
+                path.push(parent);
+                node = parent;
+            }
+            path.reverse();
+            Some(path)
+        };
+        let mut paths = tax_ids.iter().filter_map(path);
+        let mut lca = paths.next()?;
+        for other in paths {
+            let shared = lca
+                .iter()
+                .zip(other.iter())
+                .take_while(|(a, b)| a.tax_id == b.tax_id)
+                .count();
+            lca.truncate(shared);
+        }
+        lca.last().copied()
+    }
+
+    /// Nodes at a given rank, sorted by taxon ID so output order does
+    /// not depend on hash map iteration order.
+    pub fn nodes_by_rank(&self, rank: &str) -> Vec<&Node> {
         let mut nodes = vec![];
-        for node in self.nodes.iter() {
-            if node.1.rank == rank {
-                nodes.push(node.1.clone());
+        for node in self.nodes.values() {
+            if node.rank == rank {
+                nodes.push(node);
             }
         }
+        nodes.sort_by(|a, b| a.tax_id.cmp(&b.tax_id));
         nodes
     }
 }
 
 pub fn parse_taxdump(taxdump: PathBuf) -> Result<Nodes, anyhow::Error> {
-    let mut nodes = HashMap::new();
-    let mut children = HashMap::new();
+    parse_taxdump_checked(taxdump, None)
+}
+
+/// Parse an NCBI taxdump, recording malformed lines, duplicate taxon IDs
+/// and names of unknown taxa in `issues` rather than failing.
+///
+/// Without `issues`, malformed lines are an error and names of unknown taxa
+/// are skipped with a warning.
+pub fn parse_taxdump_checked(
+    taxdump: PathBuf,
+    mut issues: Option<&mut Vec<Issue>>,
+) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
 
     let mut nodes_file = taxdump.clone();
     nodes_file.push("nodes.dmp");
 
     // Parse nodes.dmp file
-    if let Ok(lines) = io::read_lines(nodes_file) {
-        for line in lines {
+    if let Ok(lines) = io::read_lines(&nodes_file) {
+        for (i, line) in lines.enumerate() {
+            utils::check_cancelled()?;
             if let Ok(s) = line {
-                let node = Node::parse(&s).unwrap().1;
-                let parent = node.parent_tax_id.clone();
-                let child = node.tax_id.clone();
-                if parent != child {
-                    match children.entry(parent) {
-                        Entry::Vacant(e) => {
-                            e.insert(vec![child]);
-                        }
-                        Entry::Occupied(mut e) => {
-                            e.get_mut().push(child);
+                let node = match Node::parse(&s) {
+                    Ok((_, node)) => node,
+                    Err(_) => match issues.as_mut() {
+                        Some(issues) => {
+                            issues.push(Issue::new(
+                                "malformed",
+                                "",
+                                format!("nodes.dmp line {}", i + 1),
+                            ));
+                            continue;
                         }
+                        None => anyhow::bail!("malformed line {} in {:?}", i + 1, nodes_file),
+                    },
+                };
+                if nodes.nodes.contains_key(&node.tax_id) {
+                    if let Some(issues) = issues.as_mut() {
+                        issues.push(Issue::new(
+                            "duplicate",
+                            &node.tax_id,
+                            format!("nodes.dmp line {}", i + 1),
+                        ));
                     }
                 }
-
-                nodes.insert(node.tax_id.clone(), node);
+                nodes.insert(node);
             }
         }
     }
@@ -297,27 +892,156 @@ pub fn parse_taxdump(taxdump: PathBuf) -> Result<Nodes, anyhow::Error> {
     names_file.push("names.dmp");
 
     // Parse names.dmp file and add to nodes
-    if let Ok(lines) = io::read_lines(names_file) {
-        for line in lines {
+    let mut unknown = 0;
+    if let Ok(lines) = io::read_lines(&names_file) {
+        for (i, line) in lines.enumerate() {
+            utils::check_cancelled()?;
             if let Ok(s) = line {
-                let name = Name::parse(&s).unwrap().1;
-                let node = nodes.get_mut(&name.tax_id).unwrap();
-                if let Some(class) = name.clone().class {
-                    if class == "scientific name" {
-                        node.scientific_name = Some(name.clone().name)
+                let name = match Name::parse(&s) {
+                    Ok((_, name)) => name,
+                    Err(_) => match issues.as_mut() {
+                        Some(issues) => {
+                            issues.push(Issue::new(
+                                "malformed",
+                                "",
+                                format!("names.dmp line {}", i + 1),
+                            ));
+                            continue;
+                        }
+                        None => anyhow::bail!("malformed line {} in {:?}", i + 1, names_file),
+                    },
+                };
+                let node = match nodes.nodes.get_mut(&name.tax_id) {
+                    Some(node) => node,
+                    None => {
+                        match issues.as_mut() {
+                            Some(issues) => issues.push(Issue::new(
+                                "unknown",
+                                &name.tax_id,
+                                format!("names.dmp line {} names an unknown taxon", i + 1),
+                            )),
+                            None => unknown += 1,
+                        }
+                        continue;
                     }
+                };
+                if name.class.as_deref() == Some("scientific name") {
+                    node.scientific_name = Some(name.name.clone())
                 }
-                let mut names = node.names.as_mut();
-                if let Some(names) = names.as_mut() {
-                    names.push(name);
-                } else {
-                    node.names = Some(vec![name]);
-                }
+                node.names.get_or_insert_with(Vec::new).push(name);
+            }
+        }
+    }
+    if unknown > 0 {
+        eprintln!(
+            "Skipped {} names of unknown taxa in {:?}",
+            unknown, names_file
+        );
+    }
+
+    parse_taxdump_extras(&taxdump, &mut nodes.nodes);
+
+    metadata::report(&taxdump, &nodes);
+    Ok(nodes)
+}
+
+/// Read lines from an optional taxdump file.
+fn dmp_lines(taxdump: &Path, file_name: &str) -> Vec<String> {
+    let path = taxdump.join(file_name);
+    if !path.exists() {
+        return vec![];
+    }
+    match io::read_lines(path) {
+        Ok(lines) => lines.map_while(Result::ok).collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn dmp_fields(line: &str) -> Vec<&str> {
+    line.trim_end_matches("\t|").split("\t|\t").collect()
+}
+
+/// Taxon IDs deleted from an NCBI taxdump, from `delnodes.dmp` if present.
+pub fn parse_delnodes(taxdump: &Path) -> HashSet<String> {
+    dmp_lines(taxdump, "delnodes.dmp")
+        .iter()
+        .filter_map(|line| {
+            dmp_fields(line)
+                .first()
+                .map(|tax_id| tax_id.trim().to_string())
+        })
+        .filter(|tax_id| !tax_id.is_empty())
+        .collect()
+}
+
+/// Ranks of the ancestor name columns in `rankedlineage.dmp`.
+pub(crate) const RANKED_LINEAGE_RANKS: [&str; 8] = [
+    "species",
+    "genus",
+    "family",
+    "order",
+    "class",
+    "phylum",
+    "kingdom",
+    "superkingdom",
+];
+
+/// Add optional new_taxdump files to parsed nodes.
+///
+/// Reads `taxidlineage.dmp`, `rankedlineage.dmp`, `host.dmp` and
+/// `typematerial.dmp` when present in the taxdump directory.
+fn parse_taxdump_extras(taxdump: &Path, nodes: &mut HashMap<String, Node>) {
+    for line in dmp_lines(taxdump, "taxidlineage.dmp") {
+        let fields = dmp_fields(&line);
+        if fields.len() < 2 {
+            continue;
+        }
+        let mut lineage: Vec<String> = fields[1].split_whitespace().map(String::from).collect();
+        // taxidlineage.dmp omits the root node
+        if let Some(first) = lineage.first().and_then(|tax_id| nodes.get(tax_id)) {
+            if first.parent_tax_id != first.tax_id {
+                lineage.insert(0, first.parent_tax_id.clone());
             }
         }
+        if let Some(node) = nodes.get_mut(fields[0]) {
+            node.lineage = Some(lineage);
+        }
+    }
+
+    for line in dmp_lines(taxdump, "rankedlineage.dmp") {
+        let fields = dmp_fields(&line);
+        if let Some(node) = nodes.get_mut(fields[0]) {
+            let ranked_lineage = RANKED_LINEAGE_RANKS
+                .iter()
+                .zip(fields.iter().skip(2))
+                .filter(|(_, name)| !name.is_empty())
+                .map(|(rank, name)| (rank.to_string(), name.to_string()))
+                .collect();
+            node.ranked_lineage = Some(ranked_lineage);
+        }
+    }
+
+    for line in dmp_lines(taxdump, "host.dmp") {
+        let fields = dmp_fields(&line);
+        if fields.len() < 2 {
+            continue;
+        }
+        if let Some(node) = nodes.get_mut(fields[0]) {
+            node.hosts = Some(fields[1].split(',').map(String::from).collect());
+        }
     }
 
-    Ok(Nodes { nodes, children })
+    for line in dmp_lines(taxdump, "typematerial.dmp") {
+        let fields = dmp_fields(&line);
+        if fields.len() < 4 {
+            continue;
+        }
+        if let Some(node) = nodes.get_mut(fields[0]) {
+            node.type_material
+                .get_or_insert_with(Vec::new)
+                .push((fields[2].to_string(), fields[3].to_string()));
+        }
+    }
 }
 
 pub fn write_taxdump(
@@ -325,7 +1049,10 @@ pub fn write_taxdump(
     root_taxon_ids: Option<Vec<String>>,
     base_taxon_id: Option<String>,
     taxdump: PathBuf,
-) {
+    compression: Option<cli::TaxdumpCompression>,
+    sorted: bool,
+    metadata: Option<&TaxdumpMetadata>,
+) -> Result<(), anyhow::Error> {
     let mut root_ids = vec![];
     match root_taxon_ids {
         Some(ids) => {
@@ -335,109 +1062,2017 @@ pub fn write_taxdump(
         }
         None => root_ids.push("1".to_string()),
     };
-    let mut nodes_writer = io::get_writer(&Some(io::append_to_path(&taxdump, "/nodes.dmp")));
-    let mut names_writer = io::get_writer(&Some(io::append_to_path(&taxdump, "/names.dmp")));
-
-    nodes.write_taxdump(
-        root_ids,
-        base_taxon_id,
-        &mut nodes_writer,
-        &mut names_writer,
-    );
+    let (provenance_root_ids, provenance_base_id) = (root_ids.clone(), base_taxon_id.clone());
+    let mut files = vec!["nodes.dmp", "names.dmp"];
+    // Archive members need a known size, so write them to scratch first
+    let dir = match compression {
+        Some(cli::TaxdumpCompression::TarGz) => utils::scratch_dir("taxdump")?,
+        _ => taxdump.clone(),
+    };
+    let suffix = match compression {
+        Some(cli::TaxdumpCompression::Gzip) => ".gz",
+        _ => "",
+    };
+    {
+        let mut nodes_writer = io::get_writer(&Some(dir.join(format!("{}{}", files[0], suffix))));
+        let mut names_writer = io::get_writer(&Some(dir.join(format!("{}{}", files[1], suffix))));
+
+        match sorted {
+            true => nodes.write_sorted_taxdump(
+                root_ids,
+                base_taxon_id,
+                &mut nodes_writer,
+                &mut names_writer,
+            ),
+            false => nodes.write_taxdump(
+                root_ids,
+                base_taxon_id,
+                &mut nodes_writer,
+                &mut names_writer,
+            ),
+        }
+        nodes_writer.flush()?;
+        names_writer.flush()?;
+    }
+    if let Some(metadata) = metadata {
+        metadata.write(&dir)?;
+        files.push(METADATA_FILE);
+        // only look for added taxa if there could be any
+        let backbone_provenance = metadata
+            .sources
+            .first()
+            .and_then(|backbone| backbone.path.as_ref())
+            .is_some_and(|path| Path::new(path).join(PROVENANCE_FILE).exists());
+        if backbone_provenance || nodes.nodes.values().any(|node| node.source.is_some()) {
+            let subtree = nodes.subtree(provenance_root_ids, provenance_base_id);
+            if metadata.write_provenance(&subtree, &dir)?.is_some() {
+                files.push(PROVENANCE_FILE);
+            }
+        }
+    }
+    if let Some(cli::TaxdumpCompression::TarGz) = compression {
+        utils::check_cancelled()?;
+        let mut archive = tar::Builder::new(io::get_writer(&Some(taxdump.join("taxdump.tar.gz"))));
+        for file in files {
+            archive.append_path_with_name(dir.join(file), file)?;
+        }
+        archive.into_inner()?.flush()?;
+        std::fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
 }
 
-pub fn parse_gbif(gbif_backbone: PathBuf) -> Result<Nodes, anyhow::Error> {
-    let mut nodes = HashMap::new();
-    let mut children = HashMap::new();
+/// Write a `rankedlineage.dmp` file to the output directory.
+pub fn write_ranked_lineage(
+    nodes: &Nodes,
+    root_taxon_ids: Option<Vec<String>>,
+    base_taxon_id: Option<String>,
+    taxdump: PathBuf,
+) {
+    let root_ids = root_taxon_ids.unwrap_or(vec!["1".to_string()]);
+    let mut writer = io::get_writer(&Some(taxdump.join("rankedlineage.dmp")));
+    nodes.write_ranked_lineage(root_ids, base_taxon_id, &mut writer);
+}
 
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'\t')
-        .from_path(gbif_backbone)?;
+/// Order in which `Descendants` visits a subtree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Traversal {
+    DepthFirst,
+    BreadthFirst,
+}
 
-    // Status can be:
-    // ACCEPTED
-    // DOUBTFUL
-    // HETEROTYPIC_SYNONYM
-    // HOMOTYPIC_SYNONYM
-    // MISAPPLIED
-    // PROPARTE_SYNONYM
-    // SYNONYM
-    let mut ignore = HashSet::new();
-    ignore.insert("DOUBTFUL");
-    ignore.insert("MISAPPLIED");
-    for result in rdr.records() {
-        let record = result?;
-        let status = record.get(4).unwrap();
-        if ignore.contains(status) {
-            continue;
+/// Iterator over a node and its descendants, from `Nodes::descendants` or
+/// `Nodes::descendants_breadth_first`.
+pub struct Descendants<'a> {
+    nodes: &'a Nodes,
+    pending: VecDeque<&'a Node>,
+    traversal: Traversal,
+}
+
+impl<'a> Descendants<'a> {
+    fn new(nodes: &'a Nodes, root_id: &String, traversal: Traversal) -> Descendants<'a> {
+        Descendants {
+            nodes,
+            pending: nodes.nodes.get(root_id).into_iter().collect(),
+            traversal,
         }
+    }
+}
 
-        let tax_id = record.get(0).unwrap().to_string();
-        let name_class = match status {
-            "ACCEPTED" => "scientific name".to_string(),
-            _ => "synonym".to_string(),
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        let node = match self.traversal {
+            Traversal::DepthFirst => self.pending.pop_back()?,
+            Traversal::BreadthFirst => self.pending.pop_front()?,
         };
-        let taxon_name = record.get(19).unwrap().to_string();
-        let mut parent_tax_id = record.get(1).unwrap().to_string();
-        if parent_tax_id == "\\N" {
-            parent_tax_id = tax_id.clone()
+        let children = self
+            .nodes
+            .sorted_children(&node.tax_id)
+            .into_iter()
+            .filter_map(|tax_id| self.nodes.nodes.get(tax_id));
+        match self.traversal {
+            Traversal::DepthFirst => {
+                let children: Vec<&Node> = children.collect();
+                self.pending.extend(children.into_iter().rev());
+            }
+            Traversal::BreadthFirst => self.pending.extend(children),
         }
-        let name = Name {
-            tax_id: tax_id.clone(),
-            name: taxon_name.clone(),
-            class: Some(name_class.clone()),
-            ..Default::default()
-        };
-        match nodes.entry(tax_id.clone()) {
-            Entry::Vacant(e) => {
-                let node = Node {
-                    tax_id,
-                    parent_tax_id,
-                    rank: record.get(5).unwrap().to_case(Case::Lower),
-                    scientific_name: if name_class == "scientific name" {
-                        Some(taxon_name)
-                    } else {
-                        None
-                    },
-                    names: Some(vec![name]),
+        Some(node)
+    }
+}
+
+/// Order taxon IDs with numeric IDs first, by value, then other IDs
+/// lexically.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::parse::compare_tax_ids;
+/// let mut tax_ids = vec!["gbif:1", "10", "9", "100", "1"];
+/// tax_ids.sort_by(|a, b| compare_tax_ids(a, b));
+/// assert_eq!(tax_ids, vec!["1", "9", "10", "100", "gbif:1"]);
+/// ```
+pub fn compare_tax_ids(a: &str, b: &str) -> std::cmp::Ordering {
+    let numeric = |tax_id: &str| tax_id.parse::<u64>().ok();
+    match (numeric(a), numeric(b)) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Source of a taxon, from the xref label of taxa added by `lookup_nodes`,
+/// falling back to the label prefix of their taxon ID, or `backbone`.
+pub(crate) fn node_source(node: &Node) -> &str {
+    match node.source.as_deref() {
+        Some("") => "unlabelled",
+        Some(label) => label,
+        None => match node.tax_id.split_once(':') {
+            Some(("", _)) => "unlabelled",
+            Some((label, _)) => label,
+            None => "backbone",
+        },
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Write a subtree as a DOT graph to `nodes.dot` in the output directory.
+pub fn write_dot(
+    nodes: &Nodes,
+    root_taxon_ids: Option<Vec<String>>,
+    base_taxon_id: Option<String>,
+    taxdump: PathBuf,
+) {
+    let root_ids = root_taxon_ids.unwrap_or(vec!["1".to_string()]);
+    let mut writer = io::get_writer(&Some(taxdump.join("nodes.dot")));
+    nodes.write_dot(root_ids, base_taxon_id, &mut writer);
+}
+
+/// Write a subtree as GraphML to `nodes.graphml` in the output directory.
+pub fn write_graphml(
+    nodes: &Nodes,
+    root_taxon_ids: Option<Vec<String>>,
+    base_taxon_id: Option<String>,
+    taxdump: PathBuf,
+) {
+    let root_ids = root_taxon_ids.unwrap_or(vec!["1".to_string()]);
+    let mut writer = io::get_writer(&Some(taxdump.join("nodes.graphml")));
+    nodes.write_graphml(root_ids, base_taxon_id, &mut writer);
+}
+
+/// Write nodes as JSON Lines to `nodes.jsonl` in the output directory.
+pub fn write_jsonl(
+    nodes: &Nodes,
+    root_taxon_ids: Option<Vec<String>>,
+    base_taxon_id: Option<String>,
+    taxdump: PathBuf,
+) {
+    let root_ids = root_taxon_ids.unwrap_or(vec!["1".to_string()]);
+    let mut writer = io::get_writer(&Some(taxdump.join("nodes.jsonl")));
+    nodes.write_jsonl(root_ids, base_taxon_id, &"1".to_string(), &mut writer);
+}
+
+pub fn parse_gbif(gbif_backbone: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+
+    let mut rdr = csv.reader(
+        ReaderBuilder::new().has_headers(false).delimiter(b'\t'),
+        &gbif_backbone,
+    )?;
+
+    // Status can be:
+    // ACCEPTED
+    // DOUBTFUL
+    // HETEROTYPIC_SYNONYM
+    // HOMOTYPIC_SYNONYM
+    // MISAPPLIED
+    // PROPARTE_SYNONYM
+    // SYNONYM
+    let mut ignore = HashSet::new();
+    ignore.insert("DOUBTFUL");
+    ignore.insert("MISAPPLIED");
+    for result in rdr.records() {
+        let record = match csv.record(result, &gbif_backbone, &HashMap::new())? {
+            Some(record) => record,
+            None => continue,
+        };
+        let status = record.get(4).unwrap();
+        if ignore.contains(status) {
+            continue;
+        }
+
+        let tax_id = record.get(0).unwrap().to_string();
+        let name_class = match status {
+            "ACCEPTED" => "scientific name".to_string(),
+            _ => "synonym".to_string(),
+        };
+        let taxon_name = record.get(19).unwrap().to_string();
+        let mut parent_tax_id = record.get(1).unwrap().to_string();
+        if parent_tax_id == "\\N" {
+            parent_tax_id = tax_id.clone()
+        }
+        let name = Name {
+            tax_id: tax_id.clone(),
+            name: taxon_name.clone(),
+            class: Some(name_class.as_str().into()),
+            ..Default::default()
+        };
+        match nodes.nodes.get_mut(&tax_id) {
+            Some(node) => {
+                if name_class == "scientific name" {
+                    node.scientific_name = Some(taxon_name);
+                }
+                if let Some(names) = node.names.as_mut() {
+                    names.push(name);
+                }
+            }
+            None => nodes.insert(Node {
+                tax_id,
+                parent_tax_id,
+                rank: record.get(5).unwrap().to_case(Case::Lower).into(),
+                scientific_name: if name_class == "scientific name" {
+                    Some(taxon_name)
+                } else {
+                    None
+                },
+                names: Some(vec![name]),
+                ..Default::default()
+            }),
+        }
+
+        // println!("{:?}", record.get(0));
+        // let node = Node {
+        //     tax_id,
+        //     parent_tax_id: record.get(1).unwrap().to_string(),
+        //     rank: record.get(5).unwrap().to_case(Case::Lower),
+        //     scientific_name: Some(record.get(19).unwrap().to_string()),
+        //     ..Default::default()
+        // };
+    }
+    Ok(nodes)
+}
+
+fn itis_reader(
+    csv: &CsvContext,
+    itis_dump: &Path,
+    table: &str,
+) -> Result<csv::Reader<File>, anyhow::Error> {
+    csv.reader(
+        ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(b'|')
+            .quoting(false)
+            .flexible(true),
+        itis_dump.join(table),
+    )
+}
+
+fn itis_field(record: &csv::ByteRecord, index: usize) -> String {
+    match record.get(index) {
+        Some(value) => String::from_utf8_lossy(value).trim().to_string(),
+        None => "".to_string(),
+    }
+}
+
+/// Parse an ITIS database dump (pipe-delimited `taxonomic_units`,
+/// `taxon_unit_types`, `synonym_links` and `vernaculars` tables).
+///
+/// Accepted taxa become nodes, while invalid names are added to their
+/// accepted taxon as synonyms and vernacular names as common names.
+pub fn parse_itis(itis_dump: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+
+    // Map (kingdom_id, rank_id) to a rank name
+    let mut ranks = HashMap::new();
+    let mut rdr = itis_reader(csv, &itis_dump, "taxon_unit_types")?;
+    for result in rdr.byte_records() {
+        let record =
+            match csv.record(result, &itis_dump.join("taxon_unit_types"), &HashMap::new())? {
+                Some(record) => record,
+                None => continue,
+            };
+        ranks.insert(
+            (itis_field(&record, 0), itis_field(&record, 1)),
+            itis_field(&record, 2).to_case(Case::Lower),
+        );
+    }
+
+    // Accepted taxa become nodes, other names are kept for synonym_links
+    let mut unaccepted = HashMap::new();
+    let mut rdr = itis_reader(csv, &itis_dump, "taxonomic_units")?;
+    for result in rdr.byte_records() {
+        let record =
+            match csv.record(result, &itis_dump.join("taxonomic_units"), &HashMap::new())? {
+                Some(record) => record,
+                None => continue,
+            };
+        let tax_id = itis_field(&record, 0);
+        let taxon_name = itis_field(&record, 25);
+        let name_usage = itis_field(&record, 10);
+        if name_usage != "valid" && name_usage != "accepted" {
+            unaccepted.insert(tax_id, taxon_name);
+            continue;
+        }
+        let mut parent_tax_id = itis_field(&record, 17);
+        if parent_tax_id.is_empty() || parent_tax_id == "0" {
+            parent_tax_id = tax_id.clone()
+        }
+        let rank = match ranks.get(&(itis_field(&record, 20), itis_field(&record, 21))) {
+            Some(rank) => rank.clone(),
+            None => "no rank".to_string(),
+        };
+        let node = Node {
+            tax_id: tax_id.clone(),
+            parent_tax_id,
+            rank: rank.into(),
+            scientific_name: Some(taxon_name.clone()),
+            names: Some(vec![Name {
+                tax_id: tax_id.clone(),
+                name: taxon_name,
+                class: Some("scientific name".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        nodes.insert(node);
+    }
+
+    // Add synonyms to accepted taxa
+    let mut rdr = itis_reader(csv, &itis_dump, "synonym_links")?;
+    for result in rdr.byte_records() {
+        let record = match csv.record(result, &itis_dump.join("synonym_links"), &HashMap::new())? {
+            Some(record) => record,
+            None => continue,
+        };
+        let accepted_tax_id = itis_field(&record, 1);
+        if let (Some(synonym), Some(node)) = (
+            unaccepted.get(&itis_field(&record, 0)),
+            nodes.nodes.get_mut(&accepted_tax_id),
+        ) {
+            if let Some(names) = node.names.as_mut() {
+                names.push(Name {
+                    tax_id: accepted_tax_id,
+                    name: synonym.clone(),
+                    class: Some("synonym".into()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // Add vernacular names to accepted taxa
+    let mut rdr = itis_reader(csv, &itis_dump, "vernaculars")?;
+    for result in rdr.byte_records() {
+        let record = match csv.record(result, &itis_dump.join("vernaculars"), &HashMap::new())? {
+            Some(record) => record,
+            None => continue,
+        };
+        let tax_id = itis_field(&record, 0);
+        if let Some(node) = nodes.nodes.get_mut(&tax_id) {
+            if let Some(names) = node.names.as_mut() {
+                names.push(Name {
+                    tax_id,
+                    name: itis_field(&record, 1),
+                    class: Some("common name".into()),
                     ..Default::default()
+                });
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Map column names to indices in a delimited file header, ignoring any
+/// namespace prefix such as `col:`, since column order varies between
+/// archives.
+fn header_columns(headers: &csv::StringRecord) -> HashMap<String, usize> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            let name = match header.rsplit_once(':') {
+                Some((_, name)) => name,
+                None => header,
+            };
+            (name.to_string(), i)
+        })
+        .collect()
+}
+
+/// Normalise a header for fuzzy matching.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::parse::normalize_header;
+/// assert_eq!(normalize_header("Scientific_Name "), "scientificname");
+/// assert_eq!(normalize_header("parent-ID"), "parentid");
+/// ```
+pub fn normalize_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Parse a Catalogue of Life Data Package (ColDP) `NameUsage.tsv` file,
+/// either directly or from an unpacked ColDP archive directory.
+///
+/// Accepted taxa become nodes and synonyms are added as names on their
+/// accepted taxon, which ColDP records as the synonym `parentID`.
+pub fn parse_coldp(coldp: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+
+    let name_usage = if coldp.is_dir() {
+        coldp.join("NameUsage.tsv")
+    } else {
+        coldp
+    };
+    let mut rdr = csv.reader(
+        ReaderBuilder::new()
+            .delimiter(b'\t')
+            .quoting(false)
+            .flexible(true),
+        &name_usage,
+    )?;
+
+    let columns = header_columns(rdr.headers()?);
+    let column = |key: &str| csv.header_column(&columns, key);
+    let (id_col, parent_col, status_col, rank_col, name_col) = (
+        column("ID")?,
+        column("parentID")?,
+        column("status")?,
+        column("rank")?,
+        column("scientificName")?,
+    );
+
+    // Status can be:
+    // accepted
+    // provisionally accepted
+    // synonym
+    // ambiguous synonym
+    // misapplied
+    let mut synonyms = vec![];
+    for result in rdr.records() {
+        let record = match csv.record(result, &name_usage, &columns)? {
+            Some(record) => record,
+            None => continue,
+        };
+        let tax_id = record.get(id_col).unwrap_or("").to_string();
+        let taxon_name = record.get(name_col).unwrap_or("").to_string();
+        let mut parent_tax_id = record.get(parent_col).unwrap_or("").to_string();
+        match record.get(status_col).unwrap_or("") {
+            "accepted" | "provisionally accepted" => (),
+            "synonym" | "ambiguous synonym" => {
+                synonyms.push((parent_tax_id, taxon_name));
+                continue;
+            }
+            _ => continue,
+        }
+        if parent_tax_id.is_empty() {
+            parent_tax_id = tax_id.clone()
+        }
+        let rank = match record.get(rank_col) {
+            Some(rank) if !rank.is_empty() => rank.to_case(Case::Lower),
+            _ => "no rank".to_string(),
+        };
+        let node = Node {
+            tax_id: tax_id.clone(),
+            parent_tax_id,
+            rank: rank.into(),
+            scientific_name: Some(taxon_name.clone()),
+            names: Some(vec![Name {
+                tax_id: tax_id.clone(),
+                name: taxon_name,
+                class: Some("scientific name".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        nodes.insert(node);
+    }
+
+    // Add synonyms to accepted taxa
+    for (tax_id, synonym) in synonyms {
+        if let Some(node) = nodes.nodes.get_mut(&tax_id) {
+            if let Some(names) = node.names.as_mut() {
+                names.push(Name {
+                    tax_id,
+                    name: synonym,
+                    class: Some("synonym".into()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+fn gtdb_rank(prefix: &str) -> Option<&str> {
+    match prefix {
+        "d" => Some("superkingdom"),
+        "p" => Some("phylum"),
+        "c" => Some("class"),
+        "o" => Some("order"),
+        "f" => Some("family"),
+        "g" => Some("genus"),
+        "s" => Some("species"),
+        _ => None,
+    }
+}
+
+/// Overrides for reading delimited taxonomy files that do not follow the
+/// strict format.
+#[derive(Clone, Debug, Default)]
+pub struct CsvSettings {
+    /// Field delimiter
+    pub delimiter: Option<u8>,
+    /// Quote character, or `Some(None)` to disable quoting
+    pub quote: Option<Option<u8>>,
+    /// Skip lines starting with this character
+    pub comment: Option<u8>,
+    /// Allow rows with differing numbers of fields
+    pub flexible: Option<bool>,
+    /// Write rows that fail to parse to this file instead of stopping
+    pub error_report: Option<PathBuf>,
+    /// Match headers ignoring case, whitespace, underscores and hyphens
+    pub fuzzy_headers: bool,
+    /// Alternative header names to try for each expected header
+    pub header_synonyms: HashMap<String, Vec<String>>,
+    /// Values to treat as missing in every column
+    pub null_values: Option<Vec<String>>,
+    /// Values to treat as missing in named columns, replacing `null_values`
+    pub null_values_by_field: HashMap<String, Vec<String>>,
+    /// Values to fill in for missing values in named columns, with
+    /// `{column}` replaced by the value of another column
+    pub field_defaults: HashMap<String, String>,
+    /// Normalisation to apply to values in named columns
+    pub field_normalization: HashMap<String, FieldNormalization>,
+    /// Controlled vocabularies for named columns from `read_vocabulary`
+    pub vocabularies: HashMap<String, HashMap<String, String>>,
+}
+
+/// Normalisation applied to values in a named column of a delimited
+/// taxonomy file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FieldNormalization {
+    /// Convert values to lowercase
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Remove leading and trailing whitespace
+    #[serde(default)]
+    pub trim: bool,
+    /// Replace runs of whitespace with a single space, also trimming values
+    #[serde(default)]
+    pub collapse_whitespace: bool,
+    /// Path to a TSV file mapping values to controlled vocabulary terms, with
+    /// a `value<TAB>term` or `term` line for each allowed value
+    pub vocabulary: Option<PathBuf>,
+}
+
+impl FieldNormalization {
+    /// Normalise a value, then map it to a controlled vocabulary term if it
+    /// is in `vocabulary`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use crate::blobtk::taxonomy::parse::FieldNormalization;
+    /// let normalization = FieldNormalization {
+    ///     lowercase: true,
+    ///     collapse_whitespace: true,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(normalization.normalize(" Sub  Species", None), "sub species");
+    /// let vocabulary = HashMap::from([("sub species".to_string(), "subspecies".to_string())]);
+    /// assert_eq!(
+    ///     normalization.normalize(" Sub  Species", Some(&vocabulary)),
+    ///     "subspecies"
+    /// );
+    /// ```
+    pub fn normalize(&self, value: &str, vocabulary: Option<&HashMap<String, String>>) -> String {
+        let mut value = match (self.collapse_whitespace, self.trim) {
+            (true, _) => value.split_whitespace().collect::<Vec<&str>>().join(" "),
+            (false, true) => value.trim().to_string(),
+            (false, false) => value.to_string(),
+        };
+        if self.lowercase {
+            value = value.to_lowercase();
+        }
+        match vocabulary.and_then(|vocabulary| vocabulary.get(&value)) {
+            Some(term) => term.clone(),
+            None => value,
+        }
+    }
+}
+
+/// Read a controlled vocabulary file for a field, with values normalised
+/// to match normalised field values.
+pub fn read_vocabulary(
+    path: &Path,
+    normalization: &FieldNormalization,
+) -> std::io::Result<HashMap<String, String>> {
+    let mut vocabulary = HashMap::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let (value, term) = line.split_once('\t').unwrap_or((line, line));
+        vocabulary.insert(
+            normalization.normalize(value, None),
+            term.trim().to_string(),
+        );
+    }
+    Ok(vocabulary)
+}
+
+/// Settings and state shared by the readers of a taxonomy's delimited
+/// files. Pass the same context to each parser so rows that fail to parse
+/// go to one error report and filled values are counted together.
+#[derive(Default)]
+pub struct CsvContext {
+    settings: CsvSettings,
+    /// Values to treat as missing in columns without per-field null values
+    null_values: Vec<String>,
+    /// Writer for rows that fail to parse, if `error_report` is set
+    errors: Option<Box<dyn Write>>,
+    /// Number of missing values filled from `field_defaults` for each field
+    imputed: BTreeMap<String, usize>,
+}
+
+impl CsvContext {
+    pub fn new(settings: CsvSettings) -> CsvContext {
+        let mut context = CsvContext::default();
+        context.set_settings(settings);
+        context
+    }
+
+    /// Use new settings, keeping the error report open if its path is
+    /// unchanged so it collects rows from several taxonomies.
+    pub fn set_settings(&mut self, settings: CsvSettings) {
+        if settings.error_report != self.settings.error_report {
+            self.errors = settings
+                .error_report
+                .as_ref()
+                .map(|path| io::get_writer(&Some(path.clone())));
+        }
+        self.null_values = settings.null_values.clone().unwrap_or_default();
+        self.settings = settings;
+    }
+
+    /// Get and reset the number of missing values filled from
+    /// `field_defaults` for each field.
+    pub fn take_imputed(&mut self) -> BTreeMap<String, usize> {
+        std::mem::take(&mut self.imputed)
+    }
+
+    /// Number of missing values filled from `field_defaults` for each field.
+    pub fn imputed(&self) -> &BTreeMap<String, usize> {
+        &self.imputed
+    }
+
+    /// Add counts of filled values, e.g. for a taxonomy loaded from a cache.
+    pub fn add_imputed(&mut self, imputed: &BTreeMap<String, usize>) {
+        for (field, count) in imputed.iter() {
+            *self.imputed.entry(field.clone()).or_default() += count;
+        }
+    }
+
+    /// Open a delimited file with format defaults from `builder`, applying
+    /// any overrides from the settings and skipping a UTF-8 byte order mark.
+    fn reader(
+        &self,
+        builder: &mut ReaderBuilder,
+        path: impl AsRef<Path>,
+    ) -> Result<csv::Reader<File>, anyhow::Error> {
+        if let Some(delimiter) = self.settings.delimiter {
+            builder.delimiter(delimiter);
+        }
+        match self.settings.quote {
+            Some(Some(quote)) => builder.quoting(true).quote(quote),
+            Some(None) => builder.quoting(false),
+            None => builder,
+        };
+        builder.comment(self.settings.comment);
+        if let Some(flexible) = self.settings.flexible {
+            builder.flexible(flexible);
+        }
+        let mut file = File::open(path.as_ref())?;
+        let mut bom = [0; 3];
+        if file.read(&mut bom)? < 3 || bom != [0xEF, 0xBB, 0xBF] {
+            file.seek(SeekFrom::Start(0))?;
+        }
+        Ok(builder.from_reader(file))
+    }
+
+    /// Find the column for a header, trying configured synonyms and fuzzy
+    /// matching if there is no exact match.
+    fn header_column(
+        &self,
+        columns: &HashMap<String, usize>,
+        key: &str,
+    ) -> Result<usize, anyhow::Error> {
+        if let Some(i) = columns.get(key) {
+            return Ok(*i);
+        }
+        let mut candidates = vec![key];
+        if let Some(synonyms) = self.settings.header_synonyms.get(key) {
+            candidates.extend(synonyms.iter().map(String::as_str));
+        }
+        for candidate in candidates {
+            let found = match columns.get(candidate) {
+                Some(i) => Some((candidate.to_string(), *i)),
+                None if self.settings.fuzzy_headers => {
+                    let normalized = normalize_header(candidate);
+                    columns
+                        .iter()
+                        .filter(|(header, _)| normalize_header(header) == normalized)
+                        .min_by_key(|(_, i)| **i)
+                        .map(|(header, i)| (header.clone(), *i))
+                }
+                None => None,
+            };
+            if let Some((header, i)) = found {
+                eprintln!("Using column {} ({}) for {}", i + 1, header, key);
+                return Ok(i);
+            }
+        }
+        Err(anyhow::anyhow!("column {} not found", key))
+    }
+
+    /// Replacement values for fields in a record, with null values blanked,
+    /// missing values filled from `field_defaults` and values normalised
+    /// using `field_normalization`.
+    fn field_replacements(
+        &mut self,
+        fields: &[&[u8]],
+        columns: &HashMap<String, usize>,
+    ) -> HashMap<usize, String> {
+        let settings = &self.settings;
+        let is_null = |i: usize, null_values: &[String]| {
+            fields
+                .get(i)
+                .and_then(|value| std::str::from_utf8(value).ok())
+                .is_some_and(|value| !value.is_empty() && is_null_value(value, null_values))
+        };
+        let mut replacements = HashMap::new();
+        for (field, null_values) in settings.null_values_by_field.iter() {
+            if let Some(i) = columns.get(field).filter(|i| is_null(**i, null_values)) {
+                replacements.insert(*i, String::new());
+            }
+        }
+        if !self.null_values.is_empty() {
+            for i in 0..fields.len() {
+                let has_field_nulls = settings
+                    .null_values_by_field
+                    .keys()
+                    .any(|field| columns.get(field) == Some(&i));
+                if !has_field_nulls && is_null(i, &self.null_values) {
+                    replacements.insert(i, String::new());
+                }
+            }
+        }
+        let value = |i: usize| match replacements.get(&i) {
+            Some(value) => value.clone(),
+            None => String::from_utf8_lossy(fields.get(i).copied().unwrap_or_default()).to_string(),
+        };
+        let mut filled = vec![];
+        for (field, template) in settings.field_defaults.iter() {
+            let i = match columns.get(field) {
+                Some(i) if *i < fields.len() && value(*i).is_empty() => *i,
+                _ => continue,
+            };
+            let default = expand_default(template, |column| {
+                columns.get(column).map_or(String::new(), |j| value(*j))
+            });
+            if !default.is_empty() {
+                filled.push((field, i, default));
+            }
+        }
+        for (field, i, default) in filled {
+            replacements.insert(i, default);
+            *self.imputed.entry(field.clone()).or_default() += 1;
+        }
+        for (field, normalization) in settings.field_normalization.iter() {
+            let (i, current) = match columns.get(field) {
+                Some(i) if replacements.contains_key(i) => (*i, replacements[i].clone()),
+                Some(i) if *i < fields.len() => match std::str::from_utf8(fields[*i]) {
+                    Ok(value) => (*i, value.to_string()),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            if current.is_empty() {
+                continue;
+            }
+            let normalized = normalization.normalize(&current, settings.vocabularies.get(field));
+            if normalized != current {
+                replacements.insert(i, normalized);
+            }
+        }
+        replacements
+    }
+
+    /// Get a record with missing values blanked or filled and values
+    /// normalised, routing parse errors to the error report if one is set.
+    ///
+    /// `columns` maps header names to indices for per-field settings, and
+    /// is empty for files without a header.
+    fn record<T: CleanFields>(
+        &mut self,
+        result: Result<T, csv::Error>,
+        path: &Path,
+        columns: &HashMap<String, usize>,
+    ) -> Result<Option<T>, anyhow::Error> {
+        let err = match result {
+            Ok(record) => return Ok(Some(record.clean_fields(self, columns))),
+            Err(err) => err,
+        };
+        match self.errors.as_mut() {
+            Some(writer) => {
+                let line = err.position().map_or(0, |position| position.line());
+                writeln!(writer, "{}\t{}\t{}", path.display(), line, err)?;
+                Ok(None)
+            }
+            None => Err(anyhow::anyhow!("{}: {}", path.display(), err)),
+        }
+    }
+}
+
+/// Check whether a value is in a list of null values, ignoring case and
+/// surrounding whitespace.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::parse::is_null_value;
+/// let nulls = vec!["NA".to_string(), "N/A".to_string()];
+/// assert!(is_null_value(" n/a", &nulls));
+/// assert!(!is_null_value("Nasonia", &nulls));
+/// ```
+pub fn is_null_value(value: &str, null_values: &[String]) -> bool {
+    let value = value.trim();
+    null_values
+        .iter()
+        .any(|null| null.trim().eq_ignore_ascii_case(value))
+}
+
+/// Expand a default value template, replacing `{column}` with the value
+/// of that column.
+///
+/// # Examples
+///
+/// ```
+/// # use crate::blobtk::taxonomy::parse::expand_default;
+/// let value = expand_default("{genus} sp.", |column| match column {
+///     "genus" => "Canis".to_string(),
+///     _ => "".to_string(),
+/// });
+/// assert_eq!(value, "Canis sp.");
+/// assert_eq!(expand_default("no rank", |_| "".to_string()), "no rank");
+/// ```
+pub fn expand_default(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        match rest[start..].find('}') {
+            Some(end) => {
+                expanded.push_str(&rest[..start]);
+                expanded.push_str(&value(&rest[start + 1..start + end]));
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Records read from delimited files with missing values replaced by empty
+/// strings or configured defaults and values normalised.
+trait CleanFields: Sized {
+    fn clean_fields(self, csv: &mut CsvContext, columns: &HashMap<String, usize>) -> Self;
+}
+
+impl CleanFields for csv::StringRecord {
+    fn clean_fields(self, csv: &mut CsvContext, columns: &HashMap<String, usize>) -> Self {
+        let fields: Vec<&[u8]> = self.iter().map(str::as_bytes).collect();
+        let replacements = csv.field_replacements(&fields, columns);
+        if replacements.is_empty() {
+            return self;
+        }
+        self.iter()
+            .enumerate()
+            .map(|(i, value)| replacements.get(&i).map_or(value, String::as_str))
+            .collect()
+    }
+}
+
+impl CleanFields for csv::ByteRecord {
+    fn clean_fields(self, csv: &mut CsvContext, columns: &HashMap<String, usize>) -> Self {
+        let fields: Vec<&[u8]> = self.iter().collect();
+        let replacements = csv.field_replacements(&fields, columns);
+        if replacements.is_empty() {
+            return self;
+        }
+        self.iter()
+            .enumerate()
+            .map(|(i, value)| replacements.get(&i).map_or(value, String::as_bytes))
+            .collect()
+    }
+}
+
+/// List `*.tsv` files in a directory, or a single file.
+fn tsv_files(path: PathBuf) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+    if path.is_dir() {
+        for entry in std::fs::read_dir(&path)? {
+            let file = entry?.path();
+            if file.extension().is_some_and(|ext| ext == "tsv") {
+                files.push(file);
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// Parse GTDB bac120/ar53 taxonomy files from a file or a directory of
+/// `*.tsv` files.
+///
+/// Each line has a genome accession and a Greengenes-style lineage
+/// (`d__Bacteria;p__...;s__...`). Intermediate nodes are synthesized from
+/// the lineage using the prefixed name as the taxon ID, domains are attached
+/// to a root node with ID `1` and genome accessions are added as names on
+/// the species node.
+pub fn parse_gtdb(gtdb: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+    nodes.insert_root();
+    let root_id = "1".to_string();
+
+    for file in tsv_files(gtdb)? {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .has_headers(false)
+                .delimiter(b'\t')
+                .flexible(true),
+            &file,
+        )?;
+        for result in rdr.records() {
+            let record = match csv.record(result, &file, &HashMap::new())? {
+                Some(record) => record,
+                None => continue,
+            };
+            let accession = match record.get(0) {
+                Some(accession) if !accession.is_empty() => accession.to_string(),
+                _ => continue,
+            };
+            let lineage = record.get(1).unwrap_or("");
+            let mut parent_tax_id = root_id.clone();
+            let mut rank = "";
+            for taxon in lineage.split(';') {
+                let (prefix, taxon_name) = match taxon.trim().split_once("__") {
+                    Some((prefix, name)) if !name.is_empty() => (prefix, name),
+                    _ => continue,
+                };
+                rank = match gtdb_rank(prefix) {
+                    Some(rank) => rank,
+                    None => continue,
+                };
+                let tax_id = taxon.trim().to_string();
+                if !nodes.nodes.contains_key(&tax_id) {
+                    nodes.insert(Node {
+                        tax_id: tax_id.clone(),
+                        parent_tax_id: parent_tax_id.clone(),
+                        rank: rank.into(),
+                        scientific_name: Some(taxon_name.to_string()),
+                        names: Some(vec![Name {
+                            tax_id: tax_id.clone(),
+                            name: taxon_name.to_string(),
+                            class: Some("scientific name".into()),
+                            ..Default::default()
+                        }]),
+                        ..Default::default()
+                    });
+                }
+                parent_tax_id = tax_id;
+            }
+            if rank != "species" {
+                continue;
+            }
+            if let Some(names) = nodes
+                .nodes
+                .get_mut(&parent_tax_id)
+                .and_then(|node| node.names.as_mut())
+            {
+                names.push(Name {
+                    tax_id: parent_tax_id.clone(),
+                    name: accession,
+                    class: Some("genome accession".into()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Parse a SILVA taxonomy export from a `tax_slv` file or a directory
+/// containing `tax_slv*.txt` and optional `taxmap*.txt` files.
+///
+/// Each `tax_slv` line has a semicolon-terminated path, taxon ID and rank.
+/// Parents are found from the path, domains are attached to a root node
+/// with ID `1` and sequence accessions from `taxmap` files are added as
+/// names on the taxon at the end of their path.
+pub fn parse_silva(silva: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+    nodes.insert_root();
+    let root_id = "1".to_string();
+
+    let mut tax_files = vec![];
+    let mut map_files = vec![];
+    if silva.is_dir() {
+        for entry in std::fs::read_dir(&silva)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+            if file_name.starts_with("tax_slv") {
+                tax_files.push(path);
+            } else if file_name.starts_with("taxmap") {
+                map_files.push(path);
+            }
+        }
+        tax_files.sort();
+        map_files.sort();
+    } else {
+        tax_files.push(silva);
+    }
+
+    // Map taxonomy paths to taxon IDs
+    let mut path_ids = HashMap::new();
+    let mut taxa = vec![];
+    for file in tax_files {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .has_headers(false)
+                .delimiter(b'\t')
+                .quoting(false)
+                .flexible(true),
+            &file,
+        )?;
+        for result in rdr.records() {
+            let record = match csv.record(result, &file, &HashMap::new())? {
+                Some(record) => record,
+                None => continue,
+            };
+            let path = record.get(0).unwrap_or("").to_string();
+            let tax_id = record.get(1).unwrap_or("").to_string();
+            if path.is_empty() || tax_id.is_empty() {
+                continue;
+            }
+            let rank = match record.get(2) {
+                Some(rank) if !rank.is_empty() => rank.to_string(),
+                _ => "no rank".to_string(),
+            };
+            path_ids.insert(path.clone(), tax_id.clone());
+            taxa.push((path, tax_id, rank));
+        }
+    }
+
+    for (path, tax_id, rank) in taxa {
+        let mut lineage: Vec<&str> = path.trim_end_matches(';').split(';').collect();
+        let taxon_name = lineage.pop().unwrap_or("").to_string();
+        let parent_tax_id = if lineage.is_empty() {
+            root_id.clone()
+        } else {
+            match path_ids.get(&format!("{};", lineage.join(";"))) {
+                Some(parent_tax_id) => parent_tax_id.clone(),
+                None => root_id.clone(),
+            }
+        };
+        let node = Node {
+            tax_id: tax_id.clone(),
+            parent_tax_id,
+            rank: rank.into(),
+            scientific_name: Some(taxon_name.clone()),
+            names: Some(vec![Name {
+                tax_id: tax_id.clone(),
+                name: taxon_name,
+                class: Some("scientific name".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        nodes.insert(node);
+    }
+
+    // Add sequence accessions to taxa
+    for file in map_files {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .has_headers(true)
+                .delimiter(b'\t')
+                .quoting(false)
+                .flexible(true),
+            &file,
+        )?;
+        for result in rdr.records() {
+            let record = match csv.record(result, &file, &HashMap::new())? {
+                Some(record) => record,
+                None => continue,
+            };
+            let accession = format!(
+                "{}.{}.{}",
+                record.get(0).unwrap_or(""),
+                record.get(1).unwrap_or(""),
+                record.get(2).unwrap_or("")
+            );
+            let tax_id = match path_ids.get(record.get(3).unwrap_or("")) {
+                Some(tax_id) => tax_id.clone(),
+                None => continue,
+            };
+            if let Some(names) = nodes
+                .nodes
+                .get_mut(&tax_id)
+                .and_then(|node| node.names.as_mut())
+            {
+                names.push(Name {
+                    tax_id,
+                    name: accession,
+                    class: Some("accession".into()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Get a taxon ID from an LSID such as
+/// `urn:lsid:marinespecies.org:taxname:137205`.
+fn lsid_id(lsid: &str) -> String {
+    match lsid.rsplit_once(':') {
+        Some((_, id)) => id.to_string(),
+        None => lsid.to_string(),
+    }
+}
+
+/// Find the taxon file in an unpacked Darwin Core Archive directory.
+fn dwca_taxon_file(dwca: PathBuf) -> PathBuf {
+    if !dwca.is_dir() {
+        return dwca;
+    }
+    for file_name in ["Taxon.txt", "taxon.txt", "Taxon.tsv", "taxon.tsv"] {
+        let taxon_file = dwca.join(file_name);
+        if taxon_file.exists() {
+            return taxon_file;
+        }
+    }
+    dwca.join("Taxon.txt")
+}
+
+/// A delimited data file in a Darwin Core Archive.
+#[derive(Clone, Debug, Default)]
+struct DwcaTable {
+    path: PathBuf,
+    delimiter: u8,
+    quote: Option<u8>,
+    header_lines: usize,
+    /// Column index for each term, without its namespace
+    columns: HashMap<String, usize>,
+    /// Constant values for terms not present as columns
+    defaults: HashMap<String, String>,
+}
+
+impl DwcaTable {
+    /// Describe a tab-delimited file using the term names in its header row.
+    fn from_header(path: PathBuf, csv: &CsvContext) -> Result<Self, anyhow::Error> {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .delimiter(b'\t')
+                .quoting(false)
+                .flexible(true),
+            &path,
+        )?;
+        let columns = header_columns(rdr.headers()?);
+        Ok(DwcaTable {
+            path,
+            delimiter: b'\t',
+            header_lines: 1,
+            columns,
+            ..Default::default()
+        })
+    }
+
+    /// Describe a `core` or `extension` element of a `meta.xml` file.
+    fn from_meta(dwca: &Path, node: roxmltree::Node, id_term: &str) -> Option<Self> {
+        let unescape = |value: &str| match value {
+            "\\t" => Some(b'\t'),
+            "" => None,
+            value => value.bytes().next(),
+        };
+        let location = node
+            .descendants()
+            .find(|child| child.has_tag_name("location"))?
+            .text()?;
+        let mut table = DwcaTable {
+            path: dwca.join(location.trim()),
+            delimiter: unescape(node.attribute("fieldsTerminatedBy").unwrap_or(","))
+                .unwrap_or(b','),
+            quote: unescape(node.attribute("fieldsEnclosedBy").unwrap_or("\"")),
+            header_lines: node
+                .attribute("ignoreHeaderLines")
+                .and_then(|lines| lines.parse().ok())
+                .unwrap_or(0),
+            ..Default::default()
+        };
+        for child in node.children().filter(|child| child.is_element()) {
+            let index = child
+                .attribute("index")
+                .and_then(|i| i.parse::<usize>().ok());
+            let term = match child.tag_name().name() {
+                "id" | "coreid" => id_term.to_string(),
+                "field" => match child.attribute("term") {
+                    Some(term) => term.rsplit(['/', ':']).next().unwrap_or(term).to_string(),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if let Some(index) = index {
+                table.columns.entry(term).or_insert(index);
+            } else if let Some(default) = child.attribute("default") {
+                table.defaults.insert(term, default.to_string());
+            }
+        }
+        Some(table)
+    }
+
+    /// Get the value of a term from a record, falling back to its default.
+    fn value<'a>(&'a self, record: &'a csv::StringRecord, term: &str) -> &'a str {
+        match self.columns.get(term) {
+            Some(i) => record.get(*i).unwrap_or(""),
+            None => self.defaults.get(term).map_or("", |value| value.as_str()),
+        }
+    }
+
+    fn has_term(&self, term: &str) -> bool {
+        self.columns.contains_key(term) || self.defaults.contains_key(term)
+    }
+
+    fn records(&self, csv: &mut CsvContext) -> Result<Vec<csv::StringRecord>, anyhow::Error> {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .delimiter(self.delimiter)
+                .quoting(self.quote.is_some())
+                .quote(self.quote.unwrap_or(b'"'))
+                .has_headers(false)
+                .flexible(true),
+            &self.path,
+        )?;
+        let mut records = vec![];
+        for result in rdr.records().skip(self.header_lines) {
+            if let Some(record) = csv.record(result, &self.path, &self.columns)? {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Read the taxon core and any vernacular name extensions of a Darwin Core
+/// Archive, using `meta.xml` to map columns to terms when present.
+fn dwca_tables(
+    dwca: PathBuf,
+    csv: &CsvContext,
+) -> Result<(DwcaTable, Vec<DwcaTable>), anyhow::Error> {
+    let meta_file = dwca.join("meta.xml");
+    if !dwca.is_dir() || !meta_file.exists() {
+        return Ok((DwcaTable::from_header(dwca_taxon_file(dwca), csv)?, vec![]));
+    }
+    let xml = std::fs::read_to_string(&meta_file)?;
+    let doc = roxmltree::Document::parse(&xml)?;
+    let core = doc
+        .descendants()
+        .find(|node| node.has_tag_name("core"))
+        .and_then(|node| DwcaTable::from_meta(&dwca, node, "taxonID"))
+        .ok_or_else(|| anyhow::anyhow!("no core file found in {}", meta_file.display()))?;
+    let vernaculars = doc
+        .descendants()
+        .filter(|node| {
+            node.has_tag_name("extension")
+                && node
+                    .attribute("rowType")
+                    .is_some_and(|row_type| row_type.ends_with("VernacularName"))
+        })
+        .filter_map(|node| DwcaTable::from_meta(&dwca, node, "taxonID"))
+        .collect();
+    Ok((core, vernaculars))
+}
+
+/// Parse Darwin Core Archive taxa, keyed by the final part of each taxon ID
+/// LSID.
+///
+/// Taxa with an `accepted` status become nodes and other names are added as
+/// synonyms of their accepted taxon, while names with an `ignore` status are
+/// skipped. Without a `taxonomicStatus` term, taxa are accepted unless they
+/// have a different `acceptedNameUsageID`.
+fn parse_dwca_taxa(
+    core: &DwcaTable,
+    vernaculars: &[DwcaTable],
+    accepted: &[&str],
+    ignore: &[&str],
+    csv: &mut CsvContext,
+) -> Result<Nodes, anyhow::Error> {
+    let mut nodes = Nodes::default();
+
+    for term in ["taxonID", "parentNameUsageID", "scientificName"] {
+        if !core.has_term(term) {
+            return Err(anyhow::anyhow!("column {} not found", term));
+        }
+    }
+
+    let mut synonyms = vec![];
+    for record in core.records(csv)? {
+        let status = core.value(&record, "taxonomicStatus").to_lowercase();
+        if ignore.contains(&status.as_str()) {
+            continue;
+        }
+        let tax_id = lsid_id(core.value(&record, "taxonID"));
+        let accepted_tax_id = lsid_id(core.value(&record, "acceptedNameUsageID"));
+        let taxon_name = core.value(&record, "scientificName").to_string();
+        let is_accepted = if status.is_empty() {
+            accepted_tax_id.is_empty() || accepted_tax_id == tax_id
+        } else {
+            accepted.contains(&status.as_str())
+        };
+        if !is_accepted {
+            if !accepted_tax_id.is_empty() && accepted_tax_id != tax_id {
+                synonyms.push((accepted_tax_id, taxon_name));
+            }
+            continue;
+        }
+        let mut parent_tax_id = lsid_id(core.value(&record, "parentNameUsageID"));
+        if parent_tax_id.is_empty() {
+            parent_tax_id = tax_id.clone()
+        }
+        let rank = match core.value(&record, "taxonRank") {
+            "" => "no rank".to_string(),
+            rank => rank.to_case(Case::Lower),
+        };
+        let node = Node {
+            tax_id: tax_id.clone(),
+            parent_tax_id,
+            rank: rank.into(),
+            scientific_name: Some(taxon_name.clone()),
+            names: Some(vec![Name {
+                tax_id: tax_id.clone(),
+                name: taxon_name,
+                class: Some("scientific name".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        nodes.insert(node);
+    }
+
+    // Add synonyms to accepted taxa
+    let mut names: Vec<(String, String, &str)> = synonyms
+        .into_iter()
+        .map(|(tax_id, name)| (tax_id, name, "synonym"))
+        .collect();
+    for table in vernaculars {
+        for record in table.records(csv)? {
+            let name = table.value(&record, "vernacularName");
+            if !name.is_empty() {
+                names.push((
+                    lsid_id(table.value(&record, "taxonID")),
+                    name.to_string(),
+                    "common name",
+                ));
+            }
+        }
+    }
+    for (tax_id, name, class) in names {
+        if let Some(node_names) = nodes
+            .nodes
+            .get_mut(&tax_id)
+            .and_then(|node| node.names.as_mut())
+        {
+            node_names.push(Name {
+                tax_id,
+                name,
+                class: Some(class.into()),
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Parse a generic Darwin Core Archive checklist from an unpacked archive
+/// directory.
+///
+/// Columns are mapped to terms using `meta.xml`, falling back to the header
+/// row of the taxon file. Vernacular name extensions are added as common
+/// names.
+pub fn parse_dwca(dwca: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let (core, vernaculars) = dwca_tables(dwca, csv)?;
+    let accepted = ["accepted", "valid", "provisionally accepted"];
+    parse_dwca_taxa(&core, &vernaculars, &accepted, &["misapplied"], csv)
+}
+
+/// Parse a WoRMS Darwin Core Archive `Taxon.txt` file, either directly or
+/// from an unpacked archive directory.
+///
+/// Taxa are keyed by AphiaID. Accepted taxa become nodes and unaccepted
+/// names are added as synonyms of their accepted taxon, while doubtful and
+/// unresolved names are ignored, as for `parse_gbif`.
+pub fn parse_worms(worms: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    // Status can be:
+    // accepted
+    // unaccepted
+    // alternate representation
+    // nomen dubium
+    // nomen nudum
+    // taxon inquirendum
+    // temporary name
+    // interim unpublished
+    // uncertain
+    // misapplied
+    let ignore = [
+        "nomen dubium",
+        "nomen nudum",
+        "taxon inquirendum",
+        "temporary name",
+        "interim unpublished",
+        "uncertain",
+        "misapplied",
+    ];
+    let (core, vernaculars) = dwca_tables(worms, csv)?;
+    parse_dwca_taxa(&core, &vernaculars, &["accepted"], &ignore, csv)
+}
+
+/// Parse an IRMNG Darwin Core Archive taxon file, either directly or from
+/// an unpacked archive directory.
+///
+/// Unlike `parse_worms`, interim and temporary names are kept as nodes so
+/// extinct and interim genera can be added to a combined taxonomy, where
+/// `lookup_nodes` labels them with the taxonomy `xref_label`.
+pub fn parse_irmng(irmng: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let accepted = ["accepted", "interim unpublished", "temporary name"];
+    let ignore = [
+        "nomen dubium",
+        "nomen nudum",
+        "taxon inquirendum",
+        "misapplied",
+    ];
+    let (core, vernaculars) = dwca_tables(irmng, csv)?;
+    parse_dwca_taxa(&core, &vernaculars, &accepted, &ignore, csv)
+}
+
+/// Parse BOLD Systems specimen TSV exports from a file or a directory of
+/// `*.tsv` files.
+///
+/// Both the `{rank}_taxID`/`{rank}_name` columns of the v3 API and the
+/// `{rank}` columns of the BOLD portal are supported, with synthesized
+/// `{rank}:{name}` taxon IDs when no BOLD taxID is given. BIN URIs are added
+/// as `bin` class names on the species of each record, or as species-level
+/// nodes named `{taxon} sp. {bin_uri}` under the lowest named taxon of
+/// records without a species name.
+pub fn parse_bold(bold: PathBuf, csv: &mut CsvContext) -> Result<Nodes, anyhow::Error> {
+    let ranks = [
+        "kingdom",
+        "phylum",
+        "class",
+        "order",
+        "family",
+        "subfamily",
+        "tribe",
+        "genus",
+        "species",
+        "subspecies",
+    ];
+    let mut taxonomy = Nodes::default();
+    taxonomy.insert_root();
+    let mut nodes = taxonomy.nodes;
+    let root_id = "1".to_string();
+
+    let add_name = |node: &mut Node, name: &str, class: &str| {
+        let names = node.names.get_or_insert_with(Vec::new);
+        if !names.iter().any(|n| n.name == name) {
+            names.push(Name {
+                tax_id: node.tax_id.clone(),
+                name: name.to_string(),
+                unique_name: format!("{}:{}", class, name),
+                class: Some(class.into()),
+            });
+        }
+    };
+
+    for file in tsv_files(bold)? {
+        let mut rdr = csv.reader(
+            ReaderBuilder::new()
+                .delimiter(b'\t')
+                .quoting(false)
+                .flexible(true),
+            &file,
+        )?;
+        let columns = header_columns(rdr.headers()?);
+        let rank_columns: Vec<(&str, Option<usize>, Option<usize>)> = ranks
+            .iter()
+            .map(|rank| {
+                let name_col = columns
+                    .get(&format!("{}_name", rank))
+                    .or(columns.get(*rank))
+                    .copied();
+                (
+                    *rank,
+                    name_col,
+                    columns.get(&format!("{}_taxID", rank)).copied(),
+                )
+            })
+            .filter(|(_, name_col, _)| name_col.is_some())
+            .collect();
+        let bin_col = columns.get("bin_uri").copied();
+
+        for result in rdr.records() {
+            let record = match csv.record(result, &file, &columns)? {
+                Some(record) => record,
+                None => continue,
+            };
+            let mut parent_tax_id = root_id.clone();
+            let mut lowest: Option<(String, String, &str)> = None;
+            for (rank, name_col, id_col) in rank_columns.iter() {
+                let taxon_name = match name_col.and_then(|i| record.get(i)) {
+                    Some(name) if !name.trim().is_empty() && name != "None" => name.trim(),
+                    _ => continue,
                 };
-                let parent = node.parent_tax_id.clone();
-                let child = node.tax_id.clone();
-                if parent != child {
-                    match children.entry(parent) {
-                        Entry::Vacant(e) => {
-                            e.insert(vec![child]);
-                        }
-                        Entry::Occupied(mut e) => {
-                            e.get_mut().push(child);
-                        }
-                    }
+                let tax_id = match id_col.and_then(|i| record.get(i)) {
+                    Some(id) if !id.trim().is_empty() => id.trim().to_string(),
+                    _ => format!("{}:{}", rank, taxon_name),
+                };
+                nodes.entry(tax_id.clone()).or_insert_with(|| Node {
+                    tax_id: tax_id.clone(),
+                    parent_tax_id: parent_tax_id.clone(),
+                    rank: (*rank).into(),
+                    scientific_name: Some(taxon_name.to_string()),
+                    names: Some(vec![Name {
+                        tax_id: tax_id.clone(),
+                        name: taxon_name.to_string(),
+                        class: Some("scientific name".into()),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                });
+                parent_tax_id = tax_id.clone();
+                lowest = Some((tax_id, taxon_name.to_string(), rank));
+            }
+            let bin_uri = match bin_col.and_then(|i| record.get(i)) {
+                Some(bin_uri) if !bin_uri.trim().is_empty() && bin_uri != "None" => bin_uri.trim(),
+                _ => continue,
+            };
+            match lowest {
+                Some((tax_id, _, rank)) if rank == "species" || rank == "subspecies" => {
+                    add_name(nodes.get_mut(&tax_id).unwrap(), bin_uri, "bin");
+                }
+                Some((tax_id, taxon_name, _)) => {
+                    let bin_name = format!("{} sp. {}", taxon_name, bin_uri);
+                    let node = nodes.entry(bin_uri.to_string()).or_insert_with(|| Node {
+                        tax_id: bin_uri.to_string(),
+                        parent_tax_id: tax_id,
+                        rank: "species".into(),
+                        scientific_name: Some(bin_name.clone()),
+                        names: Some(vec![Name {
+                            tax_id: bin_uri.to_string(),
+                            name: bin_name,
+                            class: Some("scientific name".into()),
+                            ..Default::default()
+                        }]),
+                        ..Default::default()
+                    });
+                    add_name(node, bin_uri, "bin");
                 }
+                None => (),
+            }
+        }
+    }
+
+    Ok(Nodes::from_nodes(nodes))
+}
+
+/// Get a tax_id from an NCBI Datasets JSON value, which may be a number or
+/// a string.
+fn datasets_tax_id(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Find the taxonomy report in an NCBI Datasets download directory.
+fn datasets_report_file(datasets: PathBuf) -> PathBuf {
+    if !datasets.is_dir() {
+        return datasets;
+    }
+    for file_name in [
+        "ncbi_dataset/data/taxonomy_report.jsonl",
+        "taxonomy_report.jsonl",
+        "taxonomy_report.json",
+    ] {
+        let report_file = datasets.join(file_name);
+        if report_file.exists() {
+            return report_file;
+        }
+    }
+    datasets.join("taxonomy_report.jsonl")
+}
 
-                e.insert(node);
+/// Parse an NCBI Datasets taxonomy report, either as JSON Lines or as a
+/// single JSON document with a `reports` array.
+///
+/// Each report becomes a node with its parent taken from the `parents`
+/// list. Ancestors without a report of their own are added using names and
+/// ranks from the report `classification` where available, so a lookup can
+/// be built from a report for a handful of taxa.
+pub fn parse_datasets(datasets: PathBuf) -> Result<Nodes, anyhow::Error> {
+    let report_file = datasets_report_file(datasets);
+    let content = std::fs::read_to_string(&report_file)?;
+    let mut reports = vec![];
+    match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(doc) if doc.get("reports").is_some() => {
+            reports.extend(doc["reports"].as_array().cloned().unwrap_or_default())
+        }
+        Ok(doc) => reports.push(doc),
+        Err(_) => {
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                reports.push(serde_json::from_str::<serde_json::Value>(line)?);
             }
-            Entry::Occupied(mut e) => {
-                if name_class == "scientific name" {
-                    e.get_mut().scientific_name = Some(taxon_name);
+        }
+    }
+
+    let mut nodes: HashMap<String, Node> = HashMap::new();
+    for report in reports {
+        let taxonomy = report.get("taxonomy").unwrap_or(&report);
+        let tax_id = match taxonomy.get("tax_id").and_then(datasets_tax_id) {
+            Some(tax_id) => tax_id,
+            None => continue,
+        };
+        let parents: Vec<String> = taxonomy["parents"]
+            .as_array()
+            .map(|parents| parents.iter().filter_map(datasets_tax_id).collect())
+            .unwrap_or_default();
+
+        // Add placeholder ancestors, named from the classification if possible
+        let mut classified = HashMap::new();
+        if let Some(classification) = taxonomy["classification"].as_object() {
+            for (rank, value) in classification {
+                if let Some(id) = value.get("id").and_then(datasets_tax_id) {
+                    let name = value["name"].as_str().unwrap_or("").to_string();
+                    classified.insert(id, (rank.to_case(Case::Lower), name));
                 }
-                if let Some(names) = e.get_mut().names.as_mut() {
-                    names.push(name);
+            }
+        }
+        for (i, parent) in parents.iter().enumerate() {
+            if nodes.contains_key(parent) {
+                continue;
+            }
+            let (rank, name) = classified
+                .get(parent)
+                .cloned()
+                .unwrap_or(("no rank".to_string(), String::new()));
+            let mut node = Node {
+                tax_id: parent.clone(),
+                parent_tax_id: if i > 0 {
+                    parents[i - 1].clone()
+                } else {
+                    parent.clone()
+                },
+                rank: rank.into(),
+                ..Default::default()
+            };
+            if !name.is_empty() {
+                node.scientific_name = Some(name.clone());
+                node.names = Some(vec![Name {
+                    tax_id: parent.clone(),
+                    name,
+                    class: Some("scientific name".into()),
+                    ..Default::default()
+                }]);
+            }
+            nodes.insert(parent.clone(), node);
+        }
+
+        let scientific_name = taxonomy["current_scientific_name"]["name"]
+            .as_str()
+            .or(taxonomy["organism_name"].as_str())
+            .unwrap_or("")
+            .to_string();
+        let mut names = vec![Name {
+            tax_id: tax_id.clone(),
+            name: scientific_name.clone(),
+            class: Some("scientific name".into()),
+            ..Default::default()
+        }];
+        for (key, class) in [
+            ("genbank_common_name", "genbank common name"),
+            ("curator_common_name", "common name"),
+            ("common_name", "common name"),
+        ] {
+            if let Some(name) = taxonomy[key].as_str() {
+                names.push(Name {
+                    tax_id: tax_id.clone(),
+                    name: name.to_string(),
+                    class: Some(class.into()),
+                    ..Default::default()
+                });
+            }
+        }
+        let rank = match taxonomy["rank"].as_str() {
+            Some(rank) if !rank.is_empty() => rank.replace('_', " ").to_case(Case::Lower),
+            _ => "no rank".to_string(),
+        };
+        nodes.insert(
+            tax_id.clone(),
+            Node {
+                tax_id: tax_id.clone(),
+                parent_tax_id: parents.last().unwrap_or(&tax_id).clone(),
+                rank: rank.into(),
+                scientific_name: Some(scientific_name),
+                names: Some(names),
+                lineage: Some(parents),
+                ..Default::default()
+            },
+        );
+    }
+    if nodes.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no taxonomy reports found in {}",
+            report_file.display()
+        ));
+    }
+
+    Ok(Nodes::from_nodes(nodes))
+}
+
+/// Split an ENA lineage string (`Eukaryota; Metazoa; ...; Homo; `) into
+/// ancestor names.
+pub fn ena_lineage(lineage: &str) -> Vec<String> {
+    lineage
+        .split(';')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// A taxon identified by the path of names from the root to itself.
+struct PathTaxon {
+    tax_id: String,
+    rank: String,
+    path: Vec<String>,
+    names: Vec<Name>,
+}
+
+/// Get the rank and scientific name of a taxon from a name in a path.
+type PathRank = dyn Fn(&str) -> (String, String);
+
+/// Build `Nodes` from taxa identified by their path of names.
+///
+/// Each parent is found by matching the path without its last name to the
+/// path of another taxon. Missing ancestors are created using `intermediate`,
+/// which returns the rank and scientific name for a name in a path, or the
+/// taxon is attached to a root node with ID `1` if `intermediate` is `None`.
+/// Also returns the number of taxa attached to root.
+fn path_taxa_to_nodes(taxa: Vec<PathTaxon>, intermediate: Option<&PathRank>) -> (Nodes, usize) {
+    let scientific_name = |tax_id: &str, name: &str| Name {
+        tax_id: tax_id.to_string(),
+        name: name.to_string(),
+        class: Some("scientific name".into()),
+        ..Default::default()
+    };
+    let mut nodes = Nodes::default();
+    nodes.insert_root();
+    let mut paths: HashMap<String, String> = taxa
+        .iter()
+        .map(|taxon| (taxon.path.join("; "), taxon.tax_id.clone()))
+        .collect();
+    let taken: HashSet<String> = paths.values().cloned().collect();
+
+    let mut unplaced = 0;
+    for taxon in taxa {
+        if taxon.tax_id == "1" || taxon.path.is_empty() {
+            continue;
+        }
+        let lineage = &taxon.path[..taxon.path.len() - 1];
+        let parent_tax_id = match (paths.get(&lineage.join("; ")), intermediate) {
+            _ if lineage.is_empty() => "1".to_string(),
+            (Some(parent_tax_id), _) => parent_tax_id.clone(),
+            (None, Some(intermediate)) => {
+                let mut parent_tax_id = "1".to_string();
+                for i in 0..lineage.len() {
+                    let key = lineage[..=i].join("; ");
+                    if let Some(tax_id) = paths.get(&key) {
+                        parent_tax_id = tax_id.clone();
+                        continue;
+                    }
+                    // Use the name as an ID unless it is shared by another taxon
+                    let tax_id =
+                        if taken.contains(&lineage[i]) || nodes.nodes.contains_key(&lineage[i]) {
+                            key.clone()
+                        } else {
+                            lineage[i].clone()
+                        };
+                    let (rank, name) = intermediate(&lineage[i]);
+                    nodes.insert(Node {
+                        tax_id: tax_id.clone(),
+                        parent_tax_id,
+                        rank: rank.into(),
+                        scientific_name: Some(name.clone()),
+                        names: Some(vec![scientific_name(&tax_id, &name)]),
+                        ..Default::default()
+                    });
+                    paths.insert(key, tax_id.clone());
+                    parent_tax_id = tax_id;
                 }
+                parent_tax_id
+            }
+            (None, None) => {
+                unplaced += 1;
+                "1".to_string()
             }
+        };
+        nodes.insert(Node {
+            scientific_name: taxon.names.first().map(|name| name.name.clone()),
+            tax_id: taxon.tax_id,
+            parent_tax_id,
+            rank: taxon.rank.into(),
+            names: Some(taxon.names),
+            ..Default::default()
+        });
+    }
+    for child_ids in nodes.children.values_mut() {
+        child_ids.sort();
+    }
+    (nodes, unplaced)
+}
+
+/// Convert ENA taxonomy REST API records into `Nodes`.
+///
+/// ENA records give ancestors as a lineage of names, so each parent is
+/// found by matching the lineage to the lineage and name of another record.
+/// Records with an empty lineage, or whose parent is not among the records,
+/// are attached to a root node with ID `1`.
+pub fn ena_records_to_nodes(records: &[serde_json::Value]) -> Nodes {
+    let mut taxa = vec![];
+    for record in records {
+        let tax_id = match record["taxId"].as_str() {
+            Some(tax_id) => tax_id.to_string(),
+            None => match record["taxId"].as_u64() {
+                Some(tax_id) => tax_id.to_string(),
+                None => continue,
+            },
+        };
+        let name = record["scientificName"].as_str().unwrap_or("").to_string();
+        let mut path = ena_lineage(record["lineage"].as_str().unwrap_or(""));
+        path.push(name.clone());
+        let mut names = vec![Name {
+            tax_id: tax_id.clone(),
+            name,
+            class: Some("scientific name".into()),
+            ..Default::default()
+        }];
+        if let Some(common_name) = record["commonName"].as_str() {
+            names.push(Name {
+                tax_id: tax_id.clone(),
+                name: common_name.to_string(),
+                class: Some("common name".into()),
+                ..Default::default()
+            });
         }
+        taxa.push(PathTaxon {
+            tax_id,
+            rank: record["rank"].as_str().unwrap_or("no rank").to_string(),
+            path,
+            names,
+        });
+    }
+    let (nodes, unplaced) = path_taxa_to_nodes(taxa, None);
+    if unplaced > 0 {
+        eprintln!(
+            "{} ENA taxa attached to root as their parent was not found",
+            unplaced
+        );
+    }
+    nodes
+}
 
-        // println!("{:?}", record.get(0));
-        // let node = Node {
-        //     tax_id,
-        //     parent_tax_id: record.get(1).unwrap().to_string(),
-        //     rank: record.get(5).unwrap().to_case(Case::Lower),
-        //     scientific_name: Some(record.get(19).unwrap().to_string()),
-        //     ..Default::default()
-        // };
+/// Parse a JSON Lines file of ENA taxonomy REST API records, as written by
+/// `blobtk taxonomy --ena-query`.
+pub fn parse_ena_jsonl(ena: PathBuf) -> Result<Nodes, anyhow::Error> {
+    let mut records = vec![];
+    for line in io::read_lines(ena)? {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(ena_records_to_nodes(&records))
+}
+
+/// Get the rank and name of a lineage string element, using a rank prefix
+/// (`d__`, `k__`, `p__`, ...) if present.
+fn lineage_rank(element: &str) -> (String, String) {
+    if let Some((prefix, name)) = element.split_once("__") {
+        let rank = match prefix {
+            "k" => Some("kingdom"),
+            prefix => gtdb_rank(prefix),
+        };
+        if let Some(rank) = rank {
+            return (rank.to_string(), name.to_string());
+        }
+    }
+    ("no rank".to_string(), element.to_string())
+}
+
+/// Parse a two column TSV file of taxon IDs and lineage strings
+/// (`562<tab>d__Bacteria;...;g__Escherichia;s__Escherichia coli`) into
+/// `Nodes`.
+///
+/// The last name in each lineage is the taxon itself. Intermediate taxa
+/// without their own line are created using their name as the ID, with rank
+/// prefixes used to set ranks where present.
+pub fn parse_lineage_tsv(path: PathBuf) -> Result<Nodes, anyhow::Error> {
+    let mut taxa = vec![];
+    for line in io::read_lines(&path)? {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let (tax_id, lineage) = match line.split_once('\t') {
+            Some((tax_id, lineage)) => (tax_id.trim(), lineage.split('\t').next().unwrap()),
+            None => return Err(anyhow::anyhow!("invalid lineage line: {}", line)),
+        };
+        let lineage: Vec<String> = lineage
+            .split([';', '|'])
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let (rank, name) = match lineage.last() {
+            Some(element) => lineage_rank(element),
+            None => continue,
+        };
+        taxa.push(PathTaxon {
+            tax_id: tax_id.to_string(),
+            rank,
+            path: lineage,
+            names: vec![Name {
+                tax_id: tax_id.to_string(),
+                name,
+                class: Some("scientific name".into()),
+                ..Default::default()
+            }],
+        });
     }
-    Ok(Nodes { nodes, children })
+    let (nodes, _) = path_taxa_to_nodes(taxa, Some(&lineage_rank));
+    Ok(nodes)
 }
 
 #[cfg(test)]
@@ -453,7 +3088,7 @@ mod tests {
                 Name {
                     tax_id: String::from("1"),
                     name: String::from("all"),
-                    class: Some(String::from("synonym")),
+                    class: Some(Symbol::from("synonym")),
                     ..Default::default()
                 }
             )
@@ -469,7 +3104,7 @@ mod tests {
                 Node {
                     tax_id: String::from("1"),
                     parent_tax_id: String::from("1"),
-                    rank: String::from("no rank"),
+                    rank: Symbol::from("no rank"),
                     ..Default::default()
                 }
             )
@@ -481,10 +3116,600 @@ mod tests {
                 Node {
                     tax_id: String::from("2"),
                     parent_tax_id: String::from("131567"),
-                    rank: String::from("superkingdom"),
+                    rank: Symbol::from("superkingdom"),
+                    columns: Some(
+                        ["", "0", "0", "11", "0", "0", "0", "0", "0", ""]
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect()
+                    ),
                     ..Default::default()
                 }
             )
         );
+        let line = "2\t|\t131567\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|";
+        assert_eq!(Node::parse(line).unwrap().1.to_string(), line);
+    }
+
+    #[test]
+    fn test_parse_itis() {
+        let nodes = parse_itis(
+            PathBuf::from("test/taxonomy/itis"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("180596").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "180595");
+        assert_eq!(node.scientific_name(), "Canis lupus");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["synonym".to_string()]), false),
+            vec!["Canis lupus lycaon".to_string()]
+        );
+        assert_eq!(
+            node.names_by_class(Some(&vec!["common name".to_string()]), false),
+            vec!["gray wolf".to_string()]
+        );
+        assert!(!nodes.nodes.contains_key("180597"));
+        assert_eq!(nodes.children.get("180595").unwrap(), &vec!["180596"]);
+    }
+
+    #[test]
+    fn test_write_taxdump_is_deterministic() {
+        let out_dir = std::env::temp_dir().join(format!("blobtk-taxdump-{}", std::process::id()));
+        for run in ["a", "b"] {
+            let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+            if run == "b" {
+                // Children added by lookup_nodes are not in file order
+                for children in nodes.children.values_mut() {
+                    children.reverse();
+                }
+            }
+            write_taxdump(&nodes, None, None, out_dir.join(run), None, false, None).unwrap();
+            write_taxdump(
+                &nodes,
+                None,
+                None,
+                out_dir.join(run).join("sorted"),
+                None,
+                true,
+                None,
+            )
+            .unwrap();
+        }
+        for file in [
+            "nodes.dmp",
+            "names.dmp",
+            "sorted/nodes.dmp",
+            "sorted/names.dmp",
+        ] {
+            assert_eq!(
+                std::fs::read(out_dir.join("a").join(file)).unwrap(),
+                std::fs::read(out_dir.join("b").join(file)).unwrap()
+            );
+        }
+        let sorted = std::fs::read_to_string(out_dir.join("a/sorted/nodes.dmp")).unwrap();
+        let tax_ids: Vec<u64> = sorted
+            .lines()
+            .map(|line| line.split('\t').next().unwrap().parse().unwrap())
+            .collect();
+        assert!(tax_ids.windows(2).all(|pair| pair[0] < pair[1]));
+        std::fs::remove_dir_all(out_dir).unwrap();
+        let first = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let second = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        assert_eq!(
+            first.nodes_by_rank("species"),
+            second.nodes_by_rank("species")
+        );
+    }
+
+    #[test]
+    fn test_write_outputs_to_joined_paths() {
+        // Spaces and glob characters in directory names should not affect
+        // where outputs are written on any platform
+        let out_dir = std::env::temp_dir()
+            .join(format!("blobtk paths {}", std::process::id()))
+            .join("taxdump [out]");
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        write_taxdump(&nodes, None, None, out_dir.clone(), None, false, None).unwrap();
+        write_jsonl(&nodes, None, None, out_dir.clone());
+        write_ranked_lineage(&nodes, None, None, out_dir.clone());
+        for file in ["nodes.dmp", "names.dmp", "nodes.jsonl", "rankedlineage.dmp"] {
+            assert!(out_dir.join(file).is_file(), "{} not written", file);
+        }
+        let written = parse_taxdump(out_dir.clone()).unwrap();
+        assert_eq!(written.nodes.len(), nodes.nodes.len());
+        std::fs::remove_dir_all(out_dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_write_compressed_taxdump() {
+        let out_dir = std::env::temp_dir().join(format!("blobtk_gz_{}", std::process::id()));
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        write_taxdump(&nodes, None, None, out_dir.clone(), None, false, None).unwrap();
+        let plain = std::fs::read(out_dir.join("nodes.dmp")).unwrap();
+        write_taxdump(
+            &nodes,
+            None,
+            None,
+            out_dir.clone(),
+            Some(cli::TaxdumpCompression::Gzip),
+            false,
+            None,
+        )
+        .unwrap();
+        let mut gunzipped = vec![];
+        flate2::read::GzDecoder::new(File::open(out_dir.join("nodes.dmp.gz")).unwrap())
+            .read_to_end(&mut gunzipped)
+            .unwrap();
+        assert_eq!(gunzipped, plain);
+        write_taxdump(
+            &nodes,
+            None,
+            None,
+            out_dir.clone(),
+            Some(cli::TaxdumpCompression::TarGz),
+            false,
+            None,
+        )
+        .unwrap();
+        let file = File::open(out_dir.join("taxdump.tar.gz")).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut members = vec![];
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            if name == "nodes.dmp" {
+                let mut content = vec![];
+                entry.read_to_end(&mut content).unwrap();
+                assert_eq!(content, plain);
+            }
+            members.push(name);
+        }
+        assert_eq!(members, vec!["nodes.dmp", "names.dmp"]);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_alias_ranks() {
+        let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let aliases = HashMap::from([
+            ("superkingdom".to_string(), "domain".to_string()),
+            ("division".to_string(), "phylum".to_string()),
+        ]);
+        assert_eq!(nodes.alias_ranks(&aliases), 1);
+        assert_eq!(nodes.nodes["2759"].rank, "domain");
+        assert_eq!(nodes.nodes["7711"].rank, "phylum");
+        assert_eq!(nodes.alias_ranks(&aliases), 0);
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let subspecies = nodes.nodes_by_rank("subspecies").len();
+        assert_eq!(nodes.roll_up(&["subspecies".to_string()]), subspecies);
+        assert!(nodes.nodes_by_rank("subspecies").is_empty());
+        assert!(!nodes.nodes.contains_key("9615"));
+        let wolf = &nodes.nodes["9612"];
+        assert!(wolf.names.iter().flatten().any(|name| {
+            name.name == "Canis lupus familiaris" && name.class.as_deref() == Some("synonym")
+        }));
+        assert!(!nodes.children["9612"].contains(&"9615".to_string()));
+    }
+
+    #[test]
+    fn test_descendants() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let family = "9608".to_string();
+        let depth_first: Vec<&String> = nodes.descendants(&family).map(|n| &n.tax_id).collect();
+        let breadth_first: Vec<&String> = nodes
+            .descendants_breadth_first(&family)
+            .map(|n| &n.tax_id)
+            .collect();
+        assert_eq!(depth_first.len(), breadth_first.len());
+        assert_eq!(depth_first[..4], ["9608", "9611", "9612", "9615"]);
+        assert_eq!(breadth_first[..4], ["9608", "9611", "9612", "9614"]);
+        assert_eq!(nodes.descendants(&"unknown".to_string()).count(), 0);
+
+        // Deep trees should not overflow the stack when written
+        let mut chain = Nodes::default();
+        for i in 1..=50_000 {
+            let tax_id = i.to_string();
+            let parent_tax_id = (i - 1).max(1).to_string();
+            if tax_id != parent_tax_id {
+                chain
+                    .children
+                    .insert(parent_tax_id.clone(), vec![tax_id.clone()]);
+            }
+            chain.nodes.insert(
+                tax_id.clone(),
+                Node {
+                    tax_id,
+                    parent_tax_id,
+                    rank: "no rank".into(),
+                    ..Default::default()
+                },
+            );
+        }
+        let mut nodes_writer: Box<dyn Write> = Box::new(std::io::sink());
+        let mut names_writer: Box<dyn Write> = Box::new(std::io::sink());
+        chain.write_taxdump(
+            vec!["1".to_string()],
+            None,
+            &mut nodes_writer,
+            &mut names_writer,
+        );
+        assert_eq!(chain.descendants(&"1".to_string()).count(), 50_000);
+    }
+
+    #[test]
+    fn test_write_dot_and_graphml() {
+        let mut nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        nodes.nodes.insert(
+            "gbif:1".to_string(),
+            Node {
+                tax_id: "gbif:1".to_string(),
+                parent_tax_id: "9612".to_string(),
+                rank: "species".into(),
+                scientific_name: Some("Canis \"new\" <sp>".to_string()),
+                ..Default::default()
+            },
+        );
+        nodes
+            .children
+            .entry("9612".to_string())
+            .or_default()
+            .push("gbif:1".to_string());
+        let out_dir = std::env::temp_dir().join(format!("blobtk_graph_{}", std::process::id()));
+        let root = || Some(vec!["9612".to_string()]);
+        write_dot(&nodes, root(), None, out_dir.clone());
+        write_graphml(&nodes, root(), None, out_dir.clone());
+        let dot = std::fs::read_to_string(out_dir.join("nodes.dot")).unwrap();
+        assert!(dot.starts_with("digraph taxonomy {"));
+        assert!(dot.contains(r#""9612" -> "9615";"#));
+        assert!(dot.contains(r#""9612" -> "gbif:1";"#));
+        assert!(dot.contains(r#"name="Canis \"new\" <sp>", source="gbif", style=dashed"#));
+        assert!(!dot.contains(r#"-> "9612""#));
+        let graphml = std::fs::read_to_string(out_dir.join("nodes.graphml")).unwrap();
+        assert!(graphml.contains(r#"<edge source="9612" target="gbif:1"/>"#));
+        assert!(graphml.contains(r#"<data key="name">Canis &quot;new&quot; &lt;sp&gt;</data>"#));
+        assert!(roxmltree::Document::parse(&graphml).is_ok());
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_coldp() {
+        let nodes = parse_coldp(
+            PathBuf::from("test/taxonomy/coldp"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("4QHKG").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "625M");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["synonym".to_string()]), false),
+            vec!["Canis lycaon".to_string()]
+        );
+        assert!(!nodes.nodes.contains_key("7N4Y8"));
+        assert_eq!(nodes.nodes.get("CARN").unwrap().parent_tax_id, "CARN");
+    }
+
+    #[test]
+    fn test_parse_gtdb() {
+        let nodes = parse_gtdb(
+            PathBuf::from("test/taxonomy/gtdb"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("s__Escherichia coli").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "g__Escherichia");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["genome accession".to_string()]), false),
+            vec![
+                "RS_GCF_000005845.2".to_string(),
+                "GB_GCA_000008865.2".to_string()
+            ]
+        );
+        assert_eq!(nodes.parent(&"d__Archaea".to_string()).unwrap().tax_id, "1");
+        assert_eq!(
+            nodes
+                .lineage(&"1".to_string(), &"s__Escherichia coli".to_string())
+                .len(),
+            7
+        );
+    }
+
+    #[test]
+    fn test_parse_silva() {
+        let nodes = parse_silva(
+            PathBuf::from("test/taxonomy/silva"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("2356").unwrap();
+        assert_eq!(node.rank, "genus");
+        assert_eq!(node.scientific_name(), "Escherichia-Shigella");
+        assert_eq!(node.parent_tax_id, "2355");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["accession".to_string()]), false),
+            vec!["U00096.4035531.4037072".to_string()]
+        );
+        assert_eq!(nodes.parent(&"3".to_string()).unwrap().tax_id, "1");
+    }
+
+    #[test]
+    fn test_parse_worms() {
+        let nodes = parse_worms(
+            PathBuf::from("test/taxonomy/worms"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("137205").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "137037");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["synonym".to_string()]), false),
+            vec!["Balaena boops".to_string()]
+        );
+        assert!(!nodes.nodes.contains_key("383393"));
+        assert!(!nodes.nodes.contains_key("999999"));
+    }
+
+    #[test]
+    fn test_parse_irmng() {
+        let nodes = parse_irmng(
+            PathBuf::from("test/taxonomy/irmng"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("1300001").unwrap();
+        assert_eq!(node.rank, "genus");
+        assert_eq!(node.parent_tax_id, "104950");
+        assert!(nodes.nodes.contains_key("1300002"));
+        assert_eq!(
+            nodes
+                .nodes
+                .get("1300001")
+                .unwrap()
+                .names_by_class(Some(&vec!["synonym".to_string()]), false),
+            vec!["Dimetrodonus".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_new_taxdump() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/new_taxdump")).unwrap();
+        let node = nodes.nodes.get("9612").unwrap();
+        assert_eq!(node.lineage.as_ref().unwrap()[..2], ["1", "131567"]);
+        assert_eq!(
+            node.ranked_lineage.as_ref().unwrap().get("family").unwrap(),
+            "Canidae"
+        );
+        assert_eq!(node.hosts.as_ref().unwrap(), &vec!["vertebrates", "human"]);
+        assert_eq!(
+            node.type_material.as_ref().unwrap(),
+            &vec![("neotype".to_string(), "USNM 180304".to_string())]
+        );
+        let lineage: Vec<&str> = nodes
+            .lineage(&"9608".to_string(), &"9615".to_string())
+            .iter()
+            .map(|node| node.tax_id())
+            .collect();
+        assert_eq!(lineage, vec!["9608", "9611", "9612"]);
+        let deleted = parse_delnodes(Path::new("test/taxonomy/new_taxdump"));
+        assert!(deleted.contains("1234567"));
+        assert!(parse_delnodes(Path::new("test/taxonomy/canidae/ncbi")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_dwca() {
+        let nodes = parse_dwca(
+            PathBuf::from("test/taxonomy/dwca"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("c4").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "c3");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["synonym".to_string()]), false),
+            vec!["Canis lycaon".to_string()]
+        );
+        assert_eq!(
+            node.names_by_class(Some(&vec!["common name".to_string()]), false),
+            vec!["gray wolf".to_string()]
+        );
+        assert_eq!(
+            nodes.nodes.get("c1").unwrap().scientific_name(),
+            "Carnivora Bowdich, 1821"
+        );
+        assert!(!nodes.nodes.contains_key("c5"));
+    }
+
+    #[test]
+    fn test_parse_datasets() {
+        let nodes = parse_datasets(PathBuf::from("test/taxonomy/datasets")).unwrap();
+        let node = nodes.nodes.get("9612").unwrap();
+        assert_eq!(node.rank, "species");
+        assert_eq!(node.parent_tax_id, "9611");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["common name".to_string()]), false),
+            vec!["gray wolf".to_string()]
+        );
+        let genus = nodes.nodes.get("9611").unwrap();
+        assert_eq!(genus.rank, "genus");
+        assert_eq!(genus.scientific_name(), "Canis");
+        assert_eq!(nodes.children.get("9608").unwrap(), &vec!["9611", "9625"]);
+        assert_eq!(
+            nodes.lineage(&"1".to_string(), &"9627".to_string()).len(),
+            27
+        );
+    }
+
+    #[test]
+    fn test_parse_ena_jsonl() {
+        let nodes = parse_ena_jsonl(PathBuf::from("test/taxonomy/ena/ena.jsonl")).unwrap();
+        let node = nodes.nodes.get("9612").unwrap();
+        assert_eq!(node.parent_tax_id, "9611");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["common name".to_string()]), false),
+            vec!["gray wolf".to_string()]
+        );
+        assert_eq!(nodes.nodes.get("9611").unwrap().parent_tax_id, "9608");
+        assert_eq!(nodes.nodes.get("9608").unwrap().parent_tax_id, "1");
+    }
+
+    #[test]
+    fn test_parse_bold() {
+        let nodes = parse_bold(
+            PathBuf::from("test/taxonomy/bold"),
+            &mut CsvContext::default(),
+        )
+        .unwrap();
+        let node = nodes.nodes.get("43800").unwrap();
+        assert_eq!(node.parent_tax_id, "4380");
+        assert_eq!(
+            node.names_by_class(Some(&vec!["bin".to_string()]), false),
+            vec!["BOLD:AAA7890".to_string()]
+        );
+        let bin = nodes.nodes.get("BOLD:ACF1234").unwrap();
+        assert_eq!(bin.parent_tax_id, "4380");
+        assert_eq!(bin.scientific_name(), "Vanessa sp. BOLD:ACF1234");
+        assert_eq!(nodes.nodes.get("20").unwrap().parent_tax_id, "1");
+    }
+
+    #[test]
+    fn test_parse_lineage_tsv() {
+        let nodes = parse_lineage_tsv(PathBuf::from("test/taxonomy/lineage/lineage.tsv")).unwrap();
+        let ecoli = nodes.nodes.get("562").unwrap();
+        assert_eq!(ecoli.rank, "species");
+        assert_eq!(ecoli.scientific_name(), "Escherichia coli");
+        assert_eq!(ecoli.parent_tax_id, "g__Escherichia");
+        assert_eq!(nodes.nodes["p__Proteobacteria"].parent_tax_id, "2");
+        assert_eq!(nodes.nodes["2"].parent_tax_id, "1");
+        assert_eq!(nodes.nodes["Homo"].parent_tax_id, "Hominidae");
+        assert_eq!(nodes.nodes["9606"].parent_tax_id, "Homo");
+        assert_eq!(nodes.nodes["9606"].rank, "no rank");
+    }
+
+    #[test]
+    fn test_csv_settings() {
+        let report = std::env::temp_dir().join("blobtk_test_csv_errors.tsv");
+        let mut csv = CsvContext::new(CsvSettings {
+            comment: Some(b'#'),
+            flexible: Some(false),
+            error_report: Some(report.clone()),
+            ..Default::default()
+        });
+        let nodes = parse_gtdb(
+            PathBuf::from("test/taxonomy/csv/gtdb_taxonomy.tsv"),
+            &mut csv,
+        )
+        .unwrap();
+        drop(csv);
+        assert!(nodes.nodes.contains_key("g__Escherichia"));
+        assert!(!nodes.nodes.contains_key("g__Ragged"));
+        let errors = std::fs::read_to_string(&report).unwrap();
+        assert_eq!(errors.lines().count(), 1);
+        assert!(errors.starts_with("test/taxonomy/csv/gtdb_taxonomy.tsv\t4\t"));
+    }
+
+    #[test]
+    fn test_header_column() {
+        let headers = csv::StringRecord::from(vec!["Taxon_ID", "parent id", "scientific name"]);
+        let columns = header_columns(&headers);
+        assert!(CsvContext::default()
+            .header_column(&columns, "col:ID")
+            .is_err());
+        let csv = CsvContext::new(CsvSettings {
+            fuzzy_headers: true,
+            header_synonyms: HashMap::from([("col:ID".to_string(), vec!["taxonID".to_string()])]),
+            ..Default::default()
+        });
+        let id = csv.header_column(&columns, "col:ID");
+        let parent = csv.header_column(&columns, "parentID");
+        let rank = csv.header_column(&columns, "rank");
+        assert_eq!(id.unwrap(), 0);
+        assert_eq!(parent.unwrap(), 1);
+        assert!(rank.is_err());
+    }
+
+    #[test]
+    fn test_clean_fields() {
+        let record = |values: Vec<&str>| csv::StringRecord::from(values);
+        let row = record(vec!["NA", "None", "-", "unknown", "Canis"]);
+        assert_eq!(
+            row.clone()
+                .clean_fields(&mut CsvContext::default(), &HashMap::new()),
+            row
+        );
+        let mut csv = CsvContext::new(CsvSettings {
+            null_values: Some(vec!["NA".to_string(), "None".to_string()]),
+            ..Default::default()
+        });
+        assert_eq!(
+            row.clone().clean_fields(&mut csv, &HashMap::new()),
+            record(vec!["", "", "-", "unknown", "Canis"])
+        );
+        let mut csv = CsvContext::new(CsvSettings {
+            null_values: Some(vec!["-".to_string(), "null".to_string()]),
+            null_values_by_field: HashMap::from([(
+                "rank".to_string(),
+                vec!["unknown".to_string()],
+            )]),
+            field_defaults: HashMap::from([
+                ("rank".to_string(), "no rank".to_string()),
+                ("name".to_string(), "{genus} sp.".to_string()),
+            ]),
+            ..Default::default()
+        });
+        let columns = header_columns(&record(vec!["ID", "parentID", "name", "rank", "genus"]));
+        let blanked = row.clone().clean_fields(&mut csv, &columns);
+        let unnamed = record(vec!["NULL"]).clean_fields(&mut csv, &columns);
+        assert_eq!(
+            blanked,
+            record(vec!["NA", "None", "Canis sp.", "no rank", "Canis"])
+        );
+        assert_eq!(unnamed, record(vec![""]));
+        assert_eq!(
+            csv.take_imputed(),
+            BTreeMap::from([("name".to_string(), 1), ("rank".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_field_normalization() {
+        let normalization = FieldNormalization {
+            lowercase: true,
+            collapse_whitespace: true,
+            vocabulary: Some(PathBuf::from("test/taxonomy/csv/rank_vocabulary.tsv")),
+            ..Default::default()
+        };
+        let vocabulary =
+            read_vocabulary(normalization.vocabulary.as_ref().unwrap(), &normalization).unwrap();
+        assert_eq!(vocabulary.get("sp."), Some(&"species".to_string()));
+        let mut csv = CsvContext::new(CsvSettings {
+            null_values: Some(vec!["NA".to_string()]),
+            field_normalization: HashMap::from([("rank".to_string(), normalization)]),
+            vocabularies: HashMap::from([("rank".to_string(), vocabulary)]),
+            ..Default::default()
+        });
+        let record = |values: Vec<&str>| csv::StringRecord::from(values);
+        let columns = header_columns(&record(vec!["ID", "rank"]));
+        let cleaned: Vec<csv::StringRecord> = [" Sub   Species", "SP.", "Family", "NA"]
+            .iter()
+            .map(|rank| record(vec![" ID ", rank]).clean_fields(&mut csv, &columns))
+            .collect();
+        assert_eq!(
+            cleaned,
+            vec![
+                record(vec![" ID ", "subspecies"]),
+                record(vec![" ID ", "species"]),
+                record(vec![" ID ", "family"]),
+                record(vec![" ID ", ""]),
+            ]
+        );
     }
 }
@@ -0,0 +1,345 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow;
+use serde_json::{json, Value};
+use url::Url;
+
+use super::lookup::{fuzzy_distance, NameIndex};
+use super::parse::Nodes;
+use super::taxdump_to_nodes;
+use crate::cli;
+use crate::utils;
+
+/// Largest edit distance a `/fuzzy` request can ask for.
+const MAX_DISTANCE: usize = 4;
+
+/// Largest number of names returned by a `/fuzzy` request.
+const MAX_FUZZY_MATCHES: usize = 20;
+
+/// Settings for `/fuzzy` requests.
+#[derive(Clone, Debug)]
+pub struct FuzzySettings {
+    /// Edit distance if a request does not set `max_distance`
+    pub max_distance: usize,
+    /// Allow one edit per this many characters of the query
+    pub chars_per_edit: Option<usize>,
+    pub algorithm: cli::FuzzyAlgorithm,
+}
+
+impl Default for FuzzySettings {
+    fn default() -> FuzzySettings {
+        FuzzySettings {
+            max_distance: 2,
+            chars_per_edit: None,
+            algorithm: cli::FuzzyAlgorithm::default(),
+        }
+    }
+}
+
+/// Describe a taxon and its lineage from the root.
+fn taxon(nodes: &Nodes, tax_id: &String) -> Value {
+    let node = &nodes.nodes[tax_id];
+    let lineage: Vec<Value> = nodes
+        .lineage(&"1".to_string(), tax_id)
+        .iter()
+        .map(|n| {
+            json!({
+                "tax_id": n.tax_id,
+                "rank": n.rank,
+                "scientific_name": n.scientific_name(),
+            })
+        })
+        .collect();
+    json!({
+        "tax_id": node.tax_id,
+        "rank": node.rank,
+        "scientific_name": node.scientific_name(),
+        "lineage": lineage,
+    })
+}
+
+/// Keep candidate taxa whose ancestors agree with names given for higher
+/// ranks, dropping names with no candidates left. An ancestor agrees if
+/// the index has the given name for it, and ranks missing from a lineage
+/// are not checked.
+fn filter_by_ancestors(
+    nodes: &Nodes,
+    index: &NameIndex,
+    matches: Vec<(String, usize, Vec<String>)>,
+    ancestors: &[(String, String)],
+) -> Vec<(String, usize, Vec<String>)> {
+    if ancestors.is_empty() {
+        return matches;
+    }
+    let ancestor_ids: Vec<(&str, Vec<String>)> = ancestors
+        .iter()
+        .map(|(rank, name)| (rank.as_str(), index.get(name)))
+        .collect();
+    let root_id = "1".to_string();
+    matches
+        .into_iter()
+        .filter_map(|(name, distance, tax_ids)| {
+            let tax_ids: Vec<String> = tax_ids
+                .into_iter()
+                .filter(|tax_id| {
+                    let lineage = nodes.lineage(&root_id, tax_id);
+                    ancestor_ids.iter().all(|(rank, ids)| {
+                        match lineage.iter().find(|node| node.rank == *rank) {
+                            Some(ancestor) => ids.contains(&ancestor.tax_id),
+                            None => true,
+                        }
+                    })
+                })
+                .collect();
+            (!tax_ids.is_empty()).then_some((name, distance, tax_ids))
+        })
+        .collect()
+}
+
+/// Respond to a request path with a status code and JSON body.
+///
+/// Supported endpoints are
+/// - `/lookup?q=<name or taxon ID>` for all taxa matching a name or ID
+/// - `/lineage?tax_id=<taxon ID>` for a single taxon
+/// - `/fuzzy?q=<name>&max_distance=<n>` for names within n edits, falling
+///   back to names that sound alike if the index has phonetic keys. Other
+///   parameters name a higher rank, e.g. `family=Canidae`, and only taxa
+///   with that ancestor are returned
+pub fn respond(
+    nodes: &Nodes,
+    index: &NameIndex,
+    fuzzy: &FuzzySettings,
+    target: &str,
+) -> (u16, Value) {
+    let url = match Url::parse("http://localhost").and_then(|base| base.join(target)) {
+        Ok(url) => url,
+        Err(err) => return (400, json!({ "error": err.to_string() })),
+    };
+    let param = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.to_string())
+    };
+    let missing = |key: &str| {
+        (
+            400,
+            json!({ "error": format!("missing {} parameter", key) }),
+        )
+    };
+    match url.path() {
+        "/lookup" => {
+            let query = match param("q") {
+                Some(query) => query,
+                None => return missing("q"),
+            };
+            let tax_ids = match nodes.nodes.contains_key(&query) {
+                true => vec![query.clone()],
+                false => index.get(&query),
+            };
+            let taxa: Vec<Value> = tax_ids.iter().map(|id| taxon(nodes, id)).collect();
+            (200, json!({ "query": query, "taxa": taxa }))
+        }
+        "/lineage" => {
+            let tax_id = match param("tax_id") {
+                Some(tax_id) => tax_id,
+                None => return missing("tax_id"),
+            };
+            match nodes.nodes.contains_key(&tax_id) {
+                true => (200, taxon(nodes, &tax_id)),
+                false => (
+                    404,
+                    json!({ "error": format!("taxon {} not found", tax_id) }),
+                ),
+            }
+        }
+        "/fuzzy" => {
+            let query = match param("q") {
+                Some(query) => query,
+                None => return missing("q"),
+            };
+            let max_distance = match param("max_distance").and_then(|d| d.parse::<usize>().ok()) {
+                Some(max_distance) => max_distance.min(MAX_DISTANCE),
+                None => fuzzy.max_distance,
+            };
+            let max_distance =
+                fuzzy_distance(query.chars().count(), max_distance, fuzzy.chars_per_edit);
+            let ancestors: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(key, _)| key != "q" && key != "max_distance")
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let mut matches = filter_by_ancestors(
+                nodes,
+                index,
+                index.fuzzy(&query, max_distance, &fuzzy.algorithm),
+                &ancestors,
+            );
+            let mut method = "fuzzy";
+            if matches.is_empty() {
+                matches = filter_by_ancestors(
+                    nodes,
+                    index,
+                    index.phonetic(&query, &fuzzy.algorithm),
+                    &ancestors,
+                );
+                method = "phonetic";
+            }
+            let matches: Vec<Value> = matches
+                .into_iter()
+                .take(MAX_FUZZY_MATCHES)
+                .map(|(name, distance, tax_ids)| {
+                    json!({
+                        "name": name,
+                        "distance": distance,
+                        "tax_ids": tax_ids,
+                        "match": method,
+                    })
+                })
+                .collect();
+            (200, json!({ "query": query, "matches": matches }))
+        }
+        path => (404, json!({ "error": format!("no endpoint {}", path) })),
+    }
+}
+
+fn handle(
+    mut stream: TcpStream,
+    nodes: &Nodes,
+    index: &NameIndex,
+    fuzzy: &FuzzySettings,
+) -> Result<(), anyhow::Error> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip headers, requests have no body
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let (status, body) = match parts.as_slice() {
+        ["GET", target, ..] => respond(nodes, index, fuzzy, target),
+        _ => (405, json!({ "error": "only GET requests are supported" })),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Execute the `taxonomy serve` subcommand from `blobtk`.
+pub fn serve(options: &cli::TaxonomyServeOptions) -> Result<(), anyhow::Error> {
+    let nodes = taxdump_to_nodes(&cli::TaxonomyOptions {
+        path: Some(options.path.clone()),
+        taxonomy_format: Some(options.taxonomy_format.clone()),
+        ..Default::default()
+    })?;
+    let mut index = NameIndex::new(
+        &nodes,
+        &options.name_classes,
+        options.case_sensitive,
+        options.preserve_case,
+    );
+    if options.phonetic {
+        index.build_phonetic();
+    }
+    let fuzzy = FuzzySettings {
+        max_distance: options.max_distance,
+        chars_per_edit: options.chars_per_edit,
+        algorithm: options.fuzzy_algorithm.clone(),
+    };
+    let listener = TcpListener::bind((options.host.as_str(), options.port))?;
+    // poll so a cancelled server stops between requests
+    listener.set_nonblocking(true)?;
+    eprintln!(
+        "Serving {} taxa on http://{}",
+        nodes.nodes.len(),
+        listener.local_addr()?
+    );
+    while !utils::is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = handle(stream, &nodes, &index, &fuzzy) {
+                    eprintln!("Request failed: {}", err);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => sleep(Duration::from_millis(50)),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_respond() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let mut index = NameIndex::new(&nodes, &vec!["synonym".to_string()], false, false);
+        let fuzzy = FuzzySettings::default();
+        let (status, body) = respond(&nodes, &index, &fuzzy, "/lookup?q=canis%20lupus");
+        assert_eq!(status, 200);
+        assert_eq!(body["taxa"][0]["tax_id"], "9612");
+        assert_eq!(body["taxa"][0]["lineage"][0]["tax_id"], "1");
+        let (status, body) = respond(&nodes, &index, &fuzzy, "/lineage?tax_id=9615");
+        assert_eq!(status, 200);
+        assert_eq!(body["scientific_name"], "Canis lupus familiaris");
+        let (_, body) = respond(
+            &nodes,
+            &index,
+            &fuzzy,
+            "/fuzzy?q=Canis+lupis&max_distance=1",
+        );
+        assert_eq!(body["matches"][0]["tax_ids"][0], "9612");
+        let query = "/fuzzy?q=Canis+lupis&max_distance=1&family=canidae";
+        assert_eq!(
+            respond(&nodes, &index, &fuzzy, query).1["matches"][0]["tax_ids"][0],
+            "9612"
+        );
+        let query = "/fuzzy?q=Canis+lupis&max_distance=1&family=Felidae";
+        assert_eq!(
+            respond(&nodes, &index, &fuzzy, query).1["matches"],
+            json!([])
+        );
+        let strict = FuzzySettings {
+            chars_per_edit: Some(20),
+            ..Default::default()
+        };
+        let (_, body) = respond(&nodes, &index, &strict, "/fuzzy?q=Canis+lupis");
+        assert_eq!(body["matches"].as_array().unwrap().len(), 0);
+        let query = "/fuzzy?q=Kanys+lupus&max_distance=1";
+        assert_eq!(
+            respond(&nodes, &index, &fuzzy, query).1["matches"],
+            json!([])
+        );
+        index.build_phonetic();
+        let (_, body) = respond(&nodes, &index, &fuzzy, query);
+        assert_eq!(body["matches"][0]["tax_ids"][0], "9612");
+        assert_eq!(body["matches"][0]["match"], "phonetic");
+        assert_eq!(respond(&nodes, &index, &fuzzy, "/lineage?tax_id=0").0, 404);
+        assert_eq!(respond(&nodes, &index, &fuzzy, "/lookup").0, 400);
+        assert_eq!(respond(&nodes, &index, &fuzzy, "/unknown").0, 404);
+    }
+}
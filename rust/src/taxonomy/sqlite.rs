@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow;
+use rusqlite::{params, Connection};
+
+use super::parse::{Name, Nodes};
+use crate::utils;
+
+const SCHEMA: &str = "
+    CREATE TABLE nodes (
+        tax_id TEXT PRIMARY KEY,
+        parent_tax_id TEXT NOT NULL,
+        rank TEXT NOT NULL,
+        scientific_name TEXT
+    );
+    CREATE TABLE names (
+        tax_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        unique_name TEXT,
+        class TEXT
+    );
+    CREATE TABLE xrefs (
+        tax_id TEXT NOT NULL,
+        source TEXT NOT NULL,
+        xref_id TEXT NOT NULL
+    );
+    CREATE TABLE lineage (
+        tax_id TEXT NOT NULL,
+        ancestor_tax_id TEXT NOT NULL,
+        depth INTEGER NOT NULL,
+        PRIMARY KEY (tax_id, ancestor_tax_id)
+    ) WITHOUT ROWID;
+";
+
+const INDEXES: &str = "
+    CREATE INDEX nodes_parent ON nodes (parent_tax_id);
+    CREATE INDEX names_name ON names (name COLLATE NOCASE);
+    CREATE INDEX names_tax_id ON names (tax_id);
+    CREATE INDEX xrefs_xref ON xrefs (source, xref_id);
+    CREATE INDEX xrefs_tax_id ON xrefs (tax_id);
+    CREATE INDEX lineage_ancestor ON lineage (ancestor_tax_id, depth);
+";
+
+/// Xrefs added by `lookup_nodes` are stored as names with the xref label as
+/// the class and `label:id` as the unique name.
+fn xref(name: &Name) -> Option<&str> {
+    let source = name.class.as_deref()?;
+    match name.unique_name == format!("{}:{}", source, name.name) {
+        true => Some(source),
+        false => None,
+    }
+}
+
+/// Write nodes to a SQLite database at `taxonomy.sqlite` in the output
+/// directory.
+///
+/// The database has `nodes`, `names` and `xrefs` tables and a `lineage`
+/// closure table with a row for each node and each of its ancestors,
+/// including the node itself at depth 0.
+pub fn write_sqlite(
+    nodes: &Nodes,
+    root_taxon_ids: Option<Vec<String>>,
+    base_taxon_id: Option<String>,
+    taxdump: PathBuf,
+) -> Result<(), anyhow::Error> {
+    let root_ids = root_taxon_ids.unwrap_or(vec!["1".to_string()]);
+    let path = taxdump.join("taxonomy.sqlite");
+    fs::create_dir_all(&taxdump)?;
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    utils::track_output(&path);
+    let mut conn = Connection::open(&path)?;
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    {
+        let mut insert_node = tx.prepare(
+            "INSERT OR IGNORE INTO nodes (tax_id, parent_tax_id, rank, scientific_name)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut insert_name = tx.prepare(
+            "INSERT INTO names (tax_id, name, unique_name, class) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut insert_xref =
+            tx.prepare("INSERT INTO xrefs (tax_id, source, xref_id) VALUES (?1, ?2, ?3)")?;
+        let mut insert_lineage = tx.prepare(
+            "INSERT OR IGNORE INTO lineage (tax_id, ancestor_tax_id, depth) VALUES (?1, ?2, ?3)",
+        )?;
+        for node in nodes.subtree(root_ids, base_taxon_id) {
+            utils::check_cancelled()?;
+            if insert_node.execute(params![
+                node.tax_id,
+                node.parent_tax_id,
+                node.rank.as_str(),
+                node.scientific_name
+            ])? == 0
+            {
+                continue;
+            }
+            for name in node.names.iter().flatten() {
+                match xref(name) {
+                    Some(source) => insert_xref.execute(params![node.tax_id, source, name.name])?,
+                    None => insert_name.execute(params![
+                        node.tax_id,
+                        name.name,
+                        name.unique_name,
+                        name.class.as_deref()
+                    ])?,
+                };
+            }
+            let mut ancestor = node;
+            let mut depth = 0;
+            loop {
+                insert_lineage.execute(params![node.tax_id, ancestor.tax_id, depth])?;
+                match nodes.nodes.get(&ancestor.parent_tax_id) {
+                    Some(parent)
+                        if parent.tax_id != ancestor.tax_id && depth < nodes.nodes.len() =>
+                    {
+                        ancestor = parent;
+                        depth += 1;
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    conn.execute_batch(INDEXES)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_write_sqlite() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let out = std::env::temp_dir().join("blobtk_test_sqlite");
+        write_sqlite(&nodes, None, None, out.clone()).unwrap();
+        let conn = Connection::open(out.join("taxonomy.sqlite")).unwrap();
+        let count = |sql: &str| -> usize { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM nodes"), nodes.nodes.len());
+        assert_eq!(
+            count("SELECT COUNT(*) FROM lineage WHERE tax_id = '9615' AND depth = 0"),
+            1
+        );
+        let genus: String = conn
+            .query_row(
+                "SELECT n.scientific_name FROM lineage l JOIN nodes n
+                 ON n.tax_id = l.ancestor_tax_id
+                 WHERE l.tax_id = '9615' AND n.rank = 'genus'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(genus, "Canis");
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow;
+use serde::Serialize;
+
+use super::lookup::LookupStats;
+use super::parse::{node_source, Nodes};
+use crate::io;
+
+/// Summary counts for a parsed or merged taxonomy.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TaxonomyStats {
+    /// Total number of nodes
+    pub nodes: usize,
+    /// Number of nodes at each rank
+    pub ranks: BTreeMap<String, usize>,
+    /// Number of nodes from the backbone and from each xref label
+    pub sources: BTreeMap<String, usize>,
+    /// Lookup results for each taxonomy mapped to the backbone
+    pub lookups: Vec<LookupStats>,
+    /// Number of missing values filled from `field_defaults` for each field
+    pub imputed: BTreeMap<String, usize>,
+    /// Wall time and peak memory for each stage if `--timings` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<serde_json::Value>,
+}
+
+impl TaxonomyStats {
+    /// Count nodes by rank and source.
+    pub fn new(nodes: &Nodes, lookups: Vec<LookupStats>) -> TaxonomyStats {
+        let mut stats = TaxonomyStats {
+            nodes: nodes.nodes.len(),
+            lookups,
+            ..Default::default()
+        };
+        for node in nodes.nodes.values() {
+            *stats.ranks.entry(node.rank.to_string()).or_default() += 1;
+            *stats
+                .sources
+                .entry(node_source(node).to_string())
+                .or_default() += 1;
+        }
+        stats
+    }
+
+    /// Rows of a `section\tkey\tcount` table.
+    pub fn rows(&self) -> Vec<(&str, String, usize)> {
+        let mut rows = vec![("total", "nodes".to_string(), self.nodes)];
+        for (rank, count) in self.ranks.iter() {
+            rows.push(("rank", rank.clone(), *count));
+        }
+        for (source, count) in self.sources.iter() {
+            rows.push(("source", source.clone(), *count));
+        }
+        for lookup in self.lookups.iter() {
+            for (section, count) in [
+                ("matched", lookup.matched),
+                ("added", lookup.added),
+                ("unmatched", lookup.unmatched),
+                ("xrefs", lookup.xrefs),
+            ] {
+                rows.push((section, lookup.source.clone(), count));
+            }
+        }
+        for (field, count) in self.imputed.iter() {
+            rows.push(("imputed", field.clone(), *count));
+        }
+        rows
+    }
+}
+
+/// Write taxonomy statistics as JSON if `path` has a `.json` extension or
+/// as TSV otherwise.
+pub fn write_stats(stats: &TaxonomyStats, path: &Path) -> Result<(), anyhow::Error> {
+    let mut writer = io::get_writer(&Some(path.to_path_buf()));
+    if path.extension() == Some(OsStr::new("json")) {
+        writeln!(writer, "{}", serde_json::to_string_pretty(stats)?)?;
+    } else {
+        writeln!(writer, "section\tkey\tcount")?;
+        for (section, key, count) in stats.rows() {
+            writeln!(writer, "{}\t{}\t{}", section, key, count)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::parse_taxdump;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_taxonomy_stats() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        let lookup = LookupStats {
+            source: "gbif".to_string(),
+            matched: 2,
+            xrefs: 2,
+            ..Default::default()
+        };
+        let stats = TaxonomyStats::new(&nodes, vec![lookup]);
+        assert_eq!(stats.nodes, nodes.nodes.len());
+        assert_eq!(stats.sources.get("backbone"), Some(&nodes.nodes.len()));
+        assert_eq!(stats.ranks.values().sum::<usize>(), nodes.nodes.len());
+        let rows = stats.rows();
+        assert_eq!(rows[0], ("total", "nodes".to_string(), nodes.nodes.len()));
+        assert!(rows.contains(&("xrefs", "gbif".to_string(), 2)));
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+use anyhow;
+
+use super::archive;
+use super::parse::{parse_taxdump_checked, Nodes};
+use super::taxdump_to_nodes;
+use crate::cli;
+use crate::io;
+
+/// Ranks from highest to lowest. Other ranks, such as `no rank` and
+/// `clade`, are not checked for inversions.
+const RANK_ORDER: [&str; 30] = [
+    "superkingdom",
+    "kingdom",
+    "subkingdom",
+    "superphylum",
+    "phylum",
+    "subphylum",
+    "superclass",
+    "class",
+    "subclass",
+    "infraclass",
+    "cohort",
+    "subcohort",
+    "superorder",
+    "order",
+    "suborder",
+    "infraorder",
+    "parvorder",
+    "superfamily",
+    "family",
+    "subfamily",
+    "tribe",
+    "subtribe",
+    "genus",
+    "subgenus",
+    "section",
+    "subsection",
+    "species group",
+    "species subgroup",
+    "species",
+    "subspecies",
+];
+
+fn rank_level(rank: &str) -> Option<usize> {
+    let rank = match rank {
+        "domain" => "superkingdom",
+        rank => rank,
+    };
+    RANK_ORDER.iter().position(|r| *r == rank)
+}
+
+/// A problem found in a taxonomy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Issue {
+    /// One of `malformed`, `duplicate`, `unknown`, `cycle`, `orphan`,
+    /// `unnamed` or `inversion`
+    pub kind: &'static str,
+    pub tax_id: String,
+    pub detail: String,
+}
+
+impl Issue {
+    pub fn new(kind: &'static str, tax_id: &str, detail: String) -> Issue {
+        Issue {
+            kind,
+            tax_id: tax_id.to_string(),
+            detail,
+        }
+    }
+}
+
+/// Check parsed nodes for cycles, parents that are not in the taxonomy,
+/// nodes without a scientific name and ranks above their ancestors' ranks.
+///
+/// Each cycle is reported once, starting from its lowest taxon ID. Nodes in
+/// or leading to a cycle are not checked for rank inversions.
+pub fn check_nodes(nodes: &Nodes) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut tax_ids: Vec<&String> = nodes.nodes.keys().collect();
+    tax_ids.sort();
+
+    // false while a node is on the current path, true once it is done
+    let mut visited: HashMap<&String, bool> = HashMap::new();
+    let mut cyclic: HashSet<&String> = HashSet::new();
+    for tax_id in tax_ids.iter() {
+        let mut path = vec![];
+        let mut current = *tax_id;
+        let in_cycle = loop {
+            match visited.get(current) {
+                Some(true) => break cyclic.contains(current),
+                Some(false) => {
+                    let start = path.iter().position(|id| *id == current).unwrap();
+                    let mut cycle: Vec<&String> = path[start..].to_vec();
+                    let lowest = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+                    cycle.rotate_left(lowest);
+                    let ids: Vec<&str> = cycle.iter().map(|id| id.as_str()).collect();
+                    issues.push(Issue::new(
+                        "cycle",
+                        cycle[0],
+                        format!("{} > {}", ids.join(" > "), cycle[0]),
+                    ));
+                    break true;
+                }
+                None => (),
+            }
+            visited.insert(current, false);
+            path.push(current);
+            let node = &nodes.nodes[current];
+            if node.parent_tax_id == node.tax_id {
+                break false;
+            }
+            match nodes.nodes.get(&node.parent_tax_id) {
+                Some(parent) => current = &parent.tax_id,
+                None => break false,
+            }
+        };
+        for id in path {
+            visited.insert(id, true);
+            if in_cycle {
+                cyclic.insert(id);
+            }
+        }
+    }
+
+    for tax_id in tax_ids.iter() {
+        let node = &nodes.nodes[*tax_id];
+        if node.parent_tax_id != node.tax_id && !nodes.nodes.contains_key(&node.parent_tax_id) {
+            issues.push(Issue::new(
+                "orphan",
+                tax_id,
+                format!("parent {} not found", node.parent_tax_id),
+            ));
+        }
+        if node.scientific_name().is_empty() {
+            issues.push(Issue::new(
+                "unnamed",
+                tax_id,
+                "no scientific name".to_string(),
+            ));
+        }
+        if cyclic.contains(tax_id) {
+            continue;
+        }
+        let level = match rank_level(&node.rank) {
+            Some(level) => level,
+            None => continue,
+        };
+        // compare with the nearest ranked ancestor
+        let mut ancestor = node;
+        while let Some(parent) = nodes
+            .nodes
+            .get(&ancestor.parent_tax_id)
+            .filter(|parent| parent.tax_id != ancestor.tax_id)
+        {
+            ancestor = parent;
+            if let Some(ancestor_level) = rank_level(&ancestor.rank) {
+                if ancestor_level >= level {
+                    issues.push(Issue::new(
+                        "inversion",
+                        tax_id,
+                        format!("{} below {} {}", node.rank, ancestor.rank, ancestor.tax_id),
+                    ));
+                }
+                break;
+            }
+        }
+    }
+    issues
+}
+
+/// Execute the `taxonomy validate` subcommand from `blobtk`.
+pub fn validate(options: &cli::TaxonomyValidateOptions) -> Result<(), anyhow::Error> {
+    let mut issues = vec![];
+    let nodes = match options.taxonomy_format {
+        // NCBI taxdumps are read line by line so problems in the files are
+        // reported too
+        cli::TaxonomyFormat::NCBI => {
+            let unpacked = archive::unpack(&options.path)?;
+            let taxdump = unpacked
+                .as_ref()
+                .map_or(options.path.clone(), |unpacked| unpacked.path());
+            parse_taxdump_checked(taxdump, Some(&mut issues))?
+        }
+        _ => taxdump_to_nodes(&cli::TaxonomyOptions {
+            path: Some(options.path.clone()),
+            taxonomy_format: Some(options.taxonomy_format.clone()),
+            ..Default::default()
+        })?,
+    };
+    issues.extend(check_nodes(&nodes));
+
+    let mut writer = io::get_writer(&options.output);
+    writeln!(writer, "issue\ttax_id\tdetail")?;
+    for issue in issues.iter() {
+        writeln!(writer, "{}\t{}\t{}", issue.kind, issue.tax_id, issue.detail)?;
+    }
+    writer.flush()?;
+    if issues.is_empty() {
+        eprintln!("No problems found in {} taxa", nodes.nodes.len());
+        return Ok(());
+    }
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for issue in issues.iter() {
+        *counts.entry(issue.kind).or_default() += 1;
+    }
+    for (kind, count) in counts {
+        eprintln!("{}\t{}", kind, count);
+    }
+    anyhow::bail!(
+        "found {} problems in {} taxa",
+        issues.len(),
+        nodes.nodes.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::taxonomy::parse_taxdump;
+
+    #[test]
+    fn test_validate() {
+        let nodes = parse_taxdump(PathBuf::from("test/taxonomy/canidae/ncbi")).unwrap();
+        assert_eq!(check_nodes(&nodes), vec![]);
+
+        let dir = std::env::temp_dir().join("blobtk_test_validate");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let nodes_dmp = [
+            "1\t|\t1\t|\tno rank\t|",
+            "2\t|\t1\t|\tgenus\t|",
+            "3\t|\t2\t|\tfamily\t|",
+            "4\t|\t5\t|\tspecies\t|",
+            "5\t|\t4\t|\tspecies\t|",
+            "6\t|\t7\t|\tspecies\t|",
+            "2\t|\t1\t|\tgenus\t|",
+            "8",
+            "10\t|\t1\t|\tno rank\t|",
+        ];
+        let names_dmp = [
+            "1\t|\troot\t|\t\t|\tscientific name\t|",
+            "2\t|\tGenus\t|\t\t|\tscientific name\t|",
+            "3\t|\tFamilia\t|\t\t|\tscientific name\t|",
+            "4\t|\tA a\t|\t\t|\tscientific name\t|",
+            "5\t|\tA b\t|\t\t|\tscientific name\t|",
+            "6\t|\tA c\t|\t\t|\tscientific name\t|",
+            "9\t|\tA d\t|\t\t|\tscientific name\t|",
+        ];
+        fs::write(dir.join("nodes.dmp"), nodes_dmp.join("\n")).unwrap();
+        fs::write(dir.join("names.dmp"), names_dmp.join("\n")).unwrap();
+        assert!(parse_taxdump(dir.clone()).is_err());
+
+        let mut issues = vec![];
+        let nodes = parse_taxdump_checked(dir, Some(&mut issues)).unwrap();
+        issues.extend(check_nodes(&nodes));
+        let found: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|issue| (issue.kind, issue.tax_id.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("duplicate", "2"),
+                ("malformed", ""),
+                ("unknown", "9"),
+                ("cycle", "4"),
+                ("unnamed", "10"),
+                ("inversion", "3"),
+                ("orphan", "6"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//!
+//! Invoked by calling:
+//! `blobtk upgrade <args>`
+
+use std::io::Write;
+
+use anyhow;
+
+use crate::blobdir;
+use crate::cli;
+use crate::io;
+
+pub use cli::UpgradeOptions;
+
+/// Execute the `upgrade` subcommand from `blobtk`.
+/// Rewrite a BlobDir meta.json using the current schema version.
+pub fn upgrade(options: &cli::UpgradeOptions) -> Result<(), anyhow::Error> {
+    let mut meta = blobdir::read_meta_value(&options.blobdir)?;
+    let version = blobdir::upgrade_meta(&mut meta);
+    if version > blobdir::META_VERSION {
+        return Err(anyhow::anyhow!(
+            "unable to downgrade meta.json from version {} to {}",
+            version,
+            blobdir::META_VERSION
+        ));
+    }
+    let unknown_keys = blobdir::unknown_meta_keys(&meta);
+    if !unknown_keys.is_empty() {
+        eprintln!(
+            "WARNING: keeping unknown meta.json keys: {}",
+            unknown_keys.join(", ")
+        );
+    }
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => blobdir::meta_path(&options.blobdir),
+    };
+    let mut writer = io::get_writer(&Some(output));
+    writeln!(writer, "{}", serde_json::to_string_pretty(&meta)?)?;
+    eprintln!(
+        "Upgraded meta.json from version {} to {}",
+        version,
+        blobdir::META_VERSION
+    );
+    Ok(())
+}
@@ -1,7 +1,16 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
 use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 
 use rust_decimal::prelude::*;
 
+use crate::error;
 use crate::plot::axis::Scale;
 
 pub mod compact_float {
@@ -85,13 +94,285 @@ pub fn format_si(value: &f64, digits: u32) -> String {
     format!("{}{}", rounded, suffix)
 }
 
-pub fn indexed_sort<T: Ord>(list: &[T]) -> Vec<usize> {
+/// Seed used when no `--seed` is given.
+pub const DEFAULT_SEED: u64 = 0;
+
+static SEED: OnceLock<u64> = OnceLock::new();
+
+/// Set the seed for simulated data. Only the first call has an effect.
+pub fn set_seed(seed: u64) {
+    let _ = SEED.set(seed);
+}
+
+/// Seed for simulated data, falling back to `DEFAULT_SEED`. Other commands
+/// are deterministic and only record it in their summaries.
+pub fn seed() -> u64 {
+    *SEED.get().unwrap_or(&DEFAULT_SEED)
+}
+
+static TIMINGS: OnceLock<Mutex<Vec<serde_json::Value>>> = OnceLock::new();
+
+/// Enable recording of pipeline stage timings.
+pub fn enable_timings() {
+    let _ = TIMINGS.set(Mutex::new(vec![]));
+}
+
+/// Peak resident memory of the process in kB, read from `/proc` where
+/// available.
+pub fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Records the wall time and peak memory of a pipeline stage when dropped.
+pub struct StageTimer {
+    stage: String,
+    start: Instant,
+    /// `stage` if the peak memory was reset at the start of the stage,
+    /// otherwise `process` as the peak covers the whole run so far
+    peak_scope: &'static str,
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        if let Some(timings) = TIMINGS.get() {
+            timings.lock().unwrap().push(serde_json::json!({
+                "stage": self.stage,
+                "wall_seconds": self.start.elapsed().as_secs_f64(),
+                "peak_memory_kb": peak_memory(),
+                "peak_scope": self.peak_scope,
+            }));
+        }
+    }
+}
+
+/// Start timing a pipeline stage if timings are enabled.
+///
+/// The peak memory high water mark is reset where supported so it reflects
+/// the stage rather than the whole run. Where it cannot be reset the stage
+/// is recorded with a `peak_scope` of `process`.
+pub fn stage_timer(stage: &str) -> Option<StageTimer> {
+    TIMINGS.get()?;
+    let peak_scope = match fs::write("/proc/self/clear_refs", "5") {
+        Ok(_) => "stage",
+        Err(_) => "process",
+    };
+    Some(StageTimer {
+        stage: stage.to_string(),
+        start: Instant::now(),
+        peak_scope,
+    })
+}
+
+/// Stage timings recorded so far, or `None` if timings are not enabled.
+pub fn timings() -> Option<serde_json::Value> {
+    TIMINGS
+        .get()
+        .map(|timings| serde_json::Value::Array(timings.lock().unwrap().clone()))
+}
+
+/// Write stage timings recorded so far to a JSON file.
+pub fn write_timings(path: &Path) -> Result<(), error::Error> {
+    let timings = serde_json::json!({ "timings": timings() });
+    fs::write(path, serde_json::to_string_pretty(&timings).unwrap())?;
+    Ok(())
+}
+
+static WORKSPACE: OnceLock<PathBuf> = OnceLock::new();
+
+static SCRATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the workspace directory for intermediate files. Only the first call
+/// has an effect.
+pub fn set_workspace(path: PathBuf) {
+    let _ = WORKSPACE.set(path);
+}
+
+/// Workspace directory for intermediate files and caches, falling back to
+/// `blobtk` in the system temp directory.
+pub fn workspace() -> PathBuf {
+    match WORKSPACE.get() {
+        Some(path) => path.clone(),
+        None => std::env::temp_dir().join("blobtk"),
+    }
+}
+
+/// Directory for temporary files from this process, removed by
+/// `clean_scratch` when the run finishes.
+pub fn scratch_root() -> PathBuf {
+    workspace().join("tmp").join(process::id().to_string())
+}
+
+/// Create a new, empty scratch directory for this run.
+pub fn scratch_dir(prefix: &str) -> Result<PathBuf, error::Error> {
+    let dir = scratch_root().join(format!(
+        "{}_{}",
+        prefix,
+        SCRATCH_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Remove the scratch directory for this process.
+pub fn clean_scratch() {
+    let _ = fs::remove_dir_all(scratch_root());
+}
+
+/// Resolve a cache directory option, using `<workspace>/cache/<name>` when
+/// the option is given without a value.
+pub fn cache_dir(path: &Path, name: &str) -> PathBuf {
+    match path.as_os_str().is_empty() {
+        true => workspace().join("cache").join(name),
+        false => path.to_path_buf(),
+    }
+}
+
+/// Write a value as MessagePack to a temporary file and rename, so
+/// concurrent runs never read a partial cache entry.
+pub fn write_msgpack<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), error::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension(format!("tmp{}", process::id()));
+    let mut writer = std::io::BufWriter::new(fs::File::create(&tmp)?);
+    rmp_serde::encode::write_named(&mut writer, value)
+        .map_err(|err| error::Error::SerdeError(err.to_string()))?;
+    drop(writer);
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Sample name for a reads or alignment file, the file name without
+/// sequence file extensions.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use crate::blobtk::utils::sample_name;
+/// assert_eq!(sample_name(Path::new("a/reads_1.fastq.gz")), "reads_1");
+/// assert_eq!(sample_name(Path::new("reads.fq")), "reads");
+/// assert_eq!(sample_name(Path::new("b/sample.cram")), "sample");
+/// assert_eq!(sample_name(Path::new("sample.v2.bam")), "sample.v2");
+/// ```
+pub fn sample_name(path: &Path) -> String {
+    let mut name = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+    while let Some(ext) = name.extension() {
+        match ext.to_str() {
+            Some("bam" | "cram" | "sam" | "fastq" | "fq" | "gz") => {
+                name.set_extension("");
+            }
+            _ => break,
+        }
+    }
+    name.to_string_lossy().to_string()
+}
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+static OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+/// Request cancellation of the running command.
+///
+/// Long running loops check for cancellation and stop early, returning
+/// `error::Error::Cancelled` where they can.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether cancellation has been requested.
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Clear any cancellation request and tracked outputs before a new run.
+pub fn reset_cancel() {
+    CANCELLED.store(false, Ordering::SeqCst);
+    OUTPUTS.lock().unwrap().clear();
+}
+
+/// Return `error::Error::Cancelled` if cancellation has been requested.
+pub fn check_cancelled() -> Result<(), error::Error> {
+    match is_cancelled() {
+        true => Err(error::Error::Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// Track an output file so it can be removed if the run is cancelled.
+pub fn track_output(path: &Path) {
+    OUTPUTS.lock().unwrap().push(path.to_path_buf());
+}
+
+/// Remove tracked output files, returning the paths that were removed.
+pub fn remove_partial_outputs() -> Vec<PathBuf> {
+    let mut outputs = OUTPUTS.lock().unwrap();
+    let removed = outputs
+        .drain(..)
+        .filter(|path| fs::remove_file(path).is_ok())
+        .collect();
+    removed
+}
+
+/// Indices of a list in descending order of value, with ties in
+/// descending order of index.
+///
+/// ```
+/// use blobtk::utils::indexed_sort;
+///
+/// assert_eq!(indexed_sort(&[2, 3, 1, 3]), vec![3, 1, 0, 2]);
+/// ```
+pub fn indexed_sort<T: Ord + Sync>(list: &[T]) -> Vec<usize> {
     let mut indices = (0..list.len()).collect::<Vec<_>>();
-    indices.sort_by_key(|&i| &list[i]);
-    indices.reverse();
+    // breaking ties by index gives the same order as a stable sort, without
+    // the cost of a stable parallel sort
+    indices.par_sort_unstable_by(|a, b| list[*b].cmp(&list[*a]).then(b.cmp(a)));
     indices
 }
 
+/// Number of values summed sequentially by each task in `prefix_sums`.
+const PREFIX_CHUNK_SIZE: usize = 1 << 16;
+
+/// Inclusive prefix sums, computed in parallel over chunks.
+///
+/// Chunk sums are added in a different order to a sequential sum, so
+/// floating point results match exactly only for integer values.
+///
+/// ```
+/// use blobtk::utils::prefix_sums;
+///
+/// assert_eq!(prefix_sums(&[3, 1, 2]), vec![3, 4, 6]);
+/// assert!(prefix_sums::<f64>(&[]).is_empty());
+/// ```
+pub fn prefix_sums<T>(values: &[T]) -> Vec<T>
+where
+    T: Copy + Default + Send + Sync + std::ops::Add<Output = T>,
+{
+    let mut sums = values.to_vec();
+    sums.par_chunks_mut(PREFIX_CHUNK_SIZE).for_each(|chunk| {
+        for i in 1..chunk.len() {
+            chunk[i] = chunk[i - 1] + chunk[i];
+        }
+    });
+    let mut offsets = vec![];
+    let mut offset = T::default();
+    for chunk in sums.chunks(PREFIX_CHUNK_SIZE) {
+        offsets.push(offset);
+        offset = offset + chunk[chunk.len() - 1];
+    }
+    sums.par_chunks_mut(PREFIX_CHUNK_SIZE)
+        .zip(offsets)
+        .skip(1)
+        .for_each(|(chunk, offset)| {
+            for value in chunk.iter_mut() {
+                *value = offset + *value;
+            }
+        });
+    sums
+}
+
 pub fn styled_progress_bar(total: usize, message: &str) -> ProgressBar {
     let progress_bar = ProgressBar::new(total as u64);
     let format_string = format!(
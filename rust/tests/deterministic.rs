@@ -0,0 +1,140 @@
+//! Reproducibility tests.
+//!
+//! Runs `plot`, `depth` and `filter` twice on the same inputs and checks the
+//! outputs are byte-for-byte identical, so results can be compared between
+//! runs with a checksum.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use blobtk::cli::{DepthOptions, FilterOptions, PlotOptions};
+use blobtk::depth::depth;
+use blobtk::filter::filter;
+use blobtk::plot::plot;
+use clap::Parser;
+
+/// Create an empty output directory for one run of a test.
+fn run_dir(test: &str, run: usize) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join("blobtk_deterministic")
+        .join(test)
+        .join(run.to_string());
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Copy input files into `dir` so outputs written alongside them stay there.
+fn copy_inputs(dir: &Path, files: &[&str]) {
+    for file in files {
+        let src = Path::new("test").join(file);
+        fs::copy(&src, dir.join(src.file_name().unwrap())).unwrap();
+    }
+}
+
+/// Check that every file written by the first run matches the second.
+fn assert_same_outputs(first: &Path, second: &Path) {
+    let mut names: Vec<_> = fs::read_dir(first)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert!(!names.is_empty(), "no outputs in {}", first.display());
+    for name in names {
+        let a = fs::read(first.join(&name)).unwrap();
+        let b = fs::read(second.join(&name)).unwrap();
+        assert!(
+            a == b,
+            "{:?} differs between {} and {}",
+            name,
+            first.display(),
+            second.display()
+        );
+    }
+}
+
+#[test]
+fn test_plot_is_deterministic() {
+    let dirs: Vec<_> = (0..2).map(|run| run_dir("plot", run)).collect();
+    for dir in dirs.iter() {
+        for view in ["blob", "cumulative", "legend", "snail"] {
+            let out = dir.join(format!("{}.svg", view));
+            plot(&PlotOptions::parse_from([
+                "plot",
+                "-d",
+                "test/example",
+                "-v",
+                view,
+                "-o",
+                out.to_str().unwrap(),
+            ]))
+            .unwrap();
+        }
+    }
+    assert_same_outputs(&dirs[0], &dirs[1]);
+}
+
+#[test]
+fn test_depth_is_deterministic() {
+    let dirs: Vec<_> = (0..2).map(|run| run_dir("depth", run)).collect();
+    for dir in dirs.iter() {
+        let bed = dir.join("depth.bed");
+        depth(&DepthOptions::parse_from([
+            "depth",
+            "-b",
+            "test/test.bam",
+            "-s",
+            "1000",
+            "-O",
+            bed.to_str().unwrap(),
+        ]))
+        .unwrap();
+        for name in ["depth.tsv", "depth.parquet"] {
+            let matrix = dir.join(name);
+            depth(&DepthOptions::parse_from([
+                "depth",
+                "--bams",
+                "test/test.bam",
+                "test/test.bam",
+                "-s",
+                "1000",
+                "-M",
+                matrix.to_str().unwrap(),
+            ]))
+            .unwrap();
+        }
+    }
+    assert_same_outputs(&dirs[0], &dirs[1]);
+}
+
+#[test]
+fn test_filter_is_deterministic() {
+    let dirs: Vec<_> = (0..2).map(|run| run_dir("filter", run)).collect();
+    for dir in dirs.iter() {
+        copy_inputs(
+            dir,
+            &["test.bam", "test.bam.csi", "reads_1.fq.gz", "reads_2.fq.gz"],
+        );
+        let bam = dir.join("test.bam");
+        let fastq1 = dir.join("reads_1.fq.gz");
+        let fastq2 = dir.join("reads_2.fq.gz");
+        let read_list = dir.join("reads.txt");
+        filter(&FilterOptions::parse_from([
+            "filter",
+            "-i",
+            "test/test.list",
+            "-b",
+            bam.to_str().unwrap(),
+            "-f",
+            fastq1.to_str().unwrap(),
+            "-r",
+            fastq2.to_str().unwrap(),
+            "-F",
+            "--alignment-out",
+            "-O",
+            read_list.to_str().unwrap(),
+        ]))
+        .unwrap();
+    }
+    assert_same_outputs(&dirs[0], &dirs[1]);
+}
@@ -0,0 +1,176 @@
+//! Golden image tests for plots.
+//!
+//! Renders each view of the example BlobDir in `test/example` and compares
+//! the output with SVG and PNG files in `test/golden`. Numbers in the SVG
+//! may differ by up to `SVG_TOLERANCE` and up to `PNG_TOLERANCE` of the
+//! pixels may differ, so rounding differences pass while changes to the
+//! figures fail. PNGs are rendered without fonts so they do not depend on
+//! the fonts installed, text is checked in the SVG.
+//!
+//! After an intended change to plot output, regenerate the golden files
+//! with `BLOBTK_UPDATE_GOLDEN=1 cargo test --test plot_golden` and check
+//! them before committing.
+
+use std::fs;
+use std::path::PathBuf;
+
+use blobtk::cli::PlotOptions;
+use blobtk::plot::{plot, render_png};
+use clap::Parser;
+use regex::Regex;
+use tiny_skia::Pixmap;
+use usvg::fontdb;
+
+const SVG_TOLERANCE: f64 = 0.01;
+const PNG_TOLERANCE: f64 = 0.01;
+/// Smallest channel difference counted as a changed pixel.
+const CHANNEL_TOLERANCE: u8 = 32;
+const PNG_WIDTH: u32 = 500;
+
+fn update_golden() -> bool {
+    std::env::var_os("BLOBTK_UPDATE_GOLDEN").is_some()
+}
+
+/// Split SVG text into the text between numbers and the numbers.
+fn svg_tokens(svg: &str) -> (Vec<&str>, Vec<f64>) {
+    let re = Regex::new(r"-?\d+(\.\d+)?(e-?\d+)?").unwrap();
+    let mut text = vec![];
+    let mut numbers = vec![];
+    let mut end = 0;
+    for m in re.find_iter(svg) {
+        text.push(&svg[end..m.start()]);
+        numbers.push(m.as_str().parse().unwrap());
+        end = m.end();
+    }
+    text.push(&svg[end..]);
+    (text, numbers)
+}
+
+fn compare_svg(actual: &str, golden: &str) -> Result<(), String> {
+    let (actual_text, actual_numbers) = svg_tokens(actual);
+    let (golden_text, golden_numbers) = svg_tokens(golden);
+    if let Some(i) =
+        (0..actual_text.len().min(golden_text.len())).find(|i| actual_text[*i] != golden_text[*i])
+    {
+        return Err(format!(
+            "text differs: {:?} != {:?}",
+            actual_text[i], golden_text[i]
+        ));
+    }
+    if actual_numbers.len() != golden_numbers.len() {
+        return Err(format!(
+            "{} numbers != {}",
+            actual_numbers.len(),
+            golden_numbers.len()
+        ));
+    }
+    let differences = actual_numbers
+        .iter()
+        .zip(golden_numbers.iter())
+        .filter(|(a, b)| (*a - *b).abs() > SVG_TOLERANCE)
+        .count();
+    match differences {
+        0 => Ok(()),
+        _ => Err(format!("{} numbers differ", differences)),
+    }
+}
+
+fn compare_png(actual: &Pixmap, golden: &Pixmap) -> Result<(), String> {
+    if (actual.width(), actual.height()) != (golden.width(), golden.height()) {
+        return Err(format!(
+            "size {}x{} != {}x{}",
+            actual.width(),
+            actual.height(),
+            golden.width(),
+            golden.height()
+        ));
+    }
+    let changed = actual
+        .data()
+        .chunks(4)
+        .zip(golden.data().chunks(4))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .any(|(x, y)| x.abs_diff(*y) >= CHANNEL_TOLERANCE)
+        })
+        .count();
+    let fraction = changed as f64 / (actual.width() * actual.height()) as f64;
+    match fraction > PNG_TOLERANCE {
+        true => Err(format!("{:.2}% of pixels differ", fraction * 100.0)),
+        false => Ok(()),
+    }
+}
+
+/// Plot a view of the example BlobDir and compare it with the golden files.
+fn check_view(name: &str, args: &[&str]) {
+    let golden_dir = PathBuf::from("test/golden");
+    let out_dir = std::env::temp_dir().join("blobtk_golden");
+    fs::create_dir_all(&out_dir).unwrap();
+    let svg_path = out_dir.join(format!("{}.svg", name));
+    let png_path = out_dir.join(format!("{}.png", name));
+    let mut cmd = vec![
+        "plot",
+        "-d",
+        "test/example",
+        "-o",
+        svg_path.to_str().unwrap(),
+    ];
+    cmd.extend(args);
+    plot(&PlotOptions::parse_from(cmd)).unwrap();
+    let svg = fs::read_to_string(&svg_path).unwrap();
+    let pixmap = render_png(svg.as_bytes(), PNG_WIDTH, &fontdb::Database::new());
+    pixmap.save_png(&png_path).unwrap();
+
+    let golden_svg = golden_dir.join(format!("{}.svg", name));
+    let golden_png = golden_dir.join(format!("{}.png", name));
+    if update_golden() {
+        fs::copy(&svg_path, &golden_svg).unwrap();
+        fs::copy(&png_path, &golden_png).unwrap();
+        return;
+    }
+    let result = compare_svg(&svg, &fs::read_to_string(&golden_svg).unwrap())
+        .map_err(|err| format!("{}: {}", golden_svg.display(), err))
+        .and_then(|_| {
+            compare_png(&pixmap, &Pixmap::load_png(&golden_png).unwrap())
+                .map_err(|err| format!("{}: {}", golden_png.display(), err))
+        });
+    if let Err(err) = result {
+        panic!(
+            "{} plot differs from {}, output written to {}",
+            name,
+            err,
+            out_dir.display()
+        );
+    }
+}
+
+#[test]
+fn test_blob_view() {
+    check_view("blob", &["-v", "blob"]);
+}
+
+#[test]
+fn test_cumulative_view() {
+    check_view("cumulative", &["-v", "cumulative"]);
+}
+
+#[test]
+fn test_legend_view() {
+    check_view("legend", &["-v", "legend"]);
+}
+
+#[test]
+fn test_snail_view() {
+    // fewer segments keep the golden file small
+    check_view("snail", &["-v", "snail", "-s", "100"]);
+}
+
+#[test]
+fn test_tolerance() {
+    let golden = r#"<path d="M0,1.5 L2,3"/>"#;
+    assert!(compare_svg(r#"<path d="M0,1.505 L2,3"/>"#, golden).is_ok());
+    assert!(compare_svg(r#"<path d="M0,1.6 L2,3"/>"#, golden).is_err());
+    assert!(compare_svg(r#"<path d="M0,1.5 L2,3 L4,5"/>"#, golden).is_err());
+    assert!(compare_svg(r#"<circle d="M0,1.5 L2,3"/>"#, golden).is_err());
+}